@@ -1,7 +0,0 @@
-
-
-@compute
-@workgroup_size(8,8)
-fn main() {
-    
-}
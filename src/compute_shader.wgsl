@@ -0,0 +1,16 @@
+// NOTE: smoke test for the compute path, fills the compute test texture with its uv,
+// see render_compute_test in main.rs
+
+var output: texture_storage_2d<rgba8unorm, write>;
+
+@compute
+@workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(output);
+    // NOTE: the dispatch rounds up to whole workgroups
+    if any(id.xy >= size) {
+        return;
+    }
+    let uv = (vec2<f32>(id.xy) + 0.5) / vec2<f32>(size);
+    textureStore(output, id.xy, vec4(uv, 0.0, 1.0));
+}
@@ -0,0 +1,25 @@
+// NOTE: lines queued on the cpu, see DebugDraw in main.rs. Drawn over the finished frame
+// with the g-buffer depth test like the particles
+
+var<uniform> view_proj: mat4x4<f32>;
+
+// NOTE: one per line end, see DebugVertex
+struct DebugVertex {
+    position: vec3<f32>,
+    color: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_pos: vec4<f32>,
+    @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vs_debug_draw(vertex: DebugVertex) -> VertexOutput {
+    return VertexOutput(view_proj * vec4(vertex.position, 1.0), vertex.color);
+}
+
+@fragment
+fn fs_debug_draw(vs_out: VertexOutput) -> @location(0) vec4<f32> {
+    return vs_out.color;
+}
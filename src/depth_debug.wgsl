@@ -0,0 +1,18 @@
+// #include "fullscreen.wgsl"
+
+var depth_view: texture_depth_2d;
+var<uniform> near: f32;
+var<uniform> far: f32;
+
+@fragment
+fn fs_depth_debug(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    let size = textureDimensions(depth_view);
+    let texel = min(vec2<u32>(vertex.uv * vec2<f32>(size)), size - 1u);
+    let d = textureLoad(depth_view, texel, 0);
+
+    // NOTE: depth buffer is non linear, undo the projection to get view space distance
+    let linear_depth = near * far / (far - d * (far - near));
+    // NOTE: near and far come in swapped for reverse z
+    let c = clamp((linear_depth - min(near, far)) / abs(far - near), 0.0, 1.0);
+    return vec4(vec3(c), 1.0);
+}
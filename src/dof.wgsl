@@ -0,0 +1,75 @@
+// NOTE: thin lens depth of field over the finished frame, reads the image the light pass,
+// skybox and taa resolve wrote to the dof source texture, see render_dof in main.rs
+
+// #include "fullscreen.wgsl"
+// #include "globals.wgsl"
+// #include "view_pos.wgsl"
+
+struct DofUniform {
+    // NOTE: view space distance that is in focus
+    focus_distance: f32,
+    // NOTE: lens diameter in world units
+    aperture: f32,
+    // NOTE: circle of confusion radius is clamped to this many pixels
+    max_coc_px: f32,
+    // NOTE: pixels per world unit at distance 1, half the target height over tan(fov / 2)
+    px_per_unit: f32,
+};
+
+var<uniform> dof: DofUniform;
+
+var color_view: texture_2d<f32>;
+var color_sampler: sampler;
+
+var depth_view: texture_depth_2d;
+
+const NUM_DOF_SAMPLES = 48u;
+const GOLDEN_ANGLE = 2.39996323;
+
+// NOTE: radius in pixels of the disc a point at view distance z blurs to. The lens
+// diameter scaled by how far off the focal plane the point is, projected at the
+// focus distance
+fn coc_radius_px(z: f32) -> f32 {
+    let diameter = dof.aperture * abs(z - dof.focus_distance) / z;
+    let px = 0.5 * diameter * dof.px_per_unit / dof.focus_distance;
+    return min(px, dof.max_coc_px);
+}
+
+// NOTE: rh view space, the cleared background has positive z and is infinitely far away
+fn view_distance(texel: vec2<i32>) -> f32 {
+    let p = load_view_pos_texel(depth_view, texel);
+    return select(1e9, -p.z, p.z < 0.0);
+}
+
+// NOTE: VertexOutput and vs_fullscreen come from fullscreen.wgsl
+@fragment
+fn fs_dof(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<i32>(textureDimensions(color_view));
+    let texel = min(vec2<i32>(vertex.uv * vec2<f32>(size)), size - 1);
+    let center_z = view_distance(texel);
+    let center_coc = coc_radius_px(center_z);
+    let center = textureLoad(color_view, texel, 0).rgb;
+
+    // NOTE: gather over the disc of the largest blur, each sample counts where its own
+    // disc reaches this pixel, so a blurry foreground spreads over sharp pixels behind
+    // it. Samples behind the pixel can't spread further than its own disc, so a sharp
+    // foreground edge doesn't pick up the blurry background
+    var sum = center;
+    var weight_sum = 1.0;
+    for (var i = 0u; i < NUM_DOF_SAMPLES; i++) {
+        let t = (f32(i) + 0.5) / f32(NUM_DOF_SAMPLES);
+        let r = sqrt(t) * dof.max_coc_px;
+        let a = f32(i) * GOLDEN_ANGLE;
+        let offset = r * vec2(cos(a), sin(a));
+        let sample_texel = clamp(texel + vec2<i32>(round(offset)), vec2(0), size - 1);
+        let sample_z = view_distance(sample_texel);
+        var sample_coc = coc_radius_px(sample_z);
+        if sample_z > center_z {
+            sample_coc = min(sample_coc, center_coc);
+        }
+        let weight = clamp(sample_coc - r + 1.0, 0.0, 1.0);
+        sum += weight * textureLoad(color_view, sample_texel, 0).rgb;
+        weight_sum += weight;
+    }
+    return vec4(sum / weight_sum, 1.0);
+}
@@ -0,0 +1,15 @@
+// NOTE: shared by every full screen pass, included at the top of each pass shader
+
+struct VertexOutput {
+    @builtin(position) clip_pos: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// NOTE: draws a single triangle covering the screen, no vertex buffer needed,
+// uv (0,0) is the top left and (1,1) the bottom right of the screen
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    let clip_pos = vec4(2.0 * uv.x - 1.0, 1.0 - 2.0 * uv.y, 0.0, 1.0);
+    return VertexOutput(clip_pos, uv);
+}
@@ -0,0 +1,24 @@
+// NOTE: per draw camera and frame uniform, offsets have to match Globals in main.rs.
+// Included by every shader that reads it instead of each keeping its own copy
+
+struct Globals {
+    mvp_transform: mat4x4<f32>,
+    mv_transform: mat4x4<f32>,
+    mv_rot: mat4x4<f32>,
+    cam_pos: vec3<f32>,
+    cam_dir: vec3<f32>,
+    // NOTE: unjittered view projection of the previous frame times the model, for the
+    // taa and tssao reprojection
+    prev_vp: mat4x4<f32>,
+    // NOTE: render resolution in pixels and its reciprocal
+    viewport_size: vec2<f32>,
+    inv_viewport_size: vec2<f32>,
+    // NOTE: ndc to world, far_depth is the depth buffer value where nothing was drawn
+    inv_mvp_transform: mat4x4<f32>,
+    far_depth: f32,
+    // NOTE: seconds since startup and since the previous frame
+    time_secs: f32,
+    delta_time_secs: f32,
+};
+
+var<uniform> globals: Globals;
@@ -0,0 +1,48 @@
+// NOTE: hierarchical linear depth for the finest ao pass, mip 0 is the view depth of the
+// g-buffer and every mip after keeps the closest depth of the texels it covers, see
+// HiZTexture and render_hi_z in main.rs
+
+// #include "globals.wgsl"
+// #include "view_pos.wgsl"
+
+var depth_view: texture_depth_2d;
+var src_view: texture_2d<f32>;
+var dst_view: texture_storage_2d<r32float, write>;
+
+// NOTE: background depth, far enough that the ao distance falloff drops it
+const HI_Z_FAR = 1e9;
+
+@compute
+@workgroup_size(8, 8)
+fn cs_linear_depth(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(dst_view);
+    if any(id.xy >= size) {
+        return;
+    }
+    let p = load_view_pos_texel(depth_view, vec2<i32>(id.xy));
+    // NOTE: rh view space, geometry has negative z and the cleared background positive
+    let depth = select(HI_Z_FAR, -p.z, p.z < 0.0);
+    textureStore(dst_view, id.xy, vec4(depth, 0.0, 0.0, 0.0));
+}
+
+@compute
+@workgroup_size(8, 8)
+fn cs_downsample(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(dst_view);
+    if any(id.xy >= size) {
+        return;
+    }
+    let src_size = textureDimensions(src_view);
+    // NOTE: with an odd source size the last row and column also take the texel the
+    // 2x2 footprint leaves out, otherwise it is missing from every coarser mip
+    let extra = select(vec2(0u), src_size % 2u, id.xy == size - 1u);
+    let base = 2u * id.xy;
+    var depth = HI_Z_FAR;
+    for (var y = 0u; y < 2u + extra.y; y++) {
+        for (var x = 0u; x < 2u + extra.x; x++) {
+            let texel = min(base + vec2(x, y), src_size - 1u);
+            depth = min(depth, textureLoad(src_view, texel, 0).r);
+        }
+    }
+    textureStore(dst_view, id.xy, vec4(depth, 0.0, 0.0, 0.0));
+}
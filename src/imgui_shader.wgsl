@@ -0,0 +1,36 @@
+
+struct ImguiGlobals {
+    scale: vec2<f32>,
+    translate: vec2<f32>,
+};
+
+var<uniform> globals: ImguiGlobals;
+
+var font_view: texture_2d<f32>;
+var font_sampler: sampler;
+
+struct Vertex {
+    pos: vec2<f32>,
+    uv: vec2<f32>,
+    color: u32,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_pos: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+@vertex
+fn vs_imgui(vertex: Vertex) -> VertexOutput {
+    let pos = vertex.pos * globals.scale + globals.translate;
+    let color = unpack4x8unorm(vertex.color);
+    // NOTE: imgui colors are srgb, surface does the conversion back
+    let linear_color = vec4(pow(color.rgb, vec3(2.2)), color.a);
+    return VertexOutput(vec4(pos, 0.0, 1.0), vertex.uv, linear_color);
+}
+
+@fragment
+fn fs_imgui(vs_out: VertexOutput) -> @location(0) vec4<f32> {
+    return vs_out.color * textureSample(font_view, font_sampler, vs_out.uv);
+}
@@ -1,6 +1,7 @@
+// #include "fullscreen.wgsl"
 
-var pos_view: texture_2d<f32>;
-var pos_sampler: sampler;
+// NOTE: positions are rebuilt from the depth of the level being shaded, see view_pos.wgsl
+var depth_view: texture_depth_2d;
 
 var normal_view: texture_2d<f32>;
 var normal_sampler: sampler;
@@ -8,9 +9,62 @@ var normal_sampler: sampler;
 var ao_view: texture_2d<f32>;
 var ao_sampler: sampler;
 
+var bent_normal_view: texture_2d<f32>;
+var bent_normal_sampler: sampler;
+
+// NOTE: see AmbientUniform, sky and ground are blended by the up component of the
+// ambient direction, the bent normal if use_bent_normal and the surface normal otherwise.
+// With use_ibl the irradiance cube is looked up along that direction instead
+struct AmbientParams {
+    sky: f32,
+    ground: f32,
+    use_bent_normal: u32,
+    use_ibl: u32,
+    ibl_intensity: f32,
+    // NOTE: amplitude of a once a second ambient pulse driven by globals.time_secs
+    pulse: f32,
+    pad1: u32,
+    pad2: u32,
+};
+var<uniform> ambient: AmbientParams;
+
+var irradiance_view: texture_cube<f32>;
+var irradiance_sampler: sampler;
+
+var albedo_view: texture_2d<f32>;
+var albedo_sampler: sampler;
+
+// NOTE: roughness in r, metalness in g, see MATERIAL_FORMAT
+var material_view: texture_2d<f32>;
+var material_sampler: sampler;
+
+// NOTE: screen uv of the ao comparison divider, ao is only applied left of it. Past 1
+// when the comparison is off, see AoSplitSettings
+var<uniform> split_x: f32;
+
+var shadow_view: texture_depth_2d_array;
+var shadow_sampler: sampler_comparison;
+var<uniform> shadow: ShadowParams;
+
+// NOTE: see GpuLight, kind 0 directional, 1 point, 2 spot
+struct Light {
+    position: vec3<f32>,
+    kind: u32,
+    direction: vec3<f32>,
+    intensity: f32,
+    color: vec3<f32>,
+    radius: f32,
+    cos_inner: f32,
+    cos_outer: f32,
+    shadowed: u32,
+    pad0: u32,
+};
 
-var prev_pos_view: texture_2d<f32>;
-var prev_pos_sampler: sampler;
+var<storage, read> lights: array<Light>;
+var<uniform> num_lights: u32;
+
+// NOTE: the coarser level the far ao is upsampled from
+var prev_depth_view: texture_depth_2d;
 
 var prev_normal_view: texture_2d<f32>;
 var prev_normal_sampler: sampler;
@@ -18,37 +72,82 @@ var prev_normal_sampler: sampler;
 var prev_ao_view: texture_2d<f32>;
 var prev_ao_sampler: sampler;
 
+// NOTE: min linear depth pyramid of the full resolution g-buffer, see hi_z.wgsl
+var hi_z_view: texture_2d<f32>;
+var hi_z_sampler: sampler;
 
+// #include "globals.wgsl"
+// #include "view_pos.wgsl"
 
-
-var<uniform> globals: Globals;
 var<uniform> ao_params: AOParams;
 
-
-struct Globals {
-    mvp_transform: mat4x4<f32>,   
-    mv_transform: mat4x4<f32>,   
-    mv_rot: mat4x4<f32>,
-    cam_pos: vec3<f32>,
-    cam_dir: vec3<f32>,
+// NOTE: must match MAX_SHADOW_CASCADES
+const MAX_CASCADES = 4u;
+
+struct ShadowParams {
+    light_vp: array<mat4x4<f32>, MAX_CASCADES>,
+    // NOTE: view depth where each cascade ends
+    splits: vec4<f32>,
+    sun_dir: vec3<f32>,
+    bias: f32,
+    texel_size: f32,
+    // NOTE: 0 means no shadowing
+    enabled: u32,
+    cascade_count: u32,
+    blend_width: f32,
+    // NOTE: world space size of the light for pcss, scales both the blocker search
+    // region and the penumbra
+    light_size: f32,
+    pcss_num_blocker_samples: u32,
+    // NOTE: 0 is the fixed 3x3 pcf
+    pcss_enabled: u32,
+    pad0: u32,
 };
 
-
 struct AOParams {
     num_passes: u32,
     pass_i: u32,
     ri_almost: f32,
     ao_width: f32,
 
-    pad: vec3<u32>,
+    d_max: f32,
+    r_max: f32,
+    bias: f32,
     ao_height: f32,
-};
 
-struct Vertex {
-    ws_pos: vec3<f32>,
-    ws_normal: vec3<f32>,
+    num_samples: u32,
+    // NOTE: see AoMethod::index
+    method: u32,
+    hbao_directions: u32,
+    hbao_steps: u32,
+
+    gtao_slices: u32,
+    gtao_steps: u32,
+    gtao_thickness: f32,
+    hi_z_mips: u32,
+
+    // NOTE: see HiZMipSelection::index
+    hi_z_mip_selection: u32,
+    hi_z_mip_bias: u32,
+    proj_scale: vec2<f32>,
+
+    // NOTE: see generate_ssao_kernel, unit disc offsets packed two per vec4, with hbao
+    // and gtao the unit march directions instead
+    kernel: array<vec4<f32>, 8>,
 };
 
+const AO_METHOD_HEMISPHERE = 0u;
+const AO_METHOD_HBAO = 1u;
+const AO_METHOD_GTAO = 2u;
+const HALF_PI = 1.5707963;
+const HI_Z_OFF = 0u;
+const HI_Z_KERNEL_RADIUS = 2u;
+
+fn ao_kernel_offset(i: u32) -> vec2<f32> {
+    let packed = ao_params.kernel[i / 2u];
+    return select(packed.xy, packed.zw, (i % 2u) == 1u);
+}
+
 const poisson_disc_16 = array(
     -0.6116678f,  0.04548655f, -0.26605980f, -0.6445347f,
     -0.4798763f,  0.78557830f, -0.19723210f, -0.1348270f,
@@ -60,21 +159,21 @@ const poisson_disc_16 = array(
     0.7814927f, -0.48938420f,  0.38269190f,  0.8695006f
     );
 
+// NOTE: the depth of the downsampled position instead of the position itself, the next
+// passes rebuild it from that like from the full resolution depth
 struct DownSampleOutput {
-    // @builtin(frag_depth) depth: f32,
-    @location(0) pos: vec4<f32>,
-    @location(1) normal: vec4<f32>
+    @builtin(frag_depth) depth: f32,
+    @location(0) normal: vec4<f32>
 };
 
 
 @fragment
 fn fs_downsample(vertex: VertexOutput) -> DownSampleOutput {
-    // let depth = textureSample(depth_view, depth_sampler, vertex.uv);
-    // let subpixel_depths = textureGather(depth_view, depth_sampler, vertex.uv);
-    let subpixel_px = textureGather(0, pos_view, pos_sampler, vertex.uv);
-    let subpixel_py = textureGather(1, pos_view, pos_sampler, vertex.uv);
+    let subpixel_p = gather_view_pos(depth_view, vertex.uv);
+    let subpixel_px = vec4(subpixel_p[0].x, subpixel_p[1].x, subpixel_p[2].x, subpixel_p[3].x);
+    let subpixel_py = vec4(subpixel_p[0].y, subpixel_p[1].y, subpixel_p[2].y, subpixel_p[3].y);
     // NOTE: using rh coordinate system means z values will be negative
-    let subpixel_pz = -textureGather(2, pos_view, pos_sampler, vertex.uv);
+    let subpixel_pz = -vec4(subpixel_p[0].z, subpixel_p[1].z, subpixel_p[2].z, subpixel_p[3].z);
 
     // NOTE: sorting algorithm
     // if num0 > num1: swap(num0,num1)
@@ -129,12 +228,12 @@ fn fs_downsample(vertex: VertexOutput) -> DownSampleOutput {
 
     let idx_1 = idx[1];
     let p1 = vec3(subpixel_px[idx_1], subpixel_py[idx_1], subpixel_pz[idx_1]);
-    let n1 = vec3(subpixel_nx[idx_1], subpixel_ny[idx_1], subpixel_nz[idx_1]);
+    let n1 = decode_gathered_normal(subpixel_nx, subpixel_ny, subpixel_nz, u32(idx_1));
     if p3z - p0z <= d_thresh && true {
     // if diffy <= d_thresh {
         let idx_2 = idx[2];
         let p2 = vec3(subpixel_px[idx_2], subpixel_py[idx_2], subpixel_pz[idx_2]);
-        let n2 = vec3(subpixel_nx[idx_2], subpixel_ny[idx_2], subpixel_nz[idx_2]);
+        let n2 = decode_gathered_normal(subpixel_nx, subpixel_ny, subpixel_nz, u32(idx_2));
 
         p_new = (p1 + p2) / 2.0;
         n_new = (n1 + n2) / 2.0;
@@ -147,35 +246,215 @@ fn fs_downsample(vertex: VertexOutput) -> DownSampleOutput {
 
     //NOTE: revert the negative z we added in the beginning
     p_new.z = -p_new.z;
-    // let output = DownSampleOutput(0.0, vec4(p_new, 1.0), vec4(n_new, 1.0));
-    let output = DownSampleOutput(vec4(p_new, 1.0), vec4(n_new, 1.0));
+    let output = DownSampleOutput(view_pos_to_depth(p_new), encode_normal(n_new));
     
     return output;
 }
 
-fn calc_oclusion_term(sample_uv: vec2f, p: vec3f, n: vec3f, d_max: f32) -> f32 {
-    
-    let qi = textureSample(pos_view, pos_sampler, sample_uv);
+// NOTE: view space position of a finest pass sample offset_px pixels from the shaded
+// pixel in a kernel of radius_px pixels. Past 2^hi_z_mip_bias pixels the sample reads a
+// coarser hi-z mip and rebuilds the position from the depth, the jitter is ignored
+fn ao_sample_pos(sample_uv: vec2f, offset_px: f32, radius_px: f32) -> vec4f {
+    let px = select(offset_px, radius_px, ao_params.hi_z_mip_selection == HI_Z_KERNEL_RADIUS);
+    let mip = i32(floor(log2(max(px, 1.0)))) - i32(ao_params.hi_z_mip_bias);
+    if ao_params.hi_z_mip_selection == HI_Z_OFF || mip <= 0 {
+        return load_view_pos(depth_view, sample_uv);
+    }
+    let level = min(u32(mip), ao_params.hi_z_mips - 1u);
+    let depth = textureSampleLevel(hi_z_view, hi_z_sampler, sample_uv, f32(level)).r;
+    let ndc = vec2(2.0 * sample_uv.x - 1.0, 1.0 - 2.0 * sample_uv.y);
+    return vec4(ndc * depth / ao_params.proj_scale, -depth, 1.0);
+}
+
+// NOTE: xyz is the unit direction to the sample qi, w its occlusion term
+fn calc_occlusion_and_dir(qi: vec4f, p: vec3f, n: vec3f, d_max: f32) -> vec4f {
     var d = (qi.xyz - p);
     let di = length(d);
     d /=  di;
 
     let rho = 1.0 - min(1.0, pow(di/d_max, 2.0));
 
-    let res = rho * max(dot(n, d), 0.0) * qi.w;
-    return res;
+    let res = rho * max(dot(n, d) - ao_params.bias, 0.0) * qi.w;
+    return vec4(d, res);
 }
 
+fn calc_oclusion_term(sample_uv: vec2f, p: vec3f, n: vec3f, d_max: f32) -> f32 {
+    let qi = load_view_pos(depth_view, sample_uv);
+    return calc_occlusion_and_dir(qi, p, n, d_max).w;
+}
+
+// NOTE: horizon based ao, marches each kernel direction in screen space over radius_px
+// pixels and keeps the sine of the highest horizon above the tangent plane. Only the
+// rise over the previous horizon occludes, attenuated by distance like the hemisphere
+// samples, so each direction occludes at most 1. xyz is the sum of the directions to
+// the horizons weighted by their occlusion, w the summed occlusion of all directions
+fn calc_hbao(uv: vec2f, p: vec3f, n: vec3f, d_max: f32, radius_px: f32, texel: vec2f) -> vec4f {
+    let num_directions = min(ao_params.hbao_directions, 16u);
+    let num_steps = max(ao_params.hbao_steps, 1u);
+    let step_px = radius_px / f32(num_steps);
+    // NOTE: per pixel rotation of the directions and offset of the steps, the blur
+    // turns the noise back into a smooth result instead of the banding of a fixed pattern
+    let rotation = pcss_rotation(uv);
+    let step_jitter = interleaved_gradient_noise(uv / texel + vec2(5.0, 3.0));
+
+    var occlusion = 0.0;
+    var occluded_dir = vec3(0.0);
+    for (var i: u32 = 0; i < num_directions; i++) {
+        let dir = rotation * ao_kernel_offset(i);
+        var max_sin = ao_params.bias;
+        var dir_occlusion = 0.0;
+        var horizon_dir = vec3(0.0);
+        for (var s: u32 = 0; s < num_steps; s++) {
+            // NOTE: first step lands somewhere up to one step away, never on the pixel itself
+            let sample_px = (f32(s) + 1.0 - step_jitter) * step_px;
+            let q = ao_sample_pos(uv + sample_px * dir * texel, sample_px, radius_px);
+            let d = q.xyz - p;
+            let di = max(length(d), 1e-4);
+            let sin_h = dot(n, d) / di * q.w;
+            let rho = 1.0 - min(1.0, pow(di / d_max, 2.0));
+            let rise = max(sin_h - max_sin, 0.0);
+            dir_occlusion += rho * rise;
+            horizon_dir = select(horizon_dir, d / di, rise > 0.0);
+            max_sin = max(max_sin, sin_h);
+        }
+        occlusion += dir_occlusion;
+        occluded_dir += dir_occlusion * horizon_dir;
+    }
+    return vec4(occluded_dir, occlusion);
+}
+
+// NOTE: cosine of the angle between the view vector and the direction to a gtao sample,
+// pulled towards the tangent plane horizon low_cos as the sample gets further away or
+// further in front of the shaded point than the thickness
+fn gtao_horizon_cos(delta: vec3f, view_vec: vec3f, low_cos: f32, d_max: f32) -> f32 {
+    let dist = max(length(delta), 1e-4);
+    let falloff = 1.0 - min(1.0, pow(dist / d_max, 2.0));
+    // NOTE: rh view space, a sample closer to the camera has a larger z
+    let thickness = ao_params.gtao_thickness;
+    let thin = 1.0 - smoothstep(thickness, 2.0 * thickness, delta.z);
+    return mix(low_cos, dot(delta / dist, view_vec), falloff * thin);
+}
+
+// NOTE: ground truth ao (jimenez et al. 2016). Each kernel direction is a slice through
+// the view vector, marched both ways for its two horizons, and the cosine weighted
+// visibility between them is integrated analytically against the normal projected into
+// the slice. xyz is the unnormalized bent normal, w the summed occlusion of all slices
+fn calc_gtao(uv: vec2f, p: vec3f, n: vec3f, d_max: f32, radius_px: f32, texel: vec2f) -> vec4f {
+    let num_slices = max(min(ao_params.gtao_slices, 16u), 1u);
+    let num_steps = max(ao_params.gtao_steps, 1u);
+    let step_px = radius_px / f32(num_steps);
+    let rotation = pcss_rotation(uv);
+    let step_jitter = interleaved_gradient_noise(uv / texel + vec2(5.0, 3.0));
+    let view_vec = normalize(-p);
+
+    var visibility = 0.0;
+    var bent_normal = vec3(0.0);
+    for (var i: u32 = 0; i < num_slices; i++) {
+        let dir = rotation * ao_kernel_offset(i);
+        // NOTE: uv y points down, view space y up
+        let dir_vec = vec3(dir.x, -dir.y, 0.0);
+        let ortho_dir = normalize(dir_vec - dot(dir_vec, view_vec) * view_vec);
+        let axis = cross(ortho_dir, view_vec);
+        let proj_n = n - axis * dot(n, axis);
+        let proj_n_len = length(proj_n);
+        let cos_n = clamp(dot(proj_n, view_vec) / max(proj_n_len, 1e-4), 0.0, 1.0);
+        // NOTE: angles are from the view vector, positive towards +dir
+        let n_angle = sign(dot(ortho_dir, proj_n)) * acos(cos_n);
+
+        // NOTE: both horizons start on the tangent plane
+        let low_cos0 = cos(n_angle + HALF_PI);
+        let low_cos1 = cos(n_angle - HALF_PI);
+        var horizon_cos0 = low_cos0;
+        var horizon_cos1 = low_cos1;
+        for (var s: u32 = 0; s < num_steps; s++) {
+            let offset_px = (f32(s) + 1.0 - step_jitter) * step_px;
+            let offset = offset_px * dir * texel;
+            let q0 = ao_sample_pos(uv + offset, offset_px, radius_px).xyz;
+            let q1 = ao_sample_pos(uv - offset, offset_px, radius_px).xyz;
+            horizon_cos0 = max(horizon_cos0, gtao_horizon_cos(q0 - p, view_vec, low_cos0, d_max));
+            horizon_cos1 = max(horizon_cos1, gtao_horizon_cos(q1 - p, view_vec, low_cos1, d_max));
+        }
+        // NOTE: h0 on the -dir side, both clamped to the hemisphere around the normal
+        let h0 = n_angle + clamp(-acos(horizon_cos1) - n_angle, -HALF_PI, HALF_PI);
+        let h1 = n_angle + clamp(acos(horizon_cos0) - n_angle, -HALF_PI, HALF_PI);
+        let sin_n = sin(n_angle);
+        let arc0 = (cos_n + 2.0 * h0 * sin_n - cos(2.0 * h0 - n_angle)) / 4.0;
+        let arc1 = (cos_n + 2.0 * h1 * sin_n - cos(2.0 * h1 - n_angle)) / 4.0;
+        visibility += proj_n_len * (arc0 + arc1);
+        // NOTE: halfway between the horizons is the average unoccluded direction
+        let bent_angle = 0.5 * (h0 + h1);
+        bent_normal += proj_n_len * (cos(bent_angle) * view_vec + sin(bent_angle) * ortho_dir);
+    }
+    return vec4(bent_normal, max(f32(num_slices) - visibility, 0.0));
+}
+
+// NOTE: same layout as ParticleInstance, drawn with the particle shader
+struct AoGizmo {
+    position: vec3<f32>,
+    size: f32,
+    color: vec4<f32>,
+};
+
+// NOTE: see AoGizmoUniform
+struct AoGizmoParams {
+    uv: vec2<f32>,
+    point_size: f32,
+    pad0: u32,
+};
+
+var<uniform> ao_gizmo: AoGizmoParams;
+var<storage, read_write> gizmos: array<AoGizmo>;
+
+fn view_to_world(p: vec3f) -> vec3f {
+    return (transpose(globals.mv_rot) * vec4(p, 0.0)).xyz + globals.cam_pos;
+}
+
+// NOTE: replays the finest hemisphere pass at one pixel. Instance 0 is the pixel itself,
+// instance i + 1 is where kernel sample i landed, green to red by how much it occludes.
+// Unused samples, background pixels and the horizon based methods get size 0 so the draw
+// can always be MAX_AO_SAMPLES + 1 instances
+@compute @workgroup_size(16)
+fn cs_ao_kernel_gizmos(@builtin(local_invocation_index) i: u32) {
+    let uv = ao_gizmo.uv;
+    let p = load_view_pos(depth_view, uv).xyz;
+    let n = decode_normal(textureSampleLevel(normal_view, normal_sampler, uv, 0.0));
+    let on_geometry = p.z < 0.0;
+    if i == 0u {
+        let size = select(0.0, 1.5 * ao_gizmo.point_size, on_geometry);
+        gizmos[0] = AoGizmo(view_to_world(p), size, vec4(1.0));
+    }
+
+    // NOTE: same kernel size as fs_calc_ao
+    let texel = 1.0 / vec2f(textureDimensions(depth_view).xy);
+    let R_i = max(floor(min(ao_params.r_max, ao_params.ri_almost / -p.z)), 2.0);
+    let offset = ao_kernel_offset(i);
+    let qi = ao_sample_pos(uv + R_i * offset * texel, R_i * length(offset), R_i);
+    let occlusion = clamp(calc_occlusion_and_dir(qi, p, n, ao_params.d_max).w, 0.0, 1.0);
+    let active = on_geometry
+        && ao_params.method == AO_METHOD_HEMISPHERE
+        && i < min(ao_params.num_samples, 16u);
+    let color = mix(vec4(0.2, 1.0, 0.2, 1.0), vec4(1.0, 0.2, 0.2, 1.0), occlusion);
+    let size = select(0.0, ao_gizmo.point_size, active);
+    gizmos[i + 1u] = AoGizmo(view_to_world(qi.xyz), size, color);
+}
+
+struct AoOutput {
+    @location(0) ao: vec4<f32>,
+    // NOTE: view space bent normal in xyz and the occlusion in w, only the finest pass
+    // bends the normal, the coarser ones write the surface normal
+    @location(1) bent_normal: vec4<f32>,
+};
+
 
 @fragment
-fn fs_calc_ao(vertex: VertexOutput) -> @location(0) vec4<f32> {
-    let p = textureSample(pos_view, pos_sampler, vertex.uv).xyz;
-    let n = textureSample(normal_view, normal_sampler, vertex.uv).xyz;
+fn fs_calc_ao(vertex: VertexOutput) -> AoOutput {
+    let p = load_view_pos(depth_view, vertex.uv).xyz;
+    let n = decode_normal(textureSample(normal_view, normal_sampler, vertex.uv));
 
     //NOTE: calc ao near
-    let r_max = 5.0;
+    let r_max = ao_params.r_max;
     // let d_max = 2.0;
-    let d_max = 2.0;
+    let d_max = ao_params.d_max;
     // NOTE: z is negative cause rh coordinate system
     let pz = -p.z;
     let r_i = ao_params.ri_almost / pz; 
@@ -194,25 +473,46 @@ fn fs_calc_ao(vertex: VertexOutput) -> @location(0) vec4<f32> {
     let N = f32(num_samples_x * num_samples_x);
 
 
-    let dim = vec2f(textureDimensions(pos_view).xy);
+    let dim = vec2f(textureDimensions(depth_view).xy);
     let dx = 1.0 / dim.x;
     let dy = 1.0 / dim.y;
 
     var sample_uv = vertex.uv - f32(R_i) * vec2(dx, dy);
     var near_occlusion = 0.0;
     var num_samples = 0.0;
-
-    // NOTE: for finest res sample using poisson disc
-    if IS_LAST_PASS {
-        for (var i: u32 = 0; i < 32; i = i + 2) {
-            let ix = poisson_disc_16[i]; 
-            let iy = poisson_disc_16[i+1]; 
-            sample_uv = vertex.uv + R_i * vec2(ix * dx, iy * dy);
-
-            let o = calc_oclusion_term(sample_uv, p, n, d_max);
-            near_occlusion += o;
+    var bent_normal = n;
+    var occluded_dir = vec3(0.0);
+
+    // NOTE: for finest res sample using the kernel from the cpu
+    if IS_LAST_PASS && ao_params.method == AO_METHOD_GTAO {
+        let od = calc_gtao(vertex.uv, p, n, d_max, R_i, vec2(dx, dy));
+        near_occlusion = od.w;
+        num_samples = f32(max(min(ao_params.gtao_slices, 16u), 1u));
+        bent_normal = select(n, normalize(od.xyz), dot(od.xyz, od.xyz) > 1e-6);
+    } else if IS_LAST_PASS && ao_params.method == AO_METHOD_HBAO {
+        let od = calc_hbao(vertex.uv, p, n, d_max, R_i, vec2(dx, dy));
+        near_occlusion = od.w;
+        occluded_dir = od.xyz;
+        num_samples = f32(max(min(ao_params.hbao_directions, 16u), 1u));
+        bent_normal = n - occluded_dir / num_samples;
+        bent_normal = select(n, normalize(bent_normal), dot(bent_normal, bent_normal) > 1e-6);
+    } else if IS_LAST_PASS {
+        let num_kernel_samples = min(ao_params.num_samples, 16u);
+        for (var i: u32 = 0; i < num_kernel_samples; i++) {
+            let offset = ao_kernel_offset(i);
+            sample_uv = vertex.uv + R_i * vec2(offset.x * dx, offset.y * dy);
+
+            let qi = ao_sample_pos(sample_uv, R_i * length(offset), R_i);
+            let od = calc_occlusion_and_dir(qi, p, n, d_max);
+            near_occlusion += od.w;
+            occluded_dir += od.w * od.xyz;
         }
-        num_samples = 16.0;
+        num_samples = f32(num_kernel_samples);
+        // NOTE: the samples are surface points and not rays, so the unoccluded directions
+        // are approximated by bending the normal away from the occluders by how much each
+        // one occludes. Occluders are above the tangent plane so this stays in the hemisphere
+        bent_normal = n - occluded_dir / max(num_samples, 1.0);
+        bent_normal = select(n, normalize(bent_normal), dot(bent_normal, bent_normal) > 1e-6);
     // NOTE: for coarser resolutions sample in interleaved square
     } else {
         for (var i: u32 = 0; i < num_samples_x; i++) {
@@ -236,7 +536,7 @@ fn fs_calc_ao(vertex: VertexOutput) -> @location(0) vec4<f32> {
         res[0] = near_occlusion / num_samples;
         res[1] = near_occlusion ;
         res[2] = num_samples;
-        return vec4(res,1.0);
+        return AoOutput(vec4(res,1.0), vec4(bent_normal, res[0]));
     } 
     let ao_near = vec2(near_occlusion, num_samples);
 
@@ -264,7 +564,7 @@ fn fs_calc_ao(vertex: VertexOutput) -> @location(0) vec4<f32> {
     // QUESTION: better to write loop or better to have it all written out
     const tn = 8.0;
     for (var i: u32 = 0; i < 4; i ++) {
-        let ni = vec3(superpixel_nx[i], superpixel_ny[i], superpixel_nz[i]);
+        let ni = decode_gathered_normal(superpixel_nx, superpixel_ny, superpixel_nz, i);
         let ndot = dot(n, ni);
         // NOTE: gotta clamp the ndot + 1 cause it might be sliiiightly less than 0.0,
         // which causes all kinds weird large black square artefacts that flicker occasionally
@@ -273,7 +573,8 @@ fn fs_calc_ao(vertex: VertexOutput) -> @location(0) vec4<f32> {
         w_normal[i] = pow((ndot + 1.1) / 2.1, tn);
     }
 
-    let superpixel_z = -textureGather(2, prev_pos_view, prev_pos_sampler, vertex.uv);
+    let superpixel_p = gather_view_pos(prev_depth_view, vertex.uv);
+    let superpixel_z = -vec4(superpixel_p[0].z, superpixel_p[1].z, superpixel_p[2].z, superpixel_p[3].z);
     var w_depth: vec4f;
     const tz = 16.0;
     for (var i: u32 = 0; i < 4; i ++) {
@@ -305,7 +606,7 @@ fn fs_calc_ao(vertex: VertexOutput) -> @location(0) vec4<f32> {
         let ao_final = 1.0 - (1.0 - ao_max) * (1.0 - ao_avg);
         c = vec3(ao_final);
     }
-    return vec4(c, 1.0);
+    return AoOutput(vec4(c, 1.0), vec4(bent_normal, c[0]));
 }
 
 @fragment
@@ -315,8 +616,8 @@ fn fs_blur_ao(vertex: VertexOutput) -> @location(0) vec4<f32> {
     let dx = 1.0 / f32(dim.x);
     let dy = 1.0 / f32(dim.y);
 
-    let n = textureSample(normal_view, normal_sampler, vertex.uv);
-    let p = textureSample(pos_view, pos_sampler, vertex.uv);
+    let n = decode_normal(textureSample(normal_view, normal_sampler, vertex.uv));
+    let p = load_view_pos(depth_view, vertex.uv);
 
 
     // NOTE: actual gaussian kernel should be something like 
@@ -330,10 +631,12 @@ fn fs_blur_ao(vertex: VertexOutput) -> @location(0) vec4<f32> {
     var w_tot = 0.0;
     for (var i: u32 = 0; i < 3; i++) {
         for (var j: u32 = 0; j < 3; j++) {
-            let ni = textureSample(normal_view, normal_sampler, uv);
-            let pz = textureSample(pos_view, pos_sampler, uv).z;
+            let ni = decode_normal(textureSample(normal_view, normal_sampler, uv));
+            let pz = load_view_pos(depth_view, uv).z;
 
-            var w_normal = (dot(n, ni) + 1.2) / 2.2;
+            // NOTE: the normals used to be dotted as vec4 with w = 1 on both, keep the
+            // extra 1.0 so the blur weights stay what they were tuned with
+            var w_normal = (dot(n, ni) + 1.0 + 1.2) / 2.2;
             w_normal = pow(w_normal, 8.0);
 
             var w_depth = 1.0 / (1.0 + abs(p.z - pz) * 0.2);
@@ -364,28 +667,219 @@ fn fs_blur_ao(vertex: VertexOutput) -> @location(0) vec4<f32> {
 }
 
 
+// NOTE: 1 is fully lit, 3x3 pcf with the hardware compare doing bilinear on top
+fn shadow_factor(ws_pos: vec3<f32>, cascade: u32, rotation: mat2x2<f32>) -> f32 {
+    let light_clip = shadow.light_vp[cascade] * vec4(ws_pos, 1.0);
+    let ndc = light_clip.xyz / light_clip.w;
+    let uv = vec2(0.5 * ndc.x + 0.5, 0.5 - 0.5 * ndc.y);
+    // NOTE: outside of the shadow map counts as lit
+    if (any(uv < vec2(0.0)) || any(uv > vec2(1.0)) || ndc.z > 1.0) {
+        return 1.0;
+    }
+
+    let depth = ndc.z - shadow.bias;
+    if (shadow.pcss_enabled != 0u) {
+        return pcss_factor(uv, depth, cascade, rotation);
+    }
+    var lit = 0.0;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let offset = vec2(f32(x), f32(y)) * shadow.texel_size;
+            lit += textureSampleCompareLevel(shadow_view, shadow_sampler, uv + offset, cascade, depth);
+        }
+    }
+    return lit / 9.0;
+}
+
+fn poisson_offset(i: u32) -> vec2<f32> {
+    return vec2(poisson_disc_16[2u * i], poisson_disc_16[2u * i + 1u]);
+}
+
+// #include "noise.wgsl"
+
+// NOTE: interleaved gradient noise on the pixel position, rotates the poisson disc per
+// pixel so the banding of the few taps turns into noise
+fn pcss_rotation(screen_uv: vec2<f32>) -> mat2x2<f32> {
+    let px = screen_uv * globals.viewport_size;
+    let angle = 6.2831853 * interleaved_gradient_noise(px);
+    let s = sin(angle);
+    let c = cos(angle);
+    return mat2x2(c, s, -s, c);
+}
+
+// NOTE: percentage closer soft shadows, first averages the depth of the occluders around
+// the receiver, then filters with a kernel that grows with the receiver to blocker gap
+fn pcss_factor(uv: vec2<f32>, depth: f32, cascade: u32, rotation: mat2x2<f32>) -> f32 {
+    // NOTE: the cascade projection is orthographic, [0][0] is 1 / half width in world units
+    let world_to_uv = 0.5 * shadow.light_vp[cascade][0][0];
+    let search_uv = shadow.light_size * world_to_uv;
+    let map_size = vec2<f32>(textureDimensions(shadow_view).xy);
+
+    var blocker_sum = 0.0;
+    var num_blockers = 0.0;
+    let num_samples = min(shadow.pcss_num_blocker_samples, 16u);
+    for (var i = 0u; i < num_samples; i++) {
+        let sample_uv = clamp(uv + rotation * poisson_offset(i) * search_uv, vec2(0.0), vec2(1.0));
+        let texel = min(vec2<i32>(sample_uv * map_size), vec2<i32>(map_size) - 1);
+        let blocker = textureLoad(shadow_view, texel, cascade, 0);
+        if (blocker < depth) {
+            blocker_sum += blocker;
+            num_blockers += 1.0;
+        }
+    }
+    if (num_blockers == 0.0) {
+        return 1.0;
+    }
+
+    let blocker = blocker_sum / num_blockers;
+    let penumbra_uv = clamp(
+        shadow.light_size * world_to_uv * (depth - blocker) / max(blocker, 1e-4),
+        shadow.texel_size,
+        search_uv,
+    );
+    var lit = 0.0;
+    for (var i = 0u; i < 16u; i++) {
+        let offset = rotation * poisson_offset(i) * penumbra_uv;
+        lit += textureSampleCompareLevel(shadow_view, shadow_sampler, uv + offset, cascade, depth);
+    }
+    return lit / 16.0;
+}
+
+// NOTE: picks the first cascade whose split is past the fragment and fades into the
+// next one over the last blend_width of its depth range
+fn cascaded_shadow_factor(ws_pos: vec3<f32>, view_depth: f32, rotation: mat2x2<f32>) -> f32 {
+    let last = shadow.cascade_count - 1u;
+    if (view_depth > shadow.splits[last]) {
+        return 1.0;
+    }
+    var cascade = 0u;
+    while (cascade < last && view_depth > shadow.splits[cascade]) {
+        cascade++;
+    }
+
+    let lit = shadow_factor(ws_pos, cascade, rotation);
+    if (cascade == last) {
+        return lit;
+    }
+    var start = 0.0;
+    if (cascade > 0u) {
+        start = shadow.splits[cascade - 1u];
+    }
+    let end = shadow.splits[cascade];
+    let blend_start = end - shadow.blend_width * (end - start);
+    if (view_depth <= blend_start) {
+        return lit;
+    }
+    let t = (view_depth - blend_start) / max(end - blend_start, 1e-5);
+    return mix(lit, shadow_factor(ws_pos, cascade + 1u, rotation), t);
+}
+
+const PI = 3.14159265;
+
+// NOTE: inputs of the brdf for one g-buffer texel, diffuse is the albedo without the
+// part metals reflect specularly
+struct Surface {
+    diffuse: vec3<f32>,
+    f0: vec3<f32>,
+    roughness: f32,
+};
+
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (1.0 - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+// NOTE: rough surfaces reflect less at grazing angles, for the ambient term where there
+// is no half vector
+fn fresnel_schlick_roughness(cos_theta: f32, f0: vec3<f32>, roughness: f32) -> vec3<f32> {
+    return f0 + (max(vec3(1.0 - roughness), f0) - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+// NOTE: smith with the schlick-ggx k for analytic lights
+fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let r = roughness + 1.0;
+    let k = r * r / 8.0;
+    let g_v = n_dot_v / (n_dot_v * (1.0 - k) + k);
+    let g_l = n_dot_l / (n_dot_l * (1.0 - k) + k);
+    return g_v * g_l;
+}
+
+// NOTE: cook-torrance ggx specular plus lambert diffuse, times n.l. Light intensities are
+// in units where a white lambert surface facing the light reflects the intensity, so the
+// specular lobe is scaled by pi to match the diffuse term that has no 1 / pi
+fn cook_torrance(surface: Surface, n: vec3<f32>, v: vec3<f32>, l: vec3<f32>) -> vec3<f32> {
+    let n_dot_l = max(dot(n, l), 0.0);
+    let n_dot_v = max(dot(n, v), 1e-4);
+    let h = normalize(v + l);
+    let n_dot_h = max(dot(n, h), 0.0);
+    let fresnel = fresnel_schlick(max(dot(h, v), 0.0), surface.f0);
+    let specular = distribution_ggx(n_dot_h, surface.roughness)
+        * geometry_smith(n_dot_v, n_dot_l, surface.roughness)
+        * fresnel
+        / (4.0 * n_dot_v * max(n_dot_l, 1e-4));
+    let diffuse = (1.0 - fresnel) * surface.diffuse;
+    return (diffuse + PI * specular) * n_dot_l;
+}
+
+// NOTE: ws_view is the unit vector from the surface to the camera
+fn light_contribution(light: Light, surface: Surface, ws_pos: vec3<f32>, ws_normal: vec3<f32>, ws_view: vec3<f32>, view_depth: f32, rotation: mat2x2<f32>) -> vec3<f32> {
+    var to_light: vec3<f32>;
+    var attenuation = 1.0;
+    if (light.kind == 0u) {
+        to_light = -light.direction;
+    } else {
+        let d = light.position - ws_pos;
+        let dist = length(d);
+        to_light = d / max(dist, 1e-5);
+        if (light.kind == 1u) {
+            // NOTE: inverse square with a smooth window so it reaches 0 at the radius
+            let window = clamp(1.0 - pow(dist / max(light.radius, 1e-5), 4.0), 0.0, 1.0);
+            attenuation = window * window / (1.0 + dist * dist);
+        } else {
+            let cos_angle = dot(-to_light, light.direction);
+            attenuation = smoothstep(light.cos_outer, light.cos_inner, cos_angle) / (1.0 + dist * dist);
+        }
+    }
+    var lit = cook_torrance(surface, ws_normal, ws_view, to_light) * attenuation;
+    // #ifdef SHADOWS_ENABLED
+    if (light.shadowed != 0u && shadow.enabled != 0u) {
+        lit *= cascaded_shadow_factor(ws_pos, view_depth, rotation);
+    }
+    // #endif
+    return light.color * light.intensity * lit;
+}
+
 @fragment
 fn fs_light(vertex: VertexOutput) -> @location(0) vec4<f32> {
 
 
 
     var c = vec3(0.0);
-    let view_pos = textureSample(pos_view, pos_sampler, vertex.uv);
-    let normal = textureSample(normal_view, normal_sampler, vertex.uv).xyz;
+    let view_pos = load_view_pos(depth_view, vertex.uv);
+    let normal = decode_normal(textureSample(normal_view, normal_sampler, vertex.uv));
 
     let ws_normal = transpose(globals.mv_rot) * vec4(normal, 0.0);
 
     // var depth = textureSample(depth_view, depth_sampler, vertex.uv);
 
-    let sun_dir = normalize(vec3(0.5, -1.0, -0.8));
-    let ndotl = max(dot(ws_normal.xyz, -sun_dir), 0.0);
     
 
 
     // if false{
 
     // c = vec3(0.0);
+    // NOTE: the #ifdef blocks are resolved on load, see preprocess_defines and PipelineKey
+    // #ifdef SSAO_ENABLED
     let ao = textureSample(ao_view, ao_sampler, vertex.uv);
+    // #else
+    let ao = vec4(0.0);
+    // #endif
 
         
     // let ao_max = ao[0];
@@ -393,7 +887,57 @@ fn fs_light(vertex: VertexOutput) -> @location(0) vec4<f32> {
     // let ao_final = 1.0 - (1.0 - ao_max) * (1.0 - ao_avg);
     // c = vec3(ao_final);
     // c = vec3(1.0 - ao_final);
-    c = vec3(1.0 - ao[0]);
+    let albedo = textureSample(albedo_view, albedo_sampler, vertex.uv).rgb;
+    let material = textureSample(material_view, material_sampler, vertex.uv).rg;
+    // NOTE: below about 0.05 the ggx highlight of a point light gets thinner than a pixel
+    let roughness = max(material.r, 0.05);
+    let metalness = material.g;
+    let surface = Surface(
+        albedo * (1.0 - metalness),
+        mix(vec3(0.04), albedo, metalness),
+        roughness,
+    );
+    // NOTE: view = R * (p - cam_pos), so p = R^T * view + cam_pos
+    let ws_pos = (transpose(globals.mv_rot) * vec4(view_pos.xyz, 0.0)).xyz + globals.cam_pos;
+    let ws_view = normalize(globals.cam_pos - ws_pos);
+    var direct = vec3(0.0);
+    let rotation = pcss_rotation(vertex.uv);
+    for (var i = 0u; i < num_lights; i++) {
+        // NOTE: rh view space, z is negative in front of the camera
+        direct += light_contribution(lights[i], surface, ws_pos, ws_normal.xyz, ws_view, -view_pos.z, rotation);
+    }
+    // NOTE: used to be a flat 0.3, which is still what equal sky and ground give
+    // NOTE: the bent normal comes out of the ao passes as well
+    // #ifdef SSAO_ENABLED
+    let bent_normal = textureSample(bent_normal_view, bent_normal_sampler, vertex.uv).xyz;
+    let use_bent_normal = ambient.use_bent_normal != 0u && dot(bent_normal, bent_normal) > 1e-4;
+    // #else
+    let bent_normal = normal;
+    let use_bent_normal = false;
+    // #endif
+    let ambient_dir = select(normal, normalize(bent_normal), use_bent_normal);
+    let ws_ambient_dir = (transpose(globals.mv_rot) * vec4(ambient_dir, 0.0)).xyz;
+    let hemisphere = mix(ambient.ground, ambient.sky, 0.5 * ws_ambient_dir.y + 0.5);
+    // NOTE: gamma encoded like the albedo, the product is decoded by the pow 2.2 below
+    // #ifdef IBL_ENABLED
+    let ibl = ambient.ibl_intensity
+        * textureSample(irradiance_view, irradiance_sampler, ws_ambient_dir).rgb;
+    let ambient_light = select(vec3(hemisphere), ibl, ambient.use_ibl != 0u);
+    // #else
+    let ambient_light = vec3(hemisphere);
+    // #endif
+    let ambient_pulse = 1.0 + ambient.pulse * sin(2.0 * PI * globals.time_secs);
+    // NOTE: ao only darkens the ambient, direct light has its shadows. There is no
+    // prefiltered environment, the ambient specular reflects the same irradiance
+    let n_dot_v = max(dot(ws_normal.xyz, ws_view), 0.0);
+    let ambient_specular = fresnel_schlick_roughness(n_dot_v, surface.f0, roughness);
+    let indirect = ((1.0 - ambient_specular) * surface.diffuse + ambient_specular) * ambient_light
+        * ambient_pulse;
+    let occlusion = select(ao[0], 0.0, vertex.uv.x > split_x);
+    c = indirect * (1.0 - occlusion) + direct;
+    // NOTE: one pixel wide white divider
+    let divider_px = abs(vertex.uv.x - split_x) * globals.viewport_size.x;
+    c = select(c, vec3(1.0), divider_px < 1.0);
     // c = ao.xyz;
     // let k = floor(10.0 * vertex.uv.x) / 10.0;
     // c = vec3(k);
@@ -405,20 +949,7 @@ fn fs_light(vertex: VertexOutput) -> @location(0) vec4<f32> {
     return vec4(c, 1.0);
 }
 
-struct VertexOutput {
-    @builtin(position) clip_pos: vec4<f32>,
-    @location(0) uv: vec2<f32>,
-};
-
-@vertex
-fn vs_main(vertex: Vertex) -> VertexOutput {
-    // NOTE: ws pos equal to uv coords since vertex belongs to screen covering quad
-    var uv = 0.5 * vertex.ws_pos.xy + 0.5;
-    uv.y = 1.0 - uv.y;
-
-
-    return VertexOutput(vec4(vertex.ws_pos,1.0), uv);
-}
+// NOTE: VertexOutput and vs_fullscreen come from fullscreen.wgsl
 
 
 fn uv_2_texel(uv: vec2<f32>, wh: vec2<f32>) -> vec2<f32> {
@@ -427,13 +958,6 @@ fn uv_2_texel(uv: vec2<f32>, wh: vec2<f32>) -> vec2<f32> {
     return uv * (wh - 1.0);
 }
 
-fn linearize_depth(d: f32) -> f32
-    {
-        let zFar = 100.0;
-        let zNear = 0.001;
-        return zNear * zFar / (zFar + d * (zNear - zFar));
-    }
-
 // from https://github.com/eliemichel/WebGPU-utils/blob/main/wgsl/textureGatherWeights.wgsl
 fn texture_gather_weights(t: texture_2d<f32>, coords: vec2f) -> vec2f {
     let dim = textureDimensions(t).xy;
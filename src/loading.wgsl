@@ -0,0 +1,26 @@
+// #include "fullscreen.wgsl"
+
+var<uniform> time: f32;
+var<uniform> aspect: f32;
+
+const TAU: f32 = 6.28318530718;
+const NUM_DOTS: i32 = 8;
+
+// NOTE: ring of dots in the middle of the screen with a bright one going around
+@fragment
+fn fs_loading(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    var p = 2.0 * vertex.uv - 1.0;
+    p.x *= aspect;
+
+    var c = 0.0;
+    for (var i = 0; i < NUM_DOTS; i++) {
+        let t = f32(i) / f32(NUM_DOTS);
+        let a = TAU * t;
+        let center = 0.15 * vec2(cos(a), -sin(a));
+        let inside = 1.0 - smoothstep(0.025, 0.03, length(p - center));
+        // NOTE: 0 for the dot that is lit up right now, going towards 1 for the older ones
+        let phase = fract(time - t);
+        c += inside * mix(1.0, 0.15, phase);
+    }
+    return vec4(vec3(c), 1.0);
+}
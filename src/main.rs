@@ -11,6 +11,63 @@ pub const TAU: f32 = 2.0 * PI;
 
 pub const NUM_AO_TEXTURES: usize = 5;
 
+// NOTE: units and radians per second, scaled by the frame time
+pub const CAM_SPEED: f32 = 6.0;
+pub const CAM_ANGLE_SPEED: f32 = 0.8;
+// NOTE: longest frame time the camera moves for, a hitch or a debugger pause would
+// teleport it otherwise
+pub const MAX_INPUT_DELTA_TIME: f32 = 0.1;
+// NOTE: held shift and ctrl or alt scale CAM_SPEED on top of State::move_speed
+pub const MOVE_SPEED_BOOST: f32 = 5.0;
+pub const MOVE_SPEED_PRECISION: f32 = 0.2;
+// NOTE: factor per scroll wheel line, so the same number of lines up and down cancel
+pub const MOVE_SPEED_SCROLL_STEP: f32 = 1.25;
+pub const MOVE_SPEED_RANGE: (f32, f32) = (0.05, 20.0);
+// NOTE: radians per raw mouse count while the cursor is grabbed
+pub const MOUSE_SENSITIVITY: f32 = 0.002;
+// NOTE: stick values below this are treated as 0, sticks rarely rest at exactly 0
+pub const GAMEPAD_DEADZONE: f32 = 0.15;
+
+// NOTE: blade does not report the device limit, 16 is the max on every desktop gpu
+pub const MAX_ANISOTROPY: u32 = 16;
+
+pub const SHADOW_MAP_SIZE: u32 = 2048;
+// NOTE: layers in the shadow map array, the light uniform always has room for all of them
+pub const MAX_SHADOW_CASCADES: usize = 4;
+
+// NOTE: defaults for Camera::near and far, adjustable in the debug ui
+pub const CAM_NEAR: f32 = 0.1;
+pub const CAM_FAR: f32 = 1000.0;
+
+// NOTE: with reverse z the far plane maps to depth 0 and the near plane to 1, floats have
+// most of their precision near 0 which then evens out the precision over distance
+pub fn depth_compare(reverse_z: bool) -> gpu::CompareFunction {
+    if reverse_z {
+        gpu::CompareFunction::Greater
+    } else {
+        gpu::CompareFunction::Less
+    }
+}
+
+pub fn depth_compare_or_equal(reverse_z: bool) -> gpu::CompareFunction {
+    if reverse_z {
+        gpu::CompareFunction::GreaterEqual
+    } else {
+        gpu::CompareFunction::LessEqual
+    }
+}
+
+// NOTE: the furthest depth value, what depth targets are cleared to
+pub fn depth_clear_color(reverse_z: bool) -> gpu::TextureColor {
+    if reverse_z {
+        gpu::TextureColor::TransparentBlack
+    } else {
+        gpu::TextureColor::White
+    }
+}
+
+// NOTE: vec3<f32> has 16 byte alignment in wgsl, so every vec3 needs an explicit pad after it
+// (globals_layout_matches_wgsl checks this against naga's layout of src/globals.wgsl)
 #[repr(C)]
 #[derive(Clone, Copy, Pod, Zeroable)]
 pub struct Globals {
@@ -18,10 +75,448 @@ pub struct Globals {
     mv_transform: [[f32; 4]; 4],
     mv_rot: [[f32; 4]; 4],
     cam_pos: [f32; 3],
+    pad0: u32,
     cam_dir: [f32; 3],
-    pad: [u32; 2],
+    pad1: u32,
+    // NOTE: previous frame without jitter times the model, see Camera::prev_vp. Taa and
+    // tssao reproject with it, the geometry pass has PreviousFrameGlobals instead
+    prev_vp: [[f32; 4]; 4],
+    // NOTE: render resolution in pixels, see Camera::viewport_size
+    viewport_size: [f32; 2],
+    inv_viewport_size: [f32; 2],
+    // NOTE: ndc back to world space for the passes that reconstruct positions from the
+    // depth, see view_pos.wgsl. far_depth is what the depth buffer holds where nothing was drawn
+    inv_mvp_transform: [[f32; 4]; 4],
+    far_depth: f32,
+    // NOTE: seconds since startup and since the previous frame, see Camera::time_secs
+    time_secs: f32,
+    delta_time_secs: f32,
+    pad2: u32,
+}
+
+// NOTE: offsets have to match struct Globals in globals.wgsl, which every shader reading
+// it includes
+const _: () = assert!(std::mem::size_of::<Globals>() == 384);
+const _: () = assert!(std::mem::offset_of!(Globals, cam_pos) == 192);
+const _: () = assert!(std::mem::offset_of!(Globals, cam_dir) == 208);
+const _: () = assert!(std::mem::offset_of!(Globals, prev_vp) == 224);
+const _: () = assert!(std::mem::offset_of!(Globals, viewport_size) == 288);
+const _: () = assert!(std::mem::offset_of!(Globals, inv_viewport_size) == 296);
+const _: () = assert!(std::mem::offset_of!(Globals, inv_mvp_transform) == 304);
+const _: () = assert!(std::mem::offset_of!(Globals, far_depth) == 368);
+const _: () = assert!(std::mem::offset_of!(Globals, time_secs) == 372);
+const _: () = assert!(std::mem::offset_of!(Globals, delta_time_secs) == 376);
+
+impl Globals {
+    // NOTE: model is the transform of the object being drawn, full screen passes use identity
+    pub fn new(camera: &Camera, model: &Transform) -> Self {
+        let model_mat = model.to_mat4();
+        let normal_mat = Mat4::from_mat3(model.to_normal_mat3());
+        let mvp = camera.vp() * model_mat;
+        Self {
+            mvp_transform: mvp.to_cols_array_2d(),
+            mv_transform: (camera.view() * model_mat).to_cols_array_2d(),
+            mv_rot: (camera.view_rot_only() * normal_mat).to_cols_array_2d(),
+            cam_pos: camera.pos.to_array(),
+            cam_dir: camera.right_forward_up()[1].to_array(),
+            pad0: 0,
+            pad1: 0,
+            prev_vp: (camera.prev_vp * model_mat).to_cols_array_2d(),
+            viewport_size: camera.viewport_size.to_array(),
+            inv_viewport_size: camera.viewport_size.recip().to_array(),
+            inv_mvp_transform: mvp.inverse().to_cols_array_2d(),
+            far_depth: if camera.reverse_z { 0.0 } else { 1.0 },
+            time_secs: camera.time_secs,
+            delta_time_secs: camera.delta_time_secs,
+            pad2: 0,
+        }
+    }
+}
+
+impl Globals {
+    // NOTE: the whole view without the taa jitter, the previous frame's is kept for the
+    // geometry pass, see PreviousFrameGlobals
+    pub fn unjittered(camera: &Camera) -> Self {
+        let camera = Camera {
+            jitter: Vec2::ZERO,
+            ..camera.clone()
+        };
+        Self::new(&camera, &Transform::IDENTITY)
+    }
+
+    // NOTE: globals for drawing from the light, only mvp_transform is meaningful
+    pub fn for_light(light_vp: Mat4, model: &Transform) -> Self {
+        let model_mat = model.to_mat4();
+        let mvp = light_vp * model_mat;
+        Self {
+            mvp_transform: mvp.to_cols_array_2d(),
+            mv_transform: model_mat.to_cols_array_2d(),
+            mv_rot: Mat4::from_mat3(model.to_normal_mat3()).to_cols_array_2d(),
+            cam_pos: [0.0; 3],
+            pad0: 0,
+            cam_dir: [0.0; 3],
+            pad1: 0,
+            prev_vp: (light_vp * model_mat).to_cols_array_2d(),
+            viewport_size: [SHADOW_MAP_SIZE as f32; 2],
+            inv_viewport_size: [1.0 / SHADOW_MAP_SIZE as f32; 2],
+            inv_mvp_transform: mvp.inverse().to_cols_array_2d(),
+            // NOTE: the shadow map is never reverse z
+            far_depth: 1.0,
+            time_secs: 0.0,
+            delta_time_secs: 0.0,
+            pad2: 0,
+        }
+    }
+}
+
+// NOTE: direction the sun light travels in, world space
+pub fn sun_dir() -> Vec3A {
+    Vec3A::new(0.5, -1.0, -0.8).normalize()
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct ShadowParams {
+    pub light_vp: [[[f32; 4]; 4]; MAX_SHADOW_CASCADES],
+    // NOTE: positive view space depth where each cascade ends
+    pub splits: [f32; MAX_SHADOW_CASCADES],
+    pub sun_dir: [f32; 3],
+    // NOTE: subtracted from the receiver depth in light clip space
+    pub bias: f32,
+    pub texel_size: f32,
+    // NOTE: 0 means no shadowing, the sun then lights everything it faces
+    pub enabled: u32,
+    pub cascade_count: u32,
+    // NOTE: fraction of a cascade's depth range over which it fades into the next one
+    pub blend_width: f32,
+    pub light_size: f32,
+    pub pcss_num_blocker_samples: u32,
+    pub pcss_enabled: u32,
+    pub pad: u32,
+}
+
+const _: () = assert!(std::mem::size_of::<ShadowParams>() == 320);
+
+// NOTE: runtime tweakable shadow parameters, see debug ui
+#[derive(Clone, Copy, Debug)]
+pub struct ShadowSettings {
+    pub enabled: bool,
+    pub bias: f32,
+    pub cascade_count: usize,
+    // NOTE: 0 splits the depth range uniformly, 1 logarithmically
+    pub split_lambda: f32,
+    // NOTE: view depth where the last cascade ends, nothing further away is shadowed
+    pub max_distance: f32,
+    pub blend_width: f32,
+    // NOTE: contact hardening soft shadows instead of the fixed 3x3 pcf
+    pub pcss_enabled: bool,
+    // NOTE: world units, bigger lights give wider penumbras
+    pub light_size: f32,
+    pub pcss_num_blocker_samples: u32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            bias: 0.002,
+            cascade_count: MAX_SHADOW_CASCADES,
+            split_lambda: 0.75,
+            max_distance: 60.0,
+            blend_width: 0.1,
+            pcss_enabled: false,
+            light_size: 0.1,
+            pcss_num_blocker_samples: 16,
+        }
+    }
+}
+
+// NOTE: view depth where each cascade ends, blending the uniform and logarithmic split
+// schemes, unused cascades repeat the last split
+pub fn cascade_splits(settings: &ShadowSettings, camera: &Camera) -> [f32; MAX_SHADOW_CASCADES] {
+    let count = settings.cascade_count.clamp(1, MAX_SHADOW_CASCADES);
+    let near = camera.near;
+    let far = settings
+        .max_distance
+        .clamp(near + 0.01, camera.far.max(near + 0.01));
+    let mut splits = [far; MAX_SHADOW_CASCADES];
+    for (i, split) in splits.iter_mut().enumerate().take(count) {
+        let t = (i + 1) as f32 / count as f32;
+        let uniform = near + (far - near) * t;
+        let log = near * (far / near).powf(t);
+        *split = settings.split_lambda * log + (1.0 - settings.split_lambda) * uniform;
+    }
+    splits
+}
+
+// NOTE: orthographic shadow around the bounding sphere of the camera frustum slice between
+// near and far, the sphere keeps the size constant under camera rotation and the center is
+// snapped to whole texels so the shadow edges don't crawl when moving
+pub fn light_view_proj(camera: &Camera, near: f32, far: f32) -> Mat4 {
+    let [right, forward, up] = camera.right_forward_up();
+    let tan_y = (0.5 * camera.vfov_rad).tan();
+    let tan_x = tan_y * camera.aspect;
+    let mut corners = vec![];
+    for d in [near, far] {
+        for (sx, sy) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+            corners
+                .push(camera.pos + forward * d + right * (sx * tan_x * d) + up * (sy * tan_y * d));
+        }
+    }
+    let frustum_center = corners.iter().copied().sum::<Vec3A>() / corners.len() as f32;
+    let r = corners
+        .iter()
+        .map(|c| c.distance(frustum_center))
+        .fold(0.0, f32::max);
+    // NOTE: quantized so float noise doesn't change the texel size every frame
+    let r = (r * 16.0).ceil() / 16.0;
+
+    let dir = sun_dir();
+    let light_up = if dir.y.abs() > 0.99 {
+        Vec3A::Z
+    } else {
+        Vec3A::Y
+    };
+    let rot_only = Mat4::look_to_rh(Vec3::ZERO, dir.into(), light_up.into());
+
+    let texel_world = 2.0 * r / SHADOW_MAP_SIZE as f32;
+    let mut center_ls = rot_only.transform_point3(frustum_center.into());
+    center_ls.x = (center_ls.x / texel_world).floor() * texel_world;
+    center_ls.y = (center_ls.y / texel_world).floor() * texel_world;
+    let center = rot_only.inverse().transform_point3(center_ls);
+
+    // NOTE: pull the eye back so casters behind the camera still land in the map
+    let eye = center - Vec3::from(dir) * 2.0 * r;
+    let view = Mat4::look_to_rh(eye, dir.into(), light_up.into());
+    let proj = Mat4::orthographic_rh(-r, r, -r, r, 0.0, 4.0 * r);
+    proj * view
+}
+
+pub fn cascade_view_projs(
+    camera: &Camera,
+    splits: &[f32; MAX_SHADOW_CASCADES],
+) -> [Mat4; MAX_SHADOW_CASCADES] {
+    let mut near = camera.near;
+    splits.map(|far| {
+        let vp = light_view_proj(camera, near, far);
+        near = far;
+        vp
+    })
+}
+
+// NOTE: size of the light storage buffer, extra lights are dropped
+pub const MAX_LIGHTS: usize = 256;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Light {
+    Directional {
+        // NOTE: direction the light travels in, like sun_dir
+        direction: Vec3A,
+        color: Vec3A,
+        intensity: f32,
+    },
+    Point {
+        position: Vec3A,
+        color: Vec3A,
+        intensity: f32,
+        // NOTE: no contribution past this distance
+        radius: f32,
+    },
+    Spot {
+        position: Vec3A,
+        direction: Vec3A,
+        color: Vec3A,
+        intensity: f32,
+        // NOTE: half angles in radians, full intensity inside inner, none outside outer
+        inner_cone: f32,
+        outer_cone: f32,
+    },
+}
+
+pub const LIGHT_KIND_DIRECTIONAL: u32 = 0;
+pub const LIGHT_KIND_POINT: u32 = 1;
+pub const LIGHT_KIND_SPOT: u32 = 2;
+
+// NOTE: one array element of the light storage buffer, fields unused by a kind are zero
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct GpuLight {
+    pub position: [f32; 3],
+    pub kind: u32,
+    pub direction: [f32; 3],
+    pub intensity: f32,
+    pub color: [f32; 3],
+    pub radius: f32,
+    pub cos_inner: f32,
+    pub cos_outer: f32,
+    // NOTE: 1 if the shadow map applies to this light, only ever the sun
+    pub shadowed: u32,
+    pub pad: u32,
+}
+
+const _: () = assert!(std::mem::size_of::<GpuLight>() == 64);
+
+impl Light {
+    pub fn sun() -> Self {
+        Light::Directional {
+            direction: sun_dir(),
+            color: Vec3A::ONE,
+            intensity: 0.7,
+        }
+    }
+
+    // NOTE: the lights placed from the camera with the debug ui or with g and t
+    pub fn point_at(position: Vec3A) -> Self {
+        Light::Point {
+            position,
+            color: Vec3A::new(1.0, 0.8, 0.5),
+            intensity: 2.0,
+            radius: 5.0,
+        }
+    }
+
+    pub fn spot_at(position: Vec3A, direction: Vec3A) -> Self {
+        Light::Spot {
+            position,
+            direction,
+            color: Vec3A::ONE,
+            intensity: 4.0,
+            inner_cone: 15.0_f32.to_radians(),
+            outer_cone: 25.0_f32.to_radians(),
+        }
+    }
+
+    // NOTE: None for directional lights, they are everywhere
+    pub fn position(&self) -> Option<Vec3A> {
+        match *self {
+            Light::Directional { .. } => None,
+            Light::Point { position, .. } | Light::Spot { position, .. } => Some(position),
+        }
+    }
+
+    pub fn to_gpu(&self, shadowed: bool) -> GpuLight {
+        let mut gpu_light = GpuLight::zeroed();
+        gpu_light.shadowed = shadowed as u32;
+        match *self {
+            Light::Directional {
+                direction,
+                color,
+                intensity,
+            } => {
+                gpu_light.kind = LIGHT_KIND_DIRECTIONAL;
+                gpu_light.direction = direction.normalize_or_zero().to_array();
+                gpu_light.color = color.to_array();
+                gpu_light.intensity = intensity;
+            }
+            Light::Point {
+                position,
+                color,
+                intensity,
+                radius,
+            } => {
+                gpu_light.kind = LIGHT_KIND_POINT;
+                gpu_light.position = position.to_array();
+                gpu_light.color = color.to_array();
+                gpu_light.intensity = intensity;
+                gpu_light.radius = radius;
+            }
+            Light::Spot {
+                position,
+                direction,
+                color,
+                intensity,
+                inner_cone,
+                outer_cone,
+            } => {
+                gpu_light.kind = LIGHT_KIND_SPOT;
+                gpu_light.position = position.to_array();
+                gpu_light.direction = direction.normalize_or_zero().to_array();
+                gpu_light.color = color.to_array();
+                gpu_light.intensity = intensity;
+                gpu_light.cos_inner = inner_cone.cos();
+                gpu_light.cos_outer = outer_cone.cos();
+            }
+        }
+        gpu_light
+    }
+}
+
+// NOTE: shared memory, rewritten every frame before the light pass
+pub fn create_light_buffer(ctx: &gpu::Context) -> gpu::Buffer {
+    ctx.create_buffer(gpu::BufferDesc {
+        name: "lights",
+        size: (MAX_LIGHTS * std::mem::size_of::<GpuLight>()) as u64,
+        memory: gpu::Memory::Shared,
+    })
+}
+
+// NOTE: the first directional light gets the shadow map, returns the number written
+pub fn write_lights(ctx: &gpu::Context, buffer: gpu::Buffer, lights: &[Light]) -> u32 {
+    let mut shadow_taken = false;
+    let gpu_lights = lights
+        .iter()
+        .take(MAX_LIGHTS)
+        .map(|light| {
+            let shadowed = !shadow_taken && matches!(light, Light::Directional { .. });
+            shadow_taken |= shadowed;
+            light.to_gpu(shadowed)
+        })
+        .collect::<Vec<_>>();
+    unsafe {
+        std::ptr::copy_nonoverlapping(
+            gpu_lights.as_ptr(),
+            buffer.data() as *mut GpuLight,
+            gpu_lights.len(),
+        );
+    }
+    ctx.sync_buffer(buffer);
+    gpu_lights.len() as u32
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Transform {
+    pub translation: Vec3A,
+    pub rotation: Quat,
+    pub scale: Vec3A,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Transform {
+    pub const IDENTITY: Self = Self {
+        translation: Vec3A::ZERO,
+        rotation: Quat::IDENTITY,
+        scale: Vec3A::ONE,
+    };
+
+    pub fn from_translation_rotation(translation: Vec3A, rotation: Quat) -> Self {
+        Self {
+            translation,
+            rotation,
+            scale: Vec3A::ONE,
+        }
+    }
+
+    pub fn to_mat4(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(
+            self.scale.into(),
+            self.rotation,
+            self.translation.into(),
+        )
+    }
+
+    // NOTE: inverse transpose so normals stay perpendicular under non uniform scale
+    pub fn to_normal_mat3(&self) -> Mat3 {
+        Mat3::from_mat4(self.to_mat4()).inverse().transpose()
+    }
 }
 
+// NOTE: no near/far here, the ao passes rebuild positions from depth through
+// Globals::inv_mvp_transform, which already has the camera's clip planes in it
 #[repr(C)]
 #[derive(Clone, Copy, Pod, Zeroable)]
 pub struct AOParams {
@@ -30,21 +525,332 @@ pub struct AOParams {
     pub ri_almost: f32,
     pub ao_width: f32,
 
-    pub pad: [u32; 3],
+    pub d_max: f32,
+    pub r_max: f32,
+    pub bias: f32,
     pub ao_height: f32,
+
+    pub num_samples: u32,
+    // NOTE: see AoMethod::index
+    pub method: u32,
+    pub hbao_directions: u32,
+    pub hbao_steps: u32,
+
+    pub gtao_slices: u32,
+    pub gtao_steps: u32,
+    pub gtao_thickness: f32,
+    pub hi_z_mips: u32,
+
+    // NOTE: see HiZMipSelection::index
+    pub hi_z_mip_selection: u32,
+    pub hi_z_mip_bias: u32,
+    // NOTE: x and y of the projection diagonal, to rebuild positions from hi-z depth
+    pub proj_scale: [f32; 2],
+
+    // NOTE: unit disc offsets from generate_ssao_kernel, two per vec4 since uniform arrays
+    // have a 16 byte stride. The unit march directions from march_directions for the
+    // horizon based methods
+    pub kernel: [[f32; 4]; MAX_AO_SAMPLES / 2],
+}
+
+const _: () = assert!(std::mem::size_of::<AOParams>() == 208);
+
+// NOTE: runtime tweakable ao parameters, see debug ui
+#[derive(Clone, Copy, Debug)]
+pub struct AOSettings {
+    // NOTE: max world space distance an occluder can be from the shaded point
+    pub d_max: f32,
+    // NOTE: max kernel radius in pixels
+    pub r_max: f32,
+    // NOTE: subtracted from n dot d to avoid self occlusion on flat surfaces
+    pub bias: f32,
+    pub method: AoMethod,
+    // NOTE: number of kernel samples used at finest resolution, max MAX_AO_SAMPLES
+    pub num_samples: u32,
+    pub kernel_distribution: KernelDistribution,
+    pub kernel_jitter: bool,
+    // NOTE: hbao marches this many directions, max MAX_AO_SAMPLES, with hbao_steps
+    // samples spread over the kernel radius along each
+    pub hbao_directions: u32,
+    pub hbao_steps: u32,
+    // NOTE: gtao integrates gtao_slices slices through the view vector, marching
+    // gtao_steps samples each way along each. Occluders further in front of the shaded
+    // point than gtao_thickness world units fade out, behind a thin object the first
+    // surface is visible again
+    pub gtao_slices: u32,
+    pub gtao_steps: u32,
+    pub gtao_thickness: f32,
+    // NOTE: which hi-z mip the finest pass reads a sample from, samples within
+    // 2^hi_z_mip_bias pixels always read the full resolution positions
+    pub hi_z_mip_selection: HiZMipSelection,
+    pub hi_z_mip_bias: u32,
+    // NOTE: hemisphere ambient, the light pass looks it up along the bent normal from the
+    // finest ao pass instead of the surface normal when use_bent_normals is on
+    pub use_bent_normals: bool,
+    pub ambient_sky: f32,
+    pub ambient_ground: f32,
+    // NOTE: with a skybox the ambient comes from its irradiance instead, sky and ground
+    // are only used without one or with use_ibl off
+    pub use_ibl: bool,
+    pub ibl_intensity: f32,
+    // NOTE: amplitude of a once a second pulse of the ambient, 0 is off. Only there to see
+    // Globals::time_secs reach the shaders
+    pub ambient_pulse: f32,
+}
+
+impl Default for AOSettings {
+    fn default() -> Self {
+        Self {
+            d_max: 2.0,
+            r_max: 5.0,
+            bias: 0.0,
+            method: AoMethod::Hemisphere,
+            num_samples: 16,
+            kernel_distribution: KernelDistribution::Poisson { num_rings: 3 },
+            kernel_jitter: false,
+            hbao_directions: 8,
+            hbao_steps: 6,
+            gtao_slices: 4,
+            gtao_steps: 6,
+            gtao_thickness: 1.0,
+            hi_z_mip_selection: HiZMipSelection::SampleDistance,
+            hi_z_mip_bias: 3,
+            use_bent_normals: false,
+            ambient_sky: 0.3,
+            ambient_ground: 0.3,
+            use_ibl: true,
+            ibl_intensity: 1.0,
+            ambient_pulse: 0.0,
+        }
+    }
+}
+
+pub const MAX_AO_SAMPLES: usize = 16;
+pub const MAX_MARCH_STEPS: u32 = 16;
+
+// NOTE: how the finest ao pass gathers its near occlusion, the coarser passes always
+// sample a square around the pixel and the blur is the same for all of them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AoMethod {
+    // NOTE: point samples of the kernel from generate_ssao_kernel
+    Hemisphere,
+    // NOTE: horizon based, marches directions in screen space and integrates how far the
+    // horizon along each rises above the tangent plane
+    Hbao,
+    // NOTE: ground truth ao, both horizons of slices through the view vector with the
+    // cosine weighted visibility between them integrated analytically
+    Gtao,
+}
+
+impl AoMethod {
+    pub const ALL: [AoMethod; 3] = [AoMethod::Hemisphere, AoMethod::Hbao, AoMethod::Gtao];
+
+    // NOTE: position in ALL, also the method in AOParams
+    pub fn index(&self) -> usize {
+        match self {
+            AoMethod::Hemisphere => 0,
+            AoMethod::Hbao => 1,
+            AoMethod::Gtao => 2,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AoMethod::Hemisphere => "hemisphere",
+            AoMethod::Hbao => "hbao",
+            AoMethod::Gtao => "gtao",
+        }
+    }
+}
+
+// NOTE: wide kernels spread their samples over more texels than fit in the cache, far
+// samples are read from a coarser mip of the hi-z pyramid instead. The coarser passes
+// read their own downsampled positions and never use it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HiZMipSelection {
+    // NOTE: every sample reads the full resolution positions
+    Off,
+    // NOTE: per sample, log2 of its pixel distance from the shaded pixel minus the bias,
+    // as in scalable ambient obscurance (mcguire et al. 2012)
+    SampleDistance,
+    // NOTE: per pixel, log2 of the kernel radius minus the bias. All samples of a pixel
+    // read the same mip, coarser for the near samples but more coherent
+    KernelRadius,
+}
+
+impl HiZMipSelection {
+    pub const ALL: [HiZMipSelection; 3] = [
+        HiZMipSelection::Off,
+        HiZMipSelection::SampleDistance,
+        HiZMipSelection::KernelRadius,
+    ];
+
+    // NOTE: position in ALL, also the hi_z_mip_selection in AOParams
+    pub fn index(&self) -> usize {
+        match self {
+            HiZMipSelection::Off => 0,
+            HiZMipSelection::SampleDistance => 1,
+            HiZMipSelection::KernelRadius => 2,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HiZMipSelection::Off => "off",
+            HiZMipSelection::SampleDistance => "sample distance",
+            HiZMipSelection::KernelRadius => "kernel radius",
+        }
+    }
+}
+
+// NOTE: evenly spaced unit directions over arc radians, the shader rotates them per
+// pixel. hbao marches the full circle, gtao half of it since every slice goes both ways
+pub fn march_directions(num_directions: usize, arc: f32) -> Vec<Vec2> {
+    let n = num_directions.max(1);
+    (0..n)
+        .map(|i| Vec2::from_angle(arc * i as f32 / n as f32))
+        .collect()
+}
+
+// NOTE: the finest ao pass samples in screen space around the pixel, so each hemisphere
+// distribution is used by where its directions land when projected onto the screen plane
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KernelDistribution {
+    // NOTE: directions uniform over the hemisphere, projected they bunch up at the rim
+    UniformHemisphere,
+    // NOTE: projects to a uniform disc (malley's method), more samples near the normal
+    CosineWeighted,
+    // NOTE: well separated points on concentric rings, each ring gets samples by its area
+    Poisson { num_rings: u32 },
+}
+
+// NOTE: offsets in the unit disc, scaled by the kernel radius in the shader. The
+// hemisphere ones are stratified along a golden angle spiral instead of random so a
+// handful of samples still covers the disc, jitter perturbs each by up to half the
+// average sample spacing to break up the banding of the regular pattern
+pub fn generate_ssao_kernel(
+    distribution: KernelDistribution,
+    num_samples: usize,
+    jitter: bool,
+    rng: &mut nanorand::WyRand,
+) -> Vec<Vec2> {
+    let golden_angle = PI * (3.0 - 5.0_f32.sqrt());
+    let n = num_samples.max(1);
+    let mut kernel = match distribution {
+        KernelDistribution::UniformHemisphere | KernelDistribution::CosineWeighted => (0..n)
+            .map(|i| {
+                let u = (i as f32 + 0.5) / n as f32;
+                let r = match distribution {
+                    // NOTE: cos theta uniform in 0..1, the projected radius is sin theta
+                    KernelDistribution::UniformHemisphere => (1.0 - u * u).sqrt(),
+                    _ => u.sqrt(),
+                };
+                let phi = i as f32 * golden_angle;
+                r * Vec2::new(phi.cos(), phi.sin())
+            })
+            .collect::<Vec<_>>(),
+        KernelDistribution::Poisson { num_rings } => {
+            let num_rings = (num_rings as usize).clamp(1, n);
+            // NOTE: ring k spans radii k/num_rings..(k+1)/num_rings, its area share is
+            // proportional to 2k+1
+            let total_weight = (num_rings * num_rings) as f32;
+            let mut kernel = vec![];
+            let mut assigned = 0;
+            for k in 0..num_rings {
+                let end = if k == num_rings - 1 {
+                    n
+                } else {
+                    let share = ((k + 1) * (k + 1)) as f32 / total_weight;
+                    ((share * n as f32).round() as usize).clamp(assigned + 1, n)
+                };
+                let count = end - assigned;
+                let r = (k as f32 + 0.5) / num_rings as f32;
+                // NOTE: offset every ring so samples don't line up radially
+                let offset = k as f32 * golden_angle;
+                for j in 0..count {
+                    let phi = offset + TAU * j as f32 / count as f32;
+                    kernel.push(r * Vec2::new(phi.cos(), phi.sin()));
+                }
+                assigned = end;
+                if assigned == n {
+                    break;
+                }
+            }
+            kernel
+        }
+    };
+    if jitter {
+        let spacing = 1.0 / (n as f32).sqrt();
+        for sample in kernel.iter_mut() {
+            let offset = Vec2::new(rng.generate::<f32>(), rng.generate::<f32>()) - 0.5;
+            *sample += offset * spacing;
+            // NOTE: stay inside the kernel radius
+            if sample.length() > 1.0 {
+                *sample = sample.normalize();
+            }
+        }
+    }
+    kernel
 }
 
 impl AOParams {
-    pub fn from(pass_i: usize, d_max: f32, fov_y: f32, ao_width: u32, ao_height: u32) -> Self {
+    pub fn from(
+        pass_i: usize,
+        d_max: f32,
+        fov_y: f32,
+        ao_width: u32,
+        ao_height: u32,
+        settings: &AOSettings,
+        kernel_rotation: f32,
+        hi_z_mips: u32,
+    ) -> Self {
         let s = ao_height as f32;
         let a = fov_y;
         // NOTE: eq (5) in reference paper, we need to divide by depth of specific pixel in shader tog get exact value
         let r0_almost = s * d_max / (2.0 * (a / 2.0).tan());
         let ri_almost = r0_almost / ((1 << pass_i) as f32);
         let ri_almost = ri_almost;
+        let proj_y = 1.0 / (a / 2.0).tan();
+        let num_samples = (settings.num_samples as usize).clamp(1, MAX_AO_SAMPLES);
+        // NOTE: fixed seed, the jittered kernel is the same every frame and every pass,
+        // temporal ssao rotates it per frame instead so the history sees new directions
+        let mut rng = nanorand::WyRand::new_seed(0x55a0);
+        let hbao_directions_count = (settings.hbao_directions as usize).clamp(1, MAX_AO_SAMPLES);
+        let gtao_slices = (settings.gtao_slices as usize).clamp(1, MAX_AO_SAMPLES);
+        let samples = match settings.method {
+            AoMethod::Hemisphere => generate_ssao_kernel(
+                settings.kernel_distribution,
+                num_samples,
+                settings.kernel_jitter,
+                &mut rng,
+            ),
+            AoMethod::Hbao => march_directions(hbao_directions_count, TAU),
+            AoMethod::Gtao => march_directions(gtao_slices, PI),
+        };
+        let rotation = Vec2::from_angle(kernel_rotation);
+        let mut kernel = [[0.0; 4]; MAX_AO_SAMPLES / 2];
+        for (i, sample) in samples.iter().enumerate() {
+            let sample = rotation.rotate(*sample);
+            kernel[i / 2][2 * (i % 2)] = sample.x;
+            kernel[i / 2][2 * (i % 2) + 1] = sample.y;
+        }
         Self {
             ri_almost,
-            pad: Default::default(),
+            d_max: settings.d_max,
+            r_max: settings.r_max,
+            bias: settings.bias,
+            num_samples: num_samples as u32,
+            method: settings.method.index() as u32,
+            hbao_directions: hbao_directions_count as u32,
+            hbao_steps: settings.hbao_steps.clamp(1, MAX_MARCH_STEPS),
+            gtao_slices: gtao_slices as u32,
+            gtao_steps: settings.gtao_steps.clamp(1, MAX_MARCH_STEPS),
+            gtao_thickness: settings.gtao_thickness.max(1e-4),
+            hi_z_mips,
+            hi_z_mip_selection: settings.hi_z_mip_selection.index() as u32,
+            hi_z_mip_bias: settings.hi_z_mip_bias,
+            proj_scale: [proj_y * ao_height as f32 / ao_width as f32, proj_y],
+            kernel,
             ao_width: ao_width as f32,
             ao_height: ao_height as f32,
             num_passes: NUM_AO_TEXTURES as _,
@@ -53,11 +859,54 @@ impl AOParams {
     }
 }
 
+// NOTE: the geometry pipelines take three groups, GeometryParams once per node,
+// MaterialParams per draw and PreviousFrameGlobals once per node
 #[derive(blade_macros::ShaderData)]
 pub struct GeometryParams {
     pub globals: Globals,
 }
 
+#[derive(blade_macros::ShaderData)]
+pub struct MaterialParams {
+    pub material: MaterialUniform,
+    pub albedo_view: gpu::TextureView,
+    pub albedo_sampler: gpu::Sampler,
+}
+
+// NOTE: kept out of Globals so the passes that don't need motion vectors don't carry it.
+// prev_mvp is the unjittered previous frame with the model of the node, see
+// State::prev_globals
+#[derive(blade_macros::ShaderData)]
+pub struct PreviousFrameGlobals {
+    pub prev_mvp: [[f32; 4]; 4],
+    pub prev_cam_pos: [f32; 3],
+}
+
+impl PreviousFrameGlobals {
+    pub fn new(prev_globals: &Globals, model: &Transform) -> Self {
+        let prev_vp = Mat4::from_cols_array_2d(&prev_globals.mvp_transform);
+        Self {
+            prev_mvp: (prev_vp * model.to_mat4()).to_cols_array_2d(),
+            prev_cam_pos: prev_globals.cam_pos,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct MaterialUniform {
+    pub diffuse: [f32; 4],
+    // NOTE: layer in the material texture array, -1 means albedo is the flat diffuse color
+    pub texture_layer: i32,
+    // NOTE: per mesh, 1 writes the interpolated vertex normal instead of the face normal
+    pub use_vertex_normals: u32,
+    // NOTE: written to the material g-buffer target for the light pass brdf
+    pub roughness: f32,
+    pub metalness: f32,
+}
+
+const _: () = assert!(std::mem::size_of::<MaterialUniform>() == 32);
+
 // #[derive(blade_macros::ShaderData)]
 // pub struct LightParams {
 //     pub pos_view: gpu::TextureView,
@@ -70,13 +919,12 @@ pub struct GeometryParams {
 //     pub depth_sampler: gpu::Sampler,
 // }
 
+// NOTE: every pass that needs positions binds a depth level and the globals to rebuild
+// them with, see view_pos.wgsl
 #[derive(blade_macros::ShaderData)]
-pub struct DepthPosNormalParams {
+pub struct DepthNormalParams {
     pub globals: Globals,
-    // pub depth_view: gpu::TextureView,
-    // pub depth_sampler: gpu::Sampler,
-    pub pos_view: gpu::TextureView,
-    pub pos_sampler: gpu::Sampler,
+    pub depth_view: gpu::TextureView,
 
     pub normal_view: gpu::TextureView,
     pub normal_sampler: gpu::Sampler,
@@ -84,44 +932,154 @@ pub struct DepthPosNormalParams {
 
 #[derive(blade_macros::ShaderData)]
 pub struct BlurParams {
+    pub globals: Globals,
     pub ao_params: AOParams,
 
     pub ao_view: gpu::TextureView,
     pub ao_sampler: gpu::Sampler,
 
-    pub pos_view: gpu::TextureView,
-    pub pos_sampler: gpu::Sampler,
+    pub depth_view: gpu::TextureView,
 
     pub normal_view: gpu::TextureView,
     pub normal_sampler: gpu::Sampler,
 }
 
+#[derive(blade_macros::ShaderData)]
+pub struct DepthDebugParams {
+    pub depth_view: gpu::TextureView,
+    pub near: f32,
+    pub far: f32,
+}
+
+#[derive(blade_macros::ShaderData)]
+pub struct LoadingParams {
+    // NOTE: seconds since loading started, one full spin per second
+    pub time: f32,
+    pub aspect: f32,
+}
+
+#[derive(blade_macros::ShaderData)]
+pub struct NormalDebugParams {
+    pub globals: Globals,
+    pub normal_view: gpu::TextureView,
+}
+
+#[derive(blade_macros::ShaderData)]
+pub struct ShadowPassParams {
+    pub globals: Globals,
+}
+
 #[derive(blade_macros::ShaderData)]
 pub struct LightPassParams {
     pub globals: Globals,
+    pub shadow: ShadowParams,
+    pub shadow_view: gpu::TextureView,
+    pub shadow_sampler: gpu::Sampler,
+    // NOTE: positions come from the depth and Globals::inv_mvp_transform
     pub depth_view: gpu::TextureView,
     pub depth_sampler: gpu::Sampler,
 
-    pub pos_view: gpu::TextureView,
-    pub pos_sampler: gpu::Sampler,
-
     pub normal_view: gpu::TextureView,
     pub normal_sampler: gpu::Sampler,
 
     pub ao_view: gpu::TextureView,
     pub ao_sampler: gpu::Sampler,
+
+    pub bent_normal_view: gpu::TextureView,
+    pub bent_normal_sampler: gpu::Sampler,
+    pub ambient: AmbientUniform,
+    pub irradiance_view: gpu::TextureView,
+    pub irradiance_sampler: gpu::Sampler,
+
+    pub albedo_view: gpu::TextureView,
+    pub albedo_sampler: gpu::Sampler,
+    pub material_view: gpu::TextureView,
+    pub material_sampler: gpu::Sampler,
+    // NOTE: see AoSplitSettings::split_x
+    pub split_x: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct AmbientUniform {
+    pub sky: f32,
+    pub ground: f32,
+    pub use_bent_normal: u32,
+    pub use_ibl: u32,
+    pub ibl_intensity: f32,
+    pub pulse: f32,
+    pub pad1: u32,
+    pub pad2: u32,
+}
+
+impl AmbientUniform {
+    // NOTE: has_irradiance is false without a skybox, the bound irradiance is a dummy then
+    pub fn new(settings: &AOSettings, has_irradiance: bool) -> Self {
+        Self {
+            sky: settings.ambient_sky,
+            ground: settings.ambient_ground,
+            use_bent_normal: settings.use_bent_normals as u32,
+            use_ibl: (settings.use_ibl && has_irradiance) as u32,
+            ibl_intensity: settings.ibl_intensity,
+            pulse: settings.ambient_pulse,
+            pad1: 0,
+            pad2: 0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct SkyboxUniform {
+    pub inv_vp_rot: [[f32; 4]; 4],
+    pub far_depth: f32,
+    pub pad0: u32,
+    pub pad1: u32,
+    pub pad2: u32,
+}
+
+impl SkyboxUniform {
+    pub fn new(camera: &Camera) -> Self {
+        let vp_rot = camera.projection() * camera.view_rot_only();
+        Self {
+            inv_vp_rot: vp_rot.inverse().to_cols_array_2d(),
+            far_depth: if camera.reverse_z { 0.0 } else { 1.0 },
+            pad0: 0,
+            pad1: 0,
+            pad2: 0,
+        }
+    }
+}
+
+#[derive(blade_macros::ShaderData)]
+pub struct SkyboxParams {
+    pub skybox: SkyboxUniform,
+    pub cube_view: gpu::TextureView,
+    pub cube_sampler: gpu::Sampler,
+}
+
+// NOTE: storage view the compute test writes its gradient to
+#[derive(blade_macros::ShaderData)]
+pub struct ComputeTestParams {
+    pub output: gpu::TextureView,
+}
+
+// NOTE: second bind group of the light pass, lights is an array of GpuLight
+#[derive(blade_macros::ShaderData)]
+pub struct LightListParams {
+    pub lights: gpu::BufferPiece,
+    pub num_lights: u32,
 }
 
 #[derive(blade_macros::ShaderData)]
 pub struct CalcAoParams {
-    pub pos_view: gpu::TextureView,
-    pub pos_sampler: gpu::Sampler,
+    pub globals: Globals,
+    pub depth_view: gpu::TextureView,
 
     pub normal_view: gpu::TextureView,
     pub normal_sampler: gpu::Sampler,
 
-    pub prev_pos_view: gpu::TextureView,
-    pub prev_pos_sampler: gpu::Sampler,
+    pub prev_depth_view: gpu::TextureView,
 
     pub prev_normal_view: gpu::TextureView,
     pub prev_normal_sampler: gpu::Sampler,
@@ -129,24 +1087,45 @@ pub struct CalcAoParams {
     pub prev_ao_view: gpu::TextureView,
     pub prev_ao_sampler: gpu::Sampler,
 
+    pub hi_z_view: gpu::TextureView,
+    pub hi_z_sampler: gpu::Sampler,
+
     pub ao_params: AOParams,
 }
 
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct AoGizmoUniform {
+    pub uv: [f32; 2],
+    pub point_size: f32,
+    pub pad0: u32,
+}
+
+// NOTE: the finest level ao inputs plus the instance buffer the gizmos are written to
 #[derive(blade_macros::ShaderData)]
-pub struct PosNormalPrevAOParams {
+pub struct AoGizmoParams {
     pub globals: Globals,
-    pub pos_view: gpu::TextureView,
-    pub pos_sampler: gpu::Sampler,
-
+    pub ao_params: AOParams,
+    pub ao_gizmo: AoGizmoUniform,
+    pub depth_view: gpu::TextureView,
     pub normal_view: gpu::TextureView,
     pub normal_sampler: gpu::Sampler,
+    pub hi_z_view: gpu::TextureView,
+    pub hi_z_sampler: gpu::Sampler,
+    pub gizmos: gpu::BufferPiece,
+}
 
-    pub prev_ao_view: gpu::TextureView,
-    pub prev_ao_sampler: gpu::Sampler,
+#[derive(blade_macros::ShaderData)]
+pub struct HiZLinearDepthParams {
+    pub globals: Globals,
+    pub depth_view: gpu::TextureView,
+    pub dst_view: gpu::TextureView,
+}
 
-    pub ao_params: AOParams,
-    // pub is_first_pass: u32,
-    // pub pad: [u32; 3],
+#[derive(blade_macros::ShaderData)]
+pub struct HiZDownsampleParams {
+    pub src_view: gpu::TextureView,
+    pub dst_view: gpu::TextureView,
 }
 
 // #[derive(blade_macros::ShaderData)]
@@ -155,1524 +1134,13360 @@ pub struct PosNormalPrevAOParams {
 //     pub depth_from_sampler: gpu::Sampler,
 // }
 
-#[derive(blade_macros::Vertex, Debug)]
+#[repr(C)]
+#[derive(blade_macros::Vertex, Clone, Copy, Debug, Pod, Zeroable)]
 pub struct Vertex {
     pub ws_pos: [f32; 3],
     pub ws_normal: [f32; 3],
+    pub uv: [f32; 2],
+    pub color: [f32; 4],
 }
 
-pub struct Mesh {
-    pub vertex_buf: gpu::BufferPiece,
-    pub index_buf: Option<gpu::BufferPiece>,
-    pub num_vertices: usize,
-    pub num_indices: usize,
+// NOTE: same as Vertex but with the normal packed as 10-10-10-2 snorm
+#[repr(C)]
+#[derive(blade_macros::Vertex, Clone, Copy, Debug, Pod, Zeroable)]
+pub struct PackedVertex {
+    pub ws_pos: [f32; 3],
+    pub ws_normal: u32,
+    pub uv: [f32; 2],
+    pub color: [f32; 4],
 }
 
-pub struct CpuMesh {
-    pub vertices: Vec<Vec3A>,
-    pub indices: Vec<usize>,
+impl From<&Vertex> for PackedVertex {
+    fn from(v: &Vertex) -> Self {
+        Self {
+            ws_pos: v.ws_pos,
+            ws_normal: pack_normal_10_10_10_2(Vec3A::from_array(v.ws_normal)),
+            uv: v.uv,
+            color: v.color,
+        }
+    }
 }
 
-#[derive(Clone)]
-pub struct Camera {
-    pub pos: Vec3A,
-    pub yaw: f32,
-    pub pitch: f32,
-    pub vfov_rad: f32,
-    pub aspect: f32,
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VertexFormat {
+    Full,
+    PackedNormals,
 }
 
-pub struct InputState {
-    pub ao_level: usize,
-    pub use_blur: bool,
-}
+impl VertexFormat {
+    pub fn layout(self) -> gpu::VertexLayout {
+        match self {
+            Self::Full => <Vertex as gpu::Vertex>::layout(),
+            Self::PackedNormals => <PackedVertex as gpu::Vertex>::layout(),
+        }
+    }
 
-pub struct GBuffer {
-    pub depth_textures: DepthTextures,
-    pub pos_texture: gpu::Texture,
-    pub normal_texture: gpu::Texture,
+    pub fn vertex_size(self) -> usize {
+        match self {
+            Self::Full => std::mem::size_of::<Vertex>(),
+            Self::PackedNormals => std::mem::size_of::<PackedVertex>(),
+        }
+    }
 
-    pub pos_view: gpu::TextureView,
-    pub normal_view: gpu::TextureView,
+    // NOTE: geometry vertex shader entry point that decodes this format
+    pub fn geometry_vs_entry(self) -> &'static str {
+        match self {
+            Self::Full => "vs_main",
+            Self::PackedNormals => "vs_main_packed",
+        }
+    }
 
-    pub pos_sampler: gpu::Sampler,
-    pub normal_sampler: gpu::Sampler,
+    pub fn shadow_vs_entry(self) -> &'static str {
+        match self {
+            Self::Full => "vs_shadow",
+            Self::PackedNormals => "vs_shadow_packed",
+        }
+    }
 }
 
-pub struct TextureStuff {
-    pub texture: gpu::Texture,
-    pub view: gpu::TextureView,
-    pub sampler: gpu::Sampler,
-    pub size: gpu::Extent,
+// NOTE: how the g-buffer normals are stored. Octahedral packs them into two half floats,
+// 32 bits a texel instead of the 128 of the full rgba32 float target ("--full-normals")
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NormalEncoding {
+    Octahedral,
+    Full,
 }
 
-pub struct DepthPosNormalTexture {
-    pub depth: TextureStuff,
-    pub pos: TextureStuff,
-    pub normal: TextureStuff,
+impl NormalEncoding {
+    pub fn format(self) -> gpu::TextureFormat {
+        match self {
+            Self::Octahedral => gpu::TextureFormat::Rg16Float,
+            Self::Full => gpu::TextureFormat::Rgba32Float,
+        }
+    }
+
+    pub fn texel_size(self) -> u32 {
+        match self {
+            Self::Octahedral => 4,
+            Self::Full => 16,
+        }
+    }
+
+    // NOTE: cpu side decode_normal from normal_encoding.wgsl for one texel of a readback
+    pub fn decode_texel(self, texel: &[u8]) -> Vec3A {
+        match self {
+            Self::Octahedral => {
+                let half = |i: usize| f16_to_f32(u16::from_le_bytes([texel[i], texel[i + 1]]));
+                decode_octahedral_normal(Vec2::new(half(0), half(2)))
+            }
+            Self::Full => {
+                let float = |i: usize| f32::from_le_bytes(texel[i..i + 4].try_into().unwrap());
+                Vec3A::new(float(0), float(4), float(8))
+            }
+        }
+    }
 }
 
-pub struct DownsampleTextures {
-    pub textures: Vec<DepthPosNormalTexture>,
+pub fn decode_octahedral_normal(e: Vec2) -> Vec3A {
+    let mut n = Vec3A::new(e.x, e.y, 1.0 - e.x.abs() - e.y.abs());
+    let t = (-n.z).max(0.0);
+    n.x += if n.x >= 0.0 { -t } else { t };
+    n.y += if n.y >= 0.0 { -t } else { t };
+    n.normalize()
 }
 
-pub struct AOTextures {
-    pub textures: Vec<TextureStuff>,
-    pub textures_after_blur: Vec<TextureStuff>,
-    pub dummy_texture: TextureStuff,
+pub fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+    let magnitude = match exponent {
+        // NOTE: subnormal
+        0 => mantissa * 2.0_f32.powi(-24),
+        0x1f if mantissa == 0.0 => f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2.0_f32.powi(exponent - 15),
+    };
+    sign * magnitude
 }
 
-pub struct DepthTextures {
-    pub texture_stuffs: Vec<TextureStuff>,
+pub fn pack_normal_10_10_10_2(n: Vec3A) -> u32 {
+    let pack = |x: f32| ((x.clamp(-1.0, 1.0) * 511.0).round() as i32 as u32) & 0x3ff;
+    pack(n.x) | (pack(n.y) << 10) | (pack(n.z) << 20)
 }
 
-pub fn create_downsample_and_ao_textures(
-    ctx: &gpu::Context,
-    screen_size: gpu::Extent,
-) -> (DownsampleTextures, AOTextures) {
-    let mut depth_pos_normal_textures = vec![];
-    let mut ao_textures = vec![];
-    let mut ao_textures_blur = vec![];
+pub fn unpack_normal_10_10_10_2(packed: u32) -> Vec3A {
+    // NOTE: shift up and back down to sign extend the 10 bit values
+    let unpack = |bits: u32| ((((bits & 0x3ff) << 22) as i32 >> 22) as f32 / 511.0).max(-1.0);
+    Vec3A::new(unpack(packed), unpack(packed >> 10), unpack(packed >> 20))
+}
 
-    let width = screen_size.width;
-    let height = screen_size.height;
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct ImguiGlobals {
+    pub scale: [f32; 2],
+    pub translate: [f32; 2],
+}
 
-    for i in 0..NUM_AO_TEXTURES {
-        let pow_2 = 1 << i;
-        let width_i = width / pow_2;
-        let height_i = height / pow_2;
+#[derive(blade_macros::ShaderData)]
+pub struct ImguiParams {
+    pub globals: ImguiGlobals,
+    pub font_view: gpu::TextureView,
+    pub font_sampler: gpu::Sampler,
+}
 
-        let extent_i = gpu::Extent {
-            width: width_i,
-            height: height_i,
-            depth: 1,
-        };
+#[repr(C)]
+#[derive(blade_macros::Vertex, Clone, Copy, Pod, Zeroable)]
+pub struct ImguiVertex {
+    pub pos: [f32; 2],
+    pub uv: [f32; 2],
+    // NOTE: rgba8, unpacked in shader
+    pub color: u32,
+}
 
-        let depth_texture_i = ctx.create_texture(gpu::TextureDesc {
-            name: format!("depth texture {i}").as_str(),
-            format: gpu::TextureFormat::Depth32Float,
-            size: extent_i,
-            array_layer_count: 1,
-            mip_level_count: 1,
-            dimension: gpu::TextureDimension::D2,
-            usage: gpu::TextureUsage::TARGET | gpu::TextureUsage::RESOURCE,
+pub struct ImguiRenderer {
+    pub pipeline: gpu::RenderPipeline,
+    pub font: TextureStuff,
+    pub vertex_buf: gpu::Buffer,
+    pub index_buf: gpu::Buffer,
+    pub vertex_capacity: usize,
+    pub index_capacity: usize,
+}
+
+impl ImguiRenderer {
+    pub fn new(
+        ctx: &gpu::Context,
+        upload_batch: &mut UploadBatch,
+        surface_format: gpu::TextureFormat,
+        imgui: &mut imgui::Context,
+    ) -> Self {
+        let shader = ctx.create_shader(gpu::ShaderDesc {
+            source: include_str!("imgui_shader.wgsl"),
         });
-        let depth_view_i = ctx.create_texture_view(
-            depth_texture_i,
-            gpu::TextureViewDesc {
-                name: format!("depth view {i}").as_str(),
-                format: gpu::TextureFormat::Depth32Float,
-                dimension: gpu::ViewDimension::D2,
-                subresources: &Default::default(),
+        let pipeline = ctx.create_render_pipeline(gpu::RenderPipelineDesc {
+            name: "imgui",
+            data_layouts: &[&<ImguiParams as gpu::ShaderData>::layout()],
+            vertex: shader.at("vs_imgui"),
+            vertex_fetches: &[gpu::VertexFetchState {
+                layout: &<ImguiVertex as gpu::Vertex>::layout(),
+                instanced: false,
+            }],
+            primitive: gpu::PrimitiveState {
+                topology: gpu::PrimitiveTopology::TriangleList,
+                front_face: gpu::FrontFace::Ccw,
+                cull_mode: None,
+                unclipped_depth: false,
+                wireframe: false,
             },
-        );
-        let depth_sampler_i = ctx.create_sampler(gpu::SamplerDesc {
-            name: format!("depth sampler {i}").as_str(),
-            // compare: Some(gpu::CompareFunction::LessEqual),
-            ..Default::default()
+            depth_stencil: None,
+            fragment: shader.at("fs_imgui"),
+            color_targets: &[gpu::ColorTargetState {
+                format: surface_format,
+                blend: Some(gpu::BlendState::ALPHA_BLENDING),
+                write_mask: gpu::ColorWrites::default(),
+            }],
         });
 
-        let depth_stuff_i = TextureStuff {
-            texture: depth_texture_i,
-            view: depth_view_i,
-            sampler: depth_sampler_i,
-            size: extent_i,
+        // NOTE: font atlas
+        let fonts = imgui.fonts();
+        let atlas = fonts.build_rgba32_texture();
+        let extent = gpu::Extent {
+            width: atlas.width,
+            height: atlas.height,
+            depth: 1,
         };
-
-        let pos_texture_i = ctx.create_texture(gpu::TextureDesc {
-            name: format!("pos texture {i}").as_str(),
-            format: gpu::TextureFormat::Rgba32Float,
-            size: extent_i,
+        let font_texture = ctx.create_texture(gpu::TextureDesc {
+            name: "imgui font texture",
+            format: gpu::TextureFormat::Rgba8Unorm,
+            size: extent,
             array_layer_count: 1,
             mip_level_count: 1,
             dimension: gpu::TextureDimension::D2,
-            usage: gpu::TextureUsage::TARGET | gpu::TextureUsage::RESOURCE,
+            usage: gpu::TextureUsage::COPY | gpu::TextureUsage::RESOURCE,
         });
-        let pos_view_i = ctx.create_texture_view(
-            pos_texture_i,
+        let font_view = ctx.create_texture_view(
+            font_texture,
             gpu::TextureViewDesc {
-                name: format!("pos view {i}").as_str(),
-                format: gpu::TextureFormat::Rgba32Float,
+                name: "imgui font view",
+                format: gpu::TextureFormat::Rgba8Unorm,
                 dimension: gpu::ViewDimension::D2,
                 subresources: &Default::default(),
             },
         );
-        let pos_sampler_i = ctx.create_sampler(gpu::SamplerDesc {
-            name: format!("pos sampler {i}").as_str(),
-            address_modes: Default::default(),
-            mag_filter: gpu::FilterMode::Nearest,
-            min_filter: gpu::FilterMode::Nearest,
+        let font_sampler = ctx.create_sampler(gpu::SamplerDesc {
+            name: "imgui font sampler",
+            address_modes: [gpu::AddressMode::ClampToEdge; 3],
+            mag_filter: gpu::FilterMode::Linear,
+            min_filter: gpu::FilterMode::Linear,
             mipmap_filter: gpu::FilterMode::Nearest,
             ..Default::default()
         });
 
-        let pos_stuff_i = TextureStuff {
-            texture: pos_texture_i,
-            view: pos_view_i,
-            sampler: pos_sampler_i,
-            size: extent_i,
-        };
+        upload_batch.write_texture(atlas.data, atlas.width * 4, font_texture.into(), extent);
 
-        let normal_texture_i = ctx.create_texture(gpu::TextureDesc {
-            name: format!("normal texture {i}").as_str(),
-            format: gpu::TextureFormat::Rgba32Float,
-            size: extent_i,
-            array_layer_count: 1,
-            mip_level_count: 1,
-            dimension: gpu::TextureDimension::D2,
-            usage: gpu::TextureUsage::TARGET | gpu::TextureUsage::RESOURCE,
-        });
-        let normal_view_i = ctx.create_texture_view(
-            normal_texture_i,
-            gpu::TextureViewDesc {
-                name: format!("normal view {i}").as_str(),
-                format: gpu::TextureFormat::Rgba32Float,
-                dimension: gpu::ViewDimension::D2,
-                subresources: &Default::default(),
+        // NOTE: we only ever draw the font texture so the id does not really matter
+        fonts.tex_id = imgui::TextureId::new(0);
+
+        let vertex_capacity = 1 << 12;
+        let index_capacity = 1 << 13;
+        let (vertex_buf, index_buf) = Self::create_buffers(ctx, vertex_capacity, index_capacity);
+
+        Self {
+            pipeline,
+            font: TextureStuff {
+                texture: font_texture,
+                view: font_view,
+                sampler: font_sampler,
+                size: extent,
             },
-        );
-        let normal_sampler_i = ctx.create_sampler(gpu::SamplerDesc {
-            name: format!("normal sampler {i}").as_str(),
-            address_modes: Default::default(),
-            mag_filter: gpu::FilterMode::Nearest,
-            min_filter: gpu::FilterMode::Nearest,
-            mipmap_filter: gpu::FilterMode::Nearest,
-            ..Default::default()
+            vertex_buf,
+            index_buf,
+            vertex_capacity,
+            index_capacity,
+        }
+    }
+
+    fn create_buffers(
+        ctx: &gpu::Context,
+        vertex_capacity: usize,
+        index_capacity: usize,
+    ) -> (gpu::Buffer, gpu::Buffer) {
+        let vertex_buf = ctx.create_buffer(gpu::BufferDesc {
+            name: "imgui vertex buffer",
+            size: (vertex_capacity * std::mem::size_of::<ImguiVertex>()) as u64,
+            memory: gpu::Memory::Shared,
+        });
+        let index_buf = ctx.create_buffer(gpu::BufferDesc {
+            name: "imgui index buffer",
+            size: (index_capacity * std::mem::size_of::<imgui::DrawIdx>()) as u64,
+            memory: gpu::Memory::Shared,
         });
+        (vertex_buf, index_buf)
+    }
 
-        let normal_stuff_i = TextureStuff {
-            texture: normal_texture_i,
-            view: normal_view_i,
-            sampler: normal_sampler_i,
-            size: extent_i,
-        };
+    pub fn render(
+        &mut self,
+        ctx: &gpu::Context,
+        encoder: &mut gpu::CommandEncoder,
+        target: gpu::TextureView,
+        draw_data: &imgui::DrawData,
+    ) {
+        let fb_width = draw_data.display_size[0] * draw_data.framebuffer_scale[0];
+        let fb_height = draw_data.display_size[1] * draw_data.framebuffer_scale[1];
+        if fb_width <= 0.0 || fb_height <= 0.0 || draw_data.total_idx_count == 0 {
+            return;
+        }
 
-        let depth_pos_normal_i = DepthPosNormalTexture {
-            depth: depth_stuff_i,
-            pos: pos_stuff_i,
-            normal: normal_stuff_i,
+        let num_vertices = draw_data.total_vtx_count as usize;
+        let num_indices = draw_data.total_idx_count as usize;
+        // NOTE: grow buffers, previous frame is always waited on so destroying here is fine
+        if num_vertices > self.vertex_capacity || num_indices > self.index_capacity {
+            ctx.destroy_buffer(self.vertex_buf);
+            ctx.destroy_buffer(self.index_buf);
+            self.vertex_capacity = self.vertex_capacity.max(num_vertices.next_power_of_two());
+            self.index_capacity = self.index_capacity.max(num_indices.next_power_of_two());
+            let (vertex_buf, index_buf) =
+                Self::create_buffers(ctx, self.vertex_capacity, self.index_capacity);
+            self.vertex_buf = vertex_buf;
+            self.index_buf = index_buf;
+        }
+
+        let vertex_ptr = self.vertex_buf.data() as *mut ImguiVertex;
+        let index_ptr = self.index_buf.data() as *mut imgui::DrawIdx;
+        let mut vertex_offset = 0;
+        let mut index_offset = 0;
+        for draw_list in draw_data.draw_lists() {
+            let vertices = draw_list
+                .vtx_buffer()
+                .iter()
+                .map(|v| ImguiVertex {
+                    pos: v.pos,
+                    uv: v.uv,
+                    color: u32::from_le_bytes(v.col),
+                })
+                .collect::<Vec<_>>();
+            let indices = draw_list.idx_buffer();
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    vertices.as_ptr(),
+                    vertex_ptr.add(vertex_offset),
+                    vertices.len(),
+                );
+                std::ptr::copy_nonoverlapping(
+                    indices.as_ptr(),
+                    index_ptr.add(index_offset),
+                    indices.len(),
+                );
+            }
+            vertex_offset += vertices.len();
+            index_offset += indices.len();
+        }
+        ctx.sync_buffer(self.vertex_buf);
+        ctx.sync_buffer(self.index_buf);
+
+        // NOTE: maps imgui display coordinates to clip space with y pointing down
+        let [dx, dy] = draw_data.display_pos;
+        let [dw, dh] = draw_data.display_size;
+        let globals = ImguiGlobals {
+            scale: [2.0 / dw, -2.0 / dh],
+            translate: [-1.0 - dx * 2.0 / dw, 1.0 + dy * 2.0 / dh],
         };
 
-        depth_pos_normal_textures.push(depth_pos_normal_i);
+        if let mut imgui_pass = encoder.render(
+            "imgui",
+            gpu::RenderTargetSet {
+                colors: &[gpu::RenderTarget {
+                    view: target,
+                    init_op: gpu::InitOp::Load,
+                    finish_op: gpu::FinishOp::Store,
+                }],
+                depth_stencil: None,
+            },
+        ) {
+            let mut rc = imgui_pass.with(&self.pipeline);
+            rc.bind(
+                0,
+                &ImguiParams {
+                    globals,
+                    font_view: self.font.view,
+                    font_sampler: self.font.sampler,
+                },
+            );
+            rc.bind_vertex(0, self.vertex_buf.into());
+
+            let [sx, sy] = draw_data.framebuffer_scale;
+            let mut vertex_offset = 0;
+            let mut index_offset = 0;
+            for draw_list in draw_data.draw_lists() {
+                for cmd in draw_list.commands() {
+                    match cmd {
+                        imgui::DrawCmd::Elements {
+                            count,
+                            cmd_params:
+                                imgui::DrawCmdParams {
+                                    clip_rect,
+                                    vtx_offset,
+                                    idx_offset,
+                                    ..
+                                },
+                        } => {
+                            let min_x = ((clip_rect[0] - dx) * sx).max(0.0);
+                            let min_y = ((clip_rect[1] - dy) * sy).max(0.0);
+                            let max_x = ((clip_rect[2] - dx) * sx).min(fb_width);
+                            let max_y = ((clip_rect[3] - dy) * sy).min(fb_height);
+                            if max_x <= min_x || max_y <= min_y {
+                                continue;
+                            }
+                            rc.set_scissor_rect(&gpu::ScissorRect {
+                                x: min_x as i32,
+                                y: min_y as i32,
+                                w: (max_x - min_x) as u32,
+                                h: (max_y - min_y) as u32,
+                            });
+                            let index_piece = gpu::BufferPiece {
+                                buffer: self.index_buf,
+                                offset: ((index_offset + idx_offset)
+                                    * std::mem::size_of::<imgui::DrawIdx>())
+                                    as u64,
+                            };
+                            rc.draw_indexed(
+                                index_piece,
+                                gpu::IndexType::U16,
+                                count as _,
+                                (vertex_offset + vtx_offset) as _,
+                                0,
+                                1,
+                            );
+                        }
+                        imgui::DrawCmd::ResetRenderState => {}
+                        imgui::DrawCmd::RawCallback { .. } => {}
+                    }
+                }
+                vertex_offset += draw_list.vtx_buffer().len();
+                index_offset += draw_list.idx_buffer().len();
+            }
+        }
+    }
+
+    pub fn destroy(&mut self, ctx: &gpu::Context) {
+        ctx.destroy_buffer(self.vertex_buf);
+        ctx.destroy_buffer(self.index_buf);
+        ctx.destroy_texture_view(self.font.view);
+        ctx.destroy_texture(self.font.texture);
+        ctx.destroy_sampler(self.font.sampler);
+    }
+}
 
-        //NOTE: ao texture
-        let ao_texture_i = ctx.create_texture(gpu::TextureDesc {
-            name: format!("ao texture {i}").as_str(),
-            format: gpu::TextureFormat::Rgba32Float,
-            size: extent_i,
-            array_layer_count: 1,
-            mip_level_count: 1,
-            dimension: gpu::TextureDimension::D2,
-            usage: gpu::TextureUsage::TARGET | gpu::TextureUsage::RESOURCE,
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct ParticleGlobals {
+    pub view_proj: [[f32; 4]; 4],
+    pub cam_right: [f32; 3],
+    pub pad0: u32,
+    pub cam_up: [f32; 3],
+    pub pad1: u32,
+}
+
+const _: () = assert!(std::mem::size_of::<ParticleGlobals>() == 96);
+
+#[derive(blade_macros::ShaderData)]
+pub struct ParticleParams {
+    pub globals: ParticleGlobals,
+}
+
+// NOTE: per instance data for the billboard pipeline, the quad corners come from the
+// vertex index
+#[repr(C)]
+#[derive(blade_macros::Vertex, Clone, Copy, Debug, Pod, Zeroable)]
+pub struct ParticleInstance {
+    pub position: [f32; 3],
+    pub size: f32,
+    pub color: [f32; 4],
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Particle {
+    pub position: Vec3A,
+    pub velocity: Vec3A,
+    // NOTE: seconds left, removed at 0
+    pub lifetime: f32,
+    pub size: f32,
+    pub color: Vec4,
+}
+
+pub const PARTICLE_GRAVITY: Vec3A = Vec3A::new(0.0, -2.0, 0.0);
+
+pub struct ParticleSystem {
+    pub particles: Vec<Particle>,
+    pub max_particles: usize,
+    pub particle_buf: gpu::BufferPiece,
+    pub pipeline: gpu::RenderPipeline,
+    pub enabled: bool,
+    pub emitter: Vec3A,
+    // NOTE: particles per second
+    pub spawn_rate: f32,
+    // NOTE: fractional particles carried over to the next frame
+    pub spawn_accum: f32,
+    pub rng: nanorand::WyRand,
+}
+
+impl ParticleSystem {
+    pub fn new(
+        ctx: &gpu::Context,
+        surface_format: gpu::TextureFormat,
+        max_particles: usize,
+        reverse_z: bool,
+    ) -> Self {
+        let shader = ctx.create_shader(gpu::ShaderDesc {
+            source: include_str!("particle_shader.wgsl"),
         });
-        let ao_view_i = ctx.create_texture_view(
-            ao_texture_i,
-            gpu::TextureViewDesc {
-                name: format!("ao view {i}").as_str(),
-                format: gpu::TextureFormat::Rgba32Float,
-                dimension: gpu::ViewDimension::D2,
-                subresources: &Default::default(),
+        // NOTE: tests against the g-buffer depth but doesn't write it, so particles are
+        // hidden by the scene but not by each other, blending relies on the cpu sort
+        let pipeline = ctx.create_render_pipeline(gpu::RenderPipelineDesc {
+            name: "particles",
+            data_layouts: &[&<ParticleParams as gpu::ShaderData>::layout()],
+            vertex: shader.at("vs_particle"),
+            vertex_fetches: &[gpu::VertexFetchState {
+                layout: &<ParticleInstance as gpu::Vertex>::layout(),
+                instanced: true,
+            }],
+            primitive: gpu::PrimitiveState {
+                topology: gpu::PrimitiveTopology::TriangleStrip,
+                front_face: gpu::FrontFace::Ccw,
+                cull_mode: None,
+                unclipped_depth: false,
+                wireframe: false,
             },
-        );
-        let ao_sampler_i = ctx.create_sampler(gpu::SamplerDesc {
-            name: format!("ao sampler {i}").as_str(),
-            address_modes: Default::default(),
-            mag_filter: gpu::FilterMode::Nearest,
-            min_filter: gpu::FilterMode::Nearest,
-            mipmap_filter: gpu::FilterMode::Nearest,
-            ..Default::default()
+            depth_stencil: Some(gpu::DepthStencilState {
+                format: gpu::TextureFormat::Depth32Float,
+                depth_write_enabled: false,
+                depth_compare: depth_compare(reverse_z),
+                stencil: Default::default(),
+                bias: gpu::DepthBiasState::default(),
+            }),
+            fragment: shader.at("fs_particle"),
+            color_targets: &[gpu::ColorTargetState {
+                format: surface_format,
+                blend: Some(gpu::BlendState::ALPHA_BLENDING),
+                write_mask: gpu::ColorWrites::default(),
+            }],
+        });
+        let particle_buf = ctx.create_buffer(gpu::BufferDesc {
+            name: "particle instances",
+            size: (max_particles * std::mem::size_of::<ParticleInstance>()) as u64,
+            memory: gpu::Memory::Shared,
         });
 
-        let ao_texture_stuff_i = TextureStuff {
-            texture: ao_texture_i,
-            view: ao_view_i,
-            sampler: ao_sampler_i,
-            size: extent_i,
-        };
+        Self {
+            particles: Vec::with_capacity(max_particles),
+            max_particles,
+            particle_buf: particle_buf.into(),
+            pipeline,
+            enabled: false,
+            emitter: Vec3A::ZERO,
+            spawn_rate: 200.0,
+            spawn_accum: 0.0,
+            rng: nanorand::WyRand::new(),
+        }
+    }
 
-        ao_textures.push(ao_texture_stuff_i);
+    // NOTE: small upward cone of random velocities, warm colors like sparks
+    fn spawn(&mut self) {
+        let angle = self.rng.generate::<f32>() * TAU;
+        let spread = 0.5 * self.rng.generate::<f32>();
+        let up = 2.0 + 2.0 * self.rng.generate::<f32>();
+        let velocity = Vec3A::new(spread * angle.cos(), up, spread * angle.sin());
+        let heat = self.rng.generate::<f32>();
+        self.particles.push(Particle {
+            position: self.emitter,
+            velocity,
+            lifetime: 1.5 + 1.5 * self.rng.generate::<f32>(),
+            size: 0.02 + 0.04 * self.rng.generate::<f32>(),
+            color: Vec4::new(1.0, 0.3 + 0.5 * heat, 0.1 * heat, 1.0),
+        });
+    }
 
-        //NOTE: ao texture after blur
-        let ao_blur_texture_i = ctx.create_texture(gpu::TextureDesc {
-            name: format!("ao blur texture {i}").as_str(),
-            format: gpu::TextureFormat::Rgba32Float,
-            size: extent_i,
-            array_layer_count: 1,
-            mip_level_count: 1,
-            dimension: gpu::TextureDimension::D2,
-            usage: gpu::TextureUsage::TARGET | gpu::TextureUsage::RESOURCE,
-        });
-        let ao_blur_view_i = ctx.create_texture_view(
-            ao_blur_texture_i,
-            gpu::TextureViewDesc {
-                name: format!("ao blur view {i}").as_str(),
-                format: gpu::TextureFormat::Rgba32Float,
-                dimension: gpu::ViewDimension::D2,
-                subresources: &Default::default(),
-            },
-        );
-        let ao_blur_sampler_i = ctx.create_sampler(gpu::SamplerDesc {
-            name: format!("ao blur sampler {i}").as_str(),
-            address_modes: Default::default(),
-            mag_filter: gpu::FilterMode::Nearest,
-            min_filter: gpu::FilterMode::Nearest,
-            mipmap_filter: gpu::FilterMode::Nearest,
-            ..Default::default()
-        });
+    // NOTE: euler step on the cpu, then sorted back to front and written to the shared
+    // instance buffer, the previous frame is always waited on so overwriting is fine
+    pub fn update(&mut self, ctx: &gpu::Context, dt: f32, camera: &Camera) {
+        if self.enabled {
+            self.spawn_accum += dt * self.spawn_rate;
+            while self.spawn_accum >= 1.0 && self.particles.len() < self.max_particles {
+                self.spawn();
+                self.spawn_accum -= 1.0;
+            }
+            // NOTE: don't build up a burst while the buffer is full
+            self.spawn_accum = self.spawn_accum.min(1.0);
+        }
 
-        let ao_blur_texture_stuff_i = TextureStuff {
-            texture: ao_blur_texture_i,
-            view: ao_blur_view_i,
-            sampler: ao_blur_sampler_i,
-            size: extent_i,
-        };
+        for p in self.particles.iter_mut() {
+            p.velocity += PARTICLE_GRAVITY * dt;
+            p.position += p.velocity * dt;
+            p.lifetime -= dt;
+        }
+        self.particles.retain(|p| p.lifetime > 0.0);
 
-        ao_textures_blur.push(ao_blur_texture_stuff_i);
+        let cam_pos = camera.pos;
+        self.particles.sort_by(|a, b| {
+            let da = a.position.distance_squared(cam_pos);
+            let db = b.position.distance_squared(cam_pos);
+            db.total_cmp(&da)
+        });
+
+        let instances = self
+            .particles
+            .iter()
+            .map(|p| ParticleInstance {
+                position: p.position.to_array(),
+                size: p.size,
+                // NOTE: fade out over the last second
+                color: (p.color * Vec4::new(1.0, 1.0, 1.0, p.lifetime.min(1.0))).to_array(),
+            })
+            .collect::<Vec<_>>();
+        if instances.is_empty() {
+            return;
+        }
+        unsafe {
+            let ptr = self
+                .particle_buf
+                .buffer
+                .data()
+                .add(self.particle_buf.offset as usize)
+                as *mut ParticleInstance;
+            std::ptr::copy_nonoverlapping(instances.as_ptr(), ptr, instances.len());
+        }
+        ctx.sync_buffer(self.particle_buf.buffer);
     }
 
-    let ao_dummy_texture = {
-        let dummy_extent = gpu::Extent {
-            width: 1,
-            height: 1,
-            depth: 1,
-        };
-        let ao_texture_dummy = ctx.create_texture(gpu::TextureDesc {
-            name: format!("ao texture dummy").as_str(),
-            format: gpu::TextureFormat::Rgba32Float,
-            size: dummy_extent,
-            array_layer_count: 1,
-            mip_level_count: 1,
-            dimension: gpu::TextureDimension::D2,
-            usage: gpu::TextureUsage::TARGET | gpu::TextureUsage::RESOURCE,
-        });
-        let ao_view_dummy = ctx.create_texture_view(
-            ao_texture_dummy,
-            gpu::TextureViewDesc {
-                name: format!("ao view dummy").as_str(),
-                format: gpu::TextureFormat::Rgba32Float,
-                dimension: gpu::ViewDimension::D2,
-                subresources: &Default::default(),
+    pub fn render(
+        &self,
+        encoder: &mut gpu::CommandEncoder,
+        target: gpu::TextureView,
+        depth: gpu::TextureView,
+        camera: &Camera,
+    ) {
+        if self.particles.is_empty() {
+            return;
+        }
+        let [right, _forward, up] = camera.right_forward_up();
+        if let mut particle_pass = encoder.render(
+            "particles",
+            gpu::RenderTargetSet {
+                colors: &[gpu::RenderTarget {
+                    view: target,
+                    init_op: gpu::InitOp::Load,
+                    finish_op: gpu::FinishOp::Store,
+                }],
+                depth_stencil: Some(gpu::RenderTarget {
+                    view: depth,
+                    init_op: gpu::InitOp::Load,
+                    finish_op: gpu::FinishOp::Store,
+                }),
             },
-        );
-        let ao_sampler_dummy = ctx.create_sampler(gpu::SamplerDesc {
-            name: format!("ao sampler dummy").as_str(),
-            address_modes: Default::default(),
-            mag_filter: gpu::FilterMode::Nearest,
-            min_filter: gpu::FilterMode::Nearest,
-            mipmap_filter: gpu::FilterMode::Nearest,
-            ..Default::default()
-        });
-        TextureStuff {
-            texture: ao_texture_dummy,
-            view: ao_view_dummy,
-            sampler: ao_sampler_dummy,
-            size: dummy_extent,
+        ) {
+            let mut rc = particle_pass.with(&self.pipeline);
+            rc.bind(
+                0,
+                &ParticleParams {
+                    globals: ParticleGlobals {
+                        view_proj: camera.vp().to_cols_array_2d(),
+                        cam_right: right.to_array(),
+                        pad0: 0,
+                        cam_up: up.to_array(),
+                        pad1: 0,
+                    },
+                },
+            );
+            rc.bind_vertex(0, self.particle_buf);
+            rc.draw(0, 4, 0, self.particles.len() as _);
         }
-    };
-
-    let downsample_textures = DownsampleTextures {
-        textures: depth_pos_normal_textures,
-    };
-    let ao_textures = AOTextures {
-        textures: ao_textures,
-        dummy_texture: ao_dummy_texture,
-        textures_after_blur: ao_textures_blur,
-    };
-
-    (downsample_textures, ao_textures)
-}
-
-pub struct Pipelines {
-    // pub shader_paths: Vec<std::path::Path>,
-    pub last_modified_shader_time: std::time::SystemTime,
-    pub geometry: gpu::RenderPipeline,
-    pub light: gpu::RenderPipeline,
-    pub depth_downsample: gpu::RenderPipeline,
-    pub calc_ao: gpu::RenderPipeline,
-    pub blur_ao: gpu::RenderPipeline,
-}
-
-pub fn last_time_shader_modified() -> std::time::SystemTime {
-    let geometry_shader_path = std::path::Path::new("src/shader.wgsl");
-    let light_shader_path = std::path::Path::new("src/light_shader.wgsl");
-
-    let mut t = std::time::SystemTime::UNIX_EPOCH;
-    if let Ok(t1) = geometry_shader_path.metadata() {
-        t = t.max(t1.modified().unwrap());
-    }
-    if let Ok(t2) = light_shader_path.metadata() {
-        t = t.max(t2.modified().unwrap());
     }
 
-    t
+    pub fn destroy(&mut self, ctx: &gpu::Context) {
+        ctx.destroy_buffer(self.particle_buf.buffer);
+    }
 }
 
-impl Pipelines {
-    pub fn create_pipelines(ctx: &gpu::Context, surface: &gpu::Surface) -> Option<Self> {
-        let geometry_shader_source = match std::fs::read_to_string("src/shader.wgsl") {
-            Ok(src) => src,
-            Err(err) => {
-                dbg!(err);
-                return None;
-            }
-        };
+// NOTE: the pixel plus one per kernel sample, see cs_ao_kernel_gizmos
+pub const NUM_AO_GIZMOS: usize = MAX_AO_SAMPLES + 1;
 
-        let geometry_shader = match ctx.try_create_shader(gpu::ShaderDesc {
-            source: &geometry_shader_source,
-        }) {
-            Ok(shader) => shader,
-            Err(err) => {
-                dbg!(err);
-                return None;
-            }
-        };
+// NOTE: debug points drawn with the particle shader on top of everything, without the
+// depth test since the kernel samples lie on the surfaces. The instances are written on
+// the gpu by render_ao_gizmos
+pub struct AoGizmos {
+    pub pipeline: gpu::RenderPipeline,
+    pub instance_buf: gpu::BufferPiece,
+}
 
-        // NOTE: pipeline
-        let geometry_pipeline = ctx.create_render_pipeline(gpu::RenderPipelineDesc {
-            name: "geometry",
-            data_layouts: &[&<GeometryParams as gpu::ShaderData>::layout()],
-            vertex: geometry_shader.at("vs_main"),
-            vertex_fetches: &[gpu::VertexFetchState {
-                layout: &<Vertex as gpu::Vertex>::layout(),
-                instanced: false,
-            }],
-            primitive: gpu::PrimitiveState {
-                topology: gpu::PrimitiveTopology::TriangleList,
-                front_face: gpu::FrontFace::Ccw,
-                cull_mode: Some(gpu::Face::Back),
-                unclipped_depth: false,
-                wireframe: false,
-            },
-            depth_stencil: Some(gpu::DepthStencilState {
-                format: gpu::TextureFormat::Depth32Float,
-                depth_write_enabled: true,
-                depth_compare: gpu::CompareFunction::Less,
-                stencil: Default::default(),
-                bias: gpu::DepthBiasState::default(),
-            }),
-            fragment: geometry_shader.at("fs_main"),
-            color_targets: &[
-                gpu::ColorTargetState {
-                    format: gpu::TextureFormat::Rgba32Float,
-                    blend: Some(gpu::BlendState::REPLACE),
-                    write_mask: gpu::ColorWrites::default(),
-                },
-                gpu::ColorTargetState {
-                    format: gpu::TextureFormat::Rgba32Float,
-                    blend: Some(gpu::BlendState::REPLACE),
-                    write_mask: gpu::ColorWrites::default(),
-                },
-            ],
+impl AoGizmos {
+    pub fn new(ctx: &gpu::Context, surface_format: gpu::TextureFormat) -> Self {
+        let shader = ctx.create_shader(gpu::ShaderDesc {
+            source: include_str!("particle_shader.wgsl"),
         });
-
-        let light_shader_source = match std::fs::read_to_string("src/light_shader.wgsl") {
-            Ok(src) => src,
-            Err(err) => {
-                dbg!(err);
-                return None;
-            }
-        };
-        let light_shader = match ctx.try_create_shader(gpu::ShaderDesc {
-            source: &light_shader_source,
-        }) {
-            Ok(shader) => shader,
-            Err(err) => {
-                dbg!(err);
-                return None;
-            }
-        };
-
-        let light_pipeline = ctx.create_render_pipeline(gpu::RenderPipelineDesc {
-            name: "light",
-            // data_layouts: &[&<Params as gpu::ShaderData>::layout()],
-            data_layouts: &[&<LightPassParams as gpu::ShaderData>::layout()],
-            vertex: light_shader.at("vs_main"),
+        let pipeline = ctx.create_render_pipeline(gpu::RenderPipelineDesc {
+            name: "ao gizmos",
+            data_layouts: &[&<ParticleParams as gpu::ShaderData>::layout()],
+            vertex: shader.at("vs_particle"),
             vertex_fetches: &[gpu::VertexFetchState {
-                layout: &<Vertex as gpu::Vertex>::layout(),
-                instanced: false,
+                layout: &<ParticleInstance as gpu::Vertex>::layout(),
+                instanced: true,
             }],
             primitive: gpu::PrimitiveState {
-                topology: gpu::PrimitiveTopology::TriangleList,
+                topology: gpu::PrimitiveTopology::TriangleStrip,
                 front_face: gpu::FrontFace::Ccw,
                 cull_mode: None,
                 unclipped_depth: false,
                 wireframe: false,
             },
             depth_stencil: None,
-            fragment: light_shader.at("fs_light"),
+            fragment: shader.at("fs_particle"),
             color_targets: &[gpu::ColorTargetState {
-                format: surface.info().format,
-                blend: Some(gpu::BlendState::REPLACE),
+                format: surface_format,
+                blend: Some(gpu::BlendState::ALPHA_BLENDING),
                 write_mask: gpu::ColorWrites::default(),
             }],
         });
+        let instance_buf = ctx.create_buffer(gpu::BufferDesc {
+            name: "ao gizmo instances",
+            size: (NUM_AO_GIZMOS * std::mem::size_of::<ParticleInstance>()) as u64,
+            memory: gpu::Memory::Device,
+        });
+        Self {
+            pipeline,
+            instance_buf: instance_buf.into(),
+        }
+    }
 
-        let depth_downsample_pipeline = ctx.create_render_pipeline(gpu::RenderPipelineDesc {
-            name: "depth downsample",
-            data_layouts: &[&<DepthPosNormalParams as gpu::ShaderData>::layout()],
-            vertex: light_shader.at("vs_main"),
-            vertex_fetches: &[gpu::VertexFetchState {
-                layout: &<Vertex as gpu::Vertex>::layout(),
-                instanced: false,
-            }],
-            primitive: gpu::PrimitiveState {
-                topology: gpu::PrimitiveTopology::TriangleList,
-                front_face: gpu::FrontFace::Ccw,
-                cull_mode: None,
-                unclipped_depth: false,
-                wireframe: false,
+    pub fn render(
+        &self,
+        encoder: &mut gpu::CommandEncoder,
+        target: gpu::TextureView,
+        camera: &Camera,
+    ) {
+        let [right, _forward, up] = camera.right_forward_up();
+        if let mut pass = encoder.render(
+            "ao gizmos",
+            gpu::RenderTargetSet {
+                colors: &[gpu::RenderTarget {
+                    view: target,
+                    init_op: gpu::InitOp::Load,
+                    finish_op: gpu::FinishOp::Store,
+                }],
+                depth_stencil: None,
             },
-            // depth_stencil: Some(gpu::DepthStencilState {
-            //     format: gpu::TextureFormat::Depth32Float,
-            //     depth_write_enabled: true,
-            //     depth_compare: gpu::CompareFunction::Always,
-            //     stencil: gpu::StencilState::default(),
-            //     bias: gpu::DepthBiasState::default(),
-            // }),
-            depth_stencil: None,
-            fragment: light_shader.at("fs_downsample"),
-            color_targets: &[
-                gpu::ColorTargetState {
-                    format: gpu::TextureFormat::Rgba32Float,
-                    blend: Some(gpu::BlendState::REPLACE),
-                    write_mask: gpu::ColorWrites::default(),
-                },
-                gpu::ColorTargetState {
-                    format: gpu::TextureFormat::Rgba32Float,
-                    blend: Some(gpu::BlendState::REPLACE),
-                    write_mask: gpu::ColorWrites::default(),
+        ) {
+            let mut rc = pass.with(&self.pipeline);
+            rc.bind(
+                0,
+                &ParticleParams {
+                    globals: ParticleGlobals {
+                        view_proj: camera.vp().to_cols_array_2d(),
+                        cam_right: right.to_array(),
+                        pad0: 0,
+                        cam_up: up.to_array(),
+                        pad1: 0,
+                    },
                 },
-            ],
-        });
+            );
+            rc.bind_vertex(0, self.instance_buf);
+            rc.draw(0, 4, 0, NUM_AO_GIZMOS as _);
+        }
+    }
 
-        let ao_pipeline = ctx.create_render_pipeline(gpu::RenderPipelineDesc {
-            name: "ao",
-            // TODO: fix daat layot
-            data_layouts: &[&<CalcAoParams as gpu::ShaderData>::layout()],
-            // data_layouts: &[],
-            vertex: light_shader.at("vs_main"),
+    pub fn destroy(&mut self, ctx: &gpu::Context) {
+        ctx.destroy_buffer(self.instance_buf.buffer);
+    }
+}
+
+#[repr(C)]
+#[derive(blade_macros::Vertex, Clone, Copy, Debug, Pod, Zeroable)]
+pub struct DebugVertex {
+    pub position: [f32; 3],
+    pub color: [f32; 4],
+}
+
+#[derive(blade_macros::ShaderData)]
+pub struct DebugDrawParams {
+    pub view_proj: [[f32; 4]; 4],
+}
+
+// NOTE: immediate mode lines for debugging 3d math, anything can queue world space lines
+// during a frame. flush copies them to the shared vertex buffer and clears the list, lines
+// queued after that wait for the next frame. Past max_lines they are dropped
+pub struct DebugDraw {
+    pub lines: Vec<(Vec3A, Vec3A, [f32; 4])>,
+    pub vertex_buf: gpu::Buffer,
+    pub max_lines: usize,
+    pub pipeline: gpu::RenderPipeline,
+    // NOTE: lines in vertex_buf since the last flush
+    pub num_flushed: usize,
+}
+
+impl DebugDraw {
+    pub fn new(
+        ctx: &gpu::Context,
+        surface_format: gpu::TextureFormat,
+        max_lines: usize,
+        reverse_z: bool,
+    ) -> Self {
+        let shader = ctx.create_shader(gpu::ShaderDesc {
+            source: include_str!("debug_draw.wgsl"),
+        });
+        // NOTE: tests against the g-buffer depth without writing it, like the particles
+        let pipeline = ctx.create_render_pipeline(gpu::RenderPipelineDesc {
+            name: "debug draw",
+            data_layouts: &[&<DebugDrawParams as gpu::ShaderData>::layout()],
+            vertex: shader.at("vs_debug_draw"),
             vertex_fetches: &[gpu::VertexFetchState {
-                layout: &<Vertex as gpu::Vertex>::layout(),
+                layout: &<DebugVertex as gpu::Vertex>::layout(),
                 instanced: false,
             }],
             primitive: gpu::PrimitiveState {
-                topology: gpu::PrimitiveTopology::TriangleList,
+                topology: gpu::PrimitiveTopology::LineList,
                 front_face: gpu::FrontFace::Ccw,
                 cull_mode: None,
                 unclipped_depth: false,
                 wireframe: false,
             },
-            depth_stencil: None,
-            fragment: light_shader.at("fs_calc_ao"),
+            depth_stencil: Some(gpu::DepthStencilState {
+                format: gpu::TextureFormat::Depth32Float,
+                depth_write_enabled: false,
+                depth_compare: depth_compare_or_equal(reverse_z),
+                stencil: Default::default(),
+                bias: gpu::DepthBiasState::default(),
+            }),
+            fragment: shader.at("fs_debug_draw"),
             color_targets: &[gpu::ColorTargetState {
-                format: gpu::TextureFormat::Rgba32Float,
-                blend: Some(gpu::BlendState::REPLACE),
+                format: surface_format,
+                blend: Some(gpu::BlendState::ALPHA_BLENDING),
                 write_mask: gpu::ColorWrites::default(),
             }],
         });
+        let vertex_buf = ctx.create_buffer(gpu::BufferDesc {
+            name: "debug draw lines",
+            size: (2 * max_lines * std::mem::size_of::<DebugVertex>()) as u64,
+            memory: gpu::Memory::Shared,
+        });
+        Self {
+            lines: vec![],
+            vertex_buf,
+            max_lines,
+            pipeline,
+            num_flushed: 0,
+        }
+    }
 
-        let ao_blur = ctx.create_render_pipeline(gpu::RenderPipelineDesc {
-            name: "ao blur",
-            data_layouts: &[&<BlurParams as gpu::ShaderData>::layout()],
-            vertex: light_shader.at("vs_main"),
-            vertex_fetches: &[gpu::VertexFetchState {
-                layout: &<Vertex as gpu::Vertex>::layout(),
-                instanced: false,
-            }],
-            primitive: gpu::PrimitiveState {
-                topology: gpu::PrimitiveTopology::TriangleList,
-                front_face: gpu::FrontFace::Ccw,
-                cull_mode: None,
-                unclipped_depth: false,
-                wireframe: false,
-            },
-            depth_stencil: None,
-            fragment: light_shader.at("fs_blur_ao"),
-            color_targets: &[gpu::ColorTargetState {
-                format: gpu::TextureFormat::Rgba32Float,
-                blend: Some(gpu::BlendState::REPLACE),
-                write_mask: gpu::ColorWrites::default(),
-            }],
-        });
+    pub fn line(&mut self, a: Vec3A, b: Vec3A, color: [f32; 4]) {
+        self.lines.push((a, b, color));
+    }
 
-        // NOTE: compute-pipelines
-        let compute_shader_cource = match std::fs::read_to_string("src/compute.wgsl") {
-            Ok(src) => src,
-            Err(err) => {
-                dbg!(err);
-                return None;
-            }
+    pub fn aabb(&mut self, min: Vec3A, max: Vec3A, color: [f32; 4]) {
+        let corner = |i: usize| {
+            Vec3A::new(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            )
         };
+        self.box_edges(corner, color);
+    }
 
-        let compute_shader = match ctx.try_create_shader(gpu::ShaderDesc {
-            source: &compute_shader_cource,
-        }) {
-            Ok(shader) => shader,
-            Err(err) => {
-                dbg!(err);
-                return None;
-            }
+    // NOTE: without the taa jitter, corners from the inverse vp. Clip z is 0..1 for both
+    // depth directions, reverse z only swaps which end is near
+    pub fn frustum(&mut self, camera: &Camera, color: [f32; 4]) {
+        let inv_vp = camera.unjittered_vp().inverse();
+        let corner = |i: usize| {
+            let ndc = glam::Vec3::new(
+                if i & 1 == 0 { -1.0 } else { 1.0 },
+                if i & 2 == 0 { -1.0 } else { 1.0 },
+                if i & 4 == 0 { 0.0 } else { 1.0 },
+            );
+            Vec3A::from(inv_vp.project_point3(ndc))
         };
-
-        let pipeline = ctx.create_compute_pipeline(gpu::ComputePipelineDesc {
-            name: "compute pipeline",
-            data_layouts: &[],
-            compute: compute_shader.at("main"),
-        });
-
-        let last_modified = last_time_shader_modified();
-        // let metadata = std::fs::Metadata:
-        Some(Self {
-            geometry: geometry_pipeline,
-            light: light_pipeline,
-            depth_downsample: depth_downsample_pipeline,
-            last_modified_shader_time: last_modified,
-            calc_ao: ao_pipeline,
-            blur_ao: ao_blur,
-        })
+        self.box_edges(corner, color);
     }
-}
 
-pub struct State {
-    pub delta_time: f32,
-    pub prev_time: std::time::SystemTime,
-    pub pipelines: Pipelines,
-    pub command_encoder: gpu::CommandEncoder,
-    pub ctx: gpu::Context,
-    pub surface: gpu::Surface,
-    pub prev_sync_point: Option<gpu::SyncPoint>,
-    pub meshes: Vec<Mesh>,
-    pub camera: Camera,
-    pub retained_input: RetainedInput,
-    pub screen_quad_buf: gpu::BufferPiece,
-    pub downsample_textures: DownsampleTextures,
-    pub ao_textures: AOTextures,
-    pub input_state: InputState,
-    pub mesh_to_draw: usize,
-}
+    // NOTE: three great circles, one around each axis
+    pub fn sphere(&mut self, center: Vec3A, radius: f32, color: [f32; 4], segments: usize) {
+        let segments = segments.max(3);
+        for [u, v] in [
+            [Vec3A::X, Vec3A::Y],
+            [Vec3A::Y, Vec3A::Z],
+            [Vec3A::Z, Vec3A::X],
+        ] {
+            let point = |i: usize| {
+                let angle = TAU * i as f32 / segments as f32;
+                center + radius * (angle.cos() * u + angle.sin() * v)
+            };
+            for i in 0..segments {
+                self.line(point(i), point(i + 1), color);
+            }
+        }
+    }
 
-#[derive(Default)]
-pub struct RetainedInput {
-    pub just_pressed_keys: std::collections::HashSet<winit::keyboard::KeyCode>,
-    pub held_keys: std::collections::HashSet<winit::keyboard::KeyCode>,
-}
+    // NOTE: corner i has bit 0 for x, bit 1 for y and bit 2 for z, edges join corners
+    // differing in one bit
+    fn box_edges(&mut self, corner: impl Fn(usize) -> Vec3A, color: [f32; 4]) {
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.line(corner(i), corner(i | bit), color);
+                }
+            }
+        }
+    }
 
-impl State {
-    pub fn new(window: &winit::window::Window) -> Self {
-        let ctx = unsafe {
-            gpu::Context::init(gpu::ContextDesc {
-                presentation: true,
-                validation: true,
-                timing: false,
-                capture: false,
-                overlay: true,
-                device_id: 0,
+    pub fn flush(&mut self, ctx: &gpu::Context) {
+        if self.lines.len() > self.max_lines {
+            dbg!(format!(
+                "dropping {} debug lines past {}",
+                self.lines.len() - self.max_lines,
+                self.max_lines
+            ));
+        }
+        let vertices = self
+            .lines
+            .drain(..)
+            .take(self.max_lines)
+            .flat_map(|(a, b, color)| {
+                [a, b].map(|p| DebugVertex {
+                    position: p.to_array(),
+                    color,
+                })
             })
-            .unwrap()
-        };
-        let size = window.inner_size();
-        let width = size.width;
-        let height = size.height;
-
-        let screen_extent = gpu::Extent {
-            width,
-            height,
-            depth: 1,
-        };
+            .collect::<Vec<_>>();
+        self.num_flushed = vertices.len() / 2;
+        if vertices.is_empty() {
+            return;
+        }
+        unsafe {
+            let ptr = self.vertex_buf.data() as *mut DebugVertex;
+            std::ptr::copy_nonoverlapping(vertices.as_ptr(), ptr, vertices.len());
+        }
+        ctx.sync_buffer(self.vertex_buf);
+    }
 
-        let aspect = width as f32 / height as f32;
-        let surface = ctx
-            .create_surface_configured(
-                window,
-                gpu::SurfaceConfig {
-                    size: screen_extent,
-                    usage: gpu::TextureUsage::TARGET,
-                    display_sync: gpu::DisplaySync::Recent,
-                    ..Default::default()
+    pub fn render(
+        &self,
+        encoder: &mut gpu::CommandEncoder,
+        target: gpu::TextureView,
+        depth: gpu::TextureView,
+        camera: &Camera,
+    ) {
+        if self.num_flushed == 0 {
+            return;
+        }
+        if let mut pass = encoder.render(
+            "debug draw",
+            gpu::RenderTargetSet {
+                colors: &[gpu::RenderTarget {
+                    view: target,
+                    init_op: gpu::InitOp::Load,
+                    finish_op: gpu::FinishOp::Store,
+                }],
+                depth_stencil: Some(gpu::RenderTarget {
+                    view: depth,
+                    init_op: gpu::InitOp::Load,
+                    finish_op: gpu::FinishOp::Store,
+                }),
+            },
+        ) {
+            let mut rc = pass.with(&self.pipeline);
+            rc.bind(
+                0,
+                &DebugDrawParams {
+                    view_proj: camera.vp().to_cols_array_2d(),
                 },
-            )
-            .unwrap();
+            );
+            rc.bind_vertex(0, self.vertex_buf.into());
+            rc.draw(0, 2 * self.num_flushed as u32, 0, 1);
+        }
+    }
 
-        let mut meshes = vec![];
+    pub fn destroy(&self, ctx: &gpu::Context) {
+        ctx.destroy_buffer(self.vertex_buf);
+    }
+}
 
-        let command_encoder = ctx.create_command_encoder(gpu::CommandEncoderDesc {
-            name: "main",
-            buffer_count: 1,
-        });
+pub struct Mesh {
+    // NOTE: same as the CpuMesh name, also used for the gpu buffer names
+    pub name: String,
+    pub vertex_buf: gpu::BufferPiece,
+    pub index_buf: Option<gpu::BufferPiece>,
+    pub num_vertices: usize,
+    pub num_indices: usize,
+    pub materials: Vec<GpuMaterial>,
+    // NOTE: diffuse textures of all materials as array layers, None if no material has one
+    pub material_textures: Option<TextureStuff>,
+    // NOTE: one draw call per run of faces sharing a material, in index order
+    pub draws: Vec<MeshDraw>,
+    // NOTE: the vertex buffer has per vertex normals from the file, shade with those
+    // instead of the screen space derivative normal
+    pub vertex_normals: bool,
+    // NOTE: model space bounding sphere, its projected size picks the lod
+    pub bounds_center: Vec3A,
+    pub bounds_radius: f32,
+    // NOTE: model space, also frames the camera on a model from the command line
+    pub aabb: Aabb,
+    // NOTE: simplified version drawn instead when the mesh is small enough on screen
+    pub lod: Option<Box<Mesh>>,
+    // NOTE: triangles for picking with the mouse, None for lods and until the loader's
+    // one is attached, see Uploader::queue
+    pub bvh: Option<Bvh>,
+}
 
-        let sponza = load_sponza();
-        let sibenik_cathedral = load_cathedral();
+impl Mesh {
+    // NOTE: the lod shares the material textures of the full mesh, only its own buffers
+    // are destroyed with it
+    pub fn destroy(&self, ctx: &gpu::Context) {
+        self.destroy_buffers(ctx);
+        if let Some(textures) = self.material_textures {
+            ctx.destroy_texture_view(textures.view);
+            ctx.destroy_sampler(textures.sampler);
+            ctx.destroy_texture(textures.texture);
+        }
+    }
 
-        let sibenic_mesh = upload_mesh(&ctx, sibenik_cathedral);
+    fn destroy_buffers(&self, ctx: &gpu::Context) {
+        ctx.destroy_buffer(self.vertex_buf.buffer);
+        if let Some(index_buf) = self.index_buf {
+            ctx.destroy_buffer(index_buf.buffer);
+        }
+        if let Some(lod) = self.lod.as_deref() {
+            lod.destroy_buffers(ctx);
+        }
+    }
 
-        // dbg!(a);
-        // let gpu_sponza = upload_vertices(sponza_vertices, &ctx);
-        // let gpu_vertices = upload_vertices(vertices, &ctx);
-        let sponza_mesh = upload_mesh(&ctx, sponza);
-        meshes.push(sibenic_mesh);
-        meshes.push(sponza_mesh);
+    // NOTE: height of the bounding sphere on screen as a fraction of the viewport height,
+    // 1 or more with the camera inside of it. transform is the one of the node drawing the
+    // mesh, lod_screen_size the size below which the lod is drawn
+    pub fn select_lod(
+        &self,
+        transform: &Transform,
+        camera: &Camera,
+        lod_screen_size: Option<f32>,
+    ) -> &Mesh {
+        let (Some(lod), Some(lod_screen_size)) = (self.lod.as_deref(), lod_screen_size) else {
+            return self;
+        };
+        if self.screen_size(transform, camera) < lod_screen_size {
+            lod
+        } else {
+            self
+        }
+    }
 
-        // let g_buffer = GBuffer::new(&ctx, width, height);
+    pub fn screen_size(&self, transform: &Transform, camera: &Camera) -> f32 {
+        let center = Vec3A::from(
+            transform
+                .to_mat4()
+                .transform_point3(self.bounds_center.into()),
+        );
+        let radius = self.bounds_radius * transform.scale.abs().max_element();
+        let distance = center.distance(camera.pos);
+        if distance <= radius {
+            return f32::MAX;
+        }
+        radius / (distance * (0.5 * camera.vfov_rad).tan())
+    }
 
-        let screen_size = gpu::Extent {
-            width,
-            height,
-            depth: 1,
-        };
+    pub fn num_triangles(&self) -> usize {
+        self.draws.iter().map(|draw| draw.count / 3).sum()
+    }
+}
 
-        let (downsample_textures, ao_textures) =
-            create_downsample_and_ao_textures(&ctx, screen_size);
-
-        let screen_quad_vertices = [
-            vec3(-1.0, -1.0, 0.0),
-            vec3(1.0, -1.0, 0.0),
-            vec3(-1.0, 1.0, 0.0),
-            vec3(1.0, -1.0, 0.0),
-            vec3(1.0, 1.0, 0.0),
-            vec3(-1.0, 1.0, 0.0),
-        ]
-        .map(|a| Vertex {
-            ws_pos: a.to_array(),
-            ws_normal: Default::default(),
-        });
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3A,
+    pub max: Vec3A,
+}
 
-        let screen_quad_buf = ctx.create_buffer(gpu::BufferDesc {
-            name: "screen quad buf",
-            size: (screen_quad_vertices.len() * std::mem::size_of::<Vertex>()) as u64,
-            memory: gpu::Memory::Shared,
-        });
-        unsafe {
-            std::ptr::copy_nonoverlapping(
-                screen_quad_vertices.as_ptr(),
-                screen_quad_buf.data() as *mut Vertex,
-                screen_quad_vertices.len(),
-            );
+impl Aabb {
+    // NOTE: inside out, the first point grown into it becomes the whole box
+    pub const EMPTY: Aabb = Aabb {
+        min: Vec3A::MAX,
+        max: Vec3A::MIN,
+    };
+
+    // NOTE: a point at the origin for no positions, so center and size stay finite
+    pub fn from_points(positions: &[Vec3A]) -> Self {
+        if positions.is_empty() {
+            return Self {
+                min: Vec3A::ZERO,
+                max: Vec3A::ZERO,
+            };
         }
-        ctx.sync_buffer(screen_quad_buf);
+        let min = positions
+            .iter()
+            .copied()
+            .fold(Vec3A::splat(f32::MAX), Vec3A::min);
+        let max = positions
+            .iter()
+            .copied()
+            .fold(Vec3A::splat(f32::MIN), Vec3A::max);
+        Self { min, max }
+    }
 
-        // let depth_textures = create_depth_textures(&ctx, screen_extent);
+    pub fn center(&self) -> Vec3A {
+        0.5 * (self.min + self.max)
+    }
 
-        let pipelines = Pipelines::create_pipelines(&ctx, &surface).unwrap();
+    pub fn size(&self) -> Vec3A {
+        self.max - self.min
+    }
 
-        let input_state = InputState {
-            ao_level: 0,
-            use_blur: false,
-        };
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
 
+    pub fn grow(&self, p: Vec3A) -> Aabb {
         Self {
-            command_encoder,
-            ctx,
-            surface,
-            prev_sync_point: None,
-            meshes,
-            camera: Camera::default_from_aspect(aspect),
-            retained_input: Default::default(),
-            screen_quad_buf: screen_quad_buf.into(),
-            pipelines,
-            downsample_textures,
-            ao_textures,
-            input_state,
-            delta_time: 0.1,
-            prev_time: std::time::SystemTime::now(),
-            mesh_to_draw: 0,
+            min: self.min.min(p),
+            max: self.max.max(p),
         }
     }
 
-    pub fn render_downsample(&mut self) {
-        for i in 1..NUM_AO_TEXTURES {
-            let textures_from = &self.downsample_textures.textures[i - 1];
-            let textures_to = &self.downsample_textures.textures[i];
+    // NOTE: 0 for EMPTY
+    pub fn surface_area(&self) -> f32 {
+        let s = self.size().max(Vec3A::ZERO);
+        2.0 * (s.x * s.y + s.y * s.z + s.z * s.x)
+    }
 
-            if let mut depth_downsample_pass = self.command_encoder.render(
-                format!("depth downsample {i}").as_str(),
-                gpu::RenderTargetSet {
-                    colors: &[
-                        gpu::RenderTarget {
-                            view: textures_to.pos.view,
-                            init_op: gpu::InitOp::Clear(gpu::TextureColor::White),
-                            finish_op: gpu::FinishOp::Store,
-                        },
-                        gpu::RenderTarget {
-                            view: textures_to.normal.view,
-                            init_op: gpu::InitOp::Clear(gpu::TextureColor::White),
-                            finish_op: gpu::FinishOp::Store,
-                        },
-                    ],
-                    // depth_stencil: Some(gpu::RenderTarget {
-                    //     view: textures_to.depth.view,
-                    //     init_op: gpu::InitOp::Clear(gpu::TextureColor::White),
-                    //     finish_op: gpu::FinishOp::Store,
-                    // }),
-                    depth_stencil: None,
-                },
-            ) {
-                let mut rc = depth_downsample_pass.with(&self.pipelines.depth_downsample);
-                rc.bind(
-                    0,
-                    &DepthPosNormalParams {
-                        // depth_view: textures_from.depth.view,
-                        // depth_sampler: textures_from.depth.sampler,
-                        pos_view: textures_from.pos.view,
-                        pos_sampler: textures_from.pos.sampler,
-                        normal_view: textures_from.normal.view,
-                        normal_sampler: textures_from.normal.sampler,
-                        globals: Globals {
-                            mvp_transform: self.camera.vp().to_cols_array_2d(),
-                            mv_transform: self.camera.view().to_cols_array_2d(),
-                            mv_rot: self.camera.view_rot_only().to_cols_array_2d(),
-                            cam_pos: self.camera.pos.to_array(),
-                            cam_dir: self.camera.right_forward_up()[1].to_array(),
-                            pad: [0; 2],
-                        },
-                    },
+    // NOTE: aabb around the 8 transformed corners, so it grows under rotation
+    pub fn transformed(&self, mat: &Mat4) -> Aabb {
+        let corners = (0..8)
+            .map(|i| {
+                let corner = Vec3A::new(
+                    if i & 1 == 0 { self.min.x } else { self.max.x },
+                    if i & 2 == 0 { self.min.y } else { self.max.y },
+                    if i & 4 == 0 { self.min.z } else { self.max.z },
                 );
-                rc.bind_vertex(0, self.screen_quad_buf);
-                let num_quad_vertices = 6;
-                rc.draw(0, num_quad_vertices as _, 0, 1);
-            }
-        }
+                mat.transform_point3a(corner)
+            })
+            .collect::<Vec<_>>();
+        Aabb::from_points(&corners)
     }
 
-    pub fn render_calc_ao(&mut self) {
-        for i in (0..NUM_AO_TEXTURES).rev() {
-            let ao_target = &self.ao_textures.textures[i];
-            let is_first_pass = i == NUM_AO_TEXTURES - 1;
+    // NOTE: only the corner furthest along the plane normal has to be checked, a box
+    // touching the plane counts as inside
+    pub fn outside_plane(&self, plane: &Plane) -> bool {
+        let corner = Vec3A::select(plane.normal.cmpge(Vec3A::ZERO), self.max, self.min);
+        plane.distance(corner) < 0.0
+    }
 
-            // NOTE: calc ao pass
-            if let mut calc_ao_pass = self.command_encoder.render(
-                format!("calc ao {i}").as_str(),
-                gpu::RenderTargetSet {
-                    colors: &[gpu::RenderTarget {
-                        view: ao_target.view,
-                        init_op: gpu::InitOp::Clear(gpu::TextureColor::White),
-                        finish_op: gpu::FinishOp::Store,
-                    }],
-                    depth_stencil: None,
-                },
-            ) {
-                // NOTE: these textures have same size as render target
-                let dnp = &self.downsample_textures.textures[i];
-                let prev_dnp = &self.downsample_textures.textures[(i + 1).min(NUM_AO_TEXTURES - 1)];
-                let prev_ao_blur =
-                    &self.ao_textures.textures_after_blur[(i + 1).min(NUM_AO_TEXTURES - 1)];
-                let mut rc = calc_ao_pass.with(&self.pipelines.calc_ao);
+    // NOTE: conservative, a box outside the frustum but not fully outside a single plane
+    // near a frustum corner still counts as inside
+    pub fn in_frustum(&self, planes: &[Plane; 6]) -> bool {
+        !planes.iter().any(|plane| self.outside_plane(plane))
+    }
+}
 
-                rc.bind(
-                    0,
-                    &CalcAoParams {
-                        pos_view: dnp.pos.view,
-                        pos_sampler: dnp.pos.sampler,
+// NOTE: points with a positive distance are on the side the normal points to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Plane {
+    pub normal: Vec3A,
+    pub d: f32,
+}
 
-                        normal_view: dnp.normal.view,
-                        normal_sampler: dnp.normal.sampler,
+impl Plane {
+    // NOTE: from a + b*x + c*y + d*z >= 0, normalized so distance is in world units
+    pub fn from_coefficients(coefficients: Vec4) -> Self {
+        let normal = Vec3A::from(coefficients.truncate());
+        let len = normal.length().max(f32::EPSILON);
+        Self {
+            normal: normal / len,
+            d: coefficients.w / len,
+        }
+    }
 
-                        prev_pos_view: prev_dnp.pos.view,
-                        prev_pos_sampler: prev_dnp.pos.sampler,
+    pub fn distance(&self, p: Vec3A) -> f32 {
+        self.normal.dot(p) + self.d
+    }
+}
 
-                        prev_normal_view: prev_dnp.normal.view,
-                        prev_normal_sampler: prev_dnp.normal.sampler,
+// NOTE: direction is not normalized after a transform, t then stays the same along it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Vec3A,
+    pub direction: Vec3A,
+}
+
+impl Ray {
+    pub fn at(&self, t: f32) -> Vec3A {
+        self.origin + t * self.direction
+    }
+
+    pub fn transformed(&self, mat: &Mat4) -> Self {
+        Self {
+            origin: Vec3A::from(mat.transform_point3(self.origin.into())),
+            direction: Vec3A::from(mat.transform_vector3(self.direction.into())),
+        }
+    }
+}
+
+// NOTE: screen_pos and viewport in the same pixels, 0,0 top left. World space ray from the
+// camera through the pixel, without the taa jitter
+pub fn screen_to_ray(screen_pos: Vec2, viewport: Vec2, camera: &Camera) -> Ray {
+    let uv = screen_pos / viewport.max(Vec2::ONE);
+    let ndc = Vec2::new(2.0 * uv.x - 1.0, 1.0 - 2.0 * uv.y);
+    // NOTE: any depth between the planes lies on the same ray, like in skybox.wgsl
+    let p = camera
+        .unjittered_vp()
+        .inverse()
+        .project_point3(glam::Vec3::new(ndc.x, ndc.y, 0.5));
+    Ray {
+        origin: camera.pos,
+        direction: (Vec3A::from(p) - camera.pos).normalize(),
+    }
+}
+
+// NOTE: slab test, t where the ray enters the box, 0 if it starts inside
+pub fn ray_aabb_intersect(ray: &Ray, aabb_min: Vec3A, aabb_max: Vec3A) -> Option<f32> {
+    let inv_dir = ray.direction.recip();
+    let t0 = (aabb_min - ray.origin) * inv_dir;
+    let t1 = (aabb_max - ray.origin) * inv_dir;
+    let t_enter = t0.min(t1).max_element().max(0.0);
+    let t_exit = t0.max(t1).min_element();
+    (t_enter <= t_exit).then_some(t_enter)
+}
+
+// NOTE: moller-trumbore, both sides of the triangle count
+pub fn ray_triangle_intersect(ray: &Ray, [a, b, c]: [Vec3A; 3]) -> Option<f32> {
+    let e1 = b - a;
+    let e2 = c - a;
+    let p = ray.direction.cross(e2);
+    let det = e1.dot(p);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let s = ray.origin - a;
+    let u = s.dot(p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(e1);
+    let v = ray.direction.dot(q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = e2.dot(q) * inv_det;
+    (t > 0.0).then_some(t)
+}
+
+// NOTE: nearest hit as triangle index and t, testing every triangle. Without indices every
+// three positions are a triangle, like the vertex buffer of a mesh without an index buffer
+pub fn ray_mesh_intersect(ray: &Ray, positions: &[Vec3A], indices: &[u32]) -> Option<(usize, f32)> {
+    let triangle = |i: usize| -> [Vec3A; 3] {
+        if indices.is_empty() {
+            [positions[3 * i], positions[3 * i + 1], positions[3 * i + 2]]
+        } else {
+            let [a, b, c] = [0, 1, 2].map(|k| indices[3 * i + k] as usize);
+            [positions[a], positions[b], positions[c]]
+        }
+    };
+    let num_triangles = if indices.is_empty() {
+        positions.len() / 3
+    } else {
+        indices.len() / 3
+    };
+    (0..num_triangles)
+        .filter_map(|i| Some((i, ray_triangle_intersect(ray, triangle(i))?)))
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+}
+
+// NOTE: nodes with more triangles are split if the sah finds a cheaper split
+pub const BVH_LEAF_SIZE: usize = 4;
+pub const BVH_SAH_BINS: usize = 12;
+
+#[derive(Clone, Copy, Debug)]
+pub struct BvhNode {
+    pub aabb_min: Vec3A,
+    pub aabb_max: Vec3A,
+    // NOTE: children of an inner node, unused in leaves
+    pub left: u32,
+    pub right: u32,
+    // NOTE: range in Bvh::triangles, tri_count is 0 for inner nodes
+    pub first_tri: u32,
+    pub tri_count: u32,
+}
+
+// NOTE: aabb tree over the triangles of a mesh for picking, nodes[0] is the root. Built on
+// the loader thread, model space like the mesh
+#[derive(Clone, Debug, Default)]
+pub struct Bvh {
+    pub nodes: Vec<BvhNode>,
+    // NOTE: corners in leaf order, tri_ids maps them back to triangles of the mesh
+    pub triangles: Vec<[Vec3A; 3]>,
+    pub tri_ids: Vec<u32>,
+}
+
+impl Bvh {
+    pub fn new(vertices: &[Vec3A], indices: &[usize]) -> Bvh {
+        let triangles = indices
+            .chunks_exact(3)
+            .map(|t| [vertices[t[0]], vertices[t[1]], vertices[t[2]]])
+            .collect::<Vec<_>>();
+        let centroids = triangles
+            .iter()
+            .map(|[a, b, c]| (*a + *b + *c) / 3.0)
+            .collect::<Vec<_>>();
+        let mut tri_ids = (0..triangles.len() as u32).collect::<Vec<_>>();
+        let leaf = |tri_ids: &[u32], first: usize, count: usize| {
+            let aabb = tri_ids[first..first + count]
+                .iter()
+                .flat_map(|id| triangles[*id as usize])
+                .fold(Aabb::EMPTY, |aabb, p| aabb.grow(p));
+            BvhNode {
+                aabb_min: aabb.min,
+                aabb_max: aabb.max,
+                left: 0,
+                right: 0,
+                first_tri: first as u32,
+                tri_count: count as u32,
+            }
+        };
+
+        let mut nodes = vec![];
+        let mut stack = vec![];
+        if !triangles.is_empty() {
+            nodes.push(leaf(&tri_ids, 0, triangles.len()));
+            stack.push(0);
+        }
+        while let Some(node_i) = stack.pop() {
+            let node: BvhNode = nodes[node_i];
+            let (first, count) = (node.first_tri as usize, node.tri_count as usize);
+            if count <= BVH_LEAF_SIZE {
+                continue;
+            }
+            let ids = &mut tri_ids[first..first + count];
+            let bounds = Aabb {
+                min: node.aabb_min,
+                max: node.aabb_max,
+            };
+            let Some((axis, split)) = bvh_sah_split(&triangles, &centroids, ids, &bounds) else {
+                continue;
+            };
+            let mut mid = 0;
+            for i in 0..ids.len() {
+                if centroids[ids[i] as usize][axis] < split {
+                    ids.swap(i, mid);
+                    mid += 1;
+                }
+            }
+            // NOTE: all centroids on one side, can happen through rounding at the bin edge
+            if mid == 0 || mid == count {
+                continue;
+            }
+            let left = nodes.len();
+            nodes.push(leaf(&tri_ids, first, mid));
+            nodes.push(leaf(&tri_ids, first + mid, count - mid));
+            nodes[node_i].left = left as u32;
+            nodes[node_i].right = left as u32 + 1;
+            nodes[node_i].tri_count = 0;
+            stack.push(left);
+            stack.push(left + 1);
+        }
+
+        let triangles = tri_ids.iter().map(|id| triangles[*id as usize]).collect();
+        Bvh {
+            nodes,
+            triangles,
+            tri_ids,
+        }
+    }
+
+    // NOTE: nearest hit as triangle index of the mesh and t, subtrees the ray enters
+    // further away than the nearest hit so far are skipped
+    pub fn intersect(&self, ray: &Ray) -> Option<(usize, f32)> {
+        let mut nearest: Option<(usize, f32)> = None;
+        let mut stack = vec![];
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(node_i) = stack.pop() {
+            let node: &BvhNode = &self.nodes[node_i as usize];
+            let best = nearest.map_or(f32::MAX, |(_, t)| t);
+            match ray_aabb_intersect(ray, node.aabb_min, node.aabb_max) {
+                Some(t) if t < best => {}
+                _ => continue,
+            }
+            if node.tri_count == 0 {
+                stack.push(node.left);
+                stack.push(node.right);
+                continue;
+            }
+            // NOTE: the corners of a leaf are consecutive, three positions per triangle
+            let first = node.first_tri as usize;
+            let leaf = &self.triangles[first..first + node.tri_count as usize];
+            if let Some((i, t)) = ray_mesh_intersect(ray, leaf.as_flattened(), &[]) {
+                if t < nearest.map_or(f32::MAX, |(_, t)| t) {
+                    nearest = Some((self.tri_ids[first + i] as usize, t));
+                }
+            }
+        }
+        nearest
+    }
+}
+
+// NOTE: binned surface area heuristic over the triangle centroids, axis and position of
+// the cheapest split or None if keeping the leaf is cheaper
+fn bvh_sah_split(
+    triangles: &[[Vec3A; 3]],
+    centroids: &[Vec3A],
+    ids: &[u32],
+    bounds: &Aabb,
+) -> Option<(usize, f32)> {
+    let centroid_bounds = ids
+        .iter()
+        .fold(Aabb::EMPTY, |aabb, id| aabb.grow(centroids[*id as usize]));
+    let extent = centroid_bounds.size();
+    let leaf_cost = bounds.surface_area() * ids.len() as f32;
+    let mut best: Option<(usize, f32, f32)> = None;
+    for axis in 0..3 {
+        if extent[axis] <= 0.0 {
+            continue;
+        }
+        let scale = BVH_SAH_BINS as f32 / extent[axis];
+        let mut bins = [(Aabb::EMPTY, 0); BVH_SAH_BINS];
+        for id in ids.iter() {
+            let c = centroids[*id as usize][axis];
+            let bin = (((c - centroid_bounds.min[axis]) * scale) as usize).min(BVH_SAH_BINS - 1);
+            let (aabb, count) = &mut bins[bin];
+            *aabb = triangles[*id as usize]
+                .iter()
+                .fold(*aabb, |aabb, p| aabb.grow(*p));
+            *count += 1;
+        }
+        // NOTE: split i puts bins 0..=i on the left
+        let mut left = [(0.0, 0); BVH_SAH_BINS - 1];
+        let mut acc = (Aabb::EMPTY, 0);
+        for i in 0..BVH_SAH_BINS - 1 {
+            acc = (acc.0.union(&bins[i].0), acc.1 + bins[i].1);
+            left[i] = (acc.0.surface_area(), acc.1);
+        }
+        let mut acc = (Aabb::EMPTY, 0);
+        for i in (1..BVH_SAH_BINS).rev() {
+            acc = (acc.0.union(&bins[i].0), acc.1 + bins[i].1);
+            let (left_area, left_count) = left[i - 1];
+            if left_count == 0 || acc.1 == 0 {
+                continue;
+            }
+            let cost = left_area * left_count as f32 + acc.0.surface_area() * acc.1 as f32;
+            if cost < best.map_or(f32::MAX, |(_, _, cost)| cost) {
+                let split = centroid_bounds.min[axis] + i as f32 / scale;
+                best = Some((axis, split, cost));
+            }
+        }
+    }
+    let (axis, split, cost) = best?;
+    (cost < leaf_cost).then_some((axis, split))
+}
+
+// NOTE: aabb center and the furthest point from it, not the tightest sphere but close enough
+pub fn bounding_sphere(positions: &[Vec3A]) -> (Vec3A, f32) {
+    if positions.is_empty() {
+        return (Vec3A::ZERO, 0.0);
+    }
+    let center = Aabb::from_points(positions).center();
+    let radius = positions
+        .iter()
+        .map(|p| p.distance(center))
+        .fold(0.0, f32::max);
+    (center, radius)
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MeshDraw {
+    // NOTE: into the index buffer if the mesh has one, otherwise into the vertex buffer
+    pub start: usize,
+    pub count: usize,
+    pub material: Option<usize>,
+}
+
+// NOTE: whole mesh in a single draw without a material
+pub fn single_draw(count: usize) -> Vec<MeshDraw> {
+    vec![MeshDraw {
+        start: 0,
+        count,
+        material: None,
+    }]
+}
+
+pub fn draws_from_material_runs(runs: &[MaterialRun], count: usize) -> Vec<MeshDraw> {
+    if runs.is_empty() {
+        return single_draw(count);
+    }
+    let mut draws = vec![];
+    if runs[0].first_index > 0 {
+        draws.push(MeshDraw {
+            start: 0,
+            count: runs[0].first_index,
+            material: None,
+        });
+    }
+    for (i, run) in runs.iter().enumerate() {
+        let end = runs
+            .get(i + 1)
+            .map(|next| next.first_index)
+            .unwrap_or(count);
+        if end > run.first_index {
+            draws.push(MeshDraw {
+                start: run.first_index,
+                count: end - run.first_index,
+                material: Some(run.material),
+            });
+        }
+    }
+    draws
+}
+
+#[derive(Clone, Debug)]
+pub struct Material {
+    pub name: String,
+    // NOTE: mtl Kd, used as albedo when there is no diffuse texture
+    pub diffuse: Vec3A,
+    // NOTE: mtl map_Kd, already resolved relative to the mtl file
+    pub diffuse_texture: Option<std::path::PathBuf>,
+    // NOTE: mtl Ks and Ns, parsed but not uploaded, the light pass brdf only reads
+    // roughness and metalness
+    pub specular: Vec3A,
+    pub shininess: f32,
+    // NOTE: mtl Pr or derived from Ns, gltf roughnessFactor
+    pub roughness: f32,
+    // NOTE: mtl Pm, gltf metallicFactor
+    pub metalness: f32,
+}
+
+impl Material {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            diffuse: Vec3A::ONE,
+            diffuse_texture: None,
+            specular: Vec3A::ZERO,
+            shininess: 0.0,
+            roughness: 1.0,
+            metalness: 0.0,
+        }
+    }
+}
+
+// NOTE: index into the material table of a file, see split_mesh_materials
+pub type MaterialId = usize;
+
+// NOTE: the usual blinn-phong exponent to beckmann roughness mapping, close enough for ggx
+pub fn shininess_to_roughness(shininess: f32) -> f32 {
+    (2.0 / (shininess.max(0.0) + 2.0)).sqrt()
+}
+
+// NOTE: every face from index first_index up to the next run uses material
+#[derive(Clone, Copy, Debug)]
+pub struct MaterialRun {
+    pub first_index: usize,
+    pub material: usize,
+}
+
+#[derive(Clone, Copy)]
+pub struct GpuMaterial {
+    pub diffuse: Vec3A,
+    // NOTE: layer in the mesh material_textures
+    pub layer: Option<u32>,
+    pub roughness: f32,
+    pub metalness: f32,
+}
+
+impl GpuMaterial {
+    pub fn uniform(&self) -> MaterialUniform {
+        MaterialUniform {
+            diffuse: self.diffuse.extend(1.0).to_array(),
+            texture_layer: self.layer.map(|layer| layer as i32).unwrap_or(-1),
+            use_vertex_normals: 0,
+            roughness: self.roughness.clamp(0.0, 1.0),
+            metalness: self.metalness.clamp(0.0, 1.0),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct CpuMesh {
+    // NOTE: "file" or "file/object" for obj files with a single named object
+    pub name: String,
+    pub vertices: Vec<Vec3A>,
+    pub indices: Vec<usize>,
+    pub uvs: Vec<Vec2>,
+    // NOTE: one entry per entry in indices, None if the face corner had no vt
+    pub uv_indices: Vec<Option<usize>>,
+    pub normals: Vec<Vec3A>,
+    // NOTE: one entry per entry in indices, None if the face corner had no vn
+    pub normal_indices: Vec<Option<usize>>,
+    // NOTE: either empty or one color per entry in vertices
+    pub colors: Vec<Vec4>,
+    pub materials: Vec<Material>,
+    // NOTE: sorted by first_index, faces before the first run have no material
+    pub material_runs: Vec<MaterialRun>,
+    // NOTE: o/g groups of obj files as index ranges in file order, a name that comes back
+    // later in the file has more than one range. Empty for the other formats
+    pub groups: Vec<(String, std::ops::Range<usize>)>,
+}
+
+impl CpuMesh {
+    // NOTE: what build_indexed_mesh and the upload rely on, checked after parsing since
+    // the gltf and ply parsers and the cache don't go through parse_face_corner. obj faces
+    // already fail there with their line number
+    pub fn validate(&self) -> Result<(), String> {
+        let corners = self.indices.len();
+        if corners % 3 != 0 {
+            return Err(format!(
+                "{corners} indices is not a whole number of triangles"
+            ));
+        }
+        // NOTE: build_indexed_mesh makes at most one gpu vertex per corner
+        if u32::try_from(corners).is_err() {
+            return Err(format!("{corners} corners don't fit u32 indices"));
+        }
+        let check =
+            |kind: &str, indices: &mut dyn Iterator<Item = (usize, usize)>, len| match indices
+                .find(|(_, idx)| *idx >= len)
+            {
+                Some((corner, idx)) => Err(format!(
+                    "{kind} index {idx} of triangle {} out of range, {len} defined",
+                    corner / 3
+                )),
+                None => Ok(()),
+            };
+        check(
+            "position",
+            &mut self.indices.iter().copied().enumerate(),
+            self.vertices.len(),
+        )?;
+        if self.uv_indices.len() != corners {
+            return Err(format!(
+                "{} uv indices for {corners} corners",
+                self.uv_indices.len()
+            ));
+        }
+        check(
+            "uv",
+            &mut self
+                .uv_indices
+                .iter()
+                .enumerate()
+                .filter_map(|(corner, idx)| Some((corner, (*idx)?))),
+            self.uvs.len(),
+        )?;
+        if !self.normal_indices.is_empty() && self.normal_indices.len() != corners {
+            return Err(format!(
+                "{} normal indices for {corners} corners",
+                self.normal_indices.len()
+            ));
+        }
+        check(
+            "normal",
+            &mut self
+                .normal_indices
+                .iter()
+                .enumerate()
+                .filter_map(|(corner, idx)| Some((corner, (*idx)?))),
+            self.normals.len(),
+        )?;
+        if !self.colors.is_empty() && self.colors.len() != self.vertices.len() {
+            return Err(format!(
+                "{} colors for {} vertices",
+                self.colors.len(),
+                self.vertices.len()
+            ));
+        }
+        if let Some(run) = self
+            .material_runs
+            .iter()
+            .find(|run| run.material >= self.materials.len() || run.first_index > corners)
+        {
+            return Err(format!(
+                "material run {} at corner {} out of range",
+                run.material, run.first_index
+            ));
+        }
+        if let Some((group, _)) = self
+            .groups
+            .iter()
+            .find(|(_, range)| range.start > range.end || range.end > corners)
+        {
+            return Err(format!("group {group} out of range"));
+        }
+        Ok(())
+    }
+
+    pub fn vertex_color(&self, idx: usize) -> Vec4 {
+        self.colors.get(idx).copied().unwrap_or(Vec4::ONE)
+    }
+
+    // NOTE: swaps the last two corners of every triangle, the uvs and normals of each
+    // corner go with it. The normals themselves don't change
+    pub fn flip_winding(&mut self) {
+        fn swap_corners<T>(corners: &mut [T]) {
+            for triangle in corners.chunks_exact_mut(3) {
+                triangle.swap(1, 2);
+            }
+        }
+        swap_corners(&mut self.indices);
+        swap_corners(&mut self.uv_indices);
+        swap_corners(&mut self.normal_indices);
+    }
+
+    pub fn corner_uv(&self, corner: usize) -> Vec2 {
+        self.uv_indices
+            .get(corner)
+            .copied()
+            .flatten()
+            .and_then(|uv_idx| self.uvs.get(uv_idx).copied())
+            .unwrap_or(Vec2::ZERO)
+    }
+
+    pub fn corner_normal(&self, corner: usize) -> Option<Vec3A> {
+        self.normal_indices
+            .get(corner)
+            .copied()
+            .flatten()
+            .and_then(|normal_idx| self.normals.get(normal_idx).copied())
+    }
+
+    pub fn has_vertex_normals(&self) -> bool {
+        self.normal_indices.iter().any(|idx| idx.is_some())
+    }
+
+    // NOTE: the faces of one o/g group as a mesh of their own, e.g. a single column of
+    // sponza, None if there is no group of that name
+    pub fn extract_group(&self, name: &str) -> Option<CpuMesh> {
+        let ranges = self
+            .groups
+            .iter()
+            .filter(|(group, _)| group == name)
+            .map(|(_, range)| range.clone())
+            .collect::<Vec<_>>();
+        if ranges.is_empty() {
+            return None;
+        }
+        let mut mesh = extract_mesh_ranges(self, &ranges);
+        mesh.name = format!("{}/{name}", self.name);
+        mesh.groups = vec![(name.to_string(), 0..mesh.indices.len())];
+        Some(mesh)
+    }
+}
+
+// NOTE: where an o or g line starts a new group, the group runs until the next one
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Submesh {
+    pub name: String,
+    pub index_start: usize,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct CameraKeyframe {
+    // NOTE: seconds since the start of the path
+    pub time: f32,
+    pub pos: Vec3A,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl CameraKeyframe {
+    pub fn from_camera(camera: &Camera, time: f32) -> Self {
+        Self {
+            time,
+            pos: camera.pos,
+            yaw: camera.yaw,
+            pitch: camera.pitch,
+        }
+    }
+}
+
+// NOTE: keyframes sorted by time, plays from the first keyframe's time to the last one's
+#[derive(Clone, Debug)]
+pub struct CameraPath {
+    pub keyframes: Vec<CameraKeyframe>,
+    pub looping: bool,
+}
+
+impl CameraPath {
+    pub fn duration(&self) -> f32 {
+        match (self.keyframes.first(), self.keyframes.last()) {
+            (Some(first), Some(last)) => last.time - first.time,
+            _ => 0.0,
+        }
+    }
+
+    pub fn is_finished(&self, t: f32) -> bool {
+        !self.looping && t >= self.duration()
+    }
+
+    // NOTE: t is seconds since the start of the path, catmull-rom through the positions
+    // and linear angles, yaw and pitch are not wrapped so a keyframe at yaw 350 degrees
+    // and the next at 10 degrees really turns 340 degrees back, like the camera did
+    pub fn sample(&self, t: f32) -> (Vec3A, f32, f32) {
+        let Some(first) = self.keyframes.first() else {
+            return (Vec3A::ZERO, 0.0, 0.0);
+        };
+        let duration = self.duration();
+        let t = if self.looping && duration > 0.0 {
+            t.rem_euclid(duration)
+        } else {
+            t.clamp(0.0, duration)
+        } + first.time;
+
+        let n = self.keyframes.len();
+        // NOTE: index of the keyframe ending the segment t is in, a single keyframe is a
+        // segment from itself to itself
+        let next = if n == 1 {
+            0
+        } else {
+            self.keyframes
+                .iter()
+                .position(|key| key.time > t)
+                .unwrap_or(n - 1)
+                .clamp(1, n - 1)
+        };
+        let prev = next.saturating_sub(1);
+        let a = &self.keyframes[prev];
+        let b = &self.keyframes[next];
+        let segment = b.time - a.time;
+        let s = if segment > 0.0 {
+            ((t - a.time) / segment).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+
+        // NOTE: the path ends are their own outer neighbours
+        let p0 = self.keyframes[prev.saturating_sub(1)].pos;
+        let p3 = self.keyframes[(next + 1).min(n - 1)].pos;
+        let pos = catmull_rom(p0, a.pos, b.pos, p3, s);
+        let yaw = a.yaw + (b.yaw - a.yaw) * s;
+        let pitch = a.pitch + (b.pitch - a.pitch) * s;
+        (pos, yaw, pitch)
+    }
+}
+
+// NOTE: uniform catmull-rom, passes through p1 at t = 0 and p2 at t = 1
+pub fn catmull_rom(p0: Vec3A, p1: Vec3A, p2: Vec3A, p3: Vec3A, t: f32) -> Vec3A {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    0.5 * (2.0 * p1
+        + (p2 - p0) * t
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+}
+
+#[derive(Clone)]
+pub struct Camera {
+    pub pos: Vec3A,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub vfov_rad: f32,
+    pub aspect: f32,
+    // NOTE: projection maps near to 1 and far to 0, see depth_compare
+    pub reverse_z: bool,
+    // NOTE: sub pixel offset in ndc added to the projection, set per frame while taa is on
+    pub jitter: Vec2,
+    // NOTE: unjittered vp of the previous frame, set once a frame is done
+    pub prev_vp: Mat4,
+    // NOTE: clip planes of the projection, everything reading depth linearizes with these
+    pub near: f32,
+    pub far: f32,
+    // NOTE: size of the render targets in pixels, set per frame in render
+    pub viewport_size: Vec2,
+    // NOTE: State::total_time and State::delta_time, set per frame in render for shader
+    // animations
+    pub time_secs: f32,
+    pub delta_time_secs: f32,
+}
+
+// NOTE: the runtime render toggles, there is no separate RenderSettings. Settings mirrors
+// the ones that persist
+pub struct InputState {
+    // NOTE: off skips the ao passes and draws with the light pass variant without ao
+    pub use_ao: bool,
+    pub ao_level: usize,
+    pub use_blur: bool,
+    pub ao_settings: AOSettings,
+    pub shadow_settings: ShadowSettings,
+    // NOTE: show linearized depth instead of the lit image
+    pub debug_depth: bool,
+    // NOTE: show world space normals instead of the lit image, debug_depth wins if both are set
+    pub debug_normals: bool,
+    // NOTE: sample material textures with their mip chain, off shows the aliasing without
+    pub use_mipmaps: bool,
+    // NOTE: draw the simplified mesh for meshes smaller on screen than lod_screen_size,
+    // a fraction of the viewport height, see Mesh::select_lod
+    pub use_lod: bool,
+    pub lod_screen_size: f32,
+    // NOTE: skip nodes whose aabb is outside the camera frustum in the prepass and the
+    // geometry pass, the shadow pass still draws everything
+    pub use_frustum_culling: bool,
+    // NOTE: depth only pass before the geometry pass, which then shades with an Equal test
+    pub use_depth_prepass: bool,
+    // NOTE: debug lines for the world space aabbs of the nodes in view and for the point
+    // and spot lights, see State::queue_debug_lines
+    pub debug_draw_bounds: bool,
+    pub debug_draw_lights: bool,
+    pub use_taa: bool,
+    pub taa_history_weight: f32,
+    // NOTE: accumulate ao over frames with a rotating kernel, tssao_alpha is the weight
+    // of the current frame
+    pub use_tssao: bool,
+    pub tssao_alpha: f32,
+    pub dof: DofSettings,
+    pub ssr: SsrSettings,
+    pub ao_gizmos: AoGizmoSettings,
+    pub ao_scissor: AoScissorSettings,
+    pub ao_split: AoSplitSettings,
+    // NOTE: radians per raw mouse count, see State::cursor_grabbed
+    pub mouse_sensitivity: f32,
+    // NOTE: run the compute smoke test before the geometry pass, see
+    // State::render_compute_test
+    pub compute_test: bool,
+}
+
+// NOTE: thin lens depth of field over the finished image, toggled with F4. [ and ] move
+// the focus, - and = change the aperture, f focuses on the pixel in the screen center
+#[derive(Clone, Copy, Debug)]
+pub struct DofSettings {
+    pub enabled: bool,
+    // NOTE: view space distance that is in focus
+    pub focus_distance: f32,
+    // NOTE: lens diameter in world units, 0 is a pinhole and everything is sharp
+    pub aperture: f32,
+    // NOTE: cap on the circle of confusion radius, the gather covers this many pixels
+    pub max_coc_px: f32,
+}
+
+impl Default for DofSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            focus_distance: 5.0,
+            aperture: 0.1,
+            max_coc_px: 12.0,
+        }
+    }
+}
+
+// NOTE: screen space reflections over the lit image, toggled with F5. How much each
+// surface reflects comes from the material g-buffer
+#[derive(Clone, Copy, Debug)]
+pub struct SsrSettings {
+    pub enabled: bool,
+    pub steps: u32,
+    // NOTE: view space length of the marched ray
+    pub max_distance: f32,
+    pub thickness: f32,
+}
+
+// NOTE: draws where the hemisphere kernel samples of one pixel land, toggled with F6. The
+// pixel is the screen center or follows the mouse
+#[derive(Clone, Copy, Debug)]
+pub struct AoGizmoSettings {
+    pub enabled: bool,
+    pub at_mouse: bool,
+    // NOTE: screen uv of the pixel, 0,0 top left
+    pub uv: Vec2,
+    // NOTE: world units
+    pub point_size: f32,
+}
+
+// NOTE: restricts the ao and ao blur passes to a screen rect with a scissor, toggled with
+// F7. Their targets are cleared to no occlusion instead of full, so outside the rect is
+// lit as with ao off, for timing the passes on part of the screen or comparing side by side
+#[derive(Clone, Copy, Debug)]
+pub struct AoScissorSettings {
+    pub enabled: bool,
+    // NOTE: screen uv, 0,0 top left
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl Default for AoScissorSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min: Vec2::ZERO,
+            max: Vec2::new(0.5, 1.0),
+        }
+    }
+}
+
+impl AoScissorSettings {
+    // NOTE: in texels of a target of this size, so the same uv rect at every ao level
+    pub fn rect(&self, size: gpu::Extent) -> gpu::ScissorRect {
+        let size_f = Vec2::new(size.width as f32, size.height as f32);
+        let min = (self.min.clamp(Vec2::ZERO, Vec2::ONE) * size_f).floor();
+        let max = (self.max.clamp(Vec2::ZERO, Vec2::ONE) * size_f)
+            .ceil()
+            .max(min);
+        gpu::ScissorRect {
+            x: min.x as i32,
+            y: min.y as i32,
+            w: (max.x - min.x) as u32,
+            h: (max.y - min.y) as u32,
+        }
+    }
+
+    pub fn ao_clear_color(&self) -> gpu::TextureColor {
+        if self.enabled {
+            gpu::TextureColor::TransparentBlack
+        } else {
+            gpu::TextureColor::White
+        }
+    }
+}
+
+// NOTE: ao comparison in one frame, toggled with F8. The light pass applies the ao left of
+// a vertical divider and ignores it right of it, , and . move the divider. Unlike the
+// scissor the ao passes still cover the whole screen
+#[derive(Clone, Copy, Debug)]
+pub struct AoSplitSettings {
+    pub enabled: bool,
+    // NOTE: screen uv of the divider
+    pub x: f32,
+}
+
+impl Default for AoSplitSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            x: 0.5,
+        }
+    }
+}
+
+impl AoSplitSettings {
+    // NOTE: past the right edge when off, so every pixel gets ao and no divider is drawn
+    pub fn split_x(&self) -> f32 {
+        if self.enabled {
+            self.x.clamp(0.0, 1.0)
+        } else {
+            2.0
+        }
+    }
+}
+
+impl Default for AoGizmoSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            at_mouse: false,
+            uv: Vec2::splat(0.5),
+            point_size: 0.05,
+        }
+    }
+}
+
+impl Default for SsrSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            steps: 32,
+            max_distance: 10.0,
+            thickness: 0.3,
+        }
+    }
+}
+
+impl InputState {
+    pub fn lod_screen_size(&self) -> Option<f32> {
+        self.use_lod.then_some(self.lod_screen_size)
+    }
+}
+
+// NOTE: relative to the working directory like the shaders, so next to the repository root
+pub const SETTINGS_PATH: &str = "settings.toml";
+
+// NOTE: renderer settings that survive a restart, read in State::new and written when the
+// window closes. The command line flags (taa, tssao, dof, ...) are not part of it
+#[derive(Clone, Copy, Debug)]
+pub struct Settings {
+    pub use_ao: bool,
+    pub ao_level: usize,
+    pub use_blur: bool,
+    pub ao_settings: AOSettings,
+    pub shadow_settings: ShadowSettings,
+    pub debug_depth: bool,
+    pub debug_normals: bool,
+    pub use_mipmaps: bool,
+    pub use_lod: bool,
+    pub lod_screen_size: f32,
+    pub use_frustum_culling: bool,
+    pub use_depth_prepass: bool,
+    // NOTE: only used when the file had a camera, a scene from the command line is framed
+    // on load anyway
+    pub camera: Option<SettingsCamera>,
+    pub sun_intensity: f32,
+    pub mouse_sensitivity: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SettingsCamera {
+    pub pos: Vec3A,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub vfov_rad: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        let sun_intensity = match Light::sun() {
+            Light::Directional { intensity, .. } => intensity,
+            _ => unreachable!(),
+        };
+        Self {
+            use_ao: true,
+            ao_level: 0,
+            use_blur: false,
+            ao_settings: AOSettings::default(),
+            shadow_settings: ShadowSettings::default(),
+            debug_depth: false,
+            debug_normals: false,
+            use_mipmaps: true,
+            use_lod: true,
+            lod_screen_size: 0.1,
+            use_frustum_culling: true,
+            use_depth_prepass: false,
+            camera: None,
+            sun_intensity,
+            mouse_sensitivity: MOUSE_SENSITIVITY,
+        }
+    }
+}
+
+// NOTE: a settings value as the text after "key = ", None from parse leaves the value as is
+pub trait SettingValue {
+    fn to_text(&self) -> String;
+    fn parse(text: &str) -> Option<Self>
+    where
+        Self: Sized;
+}
+
+macro_rules! impl_setting_value {
+    ($($ty:ty),*) => {
+        $(impl SettingValue for $ty {
+            fn to_text(&self) -> String {
+                self.to_string()
+            }
+            fn parse(text: &str) -> Option<Self> {
+                text.parse().ok()
+            }
+        })*
+    };
+}
+
+impl_setting_value!(bool, u32, usize, f32);
+
+// NOTE: quoted like a toml string, the names of the enums have spaces
+impl SettingValue for String {
+    fn to_text(&self) -> String {
+        format!("{self:?}")
+    }
+    fn parse(text: &str) -> Option<Self> {
+        let text = text.strip_prefix('"')?.strip_suffix('"')?;
+        Some(text.to_string())
+    }
+}
+
+// NOTE: reads are lenient, an unknown or broken line is logged and skipped
+pub trait SettingsVisitor {
+    fn visit<T: SettingValue>(&mut self, key: &str, value: &mut T);
+}
+
+pub struct SettingsReader {
+    pub values: std::collections::HashMap<String, String>,
+}
+
+impl SettingsVisitor for SettingsReader {
+    fn visit<T: SettingValue>(&mut self, key: &str, value: &mut T) {
+        let Some(text) = self.values.remove(key) else {
+            return;
+        };
+        match T::parse(&text) {
+            Some(parsed) => *value = parsed,
+            None => {
+                dbg!(format!("bad settings value for {key}: {text}"));
+            }
+        }
+    }
+}
+
+pub struct SettingsWriter {
+    pub out: String,
+}
+
+impl SettingsVisitor for SettingsWriter {
+    fn visit<T: SettingValue>(&mut self, key: &str, value: &mut T) {
+        self.out.push_str(&format!("{key} = {}\n", value.to_text()));
+    }
+}
+
+impl Settings {
+    // NOTE: the one list of keys, loading and saving both go through it so they can't
+    // drift apart. Enums are stored by name
+    pub fn visit(&mut self, v: &mut impl SettingsVisitor) {
+        v.visit("use_ao", &mut self.use_ao);
+        v.visit("ao_level", &mut self.ao_level);
+        // NOTE: indexes the ao textures, a hand edited file can ask for a level that isn't there
+        self.ao_level = self.ao_level.min(NUM_AO_TEXTURES - 1);
+        v.visit("use_blur", &mut self.use_blur);
+
+        let ao = &mut self.ao_settings;
+        v.visit("ao.d_max", &mut ao.d_max);
+        v.visit("ao.r_max", &mut ao.r_max);
+        v.visit("ao.bias", &mut ao.bias);
+        let mut method = ao.method.name().to_string();
+        v.visit("ao.method", &mut method);
+        if let Some(m) = AoMethod::ALL.iter().find(|m| m.name() == method) {
+            ao.method = *m;
+        }
+        v.visit("ao.num_samples", &mut ao.num_samples);
+        let distribution_names = ["uniform hemisphere", "cosine weighted", "poisson rings"];
+        let (distribution_i, mut num_rings) = match ao.kernel_distribution {
+            KernelDistribution::UniformHemisphere => (0, 3),
+            KernelDistribution::CosineWeighted => (1, 3),
+            KernelDistribution::Poisson { num_rings } => (2, num_rings),
+        };
+        let mut distribution = distribution_names[distribution_i].to_string();
+        v.visit("ao.kernel_distribution", &mut distribution);
+        v.visit("ao.kernel_rings", &mut num_rings);
+        ao.kernel_distribution = match distribution_names.iter().position(|n| *n == distribution) {
+            Some(0) => KernelDistribution::UniformHemisphere,
+            Some(1) => KernelDistribution::CosineWeighted,
+            Some(_) => KernelDistribution::Poisson {
+                num_rings: num_rings.clamp(1, 4),
+            },
+            None => ao.kernel_distribution,
+        };
+        v.visit("ao.kernel_jitter", &mut ao.kernel_jitter);
+        v.visit("ao.hbao_directions", &mut ao.hbao_directions);
+        v.visit("ao.hbao_steps", &mut ao.hbao_steps);
+        v.visit("ao.gtao_slices", &mut ao.gtao_slices);
+        v.visit("ao.gtao_steps", &mut ao.gtao_steps);
+        v.visit("ao.gtao_thickness", &mut ao.gtao_thickness);
+        let mut mip_selection = ao.hi_z_mip_selection.name().to_string();
+        v.visit("ao.hi_z_mip_selection", &mut mip_selection);
+        if let Some(s) = HiZMipSelection::ALL
+            .iter()
+            .find(|s| s.name() == mip_selection)
+        {
+            ao.hi_z_mip_selection = *s;
+        }
+        v.visit("ao.hi_z_mip_bias", &mut ao.hi_z_mip_bias);
+        v.visit("ao.use_bent_normals", &mut ao.use_bent_normals);
+        v.visit("ao.ambient_sky", &mut ao.ambient_sky);
+        v.visit("ao.ambient_ground", &mut ao.ambient_ground);
+        v.visit("ao.use_ibl", &mut ao.use_ibl);
+        v.visit("ao.ibl_intensity", &mut ao.ibl_intensity);
+        // NOTE: same limits as the ui sliders, the passes index fixed size arrays
+        ao.num_samples = ao.num_samples.clamp(1, MAX_AO_SAMPLES as u32);
+        ao.hbao_directions = ao.hbao_directions.clamp(1, MAX_AO_SAMPLES as u32);
+
+        let shadow = &mut self.shadow_settings;
+        v.visit("shadow.enabled", &mut shadow.enabled);
+        v.visit("shadow.bias", &mut shadow.bias);
+        v.visit("shadow.cascade_count", &mut shadow.cascade_count);
+        v.visit("shadow.split_lambda", &mut shadow.split_lambda);
+        v.visit("shadow.max_distance", &mut shadow.max_distance);
+        v.visit("shadow.blend_width", &mut shadow.blend_width);
+        v.visit("shadow.pcss_enabled", &mut shadow.pcss_enabled);
+        v.visit("shadow.light_size", &mut shadow.light_size);
+        shadow.cascade_count = shadow.cascade_count.clamp(1, MAX_SHADOW_CASCADES);
+
+        v.visit("debug_depth", &mut self.debug_depth);
+        v.visit("debug_normals", &mut self.debug_normals);
+        v.visit("use_mipmaps", &mut self.use_mipmaps);
+        v.visit("use_lod", &mut self.use_lod);
+        v.visit("lod_screen_size", &mut self.lod_screen_size);
+        v.visit("use_frustum_culling", &mut self.use_frustum_culling);
+        v.visit("use_depth_prepass", &mut self.use_depth_prepass);
+        v.visit("sun_intensity", &mut self.sun_intensity);
+        v.visit("mouse_sensitivity", &mut self.mouse_sensitivity);
+
+        // NOTE: the camera only counts if the file has all of its keys
+        let mut camera = self.camera.unwrap_or(SettingsCamera {
+            pos: Vec3A::splat(f32::NAN),
+            yaw: f32::NAN,
+            pitch: f32::NAN,
+            vfov_rad: f32::NAN,
+        });
+        v.visit("camera.x", &mut camera.pos.x);
+        v.visit("camera.y", &mut camera.pos.y);
+        v.visit("camera.z", &mut camera.pos.z);
+        v.visit("camera.yaw", &mut camera.yaw);
+        v.visit("camera.pitch", &mut camera.pitch);
+        v.visit("camera.vfov_rad", &mut camera.vfov_rad);
+        let values = [
+            camera.pos.x,
+            camera.pos.y,
+            camera.pos.z,
+            camera.yaw,
+            camera.pitch,
+        ];
+        if values
+            .iter()
+            .chain([&camera.vfov_rad])
+            .all(|x| x.is_finite())
+        {
+            self.camera = Some(camera);
+        }
+    }
+
+    // NOTE: the parts of the settings that live in the scene instead of InputState
+    pub fn from_state(input: &InputState, scene: &Scene) -> Self {
+        let camera = &scene.camera;
+        Self {
+            use_ao: input.use_ao,
+            ao_level: input.ao_level,
+            use_blur: input.use_blur,
+            ao_settings: input.ao_settings,
+            shadow_settings: input.shadow_settings,
+            debug_depth: input.debug_depth,
+            debug_normals: input.debug_normals,
+            use_mipmaps: input.use_mipmaps,
+            use_lod: input.use_lod,
+            lod_screen_size: input.lod_screen_size,
+            use_frustum_culling: input.use_frustum_culling,
+            use_depth_prepass: input.use_depth_prepass,
+            camera: Some(SettingsCamera {
+                pos: camera.pos,
+                yaw: camera.yaw,
+                pitch: camera.pitch,
+                vfov_rad: camera.vfov_rad,
+            }),
+            sun_intensity: scene
+                .sun()
+                .map(|sun| match sun {
+                    Light::Directional { intensity, .. } => *intensity,
+                    _ => unreachable!(),
+                })
+                .unwrap_or(Self::default().sun_intensity),
+            mouse_sensitivity: input.mouse_sensitivity,
+        }
+    }
+
+    pub fn apply_to_scene(&self, scene: &mut Scene) {
+        if let Some(camera) = self.camera {
+            scene.camera.pos = camera.pos;
+            scene.camera.yaw = camera.yaw;
+            scene.camera.pitch = camera.pitch;
+            scene.camera.vfov_rad = camera.vfov_rad;
+        }
+        if let Some(Light::Directional { intensity, .. }) = scene.sun_mut() {
+            *intensity = self.sun_intensity;
+        }
+    }
+
+    // NOTE: a missing file is the first start, anything unreadable falls back to defaults
+    pub fn load(path: &str) -> Self {
+        let mut settings = Self::default();
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) => {
+                if err.kind() != std::io::ErrorKind::NotFound {
+                    dbg!(format!("could not read {path}: {err}"));
+                }
+                return settings;
+            }
+        };
+        let mut values = std::collections::HashMap::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once('=') {
+                Some((key, value)) => {
+                    values.insert(key.trim().to_string(), value.trim().to_string());
+                }
+                None => {
+                    dbg!(format!("{path}:{}: expected key = value", i + 1));
+                }
+            }
+        }
+        let mut reader = SettingsReader { values };
+        settings.visit(&mut reader);
+        for key in reader.values.keys() {
+            dbg!(format!("unknown setting {key} in {path}"));
+        }
+        dbg!(format!("loaded settings from {path}"));
+        settings
+    }
+
+    pub fn save(&self, path: &str) {
+        let mut writer = SettingsWriter {
+            out: "# written on exit, edit while the renderer is closed\n".to_string(),
+        };
+        // NOTE: visit takes &mut for the reader, writing leaves the copy unchanged
+        let mut settings = *self;
+        settings.visit(&mut writer);
+        match std::fs::write(path, writer.out) {
+            Ok(()) => {
+                dbg!(format!("saved settings to {path}"));
+            }
+            Err(err) => {
+                dbg!(format!("could not write {path}: {err}"));
+            }
+        }
+    }
+}
+
+// NOTE: positions are reconstructed from the depth, see Globals::inv_mvp_transform
+pub struct GBuffer {
+    pub depth_textures: DepthTextures,
+    pub normal_texture: gpu::Texture,
+    pub normal_view: gpu::TextureView,
+    pub normal_sampler: gpu::Sampler,
+}
+
+#[derive(Clone, Copy)]
+pub struct TextureStuff {
+    pub texture: gpu::Texture,
+    pub view: gpu::TextureView,
+    pub sampler: gpu::Sampler,
+    pub size: gpu::Extent,
+}
+
+// NOTE: level 0 is the g-buffer, each coarser level is written by fs_downsample. The depth
+// is all there is of the positions, see view_pos.wgsl
+pub struct DepthNormalTexture {
+    pub depth: TextureStuff,
+    pub normal: TextureStuff,
+}
+
+pub struct DownsampleTextures {
+    pub textures: Vec<DepthNormalTexture>,
+    // NOTE: full resolution only, written by the geometry pass and read by the light pass
+    pub albedo: TextureStuff,
+    pub material: TextureStuff,
+    pub hi_z: HiZTexture,
+}
+
+pub const HI_Z_FORMAT: gpu::TextureFormat = gpu::TextureFormat::R32Float;
+// NOTE: the kernel radius is at most a few dozen pixels, coarser mips are never read
+pub const MAX_HI_Z_MIPS: u32 = 8;
+// NOTE: must match @workgroup_size in hi_z.wgsl
+pub const HI_Z_WORKGROUP_SIZE: u32 = 8;
+
+// NOTE: min linear view depth pyramid built by render_hi_z, the closest depth keeps thin
+// occluders alive in the coarse mips. texture.view covers every mip for sampling,
+// mip_views one mip each for the compute passes to read and write
+pub struct HiZTexture {
+    pub texture: TextureStuff,
+    pub mip_views: Vec<gpu::TextureView>,
+}
+
+pub fn hi_z_mip_count(size: gpu::Extent) -> u32 {
+    let levels = 32 - size.width.max(size.height).max(1).leading_zeros();
+    levels.min(MAX_HI_Z_MIPS)
+}
+
+pub fn hi_z_mip_size(size: gpu::Extent, mip: u32) -> gpu::Extent {
+    gpu::Extent {
+        width: (size.width >> mip).max(1),
+        height: (size.height >> mip).max(1),
+        depth: 1,
+    }
+}
+
+pub fn create_hi_z_texture(ctx: &gpu::Context, size: gpu::Extent) -> HiZTexture {
+    let mip_count = hi_z_mip_count(size);
+    let texture = ctx.create_texture(gpu::TextureDesc {
+        name: "hi-z texture",
+        format: HI_Z_FORMAT,
+        size,
+        array_layer_count: 1,
+        mip_level_count: mip_count,
+        dimension: gpu::TextureDimension::D2,
+        usage: gpu::TextureUsage::STORAGE | gpu::TextureUsage::RESOURCE,
+    });
+    let view = ctx.create_texture_view(
+        texture,
+        gpu::TextureViewDesc {
+            name: "hi-z view",
+            format: HI_Z_FORMAT,
+            dimension: gpu::ViewDimension::D2,
+            subresources: &Default::default(),
+        },
+    );
+    let sampler = ctx.create_sampler(gpu::SamplerDesc {
+        name: "hi-z sampler",
+        address_modes: Default::default(),
+        mag_filter: gpu::FilterMode::Nearest,
+        min_filter: gpu::FilterMode::Nearest,
+        mipmap_filter: gpu::FilterMode::Nearest,
+        ..Default::default()
+    });
+    let mip_views = (0..mip_count)
+        .map(|mip| {
+            ctx.create_texture_view(
+                texture,
+                gpu::TextureViewDesc {
+                    name: format!("hi-z view {mip}").as_str(),
+                    format: HI_Z_FORMAT,
+                    dimension: gpu::ViewDimension::D2,
+                    subresources: &gpu::TextureSubresources {
+                        base_mip_level: mip,
+                        mip_level_count: std::num::NonZeroU32::new(1),
+                        ..Default::default()
+                    },
+                },
+            )
+        })
+        .collect();
+    HiZTexture {
+        texture: TextureStuff {
+            texture,
+            view,
+            sampler,
+            size,
+        },
+        mip_views,
+    }
+}
+
+pub const ALBEDO_FORMAT: gpu::TextureFormat = gpu::TextureFormat::Rgba8Unorm;
+// NOTE: roughness in r, metalness in g
+pub const MATERIAL_FORMAT: gpu::TextureFormat = gpu::TextureFormat::Rg8Unorm;
+
+// NOTE: src_offset is into the single staging buffer created on flush
+struct BufferCopy {
+    src_offset: u64,
+    dst: gpu::BufferPiece,
+    size: u64,
+}
+
+struct TextureCopy {
+    src_offset: u64,
+    bytes_per_row: u32,
+    dst: gpu::TexturePiece,
+    size: gpu::Extent,
+}
+
+// NOTE: every staged piece starts on this boundary, enough for buffer and texture copies
+// on every backend
+pub const STAGING_ALIGNMENT: usize = 256;
+
+// NOTE: collects staging -> device copies so any number of resources can be uploaded with a
+// single staging buffer, transfer pass, submit and wait, nothing is on the gpu before flush
+// is called. The staged bytes live on the cpu until then
+#[derive(Default)]
+pub struct UploadBatch {
+    staging_data: Vec<u8>,
+    buffer_copies: Vec<BufferCopy>,
+    texture_copies: Vec<TextureCopy>,
+    // NOTE: initialized right before the copies
+    textures: Vec<gpu::Texture>,
+}
+
+impl UploadBatch {
+    fn stage(&mut self, data: &[u8]) -> u64 {
+        let offset = self.staging_data.len().next_multiple_of(STAGING_ALIGNMENT);
+        self.staging_data.resize(offset, 0);
+        self.staging_data.extend_from_slice(data);
+        offset as u64
+    }
+
+    // NOTE: device only buffer filled with data on flush
+    pub fn create_buffer(&mut self, ctx: &gpu::Context, name: &str, data: &[u8]) -> gpu::Buffer {
+        let buffer = ctx.create_buffer(gpu::BufferDesc {
+            name,
+            size: data.len() as u64,
+            memory: gpu::Memory::Device,
+        });
+        let src_offset = self.stage(data);
+        self.buffer_copies.push(BufferCopy {
+            src_offset,
+            dst: buffer.into(),
+            size: data.len() as u64,
+        });
+        buffer
+    }
+
+    // NOTE: texture needs COPY usage
+    pub fn write_texture(
+        &mut self,
+        data: &[u8],
+        bytes_per_row: u32,
+        dst: gpu::TexturePiece,
+        size: gpu::Extent,
+    ) {
+        if !self.textures.contains(&dst.texture) {
+            self.textures.push(dst.texture);
+        }
+        let src_offset = self.stage(data);
+        self.texture_copies.push(TextureCopy {
+            src_offset,
+            bytes_per_row,
+            dst,
+            size,
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer_copies.is_empty() && self.texture_copies.is_empty()
+    }
+
+    // NOTE: blocks until every copy is done and frees the staging buffers
+    pub fn flush(self, ctx: &gpu::Context, encoder: &mut gpu::CommandEncoder) {
+        if self.is_empty() {
+            return;
+        }
+        let staging_buf = ctx.create_buffer(gpu::BufferDesc {
+            name: "upload batch staging",
+            size: self.staging_data.len() as u64,
+            memory: gpu::Memory::Upload,
+        });
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                self.staging_data.as_ptr(),
+                staging_buf.data(),
+                self.staging_data.len(),
+            );
+        }
+        let src = |offset: u64| gpu::BufferPiece {
+            buffer: staging_buf,
+            offset,
+        };
+
+        encoder.start();
+        for texture in self.textures.iter() {
+            encoder.init_texture(*texture);
+        }
+        if let mut transfer = encoder.transfer("upload batch") {
+            for copy in self.buffer_copies.iter() {
+                transfer.copy_buffer_to_buffer(src(copy.src_offset), copy.dst, copy.size);
+            }
+            for copy in self.texture_copies.iter() {
+                transfer.copy_buffer_to_texture(
+                    src(copy.src_offset),
+                    copy.bytes_per_row,
+                    copy.dst,
+                    copy.size,
+                );
+            }
+        }
+        let sp = ctx.submit(encoder);
+        // NOTE: the only point the staging memory can be freed, the copies are done now
+        ctx.wait_for(&sp, !0);
+        ctx.destroy_buffer(staging_buf);
+        dbg!(format!(
+            "upload batch: {} buffers, {} texture pieces, {:.2} MB staged",
+            self.buffer_copies.len(),
+            self.texture_copies.len(),
+            self.staging_data.len() as f32 / 1e6
+        ));
+    }
+}
+
+// NOTE: every material texture is resized to this so they fit in one array texture
+pub const MATERIAL_TEXTURE_SIZE: u32 = 1024;
+
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+// NOTE: full mip chain starting with pixels itself, every level is a linear downsample of
+// the previous one rounding odd sizes down
+pub fn generate_mips_rgba8(width: u32, height: u32, pixels: &[u8]) -> Vec<Vec<u8>> {
+    let mut mips = vec![pixels.to_vec()];
+    let mut image = image::RgbaImage::from_raw(width, height, pixels.to_vec())
+        .expect("pixel count does not match size");
+    for _ in 1..mip_level_count(width, height) {
+        let w = (image.width() / 2).max(1);
+        let h = (image.height() / 2).max(1);
+        image = image::imageops::resize(&image, w, h, image::imageops::FilterType::Triangle);
+        mips.push(image.as_raw().clone());
+    }
+    mips
+}
+
+// NOTE: one rgba8 image of width x height per layer, contents are there after the batch
+// is flushed, with generate_mips the full mip chain is built on the cpu before uploading
+pub fn upload_texture_array_rgba8(
+    ctx: &gpu::Context,
+    upload_batch: &mut UploadBatch,
+    name: &str,
+    width: u32,
+    height: u32,
+    layers: &[Vec<u8>],
+    generate_mips: bool,
+) -> TextureStuff {
+    let layer_size = (width * height * 4) as usize;
+    assert!(layers.iter().all(|layer| layer.len() == layer_size));
+    let extent = gpu::Extent {
+        width,
+        height,
+        depth: 1,
+    };
+    let num_mips = if generate_mips {
+        mip_level_count(width, height)
+    } else {
+        1
+    };
+    let texture = ctx.create_texture(gpu::TextureDesc {
+        name: format!("{name} texture").as_str(),
+        format: gpu::TextureFormat::Rgba8Unorm,
+        size: extent,
+        array_layer_count: layers.len() as u32,
+        mip_level_count: num_mips,
+        dimension: gpu::TextureDimension::D2,
+        usage: gpu::TextureUsage::COPY | gpu::TextureUsage::RESOURCE,
+    });
+    let view = ctx.create_texture_view(
+        texture,
+        gpu::TextureViewDesc {
+            name: format!("{name} view").as_str(),
+            format: gpu::TextureFormat::Rgba8Unorm,
+            dimension: gpu::ViewDimension::D2Array,
+            subresources: &Default::default(),
+        },
+    );
+    let sampler = create_material_sampler(ctx, name, generate_mips, 1);
+
+    for (layer_i, layer) in layers.iter().enumerate() {
+        let mips = if generate_mips {
+            generate_mips_rgba8(width, height, layer)
+        } else {
+            vec![layer.clone()]
+        };
+        for (mip_i, pixels) in mips.iter().enumerate() {
+            let mip_width = (width >> mip_i).max(1);
+            let mip_height = (height >> mip_i).max(1);
+            upload_batch.write_texture(
+                pixels,
+                mip_width * 4,
+                gpu::TexturePiece {
+                    texture,
+                    mip_level: mip_i as u32,
+                    array_layer: layer_i as u32,
+                    origin: [0; 3],
+                },
+                gpu::Extent {
+                    width: mip_width,
+                    height: mip_height,
+                    depth: 1,
+                },
+            );
+        }
+    }
+
+    TextureStuff {
+        texture,
+        view,
+        sampler,
+        size: extent,
+    }
+}
+
+// NOTE: use_mipmaps false clamps sampling to the top level, used to compare against mipmapping
+pub fn create_material_sampler(
+    ctx: &gpu::Context,
+    name: &str,
+    use_mipmaps: bool,
+    anisotropy: u32,
+) -> gpu::Sampler {
+    ctx.create_sampler(gpu::SamplerDesc {
+        name: format!("{name} sampler").as_str(),
+        address_modes: [gpu::AddressMode::Repeat; 3],
+        mag_filter: gpu::FilterMode::Linear,
+        min_filter: gpu::FilterMode::Linear,
+        mipmap_filter: gpu::FilterMode::Linear,
+        lod_max_clamp: if use_mipmaps { None } else { Some(0.0) },
+        anisotropy_clamp: anisotropy,
+        ..Default::default()
+    })
+}
+
+// NOTE: shared by all material textures so filtering can be changed at runtime
+pub struct MaterialSamplers {
+    pub mipped: gpu::Sampler,
+    pub no_mip: gpu::Sampler,
+    pub anisotropy: u32,
+}
+
+impl MaterialSamplers {
+    // NOTE: anisotropy is clamped to [1, MAX_ANISOTROPY]
+    pub fn new(ctx: &gpu::Context, anisotropy: u32) -> Self {
+        let anisotropy = anisotropy.clamp(1, MAX_ANISOTROPY);
+        Self {
+            mipped: create_material_sampler(ctx, "material", true, anisotropy),
+            no_mip: create_material_sampler(ctx, "material no mip", false, anisotropy),
+            anisotropy,
+        }
+    }
+
+    pub fn get(&self, use_mipmaps: bool) -> gpu::Sampler {
+        if use_mipmaps {
+            self.mipped
+        } else {
+            self.no_mip
+        }
+    }
+
+    pub fn destroy(&self, ctx: &gpu::Context) {
+        ctx.destroy_sampler(self.mipped);
+        ctx.destroy_sampler(self.no_mip);
+    }
+}
+
+// NOTE: returns rgba8 pixels resized to size x size
+pub fn load_texture_rgba8<P: AsRef<std::path::Path>>(path: P, size: u32) -> Option<Vec<u8>> {
+    let path = path.as_ref();
+    let image = match image::open(path) {
+        Ok(image) => image,
+        Err(err) => {
+            dbg!(format!("could not load texture {}: {err}", path.display()));
+            return None;
+        }
+    };
+    let image = image.resize_exact(size, size, image::imageops::FilterType::Triangle);
+    Some(image.to_rgba8().into_raw())
+}
+
+// NOTE: the six faces as layers of an array texture plus a cube view of the same layers,
+// layer order is +x -x +y -y +z -z like cube views expect
+pub struct CubeTexture {
+    pub faces: TextureStuff,
+    pub cube_view: gpu::TextureView,
+}
+
+impl CubeTexture {
+    pub fn upload(
+        ctx: &gpu::Context,
+        upload_batch: &mut UploadBatch,
+        name: &str,
+        size: u32,
+        faces: &[Vec<u8>],
+    ) -> Self {
+        let faces = upload_texture_array_rgba8(ctx, upload_batch, name, size, size, faces, false);
+        let cube_view = ctx.create_texture_view(
+            faces.texture,
+            gpu::TextureViewDesc {
+                name: format!("{name} cube view").as_str(),
+                format: gpu::TextureFormat::Rgba8Unorm,
+                dimension: gpu::ViewDimension::Cube,
+                subresources: &Default::default(),
+            },
+        );
+        Self { faces, cube_view }
+    }
+
+    pub fn destroy(&self, ctx: &gpu::Context) {
+        ctx.destroy_texture_view(self.cube_view);
+        ctx.destroy_texture_view(self.faces.view);
+        ctx.destroy_sampler(self.faces.sampler);
+        ctx.destroy_texture(self.faces.texture);
+    }
+}
+
+// NOTE: irradiance is the environment convolved with a cosine lobe, what the light pass
+// uses as ambient instead of the sky and ground colors
+pub struct Skybox {
+    pub environment: CubeTexture,
+    pub irradiance: CubeTexture,
+}
+
+impl Skybox {
+    pub fn destroy(&self, ctx: &gpu::Context) {
+        self.environment.destroy(ctx);
+        self.irradiance.destroy(ctx);
+    }
+}
+
+// NOTE: irradiance varies slowly so a handful of texels a face is plenty, the environment
+// is box filtered down to IRRADIANCE_SOURCE_SIZE first to keep the convolution cheap
+pub const IRRADIANCE_SIZE: u32 = 16;
+pub const IRRADIANCE_SOURCE_SIZE: u32 = 32;
+
+// NOTE: cosine weighted average of the environment around every texel direction, i.e.
+// irradiance / pi, gamma encoded again so it can be multiplied with the albedo like the
+// flat ambient. Each source texel is weighted by the solid angle it covers
+pub fn compute_irradiance_faces(size: u32, faces: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    let n = IRRADIANCE_SOURCE_SIZE;
+    let solid_angle = |s: f32, t: f32| (2.0 / n as f32).powi(2) / (1.0 + s * s + t * t).powf(1.5);
+    let mut samples = vec![];
+    for (face, pixels) in faces.iter().enumerate() {
+        let image = image::RgbaImage::from_raw(size, size, pixels.clone()).unwrap();
+        let image = image::imageops::resize(&image, n, n, image::imageops::FilterType::Triangle);
+        for (x, y, texel) in image.enumerate_pixels() {
+            let s = 2.0 * (x as f32 + 0.5) / n as f32 - 1.0;
+            let t = 2.0 * (y as f32 + 0.5) / n as f32 - 1.0;
+            let encoded = Vec3A::new(texel[0] as f32, texel[1] as f32, texel[2] as f32) / 255.0;
+            let radiance = encoded.powf(2.2);
+            samples.push((
+                cube_face_dir(face, s, t),
+                radiance * solid_angle(s, t),
+                solid_angle(s, t),
+            ));
+        }
+    }
+    (0..6)
+        .map(|face| {
+            let mut pixels = Vec::with_capacity((IRRADIANCE_SIZE * IRRADIANCE_SIZE * 4) as usize);
+            for y in 0..IRRADIANCE_SIZE {
+                for x in 0..IRRADIANCE_SIZE {
+                    let s = 2.0 * (x as f32 + 0.5) / IRRADIANCE_SIZE as f32 - 1.0;
+                    let t = 2.0 * (y as f32 + 0.5) / IRRADIANCE_SIZE as f32 - 1.0;
+                    let normal = cube_face_dir(face, s, t);
+                    let mut sum = Vec3A::ZERO;
+                    let mut weight = 0.0;
+                    for (dir, weighted_radiance, texel_solid_angle) in samples.iter() {
+                        let cos = normal.dot(*dir);
+                        if cos > 0.0 {
+                            sum += *weighted_radiance * cos;
+                            weight += texel_solid_angle * cos;
+                        }
+                    }
+                    let average = (sum / weight.max(1e-6)).powf(1.0 / 2.2);
+                    let encode = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+                    pixels.extend([encode(average.x), encode(average.y), encode(average.z), 255]);
+                }
+            }
+            pixels
+        })
+        .collect()
+}
+
+// NOTE: file stems of the faces in a skybox folder in layer order, either name works
+pub const SKYBOX_FACE_NAMES: [[&str; 2]; 6] = [
+    ["px", "right"],
+    ["nx", "left"],
+    ["py", "top"],
+    ["ny", "bottom"],
+    ["pz", "front"],
+    ["nz", "back"],
+];
+
+// NOTE: path is a folder with the six faces or a single equirectangular image which is
+// resampled to faces here
+pub fn load_skybox(
+    ctx: &gpu::Context,
+    upload_batch: &mut UploadBatch,
+    path: &std::path::Path,
+) -> Option<Skybox> {
+    let (size, faces) = if path.is_dir() {
+        load_skybox_faces(path)?
+    } else {
+        let image = match image::open(path) {
+            Ok(image) => image,
+            Err(err) => {
+                dbg!(format!("could not load skybox {}: {err}", path.display()));
+                return None;
+            }
+        };
+        equirect_to_cube_faces(&skybox_image_rgba8(image))
+    };
+    let irradiance = compute_irradiance_faces(size, &faces);
+    Some(Skybox {
+        environment: CubeTexture::upload(ctx, upload_batch, "skybox", size, &faces),
+        irradiance: CubeTexture::upload(
+            ctx,
+            upload_batch,
+            "skybox irradiance",
+            IRRADIANCE_SIZE,
+            &irradiance,
+        ),
+    })
+}
+
+pub fn load_skybox_faces(dir: &std::path::Path) -> Option<(u32, Vec<Vec<u8>>)> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            dbg!(format!(
+                "could not read skybox folder {}: {err}",
+                dir.display()
+            ));
+            return None;
+        }
+    };
+    let paths: Vec<std::path::PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+    let mut size = 0;
+    let mut faces = vec![];
+    for names in SKYBOX_FACE_NAMES {
+        let path = paths.iter().find(|path| {
+            path.file_stem()
+                .and_then(|stem| stem.to_str())
+                .is_some_and(|stem| names.iter().any(|name| stem.eq_ignore_ascii_case(name)))
+        });
+        let Some(path) = path else {
+            dbg!(format!(
+                "skybox folder {} has no {} face",
+                dir.display(),
+                names[0]
+            ));
+            return None;
+        };
+        let image = match image::open(path) {
+            Ok(image) => skybox_image_rgba8(image),
+            Err(err) => {
+                dbg!(format!(
+                    "could not load skybox face {}: {err}",
+                    path.display()
+                ));
+                return None;
+            }
+        };
+        // NOTE: the first face decides the size, the rest are resized to it
+        if faces.is_empty() {
+            size = image.width().max(image.height());
+        }
+        let image = if image.dimensions() == (size, size) {
+            image
+        } else {
+            image::imageops::resize(&image, size, size, image::imageops::FilterType::Triangle)
+        };
+        faces.push(image.into_raw());
+    }
+    Some((size, faces))
+}
+
+// NOTE: hdr images hold linear radiance, they are tonemapped and gamma encoded here so
+// they end up like any other rgba8 image, which the shaders take to the power of 2.2
+pub fn skybox_image_rgba8(image: image::DynamicImage) -> image::RgbaImage {
+    match image {
+        image::DynamicImage::ImageRgb32F(_) | image::DynamicImage::ImageRgba32F(_) => {
+            let hdr = image.to_rgba32f();
+            let encode = |c: f32| {
+                let c = c.max(0.0);
+                ((c / (1.0 + c)).powf(1.0 / 2.2) * 255.0).round() as u8
+            };
+            image::RgbaImage::from_fn(hdr.width(), hdr.height(), |x, y| {
+                let [r, g, b, _] = hdr.get_pixel(x, y).0;
+                image::Rgba([encode(r), encode(g), encode(b), 255])
+            })
+        }
+        image => image.to_rgba8(),
+    }
+}
+
+// NOTE: world direction through s, t in -1..1 on a cube face, the face table cube map
+// sampling uses
+pub fn cube_face_dir(face: usize, s: f32, t: f32) -> Vec3A {
+    let dir = match face {
+        0 => Vec3A::new(1.0, -t, -s),
+        1 => Vec3A::new(-1.0, -t, s),
+        2 => Vec3A::new(s, 1.0, t),
+        3 => Vec3A::new(s, -1.0, -t),
+        4 => Vec3A::new(s, -t, 1.0),
+        _ => Vec3A::new(-s, -t, -1.0),
+    };
+    dir.normalize()
+}
+
+// NOTE: faces are a quarter of the equirect width, about the same texel density at the
+// horizon
+pub fn equirect_to_cube_faces(image: &image::RgbaImage) -> (u32, Vec<Vec<u8>>) {
+    let size = (image.width() / 4).max(1);
+    let faces = (0..6)
+        .map(|face| {
+            let mut pixels = Vec::with_capacity((size * size * 4) as usize);
+            for y in 0..size {
+                for x in 0..size {
+                    let s = 2.0 * (x as f32 + 0.5) / size as f32 - 1.0;
+                    let t = 2.0 * (y as f32 + 0.5) / size as f32 - 1.0;
+                    pixels.extend(sample_equirect(image, cube_face_dir(face, s, t)));
+                }
+            }
+            pixels
+        })
+        .collect();
+    (size, faces)
+}
+
+// NOTE: bilinear, wraps around horizontally and clamps at the poles. u = 0.5 looks
+// down -z like the default camera
+pub fn sample_equirect(image: &image::RgbaImage, dir: Vec3A) -> [u8; 4] {
+    let (w, h) = image.dimensions();
+    let u = 0.5 + dir.x.atan2(-dir.z) / std::f32::consts::TAU;
+    let v = dir.y.clamp(-1.0, 1.0).acos() / std::f32::consts::PI;
+    let x = u * w as f32 - 0.5;
+    let y = (v * h as f32 - 0.5).clamp(0.0, (h - 1) as f32);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let texel = |x: i64, y: i64| {
+        let x = x.rem_euclid(w as i64) as u32;
+        let y = y.min(h as i64 - 1) as u32;
+        image.get_pixel(x, y).0.map(|c| c as f32)
+    };
+    let (x0, y0) = (x0 as i64, y0 as i64);
+    let [a, b, c, d] = [
+        texel(x0, y0),
+        texel(x0 + 1, y0),
+        texel(x0, y0 + 1),
+        texel(x0 + 1, y0 + 1),
+    ];
+    std::array::from_fn(|i| {
+        let top = a[i] + (b[i] - a[i]) * fx;
+        let bottom = c[i] + (d[i] - c[i]) * fx;
+        (top + (bottom - top) * fy).round() as u8
+    })
+}
+
+// NOTE: all diffuse textures of the materials end up as layers of one array texture so a
+// mesh never has to switch textures between material runs, textures are shared between
+// materials referencing the same file and materials whose texture fails to load fall
+// back to their flat diffuse color
+pub fn upload_materials(
+    ctx: &gpu::Context,
+    upload_batch: &mut UploadBatch,
+    name: &str,
+    materials: &[Material],
+) -> (Vec<GpuMaterial>, Option<TextureStuff>) {
+    let mut layers = vec![];
+    let mut loaded = std::collections::HashMap::<std::path::PathBuf, Option<u32>>::new();
+    let gpu_materials = materials
+        .iter()
+        .map(|material| {
+            let layer = material.diffuse_texture.as_ref().and_then(|path| {
+                *loaded.entry(path.clone()).or_insert_with(|| {
+                    let pixels = load_texture_rgba8(path, MATERIAL_TEXTURE_SIZE)?;
+                    layers.push(pixels);
+                    Some(layers.len() as u32 - 1)
+                })
+            });
+            GpuMaterial {
+                diffuse: material.diffuse,
+                layer,
+                roughness: material.roughness,
+                metalness: material.metalness,
+            }
+        })
+        .collect();
+
+    let texture_array = (!layers.is_empty()).then(|| {
+        upload_texture_array_rgba8(
+            ctx,
+            upload_batch,
+            format!("{name} materials").as_str(),
+            MATERIAL_TEXTURE_SIZE,
+            MATERIAL_TEXTURE_SIZE,
+            &layers,
+            true,
+        )
+    });
+    (gpu_materials, texture_array)
+}
+
+// NOTE: render target + sampled texture with nearest sampling, like the g-buffer levels
+pub fn create_render_texture(
+    ctx: &gpu::Context,
+    name: &str,
+    format: gpu::TextureFormat,
+    size: gpu::Extent,
+) -> TextureStuff {
+    let texture = ctx.create_texture(gpu::TextureDesc {
+        name: format!("{name} texture").as_str(),
+        format,
+        size,
+        array_layer_count: 1,
+        mip_level_count: 1,
+        dimension: gpu::TextureDimension::D2,
+        usage: gpu::TextureUsage::TARGET | gpu::TextureUsage::RESOURCE,
+    });
+    let view = ctx.create_texture_view(
+        texture,
+        gpu::TextureViewDesc {
+            name: format!("{name} view").as_str(),
+            format,
+            dimension: gpu::ViewDimension::D2,
+            subresources: &Default::default(),
+        },
+    );
+    let sampler = ctx.create_sampler(gpu::SamplerDesc {
+        name: format!("{name} sampler").as_str(),
+        address_modes: Default::default(),
+        mag_filter: gpu::FilterMode::Nearest,
+        min_filter: gpu::FilterMode::Nearest,
+        mipmap_filter: gpu::FilterMode::Nearest,
+        ..Default::default()
+    });
+    TextureStuff {
+        texture,
+        view,
+        sampler,
+        size,
+    }
+}
+
+// NOTE: written by the compute test, RESOURCE so it can be looked at in a capture
+pub fn create_compute_test_texture(ctx: &gpu::Context) -> TextureStuff {
+    let format = gpu::TextureFormat::Rgba8Unorm;
+    let size = gpu::Extent {
+        width: COMPUTE_TEST_TEXTURE_SIZE,
+        height: COMPUTE_TEST_TEXTURE_SIZE,
+        depth: 1,
+    };
+    let texture = ctx.create_texture(gpu::TextureDesc {
+        name: "compute test texture",
+        format,
+        size,
+        array_layer_count: 1,
+        mip_level_count: 1,
+        dimension: gpu::TextureDimension::D2,
+        usage: gpu::TextureUsage::STORAGE | gpu::TextureUsage::RESOURCE,
+    });
+    let view = ctx.create_texture_view(
+        texture,
+        gpu::TextureViewDesc {
+            name: "compute test view",
+            format,
+            dimension: gpu::ViewDimension::D2,
+            subresources: &Default::default(),
+        },
+    );
+    let sampler = ctx.create_sampler(gpu::SamplerDesc {
+        name: "compute test sampler",
+        ..Default::default()
+    });
+    TextureStuff {
+        texture,
+        view,
+        sampler,
+        size,
+    }
+}
+
+pub struct ShadowMap {
+    // NOTE: D2Array view over all cascades for the light pass
+    pub array: TextureStuff,
+    // NOTE: one D2 view per cascade to render into
+    pub layer_views: [gpu::TextureView; MAX_SHADOW_CASCADES],
+}
+
+// NOTE: depth only, sampled with a comparison sampler for pcf
+pub fn create_shadow_map(ctx: &gpu::Context) -> ShadowMap {
+    let size = gpu::Extent {
+        width: SHADOW_MAP_SIZE,
+        height: SHADOW_MAP_SIZE,
+        depth: 1,
+    };
+    let texture = ctx.create_texture(gpu::TextureDesc {
+        name: "shadow map texture",
+        format: gpu::TextureFormat::Depth32Float,
+        size,
+        array_layer_count: MAX_SHADOW_CASCADES as u32,
+        mip_level_count: 1,
+        dimension: gpu::TextureDimension::D2,
+        usage: gpu::TextureUsage::TARGET | gpu::TextureUsage::RESOURCE,
+    });
+    let view = ctx.create_texture_view(
+        texture,
+        gpu::TextureViewDesc {
+            name: "shadow map view",
+            format: gpu::TextureFormat::Depth32Float,
+            dimension: gpu::ViewDimension::D2Array,
+            subresources: &Default::default(),
+        },
+    );
+    let layer_views = std::array::from_fn(|i| {
+        ctx.create_texture_view(
+            texture,
+            gpu::TextureViewDesc {
+                name: &format!("shadow map cascade {i} view"),
+                format: gpu::TextureFormat::Depth32Float,
+                dimension: gpu::ViewDimension::D2,
+                subresources: &gpu::TextureSubresources {
+                    base_array_layer: i as u32,
+                    array_layer_count: std::num::NonZeroU32::new(1),
+                    ..Default::default()
+                },
+            },
+        )
+    });
+    let sampler = ctx.create_sampler(gpu::SamplerDesc {
+        name: "shadow map sampler",
+        address_modes: [gpu::AddressMode::ClampToEdge; 3],
+        mag_filter: gpu::FilterMode::Linear,
+        min_filter: gpu::FilterMode::Linear,
+        mipmap_filter: gpu::FilterMode::Nearest,
+        compare: Some(gpu::CompareFunction::LessEqual),
+        ..Default::default()
+    });
+    ShadowMap {
+        array: TextureStuff {
+            texture,
+            view,
+            sampler,
+            size,
+        },
+        layer_views,
+    }
+}
+
+// NOTE: halton sequences in base 2 and 3 give well spread sub pixel jitter positions
+pub const TAA_JITTER_PHASES: u32 = 8;
+
+pub fn halton(mut index: u32, base: u32) -> f32 {
+    let mut f = 1.0;
+    let mut r = 0.0;
+    while index > 0 {
+        f /= base as f32;
+        r += f * (index % base) as f32;
+        index /= base;
+    }
+    r
+}
+
+// NOTE: jitter in ndc, within half a pixel around the pixel center
+pub fn taa_jitter(frame_index: u32, screen_size: gpu::Extent) -> Vec2 {
+    // NOTE: index 0 is (0, 0) in both bases, start at 1
+    let i = frame_index % TAA_JITTER_PHASES + 1;
+    let offset = Vec2::new(halton(i, 2), halton(i, 3)) - 0.5;
+    offset * 2.0 / Vec2::new(screen_size.width as f32, screen_size.height as f32)
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct TaaUniform {
+    pub history_weight: f32,
+    pub history_valid: u32,
+    pub pad0: u32,
+    pub pad1: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct DofUniform {
+    pub focus_distance: f32,
+    pub aperture: f32,
+    pub max_coc_px: f32,
+    pub px_per_unit: f32,
+}
+
+impl DofUniform {
+    pub fn new(settings: &DofSettings, camera: &Camera, target_height: u32) -> Self {
+        Self {
+            focus_distance: settings.focus_distance.max(camera.near),
+            aperture: settings.aperture.max(0.0),
+            max_coc_px: settings.max_coc_px.max(1.0),
+            px_per_unit: 0.5 * target_height as f32 / (0.5 * camera.vfov_rad).tan(),
+        }
+    }
+}
+
+#[derive(blade_macros::ShaderData)]
+pub struct DofParams {
+    pub globals: Globals,
+    pub dof: DofUniform,
+    pub color_view: gpu::TextureView,
+    pub color_sampler: gpu::Sampler,
+    pub depth_view: gpu::TextureView,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct SsrUniform {
+    pub proj: [[f32; 4]; 4],
+    pub view_to_world: [[f32; 4]; 4],
+    pub max_distance: f32,
+    pub thickness: f32,
+    pub steps: u32,
+    pub pad0: u32,
+}
+
+const _: () = assert!(std::mem::size_of::<SsrUniform>() == 144);
+
+impl SsrUniform {
+    pub fn new(settings: &SsrSettings, camera: &Camera) -> Self {
+        Self {
+            // NOTE: jittered, the g-buffer being marched was drawn with the same
+            proj: camera.projection().to_cols_array_2d(),
+            // NOTE: reflection directions only, the ambient fallback is looked up in world space
+            view_to_world: camera.view_rot_only().inverse().to_cols_array_2d(),
+            max_distance: settings.max_distance.max(1e-3),
+            thickness: settings.thickness.max(1e-3),
+            steps: settings.steps.max(1),
+            pad0: 0,
+        }
+    }
+}
+
+#[derive(blade_macros::ShaderData)]
+pub struct SsrParams {
+    pub globals: Globals,
+    pub ssr: SsrUniform,
+    pub ambient: AmbientUniform,
+    pub color_view: gpu::TextureView,
+    pub color_sampler: gpu::Sampler,
+    pub depth_view: gpu::TextureView,
+    pub normal_view: gpu::TextureView,
+    pub normal_sampler: gpu::Sampler,
+    pub albedo_view: gpu::TextureView,
+    pub albedo_sampler: gpu::Sampler,
+    pub material_view: gpu::TextureView,
+    pub material_sampler: gpu::Sampler,
+    pub irradiance_view: gpu::TextureView,
+    pub irradiance_sampler: gpu::Sampler,
+}
+
+#[derive(blade_macros::ShaderData)]
+pub struct TaaParams {
+    pub globals: Globals,
+    pub taa: TaaUniform,
+    pub current_view: gpu::TextureView,
+    pub current_sampler: gpu::Sampler,
+    pub history_view: gpu::TextureView,
+    pub history_sampler: gpu::Sampler,
+    pub depth_view: gpu::TextureView,
+}
+
+pub struct TaaTextures {
+    // NOTE: the light pass renders here instead of the frame while taa is on
+    pub current: TextureStuff,
+    // NOTE: ping pong, the resolve reads history[history_index] and writes the other one
+    pub history: [TextureStuff; 2],
+    pub history_index: usize,
+    pub history_valid: bool,
+    pub frame_index: u32,
+}
+
+// NOTE: same format as the surface so the light pipeline can render into current as is
+pub fn create_taa_textures(
+    ctx: &gpu::Context,
+    screen_size: gpu::Extent,
+    format: gpu::TextureFormat,
+) -> TaaTextures {
+    let create = |name: &str| {
+        let texture = ctx.create_texture(gpu::TextureDesc {
+            name: &format!("taa {name} texture"),
+            format,
+            size: screen_size,
+            array_layer_count: 1,
+            mip_level_count: 1,
+            dimension: gpu::TextureDimension::D2,
+            usage: gpu::TextureUsage::TARGET | gpu::TextureUsage::RESOURCE,
+        });
+        let view = ctx.create_texture_view(
+            texture,
+            gpu::TextureViewDesc {
+                name: &format!("taa {name} view"),
+                format,
+                dimension: gpu::ViewDimension::D2,
+                subresources: &Default::default(),
+            },
+        );
+        // NOTE: reprojected history lands between texels, filter it
+        let sampler = ctx.create_sampler(gpu::SamplerDesc {
+            name: &format!("taa {name} sampler"),
+            address_modes: [gpu::AddressMode::ClampToEdge; 3],
+            mag_filter: gpu::FilterMode::Linear,
+            min_filter: gpu::FilterMode::Linear,
+            mipmap_filter: gpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        TextureStuff {
+            texture,
+            view,
+            sampler,
+            size: screen_size,
+        }
+    };
+    TaaTextures {
+        current: create("current"),
+        history: [create("history 0"), create("history 1")],
+        history_index: 0,
+        history_valid: false,
+        frame_index: 0,
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct TssaoUniform {
+    pub alpha: f32,
+    pub history_valid: u32,
+    pub depth_tolerance: f32,
+    pub pad: u32,
+}
+
+#[derive(blade_macros::ShaderData)]
+pub struct TssaoParams {
+    pub globals: Globals,
+    pub tssao: TssaoUniform,
+    pub current_ao_view: gpu::TextureView,
+    pub current_ao_sampler: gpu::Sampler,
+    pub history_view: gpu::TextureView,
+    pub history_sampler: gpu::Sampler,
+    pub history_depth_view: gpu::TextureView,
+    pub depth_view: gpu::TextureView,
+}
+
+// NOTE: half floats so the reprojected history can be filtered, rgba32 float can't be
+pub const TSSAO_FORMAT: gpu::TextureFormat = gpu::TextureFormat::Rgba16Float;
+pub const TSSAO_DEPTH_FORMAT: gpu::TextureFormat = gpu::TextureFormat::R32Float;
+
+// NOTE: accumulated ao and the view depth it was accumulated at, full resolution. Ping pong,
+// the resolve reads [history_index] and writes the other one, which the light pass reads
+pub struct TssaoHistory {
+    pub history: [TextureStuff; 2],
+    pub history_depth: [TextureStuff; 2],
+    pub history_index: usize,
+    pub history_valid: bool,
+    pub frame_index: u32,
+}
+
+impl TssaoHistory {
+    pub fn resolved(&self) -> &TextureStuff {
+        &self.history[1 - self.history_index]
+    }
+}
+
+pub fn create_tssao_history(ctx: &gpu::Context, screen_size: gpu::Extent) -> TssaoHistory {
+    let create = |name: &str, format: gpu::TextureFormat, filter: gpu::FilterMode| {
+        let texture = ctx.create_texture(gpu::TextureDesc {
+            name: &format!("tssao {name} texture"),
+            format,
+            size: screen_size,
+            array_layer_count: 1,
+            mip_level_count: 1,
+            dimension: gpu::TextureDimension::D2,
+            usage: gpu::TextureUsage::TARGET | gpu::TextureUsage::RESOURCE,
+        });
+        let view = ctx.create_texture_view(
+            texture,
+            gpu::TextureViewDesc {
+                name: &format!("tssao {name} view"),
+                format,
+                dimension: gpu::ViewDimension::D2,
+                subresources: &Default::default(),
+            },
+        );
+        let sampler = ctx.create_sampler(gpu::SamplerDesc {
+            name: &format!("tssao {name} sampler"),
+            address_modes: [gpu::AddressMode::ClampToEdge; 3],
+            mag_filter: filter,
+            min_filter: filter,
+            mipmap_filter: gpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        TextureStuff {
+            texture,
+            view,
+            sampler,
+            size: screen_size,
+        }
+    };
+    let linear = gpu::FilterMode::Linear;
+    let nearest = gpu::FilterMode::Nearest;
+    TssaoHistory {
+        history: [
+            create("history 0", TSSAO_FORMAT, linear),
+            create("history 1", TSSAO_FORMAT, linear),
+        ],
+        history_depth: [
+            create("history depth 0", TSSAO_DEPTH_FORMAT, nearest),
+            create("history depth 1", TSSAO_DEPTH_FORMAT, nearest),
+        ],
+        history_index: 0,
+        history_valid: false,
+        frame_index: 0,
+    }
+}
+
+// NOTE: shared memory copies of the full resolution g-buffer, see write_gbuffer_dump
+pub struct GBufferReadback {
+    pub depth: gpu::Buffer,
+    pub normal: gpu::Buffer,
+    pub normal_encoding: NormalEncoding,
+    pub size: gpu::Extent,
+}
+
+impl GBufferReadback {
+    pub fn destroy(&self, ctx: &gpu::Context) {
+        ctx.destroy_buffer(self.depth);
+        ctx.destroy_buffer(self.normal);
+    }
+}
+
+// NOTE: records texture -> buffer copies into the current frame, the data is only valid
+// once the frame has been submitted and waited for
+pub fn record_gbuffer_readback(
+    ctx: &gpu::Context,
+    encoder: &mut gpu::CommandEncoder,
+    textures: &DepthNormalTexture,
+    normal_encoding: NormalEncoding,
+) -> GBufferReadback {
+    let size = textures.depth.size;
+    let texels = (size.width * size.height) as u64;
+    let normal_texel_size = normal_encoding.texel_size();
+    let create = |name: &str, texel_size: u32| {
+        ctx.create_buffer(gpu::BufferDesc {
+            name,
+            size: texels * texel_size as u64,
+            memory: gpu::Memory::Shared,
+        })
+    };
+    let readback = GBufferReadback {
+        depth: create("g-buffer depth readback", 4),
+        normal: create("g-buffer normal readback", normal_texel_size),
+        normal_encoding,
+        size,
+    };
+    if let mut transfer = encoder.transfer("g-buffer readback") {
+        let copies = [
+            (textures.depth.texture, readback.depth, 4),
+            (textures.normal.texture, readback.normal, normal_texel_size),
+        ];
+        for (texture, buffer, texel_size) in copies {
+            transfer.copy_texture_to_buffer(
+                gpu::TexturePiece {
+                    texture,
+                    mip_level: 0,
+                    array_layer: 0,
+                    origin: [0; 3],
+                },
+                buffer.into(),
+                size.width * texel_size,
+                size,
+            );
+        }
+    }
+    readback
+}
+
+// NOTE: shared memory copy of the one depth texel asked for with request, recorded after
+// the geometry pass only on frames that have a request. finish_frame waits for the gpu, so
+// between frames it holds that texel of the previous frame, see State::sample_depth. width
+// and height follow the depth texture, the buffer itself doesn't depend on its size
+pub struct ReadbackDepthBuffer {
+    pub buf: gpu::Buffer,
+    pub width: u32,
+    pub height: u32,
+    // NOTE: pixel to copy in the next record and the texel the buffer holds
+    pub pending: Option<Vec2>,
+    pub recorded: Option<[u32; 2]>,
+}
+
+impl ReadbackDepthBuffer {
+    pub fn new(ctx: &gpu::Context, size: gpu::Extent) -> Self {
+        let buf = ctx.create_buffer(gpu::BufferDesc {
+            name: "depth readback",
+            size: 4,
+            memory: gpu::Memory::Shared,
+        });
+        Self {
+            buf,
+            width: size.width,
+            height: size.height,
+            pending: None,
+            recorded: None,
+        }
+    }
+
+    pub fn destroy(&self, ctx: &gpu::Context) {
+        ctx.destroy_buffer(self.buf);
+    }
+
+    // NOTE: pixel coordinates of the depth texture, 0,0 top left, clamped to its size
+    pub fn texel(&self, pixel: Vec2) -> [u32; 2] {
+        let max = UVec2::new(self.width, self.height).saturating_sub(UVec2::ONE);
+        pixel.max(Vec2::ZERO).as_uvec2().min(max).to_array()
+    }
+
+    pub fn center(&self) -> Vec2 {
+        0.5 * Vec2::new(self.width as f32, self.height as f32)
+    }
+
+    pub fn request(&mut self, pixel: Vec2) {
+        self.pending = Some(pixel);
+    }
+
+    pub fn record(&mut self, encoder: &mut gpu::CommandEncoder, depth: &TextureStuff) {
+        self.width = depth.size.width;
+        self.height = depth.size.height;
+        let Some(pixel) = self.pending.take() else {
+            return;
+        };
+        let [x, y] = self.texel(pixel);
+        if let mut transfer = encoder.transfer("depth readback") {
+            transfer.copy_texture_to_buffer(
+                gpu::TexturePiece {
+                    texture: depth.texture,
+                    mip_level: 0,
+                    array_layer: 0,
+                    origin: [x, y, 0],
+                },
+                self.buf.into(),
+                4,
+                gpu::Extent {
+                    width: 1,
+                    height: 1,
+                    depth: 1,
+                },
+            );
+        }
+        self.recorded = Some([x, y]);
+    }
+
+    // NOTE: raw depth buffer value under pixel, None unless the last record copied that texel
+    pub fn raw_depth(&self, pixel: Vec2) -> Option<f32> {
+        (self.recorded == Some(self.texel(pixel)))
+            .then(|| unsafe { *(self.buf.data() as *const f32) })
+    }
+}
+
+// NOTE: raw floats go to exr, the png next to each is remapped to something viewable:
+// depth linearized and scaled by the furthest geometry, view space positions scaled by
+// their bounding box and normals from -1..1 to 0..1. Background pixels are black
+pub fn write_gbuffer_dump(
+    readback: &GBufferReadback,
+    dir: &std::path::Path,
+    prefix: &str,
+    camera: &Camera,
+) -> Result<(), String> {
+    std::fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+    let width = readback.size.width;
+    let height = readback.size.height;
+    let texels = (width * height) as usize;
+    // NOTE: shared memory created with exactly this many floats in record_gbuffer_readback
+    let read = |buffer: gpu::Buffer, floats: usize| -> Vec<f32> {
+        unsafe { std::slice::from_raw_parts(buffer.data() as *const f32, floats) }.to_vec()
+    };
+    let depth = read(readback.depth, texels);
+    // NOTE: rebuilt from the depth at the texel centers like view_pos.wgsl, with the same
+    // jittered projection the frame was drawn with. The background gets the (1, 1, 1, 1)
+    // the position target used to be cleared to
+    let inv_projection = camera.projection().inverse();
+    let far_depth = if camera.reverse_z { 0.0 } else { 1.0 };
+    let pos: Vec<f32> = (0..texels)
+        .flat_map(|i| {
+            if depth[i] == far_depth {
+                return [1.0; 4];
+            }
+            let x = (i % width as usize) as f32 + 0.5;
+            let y = (i / width as usize) as f32 + 0.5;
+            let ndc = Vec3::new(
+                2.0 * x / width as f32 - 1.0,
+                1.0 - 2.0 * y / height as f32,
+                depth[i],
+            );
+            let p = inv_projection.project_point3(ndc);
+            [p.x, p.y, p.z, 1.0]
+        })
+        .collect();
+    // NOTE: decoded to full floats so the exr looks the same for both normal encodings
+    let normal_texel_size = readback.normal_encoding.texel_size() as usize;
+    let normal_bytes =
+        unsafe { std::slice::from_raw_parts(readback.normal.data(), texels * normal_texel_size) };
+    let normal: Vec<f32> = normal_bytes
+        .chunks_exact(normal_texel_size)
+        .flat_map(|texel| {
+            let n = readback.normal_encoding.decode_texel(texel);
+            [n.x, n.y, n.z, 1.0]
+        })
+        .collect();
+
+    let save_exr = |name: &str, channels: Vec<f32>| -> Result<(), String> {
+        let path = dir.join(format!("{prefix}_{name}.exr"));
+        let image = image::Rgba32FImage::from_raw(width, height, channels)
+            .ok_or_else(|| format!("{name} readback has the wrong size"))?;
+        image
+            .save(&path)
+            .map_err(|err| format!("{}: {err}", path.display()))
+    };
+    let save_png = |name: &str, rgb: &dyn Fn(usize) -> Option<Vec3A>| -> Result<(), String> {
+        let path = dir.join(format!("{prefix}_{name}.png"));
+        let image = image::RgbImage::from_fn(width, height, |x, y| {
+            let c = rgb((y * width + x) as usize).unwrap_or(Vec3A::ZERO);
+            let c = (c.clamp(Vec3A::ZERO, Vec3A::ONE) * 255.0).round();
+            image::Rgb([c.x as u8, c.y as u8, c.z as u8])
+        });
+        image
+            .save(&path)
+            .map_err(|err| format!("{}: {err}", path.display()))
+    };
+
+    let linear_depth = |i: usize| {
+        let d = if camera.reverse_z {
+            1.0 - depth[i]
+        } else {
+            depth[i]
+        };
+        (d < 1.0).then(|| camera.linear_depth(depth[i]))
+    };
+    let max_depth = (0..texels)
+        .filter_map(linear_depth)
+        .fold(0.0_f32, f32::max)
+        .max(camera.near);
+    save_exr(
+        "depth",
+        depth.iter().flat_map(|d| [*d, *d, *d, 1.0]).collect(),
+    )?;
+    save_png("depth", &|i| {
+        linear_depth(i).map(|d| Vec3A::splat(d / max_depth))
+    })?;
+
+    // NOTE: rh view space, covered pixels are in front of the camera
+    let view_pos = |i: usize| {
+        let p = Vec3A::new(pos[4 * i], pos[4 * i + 1], pos[4 * i + 2]);
+        (p.z < 0.0).then_some(p)
+    };
+    let (pos_min, pos_max) = (0..texels)
+        .filter_map(view_pos)
+        .fold((Vec3A::MAX, Vec3A::MIN), |(lo, hi), p| {
+            (lo.min(p), hi.max(p))
+        });
+    let pos_extent = (pos_max - pos_min).max(Vec3A::splat(1e-6));
+    save_exr("pos", pos.clone())?;
+    save_png("pos", &|i| view_pos(i).map(|p| (p - pos_min) / pos_extent))?;
+
+    save_exr("normal", normal.clone())?;
+    save_png("normal", &|i| {
+        view_pos(i)
+            .map(|_| Vec3A::new(normal[4 * i], normal[4 * i + 1], normal[4 * i + 2]) * 0.5 + 0.5)
+    })?;
+    Ok(())
+}
+
+// NOTE: snorm since the bent normal is a view space direction in -1..1
+pub const BENT_NORMAL_FORMAT: gpu::TextureFormat = gpu::TextureFormat::Rgba8Snorm;
+
+pub struct AOTextures {
+    pub textures: Vec<TextureStuff>,
+    // NOTE: second target of the calc ao pass, one per level like textures
+    pub bent_normals: Vec<TextureStuff>,
+    pub textures_after_blur: Vec<TextureStuff>,
+    pub dummy_texture: TextureStuff,
+}
+
+pub struct DepthTextures {
+    pub texture_stuffs: Vec<TextureStuff>,
+}
+
+pub fn create_downsample_and_ao_textures(
+    ctx: &gpu::Context,
+    screen_size: gpu::Extent,
+    normal_encoding: NormalEncoding,
+) -> (DownsampleTextures, AOTextures) {
+    let mut depth_normal_textures = vec![];
+    let mut ao_textures = vec![];
+    let mut ao_textures_blur = vec![];
+    let mut bent_normals = vec![];
+
+    let width = screen_size.width;
+    let height = screen_size.height;
+
+    for i in 0..NUM_AO_TEXTURES {
+        let pow_2 = 1 << i;
+        let width_i = width / pow_2;
+        let height_i = height / pow_2;
+
+        let extent_i = gpu::Extent {
+            width: width_i,
+            height: height_i,
+            depth: 1,
+        };
+
+        let depth_texture_i = ctx.create_texture(gpu::TextureDesc {
+            name: format!("depth texture {i}").as_str(),
+            format: gpu::TextureFormat::Depth32Float,
+            size: extent_i,
+            array_layer_count: 1,
+            mip_level_count: 1,
+            dimension: gpu::TextureDimension::D2,
+            usage: gpu::TextureUsage::TARGET
+                | gpu::TextureUsage::RESOURCE
+                | gpu::TextureUsage::COPY,
+        });
+        let depth_view_i = ctx.create_texture_view(
+            depth_texture_i,
+            gpu::TextureViewDesc {
+                name: format!("depth view {i}").as_str(),
+                format: gpu::TextureFormat::Depth32Float,
+                dimension: gpu::ViewDimension::D2,
+                subresources: &Default::default(),
+            },
+        );
+        let depth_sampler_i = ctx.create_sampler(gpu::SamplerDesc {
+            name: format!("depth sampler {i}").as_str(),
+            // compare: Some(gpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        let depth_stuff_i = TextureStuff {
+            texture: depth_texture_i,
+            view: depth_view_i,
+            sampler: depth_sampler_i,
+            size: extent_i,
+        };
+
+        let normal_texture_i = ctx.create_texture(gpu::TextureDesc {
+            name: format!("normal texture {i}").as_str(),
+            format: normal_encoding.format(),
+            size: extent_i,
+            array_layer_count: 1,
+            mip_level_count: 1,
+            dimension: gpu::TextureDimension::D2,
+            usage: gpu::TextureUsage::TARGET
+                | gpu::TextureUsage::RESOURCE
+                | gpu::TextureUsage::COPY,
+        });
+        let normal_view_i = ctx.create_texture_view(
+            normal_texture_i,
+            gpu::TextureViewDesc {
+                name: format!("normal view {i}").as_str(),
+                format: normal_encoding.format(),
+                dimension: gpu::ViewDimension::D2,
+                subresources: &Default::default(),
+            },
+        );
+        let normal_sampler_i = ctx.create_sampler(gpu::SamplerDesc {
+            name: format!("normal sampler {i}").as_str(),
+            address_modes: Default::default(),
+            mag_filter: gpu::FilterMode::Nearest,
+            min_filter: gpu::FilterMode::Nearest,
+            mipmap_filter: gpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let normal_stuff_i = TextureStuff {
+            texture: normal_texture_i,
+            view: normal_view_i,
+            sampler: normal_sampler_i,
+            size: extent_i,
+        };
+
+        let depth_normal_i = DepthNormalTexture {
+            depth: depth_stuff_i,
+            normal: normal_stuff_i,
+        };
+
+        depth_normal_textures.push(depth_normal_i);
+
+        //NOTE: ao texture
+        let ao_texture_i = ctx.create_texture(gpu::TextureDesc {
+            name: format!("ao texture {i}").as_str(),
+            format: gpu::TextureFormat::Rgba32Float,
+            size: extent_i,
+            array_layer_count: 1,
+            mip_level_count: 1,
+            dimension: gpu::TextureDimension::D2,
+            usage: gpu::TextureUsage::TARGET | gpu::TextureUsage::RESOURCE,
+        });
+        let ao_view_i = ctx.create_texture_view(
+            ao_texture_i,
+            gpu::TextureViewDesc {
+                name: format!("ao view {i}").as_str(),
+                format: gpu::TextureFormat::Rgba32Float,
+                dimension: gpu::ViewDimension::D2,
+                subresources: &Default::default(),
+            },
+        );
+        let ao_sampler_i = ctx.create_sampler(gpu::SamplerDesc {
+            name: format!("ao sampler {i}").as_str(),
+            address_modes: Default::default(),
+            mag_filter: gpu::FilterMode::Nearest,
+            min_filter: gpu::FilterMode::Nearest,
+            mipmap_filter: gpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let ao_texture_stuff_i = TextureStuff {
+            texture: ao_texture_i,
+            view: ao_view_i,
+            sampler: ao_sampler_i,
+            size: extent_i,
+        };
+
+        ao_textures.push(ao_texture_stuff_i);
+
+        //NOTE: ao texture after blur
+        let ao_blur_texture_i = ctx.create_texture(gpu::TextureDesc {
+            name: format!("ao blur texture {i}").as_str(),
+            format: gpu::TextureFormat::Rgba32Float,
+            size: extent_i,
+            array_layer_count: 1,
+            mip_level_count: 1,
+            dimension: gpu::TextureDimension::D2,
+            usage: gpu::TextureUsage::TARGET | gpu::TextureUsage::RESOURCE,
+        });
+        let ao_blur_view_i = ctx.create_texture_view(
+            ao_blur_texture_i,
+            gpu::TextureViewDesc {
+                name: format!("ao blur view {i}").as_str(),
+                format: gpu::TextureFormat::Rgba32Float,
+                dimension: gpu::ViewDimension::D2,
+                subresources: &Default::default(),
+            },
+        );
+        let ao_blur_sampler_i = ctx.create_sampler(gpu::SamplerDesc {
+            name: format!("ao blur sampler {i}").as_str(),
+            address_modes: Default::default(),
+            mag_filter: gpu::FilterMode::Nearest,
+            min_filter: gpu::FilterMode::Nearest,
+            mipmap_filter: gpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let ao_blur_texture_stuff_i = TextureStuff {
+            texture: ao_blur_texture_i,
+            view: ao_blur_view_i,
+            sampler: ao_blur_sampler_i,
+            size: extent_i,
+        };
+
+        ao_textures_blur.push(ao_blur_texture_stuff_i);
+
+        bent_normals.push(create_render_texture(
+            ctx,
+            &format!("bent normal {i}"),
+            BENT_NORMAL_FORMAT,
+            extent_i,
+        ));
+    }
+
+    let ao_dummy_texture = {
+        let dummy_extent = gpu::Extent {
+            width: 1,
+            height: 1,
+            depth: 1,
+        };
+        let ao_texture_dummy = ctx.create_texture(gpu::TextureDesc {
+            name: format!("ao texture dummy").as_str(),
+            format: gpu::TextureFormat::Rgba32Float,
+            size: dummy_extent,
+            array_layer_count: 1,
+            mip_level_count: 1,
+            dimension: gpu::TextureDimension::D2,
+            usage: gpu::TextureUsage::TARGET | gpu::TextureUsage::RESOURCE,
+        });
+        let ao_view_dummy = ctx.create_texture_view(
+            ao_texture_dummy,
+            gpu::TextureViewDesc {
+                name: format!("ao view dummy").as_str(),
+                format: gpu::TextureFormat::Rgba32Float,
+                dimension: gpu::ViewDimension::D2,
+                subresources: &Default::default(),
+            },
+        );
+        let ao_sampler_dummy = ctx.create_sampler(gpu::SamplerDesc {
+            name: format!("ao sampler dummy").as_str(),
+            address_modes: Default::default(),
+            mag_filter: gpu::FilterMode::Nearest,
+            min_filter: gpu::FilterMode::Nearest,
+            mipmap_filter: gpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        TextureStuff {
+            texture: ao_texture_dummy,
+            view: ao_view_dummy,
+            sampler: ao_sampler_dummy,
+            size: dummy_extent,
+        }
+    };
+
+    let downsample_textures = DownsampleTextures {
+        textures: depth_normal_textures,
+        albedo: create_render_texture(ctx, "albedo", ALBEDO_FORMAT, screen_size),
+        material: create_render_texture(ctx, "material", MATERIAL_FORMAT, screen_size),
+        hi_z: create_hi_z_texture(ctx, screen_size),
+    };
+    let ao_textures = AOTextures {
+        textures: ao_textures,
+        bent_normals,
+        dummy_texture: ao_dummy_texture,
+        textures_after_blur: ao_textures_blur,
+    };
+
+    (downsample_textures, ao_textures)
+}
+
+pub struct Pipelines {
+    // pub shader_paths: Vec<std::path::Path>,
+    pub last_modified_shader_time: std::time::SystemTime,
+    pub vertex_format: VertexFormat,
+    pub normal_encoding: NormalEncoding,
+    pub reverse_z: bool,
+    pub geometry: gpu::RenderPipeline,
+    pub depth_prepass: gpu::RenderPipeline,
+    pub geometry_after_prepass: gpu::RenderPipeline,
+    pub wireframe: gpu::RenderPipeline,
+    pub backface: gpu::RenderPipeline,
+    pub selection: gpu::RenderPipeline,
+    pub shadow: gpu::RenderPipeline,
+    pub light: gpu::RenderPipeline,
+    pub depth_downsample: gpu::RenderPipeline,
+    pub calc_ao: gpu::RenderPipeline,
+    pub blur_ao: gpu::RenderPipeline,
+    pub depth_debug: gpu::RenderPipeline,
+    pub normal_debug: gpu::RenderPipeline,
+    pub loading: gpu::RenderPipeline,
+    pub taa_resolve: gpu::RenderPipeline,
+    pub tssao_resolve: gpu::RenderPipeline,
+    pub skybox: gpu::RenderPipeline,
+    pub dof: gpu::RenderPipeline,
+    pub ssr: gpu::RenderPipeline,
+    pub test_compute_pipeline: gpu::ComputePipeline,
+    pub hi_z_linear_depth: gpu::ComputePipeline,
+    pub hi_z_downsample: gpu::ComputePipeline,
+    pub ao_kernel_gizmos: gpu::ComputePipeline,
+}
+
+pub const DEPTH_DEBUG_SHADER_PATH: &str = "src/depth_debug.wgsl";
+pub const NORMAL_DEBUG_SHADER_PATH: &str = "src/normal_debug.wgsl";
+pub const LOADING_SHADER_PATH: &str = "src/loading.wgsl";
+pub const TAA_SHADER_PATH: &str = "src/taa.wgsl";
+pub const DOF_SHADER_PATH: &str = "src/dof.wgsl";
+pub const SSR_SHADER_PATH: &str = "src/ssr.wgsl";
+pub const TSSAO_SHADER_PATH: &str = "src/tssao.wgsl";
+pub const SKYBOX_SHADER_PATH: &str = "src/skybox.wgsl";
+pub const LIGHT_SHADER_PATH: &str = "src/light_shader.wgsl";
+pub const COMPUTE_TEST_SHADER_PATH: &str = "src/compute_shader.wgsl";
+pub const HI_Z_SHADER_PATH: &str = "src/hi_z.wgsl";
+// NOTE: matches @workgroup_size in compute_shader.wgsl
+pub const COMPUTE_TEST_WORKGROUP_SIZE: u32 = 8;
+pub const COMPUTE_TEST_TEXTURE_SIZE: u32 = 128;
+pub const NORMAL_ENCODING_SHADER_PATH: &str = "src/normal_encoding.wgsl";
+
+// NOTE: the encode_normal/decode_normal snippet plus the const picking the encoding,
+// prepended to every shader that reads or writes g-buffer normals
+pub fn read_normal_encoding_source(normal_encoding: NormalEncoding) -> Result<String, ShaderError> {
+    let source = read_shader_source(NORMAL_ENCODING_SHADER_PATH)?;
+    let full = normal_encoding == NormalEncoding::Full;
+    Ok(format!(
+        "const FULL_PRECISION_NORMALS: bool = {full};\n{source}"
+    ))
+}
+
+// NOTE: any wgsl file in src counts as well, so editing an included file also reloads
+pub fn last_time_shader_modified() -> std::time::SystemTime {
+    let shader_paths = [
+        GEOMETRY_SHADER_PATH,
+        LIGHT_SHADER_PATH,
+        FULLSCREEN_SHADER_PATH,
+        DEPTH_DEBUG_SHADER_PATH,
+        NORMAL_DEBUG_SHADER_PATH,
+        NORMAL_ENCODING_SHADER_PATH,
+        LOADING_SHADER_PATH,
+        TAA_SHADER_PATH,
+        TSSAO_SHADER_PATH,
+        SKYBOX_SHADER_PATH,
+    ];
+
+    let mut t = std::time::SystemTime::UNIX_EPOCH;
+    for path in shader_paths {
+        if let Ok(metadata) = std::path::Path::new(path).metadata() {
+            t = t.max(metadata.modified().unwrap());
+        }
+    }
+    if let Ok(entries) = std::fs::read_dir("src") {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "wgsl") {
+                if let Ok(modified) = entry.metadata().and_then(|metadata| metadata.modified()) {
+                    t = t.max(modified);
+                }
+            }
+        }
+    }
+
+    t
+}
+
+pub const GEOMETRY_SHADER_PATH: &str = "src/shader.wgsl";
+
+// NOTE: shared vertex stage + uv conventions for every full screen pass, each pass
+// shader starts with `// #include "fullscreen.wgsl"`
+pub const FULLSCREEN_SHADER_PATH: &str = "src/fullscreen.wgsl";
+
+#[derive(Debug)]
+pub enum ShaderError {
+    Io {
+        path: std::path::PathBuf,
+        err: std::io::Error,
+    },
+    Compile {
+        path: std::path::PathBuf,
+        message: String,
+    },
+    // NOTE: path is the include that was already being included further up
+    CircularInclude {
+        path: std::path::PathBuf,
+    },
+}
+
+impl std::fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io { path, err } if err.kind() == std::io::ErrorKind::NotFound => write!(
+                f,
+                "could not find shader {}, shader paths are relative to the working \
+                 directory, run from the repository root",
+                path.display()
+            ),
+            Self::Io { path, err } => write!(f, "could not read {}: {err}", path.display()),
+            Self::Compile { path, message } => {
+                write!(f, "could not compile {}: {message}", path.display())
+            }
+            Self::CircularInclude { path } => {
+                write!(f, "circular include of {}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+// NOTE: includes are resolved relative to the directory of the file at path
+pub fn read_shader_source<P: AsRef<std::path::Path>>(path: P) -> Result<String, ShaderError> {
+    let path = path.as_ref();
+    let source = std::fs::read_to_string(path).map_err(|err| ShaderError::Io {
+        path: path.to_path_buf(),
+        err,
+    })?;
+    let include_dir = path.parent().unwrap_or(std::path::Path::new("."));
+    preprocess_shader(&source, include_dir)
+}
+
+// NOTE: replaces every `// #include "file.wgsl"` line with the contents of that file,
+// recursively, nested includes are relative to the directory of the including file.
+// Still a comment for naga if a shader is used without preprocessing
+pub fn preprocess_shader(
+    source: &str,
+    include_dir: &std::path::Path,
+) -> Result<String, ShaderError> {
+    let mut including = std::collections::HashSet::new();
+    resolve_includes(source, include_dir, &mut including)
+}
+
+// NOTE: including holds the files on the current include chain, not every file included
+// so far, so the same file can still be included from two different places
+fn resolve_includes(
+    source: &str,
+    include_dir: &std::path::Path,
+    including: &mut std::collections::HashSet<std::path::PathBuf>,
+) -> Result<String, ShaderError> {
+    let mut out = String::with_capacity(source.len());
+    for line in source.lines() {
+        let Some(name) = include_directive(line) else {
+            out.push_str(line);
+            out.push('\n');
+            continue;
+        };
+        let joined = include_dir.join(name);
+        // NOTE: canonical so "a.wgsl" and "./a.wgsl" are the same file for the cycle check
+        let path = std::fs::canonicalize(&joined).map_err(|err| ShaderError::Io {
+            path: joined.clone(),
+            err,
+        })?;
+        if !including.insert(path.clone()) {
+            return Err(ShaderError::CircularInclude { path: joined });
+        }
+        let included = std::fs::read_to_string(&path).map_err(|err| ShaderError::Io {
+            path: joined.clone(),
+            err,
+        })?;
+        let nested_dir = path.parent().unwrap_or(include_dir);
+        out.push_str(&resolve_includes(&included, nested_dir, including)?);
+        including.remove(&path);
+    }
+    Ok(out)
+}
+
+// NOTE: the file name of a `// #include "file.wgsl"` line, None for any other line
+fn include_directive(line: &str) -> Option<&str> {
+    line.trim()
+        .strip_prefix("// #include")?
+        .trim()
+        .strip_prefix('"')?
+        .strip_suffix('"')
+}
+
+// NOTE: names defined for a shader variant. A non empty value replaces every whole word
+// use of the name in the source, an empty one only defines the name for the #ifdefs
+#[derive(Clone, Debug, Default)]
+pub struct ShaderDefines(pub std::collections::HashMap<&'static str, String>);
+
+impl ShaderDefines {
+    pub fn is_defined(&self, name: &str) -> bool {
+        self.0.contains_key(name)
+    }
+}
+
+// NOTE: keeps or drops the lines between `// #ifdef NAME` or `// #ifndef NAME`, an
+// optional `// #else` and `// #endif`, blocks can nest. Written as comments like the
+// includes. Directive and dropped lines stay as empty lines so the line numbers of
+// compile errors still match the file, an #endif without #ifdef is ignored
+pub fn preprocess_defines(source: &str, defines: &ShaderDefines) -> String {
+    // NOTE: per open block whether its current branch is kept, a block inside a dropped
+    // one is dropped whatever its condition
+    let mut blocks: Vec<bool> = vec![];
+    let mut out = String::with_capacity(source.len());
+    for line in source.lines() {
+        let parent_kept = blocks.iter().all(|kept| *kept);
+        match define_directive(line) {
+            Some(("#ifdef", name)) => blocks.push(parent_kept && defines.is_defined(name)),
+            Some(("#ifndef", name)) => blocks.push(parent_kept && !defines.is_defined(name)),
+            Some(("#else", _)) => {
+                if let Some(kept) = blocks.pop() {
+                    let outer_kept = blocks.iter().all(|kept| *kept);
+                    blocks.push(outer_kept && !kept);
+                }
+            }
+            Some(("#endif", _)) => {
+                blocks.pop();
+            }
+            _ if parent_kept => out.push_str(&substitute_defines(line, defines)),
+            _ => {}
+        }
+        out.push('\n');
+    }
+    out
+}
+
+// NOTE: directive and its argument of a `// #ifdef NAME` style line
+fn define_directive(line: &str) -> Option<(&str, &str)> {
+    let rest = line.trim().strip_prefix("//")?.trim_start();
+    let (directive, name) = rest.split_once(' ').unwrap_or((rest, ""));
+    matches!(directive, "#ifdef" | "#ifndef" | "#else" | "#endif").then(|| (directive, name.trim()))
+}
+
+fn substitute_defines(line: &str, defines: &ShaderDefines) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut word = String::new();
+    for ch in line.chars().chain(std::iter::once('\n')) {
+        if ch.is_ascii_alphanumeric() || ch == '_' {
+            word.push(ch);
+            continue;
+        }
+        match defines.0.get(word.as_str()) {
+            Some(value) if !value.is_empty() => out.push_str(value),
+            _ => out.push_str(&word),
+        }
+        word.clear();
+        if ch != '\n' {
+            out.push(ch);
+        }
+    }
+    out
+}
+
+// NOTE: features compiled into a light pass variant, one bit each, see light_pipeline_key
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineKey(pub u32);
+
+impl PipelineKey {
+    pub const SSAO: u32 = 1 << 0;
+    pub const SHADOWS: u32 = 1 << 1;
+    pub const IBL: u32 = 1 << 2;
+    // NOTE: what Pipelines::light is built with, also used while a variant fails to compile
+    pub const ALL: Self = Self(Self::SSAO | Self::SHADOWS | Self::IBL);
+
+    pub fn new(ssao: bool, shadows: bool, ibl: bool) -> Self {
+        let mut bits = 0;
+        if ssao {
+            bits |= Self::SSAO;
+        }
+        if shadows {
+            bits |= Self::SHADOWS;
+        }
+        if ibl {
+            bits |= Self::IBL;
+        }
+        Self(bits)
+    }
+
+    pub fn has(&self, feature: u32) -> bool {
+        self.0 & feature != 0
+    }
+
+    pub fn defines(&self) -> ShaderDefines {
+        let mut defines = ShaderDefines::default();
+        for (feature, name) in [
+            (Self::SSAO, "SSAO_ENABLED"),
+            (Self::SHADOWS, "SHADOWS_ENABLED"),
+            (Self::IBL, "IBL_ENABLED"),
+        ] {
+            if self.has(feature) {
+                defines.0.insert(name, String::new());
+            }
+        }
+        defines
+    }
+}
+
+// NOTE: path is only used for error reporting
+pub fn try_create_shader<P: AsRef<std::path::Path>>(
+    ctx: &gpu::Context,
+    path: P,
+    source: &str,
+) -> Result<gpu::Shader, ShaderError> {
+    ctx.try_create_shader(gpu::ShaderDesc { source })
+        .map_err(|err| ShaderError::Compile {
+            path: path.as_ref().to_path_buf(),
+            message: format!("{err:?}"),
+        })
+}
+
+pub fn read_geometry_shader_source(normal_encoding: NormalEncoding) -> Result<String, ShaderError> {
+    let normal_encoding_source = read_normal_encoding_source(normal_encoding)?;
+    let source = read_shader_source(GEOMETRY_SHADER_PATH)?;
+    Ok(format!("{normal_encoding_source}\n{source}"))
+}
+
+// NOTE: the light shader with the #ifdef blocks of the features in key, the depth
+// downsample and ao pipelines come from the same source built with PipelineKey::ALL
+pub fn read_light_shader_source(
+    normal_encoding: NormalEncoding,
+    key: PipelineKey,
+) -> Result<String, ShaderError> {
+    let normal_encoding_source = read_normal_encoding_source(normal_encoding)?;
+    let source = read_shader_source(LIGHT_SHADER_PATH)?;
+    Ok(preprocess_defines(
+        &format!("{normal_encoding_source}\n{source}"),
+        &key.defines(),
+    ))
+}
+
+pub fn create_light_pipeline(
+    ctx: &gpu::Context,
+    light_shader: &gpu::Shader,
+    surface_format: gpu::TextureFormat,
+) -> gpu::RenderPipeline {
+    ctx.create_render_pipeline(gpu::RenderPipelineDesc {
+        name: "light",
+        // data_layouts: &[&<Params as gpu::ShaderData>::layout()],
+        data_layouts: &[
+            &<LightPassParams as gpu::ShaderData>::layout(),
+            &<LightListParams as gpu::ShaderData>::layout(),
+        ],
+        vertex: light_shader.at("vs_fullscreen"),
+        vertex_fetches: &[],
+        primitive: gpu::PrimitiveState {
+            topology: gpu::PrimitiveTopology::TriangleList,
+            front_face: gpu::FrontFace::Ccw,
+            cull_mode: None,
+            unclipped_depth: false,
+            wireframe: false,
+        },
+        depth_stencil: None,
+        fragment: light_shader.at("fs_light"),
+        color_targets: &[gpu::ColorTargetState {
+            format: surface_format,
+            blend: Some(gpu::BlendState::REPLACE),
+            write_mask: gpu::ColorWrites::default(),
+        }],
+    })
+}
+
+// NOTE: compiles the light pass variant for key, cached in State::light_variants
+pub fn create_light_variant(
+    ctx: &gpu::Context,
+    surface_format: gpu::TextureFormat,
+    normal_encoding: NormalEncoding,
+    key: PipelineKey,
+) -> Result<gpu::RenderPipeline, ShaderError> {
+    let source = read_light_shader_source(normal_encoding, key)?;
+    let shader = try_create_shader(ctx, LIGHT_SHADER_PATH, &source)?;
+    Ok(create_light_pipeline(ctx, &shader, surface_format))
+}
+
+impl Pipelines {
+    // NOTE: creates every pipeline from scratch, used both at startup and for shader
+    // hot-reload, surface format is passed in since it is only known after surface creation
+    pub fn rebuild(
+        ctx: &gpu::Context,
+        surface_format: gpu::TextureFormat,
+        vertex_format: VertexFormat,
+        normal_encoding: NormalEncoding,
+        reverse_z: bool,
+    ) -> Result<Self, ShaderError> {
+        let normal_encoding_source = read_normal_encoding_source(normal_encoding)?;
+        let geometry_shader_source = read_geometry_shader_source(normal_encoding)?;
+        let geometry_shader =
+            try_create_shader(ctx, GEOMETRY_SHADER_PATH, &geometry_shader_source)?;
+
+        // NOTE: normal, albedo and material g-buffer targets, shared by all geometry pipelines.
+        // Positions come from the depth, see view_pos.wgsl.
+        // All single sample. blade-graphics at the pinned rev (ad223975) has no sample count:
+        // TextureDesc is name, format, size, array_layer_count, mip_level_count, dimension
+        // and usage, RenderPipelineDesc has no multisample state, so there is no msaa path
+        // (would also need a custom resolve, averaging depths/normals is not meaningful)
+        let geometry_targets = [
+            gpu::ColorTargetState {
+                format: normal_encoding.format(),
+                blend: Some(gpu::BlendState::REPLACE),
+                write_mask: gpu::ColorWrites::default(),
+            },
+            gpu::ColorTargetState {
+                format: ALBEDO_FORMAT,
+                blend: Some(gpu::BlendState::REPLACE),
+                write_mask: gpu::ColorWrites::default(),
+            },
+            gpu::ColorTargetState {
+                format: MATERIAL_FORMAT,
+                blend: Some(gpu::BlendState::REPLACE),
+                write_mask: gpu::ColorWrites::default(),
+            },
+        ];
+
+        // NOTE: pipeline
+        let geometry_pipeline = ctx.create_render_pipeline(gpu::RenderPipelineDesc {
+            name: "geometry",
+            data_layouts: &[
+                &<GeometryParams as gpu::ShaderData>::layout(),
+                &<MaterialParams as gpu::ShaderData>::layout(),
+                &<PreviousFrameGlobals as gpu::ShaderData>::layout(),
+            ],
+            vertex: geometry_shader.at(vertex_format.geometry_vs_entry()),
+            vertex_fetches: &[gpu::VertexFetchState {
+                layout: &vertex_format.layout(),
+                instanced: false,
+            }],
+            primitive: gpu::PrimitiveState {
+                topology: gpu::PrimitiveTopology::TriangleList,
+                front_face: gpu::FrontFace::Ccw,
+                cull_mode: Some(gpu::Face::Back),
+                unclipped_depth: false,
+                wireframe: false,
+            },
+            depth_stencil: Some(gpu::DepthStencilState {
+                format: gpu::TextureFormat::Depth32Float,
+                depth_write_enabled: true,
+                depth_compare: depth_compare(reverse_z),
+                stencil: Default::default(),
+                bias: gpu::DepthBiasState::default(),
+            }),
+            fragment: geometry_shader.at("fs_main"),
+            color_targets: &geometry_targets,
+        });
+
+        // NOTE: depth only version of the geometry pass, same vertex transform as vs_main
+        // so the depth matches exactly for the Equal compare afterwards
+        let depth_prepass_pipeline = ctx.create_render_pipeline(gpu::RenderPipelineDesc {
+            name: "depth prepass",
+            data_layouts: &[&<ShadowPassParams as gpu::ShaderData>::layout()],
+            vertex: geometry_shader.at(vertex_format.shadow_vs_entry()),
+            vertex_fetches: &[gpu::VertexFetchState {
+                layout: &vertex_format.layout(),
+                instanced: false,
+            }],
+            primitive: gpu::PrimitiveState {
+                topology: gpu::PrimitiveTopology::TriangleList,
+                front_face: gpu::FrontFace::Ccw,
+                cull_mode: Some(gpu::Face::Back),
+                unclipped_depth: false,
+                wireframe: false,
+            },
+            depth_stencil: Some(gpu::DepthStencilState {
+                format: gpu::TextureFormat::Depth32Float,
+                depth_write_enabled: true,
+                depth_compare: depth_compare(reverse_z),
+                stencil: Default::default(),
+                bias: gpu::DepthBiasState::default(),
+            }),
+            fragment: geometry_shader.at("fs_shadow"),
+            color_targets: &[],
+        });
+
+        // NOTE: geometry after the depth prepass, only the front most fragment passes Equal
+        // so fs_main runs once per pixel
+        let geometry_after_prepass_pipeline = ctx.create_render_pipeline(gpu::RenderPipelineDesc {
+            name: "geometry after prepass",
+            data_layouts: &[
+                &<GeometryParams as gpu::ShaderData>::layout(),
+                &<MaterialParams as gpu::ShaderData>::layout(),
+                &<PreviousFrameGlobals as gpu::ShaderData>::layout(),
+            ],
+            vertex: geometry_shader.at(vertex_format.geometry_vs_entry()),
+            vertex_fetches: &[gpu::VertexFetchState {
+                layout: &vertex_format.layout(),
+                instanced: false,
+            }],
+            primitive: gpu::PrimitiveState {
+                topology: gpu::PrimitiveTopology::TriangleList,
+                front_face: gpu::FrontFace::Ccw,
+                cull_mode: Some(gpu::Face::Back),
+                unclipped_depth: false,
+                wireframe: false,
+            },
+            depth_stencil: Some(gpu::DepthStencilState {
+                format: gpu::TextureFormat::Depth32Float,
+                depth_write_enabled: false,
+                depth_compare: gpu::CompareFunction::Equal,
+                stencil: Default::default(),
+                bias: gpu::DepthBiasState::default(),
+            }),
+            fragment: geometry_shader.at("fs_main"),
+            color_targets: &geometry_targets,
+        });
+
+        // NOTE: same as geometry but rasterizes only the triangle edges in a flat color,
+        // no culling so the back side of the mesh shows up as well
+        let wireframe_pipeline = ctx.create_render_pipeline(gpu::RenderPipelineDesc {
+            name: "wireframe",
+            data_layouts: &[
+                &<GeometryParams as gpu::ShaderData>::layout(),
+                &<MaterialParams as gpu::ShaderData>::layout(),
+                &<PreviousFrameGlobals as gpu::ShaderData>::layout(),
+            ],
+            vertex: geometry_shader.at(vertex_format.geometry_vs_entry()),
+            vertex_fetches: &[gpu::VertexFetchState {
+                layout: &vertex_format.layout(),
+                instanced: false,
+            }],
+            primitive: gpu::PrimitiveState {
+                topology: gpu::PrimitiveTopology::TriangleList,
+                front_face: gpu::FrontFace::Ccw,
+                cull_mode: None,
+                unclipped_depth: false,
+                wireframe: true,
+            },
+            depth_stencil: Some(gpu::DepthStencilState {
+                format: gpu::TextureFormat::Depth32Float,
+                depth_write_enabled: true,
+                depth_compare: depth_compare(reverse_z),
+                stencil: Default::default(),
+                bias: gpu::DepthBiasState::default(),
+            }),
+            fragment: geometry_shader.at("fs_wireframe"),
+            color_targets: &geometry_targets,
+        });
+
+        // NOTE: edges of the back faces only, drawn after the solid pass, the bias keeps
+        // them from z-fighting with the solid faces they share an edge with
+        let backface_pipeline = ctx.create_render_pipeline(gpu::RenderPipelineDesc {
+            name: "backface wireframe",
+            data_layouts: &[
+                &<GeometryParams as gpu::ShaderData>::layout(),
+                &<MaterialParams as gpu::ShaderData>::layout(),
+                &<PreviousFrameGlobals as gpu::ShaderData>::layout(),
+            ],
+            vertex: geometry_shader.at(vertex_format.geometry_vs_entry()),
+            vertex_fetches: &[gpu::VertexFetchState {
+                layout: &vertex_format.layout(),
+                instanced: false,
+            }],
+            primitive: gpu::PrimitiveState {
+                topology: gpu::PrimitiveTopology::TriangleList,
+                front_face: gpu::FrontFace::Ccw,
+                cull_mode: Some(gpu::Face::Front),
+                unclipped_depth: false,
+                wireframe: true,
+            },
+            depth_stencil: Some(gpu::DepthStencilState {
+                format: gpu::TextureFormat::Depth32Float,
+                depth_write_enabled: true,
+                depth_compare: depth_compare_or_equal(reverse_z),
+                stencil: Default::default(),
+                bias: gpu::DepthBiasState {
+                    constant: 1,
+                    slope_scale: 1.0,
+                    clamp: 0.0,
+                },
+            }),
+            fragment: geometry_shader.at("fs_backface"),
+            color_targets: &geometry_targets,
+        });
+
+        // NOTE: front face edges of the node picked with the mouse, drawn after the solid
+        // pass with the same bias as the back face edges
+        let selection_pipeline = ctx.create_render_pipeline(gpu::RenderPipelineDesc {
+            name: "selection wireframe",
+            data_layouts: &[
+                &<GeometryParams as gpu::ShaderData>::layout(),
+                &<MaterialParams as gpu::ShaderData>::layout(),
+                &<PreviousFrameGlobals as gpu::ShaderData>::layout(),
+            ],
+            vertex: geometry_shader.at(vertex_format.geometry_vs_entry()),
+            vertex_fetches: &[gpu::VertexFetchState {
+                layout: &vertex_format.layout(),
+                instanced: false,
+            }],
+            primitive: gpu::PrimitiveState {
+                topology: gpu::PrimitiveTopology::TriangleList,
+                front_face: gpu::FrontFace::Ccw,
+                cull_mode: Some(gpu::Face::Back),
+                unclipped_depth: false,
+                wireframe: true,
+            },
+            depth_stencil: Some(gpu::DepthStencilState {
+                format: gpu::TextureFormat::Depth32Float,
+                depth_write_enabled: true,
+                depth_compare: depth_compare_or_equal(reverse_z),
+                stencil: Default::default(),
+                bias: gpu::DepthBiasState {
+                    constant: 1,
+                    slope_scale: 1.0,
+                    clamp: 0.0,
+                },
+            }),
+            fragment: geometry_shader.at("fs_selection"),
+            color_targets: &geometry_targets,
+        });
+
+        // NOTE: depth only from the light, front faces like the geometry pass
+        let shadow_pipeline = ctx.create_render_pipeline(gpu::RenderPipelineDesc {
+            name: "shadow",
+            data_layouts: &[&<ShadowPassParams as gpu::ShaderData>::layout()],
+            vertex: geometry_shader.at(vertex_format.shadow_vs_entry()),
+            vertex_fetches: &[gpu::VertexFetchState {
+                layout: &vertex_format.layout(),
+                instanced: false,
+            }],
+            primitive: gpu::PrimitiveState {
+                topology: gpu::PrimitiveTopology::TriangleList,
+                front_face: gpu::FrontFace::Ccw,
+                cull_mode: Some(gpu::Face::Back),
+                unclipped_depth: false,
+                wireframe: false,
+            },
+            depth_stencil: Some(gpu::DepthStencilState {
+                format: gpu::TextureFormat::Depth32Float,
+                depth_write_enabled: true,
+                depth_compare: gpu::CompareFunction::Less,
+                stencil: Default::default(),
+                bias: gpu::DepthBiasState::default(),
+            }),
+            fragment: geometry_shader.at("fs_shadow"),
+            color_targets: &[],
+        });
+
+        let light_shader_source = read_light_shader_source(normal_encoding, PipelineKey::ALL)?;
+        let light_shader = try_create_shader(ctx, LIGHT_SHADER_PATH, &light_shader_source)?;
+
+        let light_pipeline = create_light_pipeline(ctx, &light_shader, surface_format);
+
+        let depth_downsample_pipeline = ctx.create_render_pipeline(gpu::RenderPipelineDesc {
+            name: "depth downsample",
+            data_layouts: &[&<DepthNormalParams as gpu::ShaderData>::layout()],
+            vertex: light_shader.at("vs_fullscreen"),
+            vertex_fetches: &[],
+            primitive: gpu::PrimitiveState {
+                topology: gpu::PrimitiveTopology::TriangleList,
+                front_face: gpu::FrontFace::Ccw,
+                cull_mode: None,
+                unclipped_depth: false,
+                wireframe: false,
+            },
+            // NOTE: the downsampled position goes out as frag_depth
+            depth_stencil: Some(gpu::DepthStencilState {
+                format: gpu::TextureFormat::Depth32Float,
+                depth_write_enabled: true,
+                depth_compare: gpu::CompareFunction::Always,
+                stencil: Default::default(),
+                bias: gpu::DepthBiasState::default(),
+            }),
+            fragment: light_shader.at("fs_downsample"),
+            color_targets: &[gpu::ColorTargetState {
+                format: normal_encoding.format(),
+                blend: Some(gpu::BlendState::REPLACE),
+                write_mask: gpu::ColorWrites::default(),
+            }],
+        });
+
+        let ao_pipeline = ctx.create_render_pipeline(gpu::RenderPipelineDesc {
+            name: "ao",
+            // TODO: fix daat layot
+            data_layouts: &[&<CalcAoParams as gpu::ShaderData>::layout()],
+            // data_layouts: &[],
+            vertex: light_shader.at("vs_fullscreen"),
+            vertex_fetches: &[],
+            primitive: gpu::PrimitiveState {
+                topology: gpu::PrimitiveTopology::TriangleList,
+                front_face: gpu::FrontFace::Ccw,
+                cull_mode: None,
+                unclipped_depth: false,
+                wireframe: false,
+            },
+            depth_stencil: None,
+            fragment: light_shader.at("fs_calc_ao"),
+            color_targets: &[
+                gpu::ColorTargetState {
+                    format: gpu::TextureFormat::Rgba32Float,
+                    blend: Some(gpu::BlendState::REPLACE),
+                    write_mask: gpu::ColorWrites::default(),
+                },
+                gpu::ColorTargetState {
+                    format: BENT_NORMAL_FORMAT,
+                    blend: Some(gpu::BlendState::REPLACE),
+                    write_mask: gpu::ColorWrites::default(),
+                },
+            ],
+        });
+
+        let ao_blur = ctx.create_render_pipeline(gpu::RenderPipelineDesc {
+            name: "ao blur",
+            data_layouts: &[&<BlurParams as gpu::ShaderData>::layout()],
+            vertex: light_shader.at("vs_fullscreen"),
+            vertex_fetches: &[],
+            primitive: gpu::PrimitiveState {
+                topology: gpu::PrimitiveTopology::TriangleList,
+                front_face: gpu::FrontFace::Ccw,
+                cull_mode: None,
+                unclipped_depth: false,
+                wireframe: false,
+            },
+            depth_stencil: None,
+            fragment: light_shader.at("fs_blur_ao"),
+            color_targets: &[gpu::ColorTargetState {
+                format: gpu::TextureFormat::Rgba32Float,
+                blend: Some(gpu::BlendState::REPLACE),
+                write_mask: gpu::ColorWrites::default(),
+            }],
+        });
+
+        let depth_debug_shader_source = read_shader_source(DEPTH_DEBUG_SHADER_PATH)?;
+        let depth_debug_shader =
+            try_create_shader(ctx, DEPTH_DEBUG_SHADER_PATH, &depth_debug_shader_source)?;
+
+        let depth_debug_pipeline = ctx.create_render_pipeline(gpu::RenderPipelineDesc {
+            name: "depth debug",
+            data_layouts: &[&<DepthDebugParams as gpu::ShaderData>::layout()],
+            vertex: depth_debug_shader.at("vs_fullscreen"),
+            vertex_fetches: &[],
+            primitive: gpu::PrimitiveState {
+                topology: gpu::PrimitiveTopology::TriangleList,
+                front_face: gpu::FrontFace::Ccw,
+                cull_mode: None,
+                unclipped_depth: false,
+                wireframe: false,
+            },
+            depth_stencil: None,
+            fragment: depth_debug_shader.at("fs_depth_debug"),
+            color_targets: &[gpu::ColorTargetState {
+                format: surface_format,
+                blend: Some(gpu::BlendState::REPLACE),
+                write_mask: gpu::ColorWrites::default(),
+            }],
+        });
+
+        let normal_debug_shader_source = format!(
+            "{normal_encoding_source}\n{}",
+            read_shader_source(NORMAL_DEBUG_SHADER_PATH)?
+        );
+        let normal_debug_shader =
+            try_create_shader(ctx, NORMAL_DEBUG_SHADER_PATH, &normal_debug_shader_source)?;
+
+        let normal_debug_pipeline = ctx.create_render_pipeline(gpu::RenderPipelineDesc {
+            name: "normal debug",
+            data_layouts: &[&<NormalDebugParams as gpu::ShaderData>::layout()],
+            vertex: normal_debug_shader.at("vs_fullscreen"),
+            vertex_fetches: &[],
+            primitive: gpu::PrimitiveState {
+                topology: gpu::PrimitiveTopology::TriangleList,
+                front_face: gpu::FrontFace::Ccw,
+                cull_mode: None,
+                unclipped_depth: false,
+                wireframe: false,
+            },
+            depth_stencil: None,
+            fragment: normal_debug_shader.at("fs_normal_debug"),
+            color_targets: &[gpu::ColorTargetState {
+                format: surface_format,
+                blend: Some(gpu::BlendState::REPLACE),
+                write_mask: gpu::ColorWrites::default(),
+            }],
+        });
+
+        let loading_shader_source = read_shader_source(LOADING_SHADER_PATH)?;
+        let loading_shader = try_create_shader(ctx, LOADING_SHADER_PATH, &loading_shader_source)?;
+
+        let loading_pipeline = ctx.create_render_pipeline(gpu::RenderPipelineDesc {
+            name: "loading",
+            data_layouts: &[&<LoadingParams as gpu::ShaderData>::layout()],
+            vertex: loading_shader.at("vs_fullscreen"),
+            vertex_fetches: &[],
+            primitive: gpu::PrimitiveState {
+                topology: gpu::PrimitiveTopology::TriangleList,
+                front_face: gpu::FrontFace::Ccw,
+                cull_mode: None,
+                unclipped_depth: false,
+                wireframe: false,
+            },
+            depth_stencil: None,
+            fragment: loading_shader.at("fs_loading"),
+            color_targets: &[gpu::ColorTargetState {
+                format: surface_format,
+                blend: Some(gpu::BlendState::REPLACE),
+                write_mask: gpu::ColorWrites::default(),
+            }],
+        });
+
+        let taa_shader_source = read_shader_source(TAA_SHADER_PATH)?;
+        let taa_shader = try_create_shader(ctx, TAA_SHADER_PATH, &taa_shader_source)?;
+
+        // NOTE: writes the resolved color to the frame and the next history at once
+        let taa_resolve_pipeline = ctx.create_render_pipeline(gpu::RenderPipelineDesc {
+            name: "taa resolve",
+            data_layouts: &[&<TaaParams as gpu::ShaderData>::layout()],
+            vertex: taa_shader.at("vs_fullscreen"),
+            vertex_fetches: &[],
+            primitive: gpu::PrimitiveState {
+                topology: gpu::PrimitiveTopology::TriangleList,
+                front_face: gpu::FrontFace::Ccw,
+                cull_mode: None,
+                unclipped_depth: false,
+                wireframe: false,
+            },
+            depth_stencil: None,
+            fragment: taa_shader.at("fs_taa_resolve"),
+            color_targets: &[
+                gpu::ColorTargetState {
+                    format: surface_format,
+                    blend: Some(gpu::BlendState::REPLACE),
+                    write_mask: gpu::ColorWrites::default(),
+                },
+                gpu::ColorTargetState {
+                    format: surface_format,
+                    blend: Some(gpu::BlendState::REPLACE),
+                    write_mask: gpu::ColorWrites::default(),
+                },
+            ],
+        });
+
+        let dof_shader_source = read_shader_source(DOF_SHADER_PATH)?;
+        let dof_shader = try_create_shader(ctx, DOF_SHADER_PATH, &dof_shader_source)?;
+
+        let dof_pipeline = ctx.create_render_pipeline(gpu::RenderPipelineDesc {
+            name: "dof",
+            data_layouts: &[&<DofParams as gpu::ShaderData>::layout()],
+            vertex: dof_shader.at("vs_fullscreen"),
+            vertex_fetches: &[],
+            primitive: gpu::PrimitiveState {
+                topology: gpu::PrimitiveTopology::TriangleList,
+                front_face: gpu::FrontFace::Ccw,
+                cull_mode: None,
+                unclipped_depth: false,
+                wireframe: false,
+            },
+            depth_stencil: None,
+            fragment: dof_shader.at("fs_dof"),
+            color_targets: &[gpu::ColorTargetState {
+                format: surface_format,
+                blend: Some(gpu::BlendState::REPLACE),
+                write_mask: gpu::ColorWrites::default(),
+            }],
+        });
+
+        let ssr_shader_source = format!(
+            "{normal_encoding_source}\n{}",
+            read_shader_source(SSR_SHADER_PATH)?
+        );
+        let ssr_shader = try_create_shader(ctx, SSR_SHADER_PATH, &ssr_shader_source)?;
+
+        let ssr_pipeline = ctx.create_render_pipeline(gpu::RenderPipelineDesc {
+            name: "ssr",
+            data_layouts: &[&<SsrParams as gpu::ShaderData>::layout()],
+            vertex: ssr_shader.at("vs_fullscreen"),
+            vertex_fetches: &[],
+            primitive: gpu::PrimitiveState {
+                topology: gpu::PrimitiveTopology::TriangleList,
+                front_face: gpu::FrontFace::Ccw,
+                cull_mode: None,
+                unclipped_depth: false,
+                wireframe: false,
+            },
+            depth_stencil: None,
+            fragment: ssr_shader.at("fs_ssr"),
+            color_targets: &[gpu::ColorTargetState {
+                format: surface_format,
+                blend: Some(gpu::BlendState::REPLACE),
+                write_mask: gpu::ColorWrites::default(),
+            }],
+        });
+
+        let tssao_shader_source = read_shader_source(TSSAO_SHADER_PATH)?;
+        let tssao_shader = try_create_shader(ctx, TSSAO_SHADER_PATH, &tssao_shader_source)?;
+
+        // NOTE: writes the accumulated ao and its depth to the next history at once
+        let tssao_resolve_pipeline = ctx.create_render_pipeline(gpu::RenderPipelineDesc {
+            name: "tssao resolve",
+            data_layouts: &[&<TssaoParams as gpu::ShaderData>::layout()],
+            vertex: tssao_shader.at("vs_fullscreen"),
+            vertex_fetches: &[],
+            primitive: gpu::PrimitiveState {
+                topology: gpu::PrimitiveTopology::TriangleList,
+                front_face: gpu::FrontFace::Ccw,
+                cull_mode: None,
+                unclipped_depth: false,
+                wireframe: false,
+            },
+            depth_stencil: None,
+            fragment: tssao_shader.at("fs_tssao_resolve"),
+            color_targets: &[
+                gpu::ColorTargetState {
+                    format: TSSAO_FORMAT,
+                    blend: Some(gpu::BlendState::REPLACE),
+                    write_mask: gpu::ColorWrites::default(),
+                },
+                gpu::ColorTargetState {
+                    format: TSSAO_DEPTH_FORMAT,
+                    blend: Some(gpu::BlendState::REPLACE),
+                    write_mask: gpu::ColorWrites::default(),
+                },
+            ],
+        });
+
+        let skybox_shader_source = read_shader_source(SKYBOX_SHADER_PATH)?;
+        let skybox_shader = try_create_shader(ctx, SKYBOX_SHADER_PATH, &skybox_shader_source)?;
+
+        // NOTE: the triangle sits on the far plane, or equal lets it through exactly where
+        // the g-buffer depth still has its clear value
+        let skybox_pipeline = ctx.create_render_pipeline(gpu::RenderPipelineDesc {
+            name: "skybox",
+            data_layouts: &[&<SkyboxParams as gpu::ShaderData>::layout()],
+            vertex: skybox_shader.at("vs_skybox"),
+            vertex_fetches: &[],
+            primitive: gpu::PrimitiveState {
+                topology: gpu::PrimitiveTopology::TriangleList,
+                front_face: gpu::FrontFace::Ccw,
+                cull_mode: None,
+                unclipped_depth: false,
+                wireframe: false,
+            },
+            depth_stencil: Some(gpu::DepthStencilState {
+                format: gpu::TextureFormat::Depth32Float,
+                depth_write_enabled: false,
+                depth_compare: depth_compare_or_equal(reverse_z),
+                stencil: Default::default(),
+                bias: gpu::DepthBiasState::default(),
+            }),
+            fragment: skybox_shader.at("fs_skybox"),
+            color_targets: &[gpu::ColorTargetState {
+                format: surface_format,
+                blend: Some(gpu::BlendState::REPLACE),
+                write_mask: gpu::ColorWrites::default(),
+            }],
+        });
+
+        // NOTE: compute-pipelines
+        let compute_shader_source = read_shader_source(COMPUTE_TEST_SHADER_PATH)?;
+        let compute_shader =
+            try_create_shader(ctx, COMPUTE_TEST_SHADER_PATH, &compute_shader_source)?;
+
+        let test_compute_pipeline = ctx.create_compute_pipeline(gpu::ComputePipelineDesc {
+            name: "test compute",
+            data_layouts: &[&<ComputeTestParams as gpu::ShaderData>::layout()],
+            compute: compute_shader.at("main"),
+        });
+
+        let hi_z_shader_source = read_shader_source(HI_Z_SHADER_PATH)?;
+        let hi_z_shader = try_create_shader(ctx, HI_Z_SHADER_PATH, &hi_z_shader_source)?;
+        let hi_z_linear_depth_pipeline = ctx.create_compute_pipeline(gpu::ComputePipelineDesc {
+            name: "hi-z linear depth",
+            data_layouts: &[&<HiZLinearDepthParams as gpu::ShaderData>::layout()],
+            compute: hi_z_shader.at("cs_linear_depth"),
+        });
+        let hi_z_downsample_pipeline = ctx.create_compute_pipeline(gpu::ComputePipelineDesc {
+            name: "hi-z downsample",
+            data_layouts: &[&<HiZDownsampleParams as gpu::ShaderData>::layout()],
+            compute: hi_z_shader.at("cs_downsample"),
+        });
+
+        let ao_kernel_gizmos_pipeline = ctx.create_compute_pipeline(gpu::ComputePipelineDesc {
+            name: "ao kernel gizmos",
+            data_layouts: &[&<AoGizmoParams as gpu::ShaderData>::layout()],
+            compute: light_shader.at("cs_ao_kernel_gizmos"),
+        });
+
+        let last_modified = last_time_shader_modified();
+        // let metadata = std::fs::Metadata:
+        Ok(Self {
+            vertex_format,
+            normal_encoding,
+            reverse_z,
+            geometry: geometry_pipeline,
+            depth_prepass: depth_prepass_pipeline,
+            geometry_after_prepass: geometry_after_prepass_pipeline,
+            wireframe: wireframe_pipeline,
+            backface: backface_pipeline,
+            selection: selection_pipeline,
+            shadow: shadow_pipeline,
+            light: light_pipeline,
+            depth_downsample: depth_downsample_pipeline,
+            last_modified_shader_time: last_modified,
+            calc_ao: ao_pipeline,
+            blur_ao: ao_blur,
+            depth_debug: depth_debug_pipeline,
+            normal_debug: normal_debug_pipeline,
+            loading: loading_pipeline,
+            taa_resolve: taa_resolve_pipeline,
+            tssao_resolve: tssao_resolve_pipeline,
+            skybox: skybox_pipeline,
+            dof: dof_pipeline,
+            ssr: ssr_pipeline,
+            test_compute_pipeline,
+            hi_z_linear_depth: hi_z_linear_depth_pipeline,
+            hi_z_downsample: hi_z_downsample_pipeline,
+            ao_kernel_gizmos: ao_kernel_gizmos_pipeline,
+        })
+    }
+}
+
+// NOTE: cpu side mesh loading on a worker thread, meshes arrive in the order they are loaded
+// NOTE: index buffers, lod and bvh are built on the loader thread as well so the main
+// thread only uploads
+pub struct LoadedMesh {
+    pub mesh: IndexedMesh,
+    pub lod: Option<IndexedMesh>,
+    pub bvh: Bvh,
+    pub file: usize,
+}
+
+impl LoadedMesh {
+    pub fn new(mesh: CpuMesh, file: usize) -> Self {
+        let lod = simplified_lod(&mesh).map(IndexedMesh::new);
+        let bvh = Bvh::new(&mesh.vertices, &mesh.indices);
+        dbg!(format!(
+            "bvh for {}: {} nodes over {} triangles",
+            mesh.name,
+            bvh.nodes.len(),
+            bvh.triangles.len()
+        ));
+        Self {
+            mesh: IndexedMesh::new(mesh),
+            lod,
+            bvh,
+            file,
+        }
+    }
+
+    // NOTE: new through the processed cache, see processed_cache_path
+    pub fn cached(mesh: CpuMesh, file: usize) -> Self {
+        let cache_path = processed_cache_path(&mesh);
+        let mesh = match read_processed_cache(&cache_path, mesh, file) {
+            Ok(loaded) => {
+                dbg!(format!(
+                    "loaded {} buffers from {}",
+                    loaded.mesh.cpu.name,
+                    cache_path.display()
+                ));
+                return loaded;
+            }
+            Err(mesh) => mesh,
+        };
+        let loaded = Self::new(mesh, file);
+        if let Err(err) = write_processed_cache(&cache_path, &loaded) {
+            dbg!(format!(
+                "could not write mesh cache {}: {err}",
+                cache_path.display()
+            ));
+        }
+        loaded
+    }
+}
+
+// NOTE: a file that fails to load sends Failed and nothing else for its slot
+pub enum LoaderMessage {
+    Mesh(LoadedMesh),
+    Failed { file: usize, error: ObjError },
+}
+
+pub struct MeshLoader {
+    pub receiver: std::sync::mpsc::Receiver<LoaderMessage>,
+    // NOTE: drives the loading screen animation
+    pub time: f32,
+}
+
+// NOTE: one loader per file, a file can give many meshes
+pub type MeshLoadFn = Box<dyn FnOnce() -> Result<Vec<CpuMesh>, ObjError> + Send>;
+
+impl MeshLoader {
+    // NOTE: the thread is never joined, if the window closes mid load it is simply
+    // torn down with the process. cube_on_error puts a cube in the slot of a file that
+    // failed, otherwise the slot stays empty
+    pub fn spawn(loaders: Vec<MeshLoadFn>, cube_on_error: bool) -> Self {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            for (file, load) in loaders.into_iter().enumerate() {
+                // NOTE: keep the file slot so the file indices stay the same
+                let meshes = match load() {
+                    Ok(meshes) => meshes,
+                    Err(error) => {
+                        dbg!(format!("!!! FAILED TO LOAD MESH: {error} !!!"));
+                        if sender.send(LoaderMessage::Failed { file, error }).is_err() {
+                            return;
+                        }
+                        if cube_on_error {
+                            vec![cube_mesh()]
+                        } else {
+                            vec![]
+                        }
+                    }
+                };
+                for mesh in meshes {
+                    let loaded = LoadedMesh::cached(mesh, file);
+                    if sender.send(LoaderMessage::Mesh(loaded)).is_err() {
+                        // NOTE: receiver is gone, nobody wants the rest
+                        return;
+                    }
+                }
+            }
+        });
+        Self {
+            receiver,
+            time: 0.0,
+        }
+    }
+}
+
+// NOTE: an uploaded mesh placed in the scene, more than one node can draw the same mesh
+#[derive(Clone, Copy, Debug)]
+pub struct Node {
+    // NOTE: into State::meshes
+    pub mesh_index: usize,
+    pub transform: Transform,
+    pub visible: bool,
+    // NOTE: index of the file the mesh was loaded from, the arrow keys switch between files
+    pub file: usize,
+}
+
+// NOTE: what gets drawn, State is the renderer and owns the gpu meshes the nodes point to
+pub struct Scene {
+    pub nodes: Vec<Node>,
+    // NOTE: the first directional light is the sun and gets the shadow map
+    pub lights: Vec<Light>,
+    pub camera: Camera,
+}
+
+impl Scene {
+    pub fn new(camera: Camera) -> Self {
+        Self {
+            nodes: vec![],
+            lights: vec![Light::sun()],
+            camera,
+        }
+    }
+
+    // NOTE: false and logged when the light buffer is full, write_lights would drop it
+    pub fn add_light(&mut self, light: Light) -> bool {
+        if self.lights.len() >= MAX_LIGHTS {
+            dbg!(format!("light buffer is full, {MAX_LIGHTS} lights"));
+            return false;
+        }
+        self.lights.push(light);
+        dbg!(format!("{} lights", self.lights.len()));
+        true
+    }
+
+    // NOTE: removes the point or spot light closest to pos, directional lights stay
+    pub fn remove_nearest_light(&mut self, pos: Vec3A) -> Option<Light> {
+        let nearest = self
+            .lights
+            .iter()
+            .enumerate()
+            .filter_map(|(i, light)| Some((i, light.position()?.distance_squared(pos))))
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(i, _)| i)?;
+        let light = self.lights.remove(nearest);
+        dbg!(format!("removed a light, {} left", self.lights.len()));
+        Some(light)
+    }
+
+    // NOTE: the first directional light, see lights
+    pub fn sun(&self) -> Option<&Light> {
+        self.lights
+            .iter()
+            .find(|light| matches!(light, Light::Directional { .. }))
+    }
+
+    pub fn sun_mut(&mut self) -> Option<&mut Light> {
+        self.lights
+            .iter_mut()
+            .find(|light| matches!(light, Light::Directional { .. }))
+    }
+
+    pub fn add_node(&mut self, mesh_index: usize, file: usize) -> &mut Node {
+        self.nodes.push(Node {
+            mesh_index,
+            transform: Transform::IDENTITY,
+            visible: true,
+            file,
+        });
+        self.nodes.last_mut().unwrap()
+    }
+
+    // NOTE: loads any file load_meshes takes, the meshes are handed back for the caller
+    // to upload in order, node i draws mesh first_mesh_index + i. The camera is framed
+    // on the whole file
+    pub fn from_obj<P: AsRef<std::path::Path>>(
+        path: P,
+        options: MeshLoadOptions,
+        camera: Camera,
+        first_mesh_index: usize,
+    ) -> Result<(Self, Vec<CpuMesh>), ObjError> {
+        let meshes = load_meshes(path, options)?;
+        let mut scene = Self::new(camera);
+        for i in 0..meshes.len() {
+            scene.add_node(first_mesh_index + i, 0);
+        }
+        let aabb = meshes
+            .iter()
+            .filter(|mesh| !mesh.vertices.is_empty())
+            .map(|mesh| Aabb::from_points(&mesh.vertices))
+            .reduce(|a, b| a.union(&b));
+        if let Some(aabb) = aabb {
+            scene.camera.frame_aabb(&aabb);
+        }
+        Ok((scene, meshes))
+    }
+}
+
+pub struct State {
+    pub delta_time: f32,
+    pub prev_time: std::time::Instant,
+    // NOTE: Globals::unjittered of the previous frame, updated once a frame is done
+    pub prev_globals: Globals,
+    // NOTE: right clicking the window hides and grabs the cursor for mouse look, escape or
+    // losing focus releases it. The gizmo pick ignores the mouse while grabbed and left
+    // clicks pick at the crosshair in the screen center
+    pub cursor_grabbed: bool,
+    // NOTE: multiplier on CAM_SPEED set with the scroll wheel, shown in the title
+    pub move_speed: f32,
+    // NOTE: into scene.nodes, set by clicking a mesh and drawn with a wireframe on top
+    pub selected_node: Option<usize>,
+    // NOTE: sum of delta_time over the frames so far
+    pub total_time: f32,
+    pub pipelines: Pipelines,
+    pub command_encoder: gpu::CommandEncoder,
+    pub ctx: gpu::Context,
+    pub surface: gpu::Surface,
+    pub prev_sync_point: Option<gpu::SyncPoint>,
+    pub meshes: Vec<Mesh>,
+    // NOTE: Some while meshes are still being loaded, the loading screen is shown until then
+    pub mesh_loader: Option<MeshLoader>,
+    // NOTE: the latest file that failed to load and why, shown in the title. Cleared when
+    // new files are dropped
+    pub load_error: Option<String>,
+    // NOTE: files dropped on the window since the last frame, see load_dropped_files
+    pub dropped_files: Vec<std::path::PathBuf>,
+    pub drop_options: MeshLoadOptions,
+    // NOTE: used for draws without a material
+    pub default_material: GpuMaterial,
+    // NOTE: single layer, bound in place of the material textures for meshes without any
+    pub white_texture: TextureStuff,
+    pub material_samplers: MaterialSamplers,
+    pub scene: Scene,
+    // NOTE: per scene node, visible and not frustum culled this frame, see cull_nodes
+    pub nodes_in_view: Vec<bool>,
+    pub culled_nodes: usize,
+    // NOTE: triangles of the nodes in view drawn with the full mesh and with its lod
+    pub lod_triangles: [usize; 2],
+    pub retained_input: RetainedInput,
+    pub downsample_textures: DownsampleTextures,
+    pub ao_textures: AOTextures,
+    pub shadow_map: ShadowMap,
+    pub taa_textures: TaaTextures,
+    // NOTE: the light pass, skybox and taa resolve render here instead of the frame while
+    // depth of field is on, same format as the surface
+    pub dof_source: TextureStuff,
+    // NOTE: the light pass and skybox render here while ssr is on, the ssr pass then
+    // writes wherever they would have gone
+    pub ssr_source: TextureStuff,
+    pub tssao_history: TssaoHistory,
+    // NOTE: from "--skybox", the background stays as the light pass leaves it without one
+    pub skybox: Option<Skybox>,
+    // NOTE: bound in place of the skybox irradiance when there is none
+    pub dummy_irradiance: CubeTexture,
+    // NOTE: filled by the test compute pipeline every frame, nothing reads it yet
+    pub compute_test_texture: TextureStuff,
+    // NOTE: light pass variants compiled on first use, None if the variant failed to
+    // compile, Pipelines::light is drawn with instead. Cleared when the shaders reload
+    pub light_variants: std::collections::HashMap<PipelineKey, Option<gpu::RenderPipeline>>,
+    pub input_state: InputState,
+    pub file_to_draw: usize,
+    pub num_files: usize,
+    // NOTE: true for a model from the command line, the camera is framed on its aabb once
+    // it has loaded, scene_aabb remembers it for resetting the camera
+    pub frame_camera_on_load: bool,
+    pub scene_aabb: Option<Aabb>,
+    // NOTE: toggled with F1, draws triangle edges instead of filled triangles
+    pub wireframe_mode: bool,
+    // NOTE: toggled with F2, overlays the back face edges on top of the solid meshes
+    pub topology_debug: bool,
+    // NOTE: set with F3, the next rendered frame copies the g-buffer to disk
+    pub dump_gbuffer: bool,
+    // NOTE: set with f, the next rendered frame reads back the center pixel depth and
+    // moves the dof focus there
+    pub autofocus: bool,
+    pub gbuffer_dump_count: u32,
+    pub depth_readback: ReadbackDepthBuffer,
+    pub gilrs: Option<gilrs::Gilrs>,
+    pub imgui: imgui::Context,
+    pub imgui_platform: imgui_winit_support::WinitPlatform,
+    pub imgui_renderer: ImguiRenderer,
+    pub particles: ParticleSystem,
+    pub ao_gizmos: AoGizmos,
+    pub debug_draw: DebugDraw,
+    // NOTE: set with "freeze culling frustum", culling keeps testing against this camera
+    // and its frustum is drawn with the debug lines while the camera moves on
+    pub frozen_camera: Option<Camera>,
+    pub light_buffer: gpu::Buffer,
+    // NOTE: recorded with c, retimed by keyframe_duration when the path starts playing
+    pub camera_keyframes: Vec<CameraKeyframe>,
+    // NOTE: Some while a path is playing, overrides the input driven camera every frame
+    pub camera_path: Option<CameraPath>,
+    pub camera_path_start: std::time::Instant,
+    // NOTE: seconds spent going from one keyframe to the next
+    pub keyframe_duration: f32,
+}
+
+#[derive(Default)]
+pub struct RetainedInput {
+    pub just_pressed_keys: std::collections::HashSet<winit::keyboard::KeyCode>,
+    pub held_keys: std::collections::HashSet<winit::keyboard::KeyCode>,
+    // NOTE: raw mouse counts since the last handle_input, only while the cursor is grabbed
+    pub mouse_delta: Vec2,
+}
+
+impl State {
+    pub fn new(window: &winit::window::Window, args: Args) -> Self {
+        let scene_args = args.scene;
+        let ctx = unsafe {
+            gpu::Context::init(gpu::ContextDesc {
+                presentation: true,
+                validation: true,
+                timing: args.timing,
+                capture: false,
+                overlay: true,
+                device_id: 0,
+            })
+            .unwrap()
+        };
+        let size = window.inner_size();
+        let width = size.width;
+        let height = size.height;
+
+        let screen_extent = gpu::Extent {
+            width,
+            height,
+            depth: 1,
+        };
+
+        let aspect = width as f32 / height as f32;
+        let surface = ctx
+            .create_surface_configured(
+                window,
+                gpu::SurfaceConfig {
+                    size: screen_extent,
+                    usage: gpu::TextureUsage::TARGET,
+                    display_sync: gpu::DisplaySync::Recent,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        let meshes = vec![];
+
+        let mut command_encoder = ctx.create_command_encoder(gpu::CommandEncoderDesc {
+            name: "main",
+            buffer_count: 1,
+        });
+
+        // NOTE: a mesh path on the command line replaces the default scenes, otherwise
+        // same order as before, cathedral is scene 0 and sponza scene 1
+        // NOTE: without sponza on disk the procedural scene takes its place
+        let options = scene_args.load_options;
+        let frame_camera_on_load = scene_args.path.is_some() || scene_args.procedural;
+        let loaders: Vec<MeshLoadFn> = match scene_args.path {
+            Some(path) => vec![Box::new(move || load_meshes(path, options))],
+            None if scene_args.procedural => vec![Box::new(move || load_procedural(options))],
+            None if !std::path::Path::new(SPONZA_PATH).exists() => {
+                dbg!(format!(
+                    "{SPONZA_PATH} not found, showing the procedural scene in its place. \
+                     {SPONZA_DOWNLOAD_HINT}"
+                ));
+                vec![
+                    Box::new(move || load_cathedral(options)),
+                    Box::new(move || load_procedural(options)),
+                ]
+            }
+            None => vec![
+                Box::new(move || load_cathedral(options)),
+                Box::new(move || load_sponza(options)),
+            ],
+        };
+        let num_files = loaders.len();
+        // NOTE: a default scene that fails shows a cube, so there is always something to look at
+        let mesh_loader = MeshLoader::spawn(loaders, true);
+
+        let vertex_format = args.vertex_format;
+        let normal_encoding = args.normal_encoding;
+
+        // dbg!(a);
+        // let gpu_sponza = upload_vertices(sponza_vertices, &ctx);
+        // let gpu_vertices = upload_vertices(vertices, &ctx);
+
+        // NOTE: everything created during startup is uploaded in one go once imgui is set up
+        let mut upload_batch = UploadBatch::default();
+        let white_texture = upload_texture_array_rgba8(
+            &ctx,
+            &mut upload_batch,
+            "white",
+            1,
+            1,
+            &[vec![255; 4]],
+            false,
+        );
+        let skybox = scene_args
+            .skybox
+            .as_deref()
+            .and_then(|path| load_skybox(&ctx, &mut upload_batch, path));
+        let dummy_irradiance = CubeTexture::upload(
+            &ctx,
+            &mut upload_batch,
+            "dummy irradiance",
+            1,
+            &[(); 6].map(|_| vec![255; 4]),
+        );
+        let material_samplers = MaterialSamplers::new(&ctx, args.anisotropy);
+        let default_material = GpuMaterial {
+            diffuse: Vec3A::ONE,
+            layer: None,
+            roughness: 1.0,
+            metalness: 0.0,
+        };
+
+        let file_to_draw = 0;
+
+        // let g_buffer = GBuffer::new(&ctx, width, height);
+
+        let screen_size = gpu::Extent {
+            width,
+            height,
+            depth: 1,
+        };
+
+        let (downsample_textures, ao_textures) =
+            create_downsample_and_ao_textures(&ctx, screen_size, normal_encoding);
+        let shadow_map = create_shadow_map(&ctx);
+        let compute_test_texture = create_compute_test_texture(&ctx);
+        let taa_textures = create_taa_textures(&ctx, screen_size, surface.info().format);
+        let dof_source =
+            create_render_texture(&ctx, "dof source", surface.info().format, screen_size);
+        let ssr_source =
+            create_render_texture(&ctx, "ssr source", surface.info().format, screen_size);
+        let tssao_history = create_tssao_history(&ctx, screen_size);
+
+        // let depth_textures = create_depth_textures(&ctx, screen_extent);
+
+        let reverse_z = args.reverse_z;
+        let pipelines = match Pipelines::rebuild(
+            &ctx,
+            surface.info().format,
+            vertex_format,
+            normal_encoding,
+            reverse_z,
+        ) {
+            Ok(pipelines) => pipelines,
+            // NOTE: nothing to fall back to before the first build, unlike a reload
+            Err(err) => {
+                dbg!(format!("!!! COULD NOT BUILD THE PIPELINES: {err} !!!"));
+                std::process::exit(1);
+            }
+        };
+
+        let settings = Settings::load(SETTINGS_PATH);
+        let mut scene = Scene::new(Camera {
+            reverse_z,
+            ..Camera::default_from_aspect(aspect)
+        });
+        settings.apply_to_scene(&mut scene);
+        let prev_globals = Globals::unjittered(&scene.camera);
+        let input_state = InputState {
+            use_ao: settings.use_ao,
+            ao_level: settings.ao_level,
+            use_blur: settings.use_blur,
+            ao_settings: settings.ao_settings,
+            shadow_settings: settings.shadow_settings,
+            debug_depth: settings.debug_depth,
+            debug_normals: settings.debug_normals,
+            use_mipmaps: settings.use_mipmaps,
+            use_lod: settings.use_lod,
+            lod_screen_size: settings.lod_screen_size,
+            use_frustum_culling: settings.use_frustum_culling,
+            use_depth_prepass: settings.use_depth_prepass,
+            debug_draw_bounds: false,
+            debug_draw_lights: false,
+            use_taa: args.use_taa,
+            taa_history_weight: 0.9,
+            use_tssao: args.use_tssao,
+            tssao_alpha: 0.1,
+            dof: DofSettings {
+                enabled: args.dof,
+                ..DofSettings::default()
+            },
+            ssr: SsrSettings {
+                enabled: args.ssr,
+                ..SsrSettings::default()
+            },
+            ao_gizmos: AoGizmoSettings {
+                enabled: args.ao_gizmos,
+                ..AoGizmoSettings::default()
+            },
+            ao_scissor: AoScissorSettings {
+                enabled: args.ao_scissor,
+                ..AoScissorSettings::default()
+            },
+            ao_split: AoSplitSettings {
+                enabled: args.ao_split,
+                ..AoSplitSettings::default()
+            },
+            mouse_sensitivity: settings.mouse_sensitivity,
+            compute_test: args.compute_test,
+        };
+
+        let mut imgui = imgui::Context::create();
+        imgui.set_ini_filename(None);
+        imgui
+            .fonts()
+            .add_font(&[imgui::FontSource::DefaultFontData { config: None }]);
+        let mut imgui_platform = imgui_winit_support::WinitPlatform::new(&mut imgui);
+        imgui_platform.attach_window(
+            imgui.io_mut(),
+            window,
+            imgui_winit_support::HiDpiMode::Default,
+        );
+        let imgui_renderer =
+            ImguiRenderer::new(&ctx, &mut upload_batch, surface.info().format, &mut imgui);
+        let particles = ParticleSystem::new(&ctx, surface.info().format, 4096, reverse_z);
+        let debug_draw = DebugDraw::new(&ctx, surface.info().format, 16384, reverse_z);
+        let ao_gizmos = AoGizmos::new(&ctx, surface.info().format);
+        let light_buffer = create_light_buffer(&ctx);
+        let depth_readback =
+            ReadbackDepthBuffer::new(&ctx, downsample_textures.textures[0].depth.size);
+
+        // NOTE: the only upload submission during startup
+        upload_batch.flush(&ctx, &mut command_encoder);
+
+        let gilrs = match gilrs::Gilrs::new() {
+            Ok(gilrs) => Some(gilrs),
+            Err(err) => {
+                dbg!(err);
+                None
+            }
+        };
+
+        Self {
+            command_encoder,
+            ctx,
+            surface,
+            prev_sync_point: None,
+            meshes,
+            mesh_loader: Some(mesh_loader),
+            load_error: None,
+            dropped_files: vec![],
+            drop_options: scene_args.drop_options,
+            default_material,
+            white_texture,
+            material_samplers,
+            scene,
+            retained_input: Default::default(),
+            pipelines,
+            downsample_textures,
+            ao_textures,
+            shadow_map,
+            compute_test_texture,
+            taa_textures,
+            dof_source,
+            ssr_source,
+            tssao_history,
+            skybox,
+            dummy_irradiance,
+            input_state,
+            delta_time: 0.1,
+            prev_time: std::time::Instant::now(),
+            prev_globals,
+            cursor_grabbed: false,
+            move_speed: 1.0,
+            selected_node: None,
+            total_time: 0.0,
+            file_to_draw,
+            num_files,
+            frame_camera_on_load,
+            scene_aabb: None,
+            nodes_in_view: vec![],
+            light_variants: std::collections::HashMap::new(),
+            culled_nodes: 0,
+            lod_triangles: [0; 2],
+            wireframe_mode: false,
+            topology_debug: false,
+            dump_gbuffer: false,
+            autofocus: false,
+            gbuffer_dump_count: 0,
+            depth_readback,
+            gilrs,
+            imgui,
+            imgui_platform,
+            imgui_renderer,
+            particles,
+            ao_gizmos,
+            debug_draw,
+            frozen_camera: None,
+            light_buffer,
+            camera_keyframes: vec![],
+            camera_path: None,
+            camera_path_start: std::time::Instant::now(),
+            keyframe_duration: 2.0,
+        }
+    }
+
+    pub fn render_downsample(&mut self) {
+        for i in 1..NUM_AO_TEXTURES {
+            let textures_from = &self.downsample_textures.textures[i - 1];
+            let textures_to = &self.downsample_textures.textures[i];
+
+            if let mut depth_downsample_pass = self.command_encoder.render(
+                format!("depth downsample {i}").as_str(),
+                gpu::RenderTargetSet {
+                    colors: &[gpu::RenderTarget {
+                        view: textures_to.normal.view,
+                        init_op: gpu::InitOp::Clear(gpu::TextureColor::White),
+                        finish_op: gpu::FinishOp::Store,
+                    }],
+                    depth_stencil: Some(gpu::RenderTarget {
+                        view: textures_to.depth.view,
+                        init_op: gpu::InitOp::Clear(depth_clear_color(self.pipelines.reverse_z)),
+                        finish_op: gpu::FinishOp::Store,
+                    }),
+                },
+            ) {
+                let mut rc = depth_downsample_pass.with(&self.pipelines.depth_downsample);
+                rc.bind(
+                    0,
+                    &DepthNormalParams {
+                        depth_view: textures_from.depth.view,
+                        normal_view: textures_from.normal.view,
+                        normal_sampler: textures_from.normal.sampler,
+                        globals: Globals::new(&self.scene.camera, &Transform::IDENTITY),
+                    },
+                );
+                // NOTE: full screen triangle generated in vertex shader
+                rc.draw(0, 3, 0, 1);
+            }
+        }
+    }
+
+    // NOTE: one pass per mip so each one sees the finished mip before it
+    pub fn render_hi_z(&mut self) {
+        let hi_z = &self.downsample_textures.hi_z;
+        for (mip, dst_view) in hi_z.mip_views.iter().enumerate() {
+            let size = hi_z_mip_size(hi_z.texture.size, mip as u32);
+            let groups = [
+                size.width.div_ceil(HI_Z_WORKGROUP_SIZE),
+                size.height.div_ceil(HI_Z_WORKGROUP_SIZE),
+                1,
+            ];
+            if let mut hi_z_pass = self.command_encoder.compute(format!("hi-z {mip}").as_str()) {
+                if mip == 0 {
+                    let mut pc = hi_z_pass.with(&self.pipelines.hi_z_linear_depth);
+                    pc.bind(
+                        0,
+                        &HiZLinearDepthParams {
+                            globals: Globals::new(&self.scene.camera, &Transform::IDENTITY),
+                            depth_view: self.downsample_textures.textures[0].depth.view,
+                            dst_view: *dst_view,
+                        },
+                    );
+                    pc.dispatch(groups);
+                } else {
+                    let mut pc = hi_z_pass.with(&self.pipelines.hi_z_downsample);
+                    pc.bind(
+                        0,
+                        &HiZDownsampleParams {
+                            src_view: hi_z.mip_views[mip - 1],
+                            dst_view: *dst_view,
+                        },
+                    );
+                    pc.dispatch(groups);
+                }
+            }
+        }
+    }
+
+    // NOTE: golden angle steps spread the rotations of consecutive frames evenly
+    pub fn ao_kernel_rotation(&self, use_tssao: bool) -> f32 {
+        if use_tssao {
+            self.tssao_history.frame_index as f32 * 2.399_963
+        } else {
+            0.0
+        }
+    }
+
+    pub fn render_calc_ao(&mut self, use_tssao: bool) {
+        let hi_z_mips = self.downsample_textures.hi_z.mip_views.len() as u32;
+        let kernel_rotation = self.ao_kernel_rotation(use_tssao);
+        let scissor = self.input_state.ao_scissor;
+        for i in (0..NUM_AO_TEXTURES).rev() {
+            let ao_target = &self.ao_textures.textures[i];
+            let bent_normal_target = &self.ao_textures.bent_normals[i];
+            let is_first_pass = i == NUM_AO_TEXTURES - 1;
+
+            // NOTE: calc ao pass
+            if let mut calc_ao_pass = self.command_encoder.render(
+                format!("calc ao {i}").as_str(),
+                gpu::RenderTargetSet {
+                    colors: &[
+                        gpu::RenderTarget {
+                            view: ao_target.view,
+                            init_op: gpu::InitOp::Clear(scissor.ao_clear_color()),
+                            finish_op: gpu::FinishOp::Store,
+                        },
+                        gpu::RenderTarget {
+                            view: bent_normal_target.view,
+                            init_op: gpu::InitOp::Clear(gpu::TextureColor::TransparentBlack),
+                            finish_op: gpu::FinishOp::Store,
+                        },
+                    ],
+                    depth_stencil: None,
+                },
+            ) {
+                // NOTE: these textures have same size as render target
+                let dnp = &self.downsample_textures.textures[i];
+                let prev_dnp = &self.downsample_textures.textures[(i + 1).min(NUM_AO_TEXTURES - 1)];
+                let prev_ao_blur =
+                    &self.ao_textures.textures_after_blur[(i + 1).min(NUM_AO_TEXTURES - 1)];
+                let hi_z = &self.downsample_textures.hi_z;
+                let mut rc = calc_ao_pass.with(&self.pipelines.calc_ao);
+                if scissor.enabled {
+                    rc.set_scissor_rect(&scissor.rect(ao_target.size));
+                }
+
+                rc.bind(
+                    0,
+                    &CalcAoParams {
+                        globals: Globals::new(&self.scene.camera, &Transform::IDENTITY),
+                        depth_view: dnp.depth.view,
+
+                        normal_view: dnp.normal.view,
+                        normal_sampler: dnp.normal.sampler,
+
+                        prev_depth_view: prev_dnp.depth.view,
+
+                        prev_normal_view: prev_dnp.normal.view,
+                        prev_normal_sampler: prev_dnp.normal.sampler,
 
                         prev_ao_view: prev_ao_blur.view,
                         prev_ao_sampler: prev_ao_blur.sampler,
 
-                        ao_params: AOParams::from(
-                            i,
-                            1.0,
-                            self.camera.vfov_rad,
-                            ao_target.size.width,
-                            ao_target.size.height,
-                        ),
-                    },
-                );
-                rc.bind_vertex(0, self.screen_quad_buf);
-                let num_quad_vertices = 6;
-                rc.draw(0, num_quad_vertices as _, 0, 1);
+                        hi_z_view: hi_z.texture.view,
+                        hi_z_sampler: hi_z.texture.sampler,
+
+                        ao_params: AOParams::from(
+                            i,
+                            1.0,
+                            self.scene.camera.vfov_rad,
+                            ao_target.size.width,
+                            ao_target.size.height,
+                            &self.input_state.ao_settings,
+                            kernel_rotation,
+                            hi_z_mips,
+                        ),
+                    },
+                );
+                // NOTE: full screen triangle generated in vertex shader
+                rc.draw(0, 3, 0, 1);
+            }
+
+            // NOTE: blur ao pass
+
+            let ao_blur_target = &self.ao_textures.textures_after_blur[i];
+            let dpn = &self.downsample_textures.textures[i];
+            if let mut blur_ao_pass = self.command_encoder.render(
+                format!("blur ao {i}").as_str(),
+                gpu::RenderTargetSet {
+                    colors: &[gpu::RenderTarget {
+                        view: ao_blur_target.view,
+                        init_op: gpu::InitOp::Clear(scissor.ao_clear_color()),
+                        finish_op: gpu::FinishOp::Store,
+                    }],
+                    depth_stencil: None,
+                },
+            ) {
+                // NOTE: these textures have same size as render target
+                let mut rc = blur_ao_pass.with(&self.pipelines.blur_ao);
+                if scissor.enabled {
+                    rc.set_scissor_rect(&scissor.rect(ao_blur_target.size));
+                }
+
+                rc.bind(
+                    0,
+                    &BlurParams {
+                        globals: Globals::new(&self.scene.camera, &Transform::IDENTITY),
+                        ao_view: ao_target.view,
+                        ao_sampler: ao_target.sampler,
+                        ao_params: AOParams::from(
+                            i,
+                            1.0,
+                            self.scene.camera.vfov_rad,
+                            ao_target.size.width,
+                            ao_target.size.height,
+                            &self.input_state.ao_settings,
+                            kernel_rotation,
+                            hi_z_mips,
+                        ),
+                        depth_view: dpn.depth.view,
+                        normal_view: dpn.normal.view,
+                        normal_sampler: dpn.normal.sampler,
+                    },
+                );
+                // NOTE: full screen triangle generated in vertex shader
+                rc.draw(0, 3, 0, 1);
+            }
+        }
+    }
+
+    // NOTE: queued for the next frame, an error for files load_meshes can't parse
+    pub fn drop_file(&mut self, path: std::path::PathBuf) -> Result<(), String> {
+        if !is_mesh_path(&path) {
+            return Err(format!(
+                "can't load {}, only obj, ply, gltf and glb files",
+                path.display()
+            ));
+        }
+        self.dropped_files.push(path);
+        Ok(())
+    }
+
+    // NOTE: the files dropped since the last frame replace the whole scene, one file each
+    // like the default scenes. A load still in flight is given up together with the
+    // meshes it already delivered, so the last drop always wins
+    pub fn load_dropped_files(&mut self) {
+        if self.dropped_files.is_empty() {
+            return;
+        }
+        let paths = std::mem::take(&mut self.dropped_files);
+        dbg!(format!("loading {} dropped file(s)", paths.len()));
+        // NOTE: the loader thread stops on its next send once the receiver is gone
+        self.mesh_loader = None;
+        // NOTE: finish_frame waits for the gpu, nothing is still using the buffers
+        for mesh in self.meshes.drain(..) {
+            mesh.destroy(&self.ctx);
+        }
+        self.scene.nodes.clear();
+        self.nodes_in_view.clear();
+        self.selected_node = None;
+        let options = self.drop_options;
+        let loaders = paths
+            .into_iter()
+            .map(|path| Box::new(move || load_meshes(path, options)) as MeshLoadFn)
+            .collect::<Vec<_>>();
+        self.num_files = loaders.len();
+        self.file_to_draw = 0;
+        self.frame_camera_on_load = true;
+        self.scene_aabb = None;
+        self.load_error = None;
+        // NOTE: no cube for dropped files, a failure leaves the scene empty with the error in
+        // the title
+        self.mesh_loader = Some(MeshLoader::spawn(loaders, false));
+    }
+
+    // NOTE: uploads whatever the loader thread has finished so far, call before render.
+    // true if a mesh arrived or the loader finished, so the title needs an update
+    pub fn receive_loaded_meshes(&mut self) -> bool {
+        let Some(loader) = self.mesh_loader.as_mut() else {
+            return false;
+        };
+        loader.time += self.delta_time;
+
+        let mut uploader = Uploader::new(&self.ctx, self.pipelines.vertex_format);
+        // NOTE: the uploader hands the meshes back in queue order
+        let mut files = vec![];
+        let mut failed = false;
+        loop {
+            match loader.receiver.try_recv() {
+                Ok(LoaderMessage::Failed { file, error }) => {
+                    failed = true;
+                    self.load_error = Some(format!("file {}: {error}", file + 1));
+                }
+                Ok(LoaderMessage::Mesh(LoadedMesh {
+                    mesh,
+                    lod,
+                    bvh,
+                    file,
+                })) => {
+                    files.push(file);
+                    let handle = uploader.queue(mesh, bvh);
+                    if let Some(lod) = lod {
+                        uploader.queue_lod(handle, lod);
+                    }
+                }
+                Err(std::sync::mpsc::TryRecvError::Empty) => break,
+                Err(std::sync::mpsc::TryRecvError::Disconnected) => {
+                    dbg!("all meshes loaded");
+                    self.mesh_loader = None;
+                    break;
+                }
+            }
+        }
+        let changed = !files.is_empty() || failed || self.mesh_loader.is_none();
+        let meshes = uploader.flush(&mut self.command_encoder);
+        for (mesh, file) in meshes.into_iter().zip(files) {
+            let visible = file == self.file_to_draw;
+            self.scene.add_node(self.meshes.len(), file).visible = visible;
+            if self.frame_camera_on_load {
+                let aabb = match self.scene_aabb {
+                    Some(scene_aabb) => scene_aabb.union(&mesh.aabb),
+                    None => mesh.aabb,
+                };
+                self.scene_aabb = Some(aabb);
+                self.scene.camera.frame_aabb(&aabb);
+            }
+            self.meshes.push(mesh);
+        }
+        changed
+    }
+
+    // NOTE: window title, counts the uploaded meshes while the loader runs and shows the
+    // last load error once it is done
+    pub fn title(&self) -> String {
+        if self.mesh_loader.is_some() {
+            format!("ssao - loading, {} meshes so far", self.meshes.len())
+        } else if let Some(err) = &self.load_error {
+            format!("ssao - failed to load {err}")
+        } else {
+            format!("ssao - move speed {:.2}x", self.move_speed)
+        }
+    }
+
+    // NOTE: lines of the scroll wheel, up is positive and speeds up
+    pub fn scroll_move_speed(&mut self, lines: f32) {
+        let (min, max) = MOVE_SPEED_RANGE;
+        self.move_speed = (self.move_speed * MOVE_SPEED_SCROLL_STEP.powf(lines)).clamp(min, max);
+    }
+
+    pub fn render(&mut self) {
+        self.total_time += self.delta_time;
+        self.scene.camera.time_secs = self.total_time;
+        self.scene.camera.delta_time_secs = self.delta_time;
+
+        if let Some(loader) = self.mesh_loader.as_ref() {
+            let time = loader.time;
+            self.render_loading(time);
+            return;
+        }
+
+        self.command_encoder.start();
+        for texture in self.downsample_textures.textures.iter() {
+            self.command_encoder.init_texture(texture.depth.texture);
+            self.command_encoder.init_texture(texture.normal.texture);
+        }
+
+        self.command_encoder
+            .init_texture(self.downsample_textures.albedo.texture);
+        self.command_encoder
+            .init_texture(self.downsample_textures.material.texture);
+        self.command_encoder
+            .init_texture(self.downsample_textures.hi_z.texture.texture);
+
+        for t in self.ao_textures.textures.iter() {
+            self.command_encoder.init_texture(t.texture);
+        }
+        for t in self.ao_textures.textures_after_blur.iter() {
+            self.command_encoder.init_texture(t.texture);
+        }
+        for t in self.ao_textures.bent_normals.iter() {
+            self.command_encoder.init_texture(t.texture);
+        }
+        self.command_encoder
+            .init_texture(self.ao_textures.dummy_texture.texture);
+        self.command_encoder
+            .init_texture(self.shadow_map.array.texture);
+        self.command_encoder
+            .init_texture(self.compute_test_texture.texture);
+        self.command_encoder
+            .init_texture(self.taa_textures.current.texture);
+        self.command_encoder.init_texture(self.dof_source.texture);
+        self.command_encoder.init_texture(self.ssr_source.texture);
+        for history in self.taa_textures.history.iter() {
+            self.command_encoder.init_texture(history.texture);
+        }
+        for history in self
+            .tssao_history
+            .history
+            .iter()
+            .chain(self.tssao_history.history_depth.iter())
+        {
+            self.command_encoder.init_texture(history.texture);
+        }
+
+        let use_taa = self.input_state.use_taa
+            && !self.input_state.debug_depth
+            && !self.input_state.debug_normals;
+        let use_tssao = self.input_state.use_tssao
+            && !self.input_state.debug_depth
+            && !self.input_state.debug_normals;
+        self.scene.camera.jitter = if use_taa {
+            taa_jitter(
+                self.taa_textures.frame_index,
+                self.taa_textures.current.size,
+            )
+        } else {
+            Vec2::ZERO
+        };
+        let size = self.taa_textures.current.size;
+        self.scene.camera.viewport_size = Vec2::new(size.width as f32, size.height as f32);
+
+        self.cull_nodes();
+        self.queue_debug_lines();
+        self.debug_draw.flush(&self.ctx);
+
+        let shadow_splits = cascade_splits(&self.input_state.shadow_settings, &self.scene.camera);
+        let light_vps = cascade_view_projs(&self.scene.camera, &shadow_splits);
+        if self.input_state.shadow_settings.enabled {
+            self.render_shadow(&light_vps);
+        }
+
+        // NOTE: wireframe lines don't match the filled triangle depth, no prepass for them
+        let depth_prepass = self.input_state.use_depth_prepass && !self.wireframe_mode;
+        if depth_prepass {
+            self.render_depth_prepass();
+        }
+
+        if self.input_state.compute_test {
+            self.render_compute_test();
+        }
+
+        let geometry_target = &self.downsample_textures.textures[0];
+
+        if let mut geometry_pass = self.command_encoder.render(
+            "geometry",
+            gpu::RenderTargetSet {
+                colors: &[
+                    gpu::RenderTarget {
+                        view: geometry_target.normal.view,
+                        init_op: gpu::InitOp::Clear(gpu::TextureColor::White),
+                        finish_op: gpu::FinishOp::Store,
+                    },
+                    gpu::RenderTarget {
+                        view: self.downsample_textures.albedo.view,
+                        init_op: gpu::InitOp::Clear(gpu::TextureColor::White),
+                        finish_op: gpu::FinishOp::Store,
+                    },
+                    gpu::RenderTarget {
+                        view: self.downsample_textures.material.view,
+                        init_op: gpu::InitOp::Clear(gpu::TextureColor::White),
+                        finish_op: gpu::FinishOp::Store,
+                    },
+                ],
+                depth_stencil: Some(gpu::RenderTarget {
+                    view: geometry_target.depth.view,
+                    init_op: if depth_prepass {
+                        gpu::InitOp::Load
+                    } else {
+                        gpu::InitOp::Clear(depth_clear_color(self.pipelines.reverse_z))
+                    },
+                    finish_op: gpu::FinishOp::Store,
+                }),
+            },
+        ) {
+            let main_pipeline = if self.wireframe_mode {
+                &self.pipelines.wireframe
+            } else if depth_prepass {
+                &self.pipelines.geometry_after_prepass
+            } else {
+                &self.pipelines.geometry
+            };
+            // NOTE: topology debug draws everything a second time on top of the solid pass,
+            // the selection only the selected node. None draws all nodes in view
+            let mut pipelines = vec![(main_pipeline, None)];
+            if self.topology_debug {
+                pipelines.push((&self.pipelines.backface, None));
+            }
+            if let Some(selected) = self.selected_node {
+                pipelines.push((&self.pipelines.selection, Some(selected)));
+            }
+
+            for (pipeline, only_node) in pipelines {
+                let mut rc = geometry_pass.with(pipeline);
+
+                for (_, (node, _)) in self
+                    .scene
+                    .nodes
+                    .iter()
+                    .zip(self.nodes_in_view.iter())
+                    .enumerate()
+                    .filter(|(i, (_, in_view))| **in_view && only_node.unwrap_or(*i) == *i)
+                {
+                    // NOTE: the lod is drawn with the transform of the node as well
+                    let globals = Globals::new(&self.scene.camera, &node.transform);
+                    let prev_frame = PreviousFrameGlobals::new(&self.prev_globals, &node.transform);
+                    let mesh = self.meshes[node.mesh_index].select_lod(
+                        &node.transform,
+                        &self.scene.camera,
+                        self.input_state.lod_screen_size(),
+                    );
+                    // NOTE: same texture array for every draw of the mesh, only the layer changes
+                    let albedo_texture = mesh.material_textures.unwrap_or(self.white_texture);
+                    let albedo_sampler = self.material_samplers.get(self.input_state.use_mipmaps);
+                    rc.bind(0, &GeometryParams { globals });
+                    rc.bind(2, &prev_frame);
+                    rc.bind_vertex(0, mesh.vertex_buf);
+                    for draw in mesh.draws.iter() {
+                        let mut material_uniform = draw
+                            .material
+                            .and_then(|i| mesh.materials.get(i))
+                            .unwrap_or(&self.default_material)
+                            .uniform();
+                        material_uniform.use_vertex_normals = mesh.vertex_normals as u32;
+                        rc.bind(
+                            1,
+                            &MaterialParams {
+                                material: material_uniform,
+                                albedo_view: albedo_texture.view,
+                                albedo_sampler,
+                            },
+                        );
+                        if let Some(index_buf) = mesh.index_buf {
+                            let index_buf = gpu::BufferPiece {
+                                buffer: index_buf.buffer,
+                                offset: index_buf.offset
+                                    + (draw.start * std::mem::size_of::<u32>()) as u64,
+                            };
+                            rc.draw_indexed(
+                                index_buf,
+                                gpu::IndexType::U32,
+                                draw.count as _,
+                                0,
+                                0,
+                                1,
+                            );
+                        } else {
+                            rc.draw(draw.start as _, draw.count as _, 0, 1);
+                        }
+                    }
+                }
+            }
+        }
+
+        let gbuffer_readback = std::mem::take(&mut self.dump_gbuffer).then(|| {
+            record_gbuffer_readback(
+                &self.ctx,
+                &mut self.command_encoder,
+                &self.downsample_textures.textures[0],
+                self.pipelines.normal_encoding,
+            )
+        });
+
+        let autofocus = std::mem::take(&mut self.autofocus);
+        let center_pixel = self.depth_readback.center();
+        if autofocus {
+            self.depth_readback.request(center_pixel);
+        }
+        self.depth_readback.record(
+            &mut self.command_encoder,
+            &self.downsample_textures.textures[0].depth,
+        );
+
+        let frame = self.surface.acquire_frame();
+        self.command_encoder.init_texture(frame.texture());
+
+        if self.input_state.debug_depth {
+            self.render_depth_debug(frame.texture_view());
+        } else if self.input_state.debug_normals {
+            self.render_normal_debug(frame.texture_view());
+        } else {
+            self.render_downsample();
+            if self.input_state.use_ao {
+                if self.input_state.ao_settings.hi_z_mip_selection != HiZMipSelection::Off {
+                    self.render_hi_z();
+                }
+                self.render_calc_ao(use_tssao);
+                if self.input_state.ao_gizmos.enabled {
+                    self.render_ao_gizmos(use_tssao);
+                }
+                if use_tssao {
+                    self.render_tssao_resolve();
+                }
+            }
+            let use_dof = self.input_state.dof.enabled;
+            let output = if use_dof {
+                self.dof_source.view
+            } else {
+                frame.texture_view()
+            };
+            let lit_target = if use_taa {
+                self.taa_textures.current.view
+            } else {
+                output
+            };
+            let use_ssr = self.input_state.ssr.enabled;
+            let light_target = if use_ssr {
+                self.ssr_source.view
+            } else {
+                lit_target
+            };
+            self.render_light(light_target, &shadow_splits, &light_vps, use_tssao);
+            self.render_skybox(light_target);
+            // NOTE: before the taa resolve so the jittered reflections get resolved too
+            if use_ssr {
+                self.render_ssr(lit_target);
+            }
+            if use_taa {
+                self.render_taa_resolve(output);
+            }
+            if use_dof {
+                self.render_dof(frame.texture_view());
+            }
+            // NOTE: after the resolve, particles move on their own and would smear
+            self.particles.render(
+                &mut self.command_encoder,
+                frame.texture_view(),
+                self.downsample_textures.textures[0].depth.view,
+                &self.scene.camera,
+            );
+            self.debug_draw.render(
+                &mut self.command_encoder,
+                frame.texture_view(),
+                self.downsample_textures.textures[0].depth.view,
+                &self.scene.camera,
+            );
+            if self.input_state.use_ao && self.input_state.ao_gizmos.enabled {
+                self.ao_gizmos.render(
+                    &mut self.command_encoder,
+                    frame.texture_view(),
+                    &self.scene.camera,
+                );
+            }
+        }
+
+        if use_taa {
+            self.taa_textures.history_index = 1 - self.taa_textures.history_index;
+            self.taa_textures.frame_index = self.taa_textures.frame_index.wrapping_add(1);
+        }
+        self.taa_textures.history_valid = use_taa;
+        if use_tssao {
+            self.tssao_history.history_index = 1 - self.tssao_history.history_index;
+            self.tssao_history.frame_index = self.tssao_history.frame_index.wrapping_add(1);
+        }
+        self.tssao_history.history_valid = use_tssao;
+        self.scene.camera.prev_vp = self.scene.camera.unjittered_vp();
+        self.prev_globals = Globals::unjittered(&self.scene.camera);
+
+        self.finish_frame(frame);
+
+        // NOTE: finish_frame waited for the gpu, the readback buffers are filled by now and
+        // depth_readback holds this frame
+        let focus_depth = if autofocus {
+            self.depth_readback.raw_depth(center_pixel)
+        } else {
+            None
+        };
+        if let Some(raw) = focus_depth {
+            let far_depth = if self.scene.camera.reverse_z {
+                0.0
+            } else {
+                1.0
+            };
+            if raw != far_depth {
+                self.input_state.dof.focus_distance = self.scene.camera.linear_depth(raw);
+                dbg!(self.input_state.dof.focus_distance);
+            } else {
+                dbg!("nothing to focus on in the center of the screen");
+            }
+        }
+        if let Some(readback) = gbuffer_readback {
+            let dir = std::path::Path::new("target/gbuffer_dump");
+            let prefix = format!("frame_{}", self.gbuffer_dump_count);
+            match write_gbuffer_dump(&readback, dir, &prefix, &self.scene.camera) {
+                Ok(()) => dbg!(format!("wrote g-buffer dump {prefix} to {}", dir.display())),
+                Err(err) => dbg!(format!("could not write g-buffer dump: {err}")),
+            };
+            readback.destroy(&self.ctx);
+            self.gbuffer_dump_count += 1;
+        }
+    }
+
+    // NOTE: draws the debug ui on top of everything, presents and waits for the gpu
+    pub fn finish_frame(&mut self, frame: gpu::Frame) {
+        let draw_data = self.imgui.render();
+        self.imgui_renderer.render(
+            &self.ctx,
+            &mut self.command_encoder,
+            frame.texture_view(),
+            draw_data,
+        );
+        self.command_encoder.present(frame);
+
+        let sp = self.ctx.submit(&mut self.command_encoder);
+        self.ctx.wait_for(&sp, !0);
+    }
+
+    // NOTE: shown instead of the scene until the mesh loader is done
+    pub fn render_loading(&mut self, time: f32) {
+        self.command_encoder.start();
+        let frame = self.surface.acquire_frame();
+        self.command_encoder.init_texture(frame.texture());
+        if let mut pass = self.command_encoder.render(
+            "loading",
+            gpu::RenderTargetSet {
+                colors: &[gpu::RenderTarget {
+                    view: frame.texture_view(),
+                    init_op: gpu::InitOp::Clear(gpu::TextureColor::OpaqueBlack),
+                    finish_op: gpu::FinishOp::Store,
+                }],
+                depth_stencil: None,
+            },
+        ) {
+            let mut rc = pass.with(&self.pipelines.loading);
+            rc.bind(
+                0,
+                &LoadingParams {
+                    time,
+                    aspect: self.scene.camera.aspect,
+                },
+            );
+            rc.draw(0, 3, 0, 1);
+        }
+
+        self.finish_frame(frame);
+    }
+
+    // NOTE: replaces the light pass, writes linearized depth as grayscale straight to the frame
+    pub fn render_depth_debug(&mut self, target: gpu::TextureView) {
+        let geometry_target = &self.downsample_textures.textures[0];
+        if let mut pass = self.command_encoder.render(
+            "depth debug",
+            gpu::RenderTargetSet {
+                colors: &[gpu::RenderTarget {
+                    view: target,
+                    init_op: gpu::InitOp::Clear(gpu::TextureColor::White),
+                    finish_op: gpu::FinishOp::Store,
+                }],
+                depth_stencil: None,
+            },
+        ) {
+            let mut rc = pass.with(&self.pipelines.depth_debug);
+            rc.bind(
+                0,
+                &DepthDebugParams {
+                    depth_view: geometry_target.depth.view,
+                    // NOTE: reverse z depth is 1 - depth, linearizing with near and far
+                    // swapped undoes that
+                    near: if self.pipelines.reverse_z {
+                        self.scene.camera.far
+                    } else {
+                        self.scene.camera.near
+                    },
+                    far: if self.pipelines.reverse_z {
+                        self.scene.camera.near
+                    } else {
+                        self.scene.camera.far
+                    },
+                },
+            );
+            rc.draw(0, 3, 0, 1);
+        }
+    }
+
+    // NOTE: replaces the light pass, writes world space normals mapped to [0,1] straight to the frame
+    pub fn render_normal_debug(&mut self, target: gpu::TextureView) {
+        let geometry_target = &self.downsample_textures.textures[0];
+        if let mut pass = self.command_encoder.render(
+            "normal debug",
+            gpu::RenderTargetSet {
+                colors: &[gpu::RenderTarget {
+                    view: target,
+                    init_op: gpu::InitOp::Clear(gpu::TextureColor::White),
+                    finish_op: gpu::FinishOp::Store,
+                }],
+                depth_stencil: None,
+            },
+        ) {
+            let mut rc = pass.with(&self.pipelines.normal_debug);
+            rc.bind(
+                0,
+                &NormalDebugParams {
+                    globals: Globals::new(&self.scene.camera, &Transform::IDENTITY),
+                    normal_view: geometry_target.normal.view,
+                },
+            );
+            rc.draw(0, 3, 0, 1);
+        }
+    }
+
+    // NOTE: the features the light pass needs this frame, ibl only with a skybox
+    pub fn light_pipeline_key(&self) -> PipelineKey {
+        let ao_settings = &self.input_state.ao_settings;
+        PipelineKey::new(
+            self.input_state.use_ao,
+            self.input_state.shadow_settings.enabled,
+            ao_settings.use_ibl && self.skybox.is_some(),
+        )
+    }
+
+    // NOTE: lines for the debug draw checkboxes, after cull_nodes so the bounds are the
+    // ones of the nodes in view
+    pub fn queue_debug_lines(&mut self) {
+        let draw = &mut self.debug_draw;
+        if self.input_state.debug_draw_bounds {
+            for (node, _) in self
+                .scene
+                .nodes
+                .iter()
+                .zip(self.nodes_in_view.iter())
+                .filter(|(_, in_view)| **in_view)
+            {
+                let aabb = self.meshes[node.mesh_index]
+                    .aabb
+                    .transformed(&node.transform.to_mat4());
+                draw.aabb(aabb.min, aabb.max, [0.2, 1.0, 0.2, 1.0]);
+            }
+        }
+        if self.input_state.debug_draw_lights {
+            for light in self.scene.lights.iter() {
+                match *light {
+                    Light::Point {
+                        position,
+                        color,
+                        radius,
+                        ..
+                    } => draw.sphere(position, radius, color.extend(1.0).to_array(), 32),
+                    // NOTE: a small sphere at the light and a line along its direction
+                    Light::Spot {
+                        position,
+                        direction,
+                        color,
+                        ..
+                    } => {
+                        let color = color.extend(1.0).to_array();
+                        draw.sphere(position, 0.1, color, 8);
+                        draw.line(position, position + direction, color);
+                    }
+                    Light::Directional { .. } => {}
+                }
+            }
+        }
+        if let Some(camera) = self.frozen_camera.as_ref() {
+            draw.frustum(camera, [1.0, 1.0, 0.2, 1.0]);
+        }
+    }
+
+    // NOTE: world space aabb of every visible node against the camera frustum
+    pub fn cull_nodes(&mut self) {
+        let camera = self.frozen_camera.as_ref().unwrap_or(&self.scene.camera);
+        let planes = camera.frustum_planes();
+        let use_culling = self.input_state.use_frustum_culling;
+        self.nodes_in_view = self
+            .scene
+            .nodes
+            .iter()
+            .map(|node| {
+                node.visible
+                    && (!use_culling
+                        || self.meshes[node.mesh_index]
+                            .aabb
+                            .transformed(&node.transform.to_mat4())
+                            .in_frustum(&planes))
+            })
+            .collect();
+        let num_visible = self.scene.nodes.iter().filter(|node| node.visible).count();
+        let num_in_view = self
+            .nodes_in_view
+            .iter()
+            .filter(|in_view| **in_view)
+            .count();
+        self.culled_nodes = num_visible - num_in_view;
+
+        let lod_screen_size = self.input_state.lod_screen_size();
+        self.lod_triangles = [0; 2];
+        for (node, _) in self
+            .scene
+            .nodes
+            .iter()
+            .zip(self.nodes_in_view.iter())
+            .filter(|(_, in_view)| **in_view)
+        {
+            let mesh = &self.meshes[node.mesh_index];
+            let drawn = mesh.select_lod(&node.transform, &self.scene.camera, lod_screen_size);
+            let level = !std::ptr::eq(drawn, mesh) as usize;
+            self.lod_triangles[level] += drawn.num_triangles();
+        }
+    }
+
+    // NOTE: fills the g-buffer depth so the geometry pass only shades visible fragments
+    pub fn render_depth_prepass(&mut self) {
+        let geometry_target = &self.downsample_textures.textures[0];
+        if let mut prepass = self.command_encoder.render(
+            "depth prepass",
+            gpu::RenderTargetSet {
+                colors: &[],
+                depth_stencil: Some(gpu::RenderTarget {
+                    view: geometry_target.depth.view,
+                    init_op: gpu::InitOp::Clear(depth_clear_color(self.pipelines.reverse_z)),
+                    finish_op: gpu::FinishOp::Store,
+                }),
+            },
+        ) {
+            let mut rc = prepass.with(&self.pipelines.depth_prepass);
+            for (node, _) in self
+                .scene
+                .nodes
+                .iter()
+                .zip(self.nodes_in_view.iter())
+                .filter(|(_, in_view)| **in_view)
+            {
+                let globals = Globals::new(&self.scene.camera, &node.transform);
+                let mesh = self.meshes[node.mesh_index].select_lod(
+                    &node.transform,
+                    &self.scene.camera,
+                    self.input_state.lod_screen_size(),
+                );
+                rc.bind(0, &ShadowPassParams { globals });
+                rc.bind_vertex(0, mesh.vertex_buf);
+                if let Some(index_buf) = mesh.index_buf {
+                    rc.draw_indexed(
+                        index_buf,
+                        gpu::IndexType::U32,
+                        mesh.num_indices as _,
+                        0,
+                        0,
+                        1,
+                    );
+                } else {
+                    rc.draw(0, mesh.num_vertices as _, 0, 1);
+                }
+            }
+        }
+    }
+
+    // NOTE: depth of every visible mesh as seen from the sun, one pass per cascade layer
+    pub fn render_shadow(&mut self, light_vps: &[Mat4; MAX_SHADOW_CASCADES]) {
+        let cascade_count = self
+            .input_state
+            .shadow_settings
+            .cascade_count
+            .clamp(1, MAX_SHADOW_CASCADES);
+        for (cascade_i, light_vp) in light_vps.iter().enumerate().take(cascade_count) {
+            if let mut shadow_pass = self.command_encoder.render(
+                "shadow",
+                gpu::RenderTargetSet {
+                    colors: &[],
+                    depth_stencil: Some(gpu::RenderTarget {
+                        view: self.shadow_map.layer_views[cascade_i],
+                        init_op: gpu::InitOp::Clear(gpu::TextureColor::White),
+                        finish_op: gpu::FinishOp::Store,
+                    }),
+                },
+            ) {
+                let mut rc = shadow_pass.with(&self.pipelines.shadow);
+                for node in self.scene.nodes.iter().filter(|node| node.visible) {
+                    let globals = Globals::for_light(*light_vp, &node.transform);
+                    // NOTE: picked by distance to the main camera, not the light
+                    let mesh = self.meshes[node.mesh_index].select_lod(
+                        &node.transform,
+                        &self.scene.camera,
+                        self.input_state.lod_screen_size(),
+                    );
+                    rc.bind(0, &ShadowPassParams { globals });
+                    rc.bind_vertex(0, mesh.vertex_buf);
+                    if let Some(index_buf) = mesh.index_buf {
+                        rc.draw_indexed(
+                            index_buf,
+                            gpu::IndexType::U32,
+                            mesh.num_indices as _,
+                            0,
+                            0,
+                            1,
+                        );
+                    } else {
+                        rc.draw(0, mesh.num_vertices as _, 0, 1);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn render_light(
+        &mut self,
+        target: gpu::TextureView,
+        shadow_splits: &[f32; MAX_SHADOW_CASCADES],
+        light_vps: &[Mat4; MAX_SHADOW_CASCADES],
+        use_tssao: bool,
+    ) {
+        let shadow_settings = self.input_state.shadow_settings;
+        let key = self.light_pipeline_key();
+        if !self.light_variants.contains_key(&key) {
+            let variant = match create_light_variant(
+                &self.ctx,
+                self.surface.info().format,
+                self.pipelines.normal_encoding,
+                key,
+            ) {
+                Ok(pipeline) => Some(pipeline),
+                Err(err) => {
+                    dbg!(format!("light variant {key:?}: {err}"));
+                    None
+                }
+            };
+            self.light_variants.insert(key, variant);
+        }
+        let num_lights = write_lights(&self.ctx, self.light_buffer, &self.scene.lights);
+        let textures_for_light_pass = &self.downsample_textures.textures[0];
+        // let textures_for_light_pass = &self.downsample_textures.textures.last().unwrap();
+        if let mut light_pass = self.command_encoder.render(
+            "light",
+            gpu::RenderTargetSet {
+                colors: &[gpu::RenderTarget {
+                    view: target,
+                    init_op: gpu::InitOp::Clear(gpu::TextureColor::White),
+                    finish_op: gpu::FinishOp::Store,
+                }],
+                depth_stencil: None,
+            },
+        ) {
+            let pipeline = self
+                .light_variants
+                .get(&key)
+                .and_then(Option::as_ref)
+                .unwrap_or(&self.pipelines.light);
+            let mut rc = light_pass.with(pipeline);
+
+            let use_blurred_texture = self.input_state.use_blur;
+            let ao_index = self.input_state.ao_level;
+            let ao_texture = if use_tssao {
+                self.tssao_history.resolved()
+            } else if use_blurred_texture {
+                &self.ao_textures.textures_after_blur[ao_index]
+            } else {
+                &self.ao_textures.textures[ao_index]
+            };
+            let bent_normal_texture = &self.ao_textures.bent_normals[ao_index];
+            let irradiance = match self.skybox.as_ref() {
+                Some(skybox) => &skybox.irradiance,
+                None => &self.dummy_irradiance,
+            };
+            // let ao_texture = &self.ao_textures.textures[0];
+            // let ao_texture = &self.downsample_textures.textures[1].normal;
+            rc.bind(
+                0,
+                &LightPassParams {
+                    shadow: ShadowParams {
+                        light_vp: light_vps.map(|vp| vp.to_cols_array_2d()),
+                        splits: *shadow_splits,
+                        sun_dir: sun_dir().to_array(),
+                        bias: shadow_settings.bias,
+                        texel_size: 1.0 / SHADOW_MAP_SIZE as f32,
+                        enabled: shadow_settings.enabled as u32,
+                        cascade_count: shadow_settings.cascade_count.clamp(1, MAX_SHADOW_CASCADES)
+                            as u32,
+                        blend_width: shadow_settings.blend_width,
+                        light_size: shadow_settings.light_size,
+                        pcss_num_blocker_samples: shadow_settings.pcss_num_blocker_samples,
+                        pcss_enabled: shadow_settings.pcss_enabled as u32,
+                        pad: 0,
+                    },
+                    shadow_view: self.shadow_map.array.view,
+                    shadow_sampler: self.shadow_map.array.sampler,
+                    normal_view: textures_for_light_pass.normal.view,
+                    normal_sampler: textures_for_light_pass.normal.sampler,
+                    depth_view: textures_for_light_pass.depth.view,
+                    depth_sampler: textures_for_light_pass.depth.sampler,
+                    globals: Globals::new(&self.scene.camera, &Transform::IDENTITY),
+                    ao_view: ao_texture.view,
+                    ao_sampler: ao_texture.sampler,
+                    bent_normal_view: bent_normal_texture.view,
+                    bent_normal_sampler: bent_normal_texture.sampler,
+                    ambient: AmbientUniform::new(
+                        &self.input_state.ao_settings,
+                        self.skybox.is_some(),
+                    ),
+                    irradiance_view: irradiance.cube_view,
+                    irradiance_sampler: irradiance.faces.sampler,
+                    albedo_view: self.downsample_textures.albedo.view,
+                    albedo_sampler: self.downsample_textures.albedo.sampler,
+                    material_view: self.downsample_textures.material.view,
+                    material_sampler: self.downsample_textures.material.sampler,
+                    split_x: self.input_state.ao_split.split_x(),
+                },
+            );
+            rc.bind(
+                1,
+                &LightListParams {
+                    lights: self.light_buffer.into(),
+                    num_lights,
+                },
+            );
+            // NOTE: full screen triangle generated in vertex shader
+            rc.draw(0, 3, 0, 1);
+        }
+    }
+
+    // NOTE: writes the gizmo instances for the picked pixel from the finest level inputs,
+    // the same AOParams the finest calc ao pass gets
+    pub fn render_ao_gizmos(&mut self, use_tssao: bool) {
+        let textures = &self.downsample_textures.textures[0];
+        let ao_target = &self.ao_textures.textures[0];
+        let hi_z = &self.downsample_textures.hi_z;
+        let ao_params = AOParams::from(
+            0,
+            1.0,
+            self.scene.camera.vfov_rad,
+            ao_target.size.width,
+            ao_target.size.height,
+            &self.input_state.ao_settings,
+            self.ao_kernel_rotation(use_tssao),
+            hi_z.mip_views.len() as u32,
+        );
+        let settings = &self.input_state.ao_gizmos;
+        if let mut compute_pass = self.command_encoder.compute("ao kernel gizmos") {
+            let mut pc = compute_pass.with(&self.pipelines.ao_kernel_gizmos);
+            pc.bind(
+                0,
+                &AoGizmoParams {
+                    globals: Globals::new(&self.scene.camera, &Transform::IDENTITY),
+                    ao_params,
+                    ao_gizmo: AoGizmoUniform {
+                        uv: settings.uv.to_array(),
+                        point_size: settings.point_size,
+                        pad0: 0,
+                    },
+                    depth_view: textures.depth.view,
+                    normal_view: textures.normal.view,
+                    normal_sampler: textures.normal.sampler,
+                    hi_z_view: hi_z.texture.view,
+                    hi_z_sampler: hi_z.texture.sampler,
+                    gizmos: self.ao_gizmos.instance_buf,
+                },
+            );
+            pc.dispatch([1, 1, 1]);
+        }
+    }
+
+    pub fn render_compute_test(&mut self) {
+        let size = self.compute_test_texture.size;
+        let groups = [
+            size.width.div_ceil(COMPUTE_TEST_WORKGROUP_SIZE),
+            size.height.div_ceil(COMPUTE_TEST_WORKGROUP_SIZE),
+            1,
+        ];
+        if let mut compute_pass = self.command_encoder.compute("compute test") {
+            let mut pc = compute_pass.with(&self.pipelines.test_compute_pipeline);
+            pc.bind(
+                0,
+                &ComputeTestParams {
+                    output: self.compute_test_texture.view,
+                },
+            );
+            pc.dispatch(groups);
+        }
+    }
+
+    pub fn render_skybox(&mut self, target: gpu::TextureView) {
+        let Some(skybox) = self.skybox.as_ref() else {
+            return;
+        };
+        if let mut skybox_pass = self.command_encoder.render(
+            "skybox",
+            gpu::RenderTargetSet {
+                colors: &[gpu::RenderTarget {
+                    view: target,
+                    init_op: gpu::InitOp::Load,
+                    finish_op: gpu::FinishOp::Store,
+                }],
+                depth_stencil: Some(gpu::RenderTarget {
+                    view: self.downsample_textures.textures[0].depth.view,
+                    init_op: gpu::InitOp::Load,
+                    finish_op: gpu::FinishOp::Store,
+                }),
+            },
+        ) {
+            let mut rc = skybox_pass.with(&self.pipelines.skybox);
+            rc.bind(
+                0,
+                &SkyboxParams {
+                    skybox: SkyboxUniform::new(&self.scene.camera),
+                    cube_view: skybox.environment.cube_view,
+                    cube_sampler: skybox.environment.faces.sampler,
+                },
+            );
+            rc.draw(0, 3, 0, 1);
+        }
+    }
+
+    // NOTE: the ao the light pass would use on its own, blended with the reprojected history
+    pub fn render_tssao_resolve(&mut self) {
+        let tssao = &self.tssao_history;
+        let ao_index = self.input_state.ao_level;
+        let current = if self.input_state.use_blur {
+            &self.ao_textures.textures_after_blur[ao_index]
+        } else {
+            &self.ao_textures.textures[ao_index]
+        };
+        let history = &tssao.history[tssao.history_index];
+        let history_depth = &tssao.history_depth[tssao.history_index];
+        let next_history = &tssao.history[1 - tssao.history_index];
+        let next_history_depth = &tssao.history_depth[1 - tssao.history_index];
+        let depth = &self.downsample_textures.textures[0].depth;
+        if let mut pass = self.command_encoder.render(
+            "tssao resolve",
+            gpu::RenderTargetSet {
+                colors: &[
+                    gpu::RenderTarget {
+                        view: next_history.view,
+                        init_op: gpu::InitOp::Clear(gpu::TextureColor::White),
+                        finish_op: gpu::FinishOp::Store,
+                    },
+                    gpu::RenderTarget {
+                        view: next_history_depth.view,
+                        init_op: gpu::InitOp::Clear(gpu::TextureColor::TransparentBlack),
+                        finish_op: gpu::FinishOp::Store,
+                    },
+                ],
+                depth_stencil: None,
+            },
+        ) {
+            let mut rc = pass.with(&self.pipelines.tssao_resolve);
+            rc.bind(
+                0,
+                &TssaoParams {
+                    globals: Globals::new(&self.scene.camera, &Transform::IDENTITY),
+                    tssao: TssaoUniform {
+                        alpha: self.input_state.tssao_alpha,
+                        history_valid: tssao.history_valid as u32,
+                        depth_tolerance: 0.05,
+                        pad: 0,
+                    },
+                    current_ao_view: current.view,
+                    current_ao_sampler: current.sampler,
+                    history_view: history.view,
+                    history_sampler: history.sampler,
+                    history_depth_view: history_depth.view,
+                    depth_view: depth.view,
+                },
+            );
+            rc.draw(0, 3, 0, 1);
+        }
+    }
+
+    // NOTE: reflects the lit image of this frame in itself, misses fall back to the same
+    // ambient the light pass uses
+    pub fn render_ssr(&mut self, target: gpu::TextureView) {
+        let textures = &self.downsample_textures.textures[0];
+        let irradiance = match self.skybox.as_ref() {
+            Some(skybox) => &skybox.irradiance,
+            None => &self.dummy_irradiance,
+        };
+        if let mut pass = self.command_encoder.render(
+            "ssr",
+            gpu::RenderTargetSet {
+                colors: &[gpu::RenderTarget {
+                    view: target,
+                    init_op: gpu::InitOp::Clear(gpu::TextureColor::White),
+                    finish_op: gpu::FinishOp::Store,
+                }],
+                depth_stencil: None,
+            },
+        ) {
+            let mut rc = pass.with(&self.pipelines.ssr);
+            rc.bind(
+                0,
+                &SsrParams {
+                    globals: Globals::new(&self.scene.camera, &Transform::IDENTITY),
+                    ssr: SsrUniform::new(&self.input_state.ssr, &self.scene.camera),
+                    ambient: AmbientUniform::new(
+                        &self.input_state.ao_settings,
+                        self.skybox.is_some(),
+                    ),
+                    color_view: self.ssr_source.view,
+                    color_sampler: self.ssr_source.sampler,
+                    depth_view: textures.depth.view,
+                    normal_view: textures.normal.view,
+                    normal_sampler: textures.normal.sampler,
+                    albedo_view: self.downsample_textures.albedo.view,
+                    albedo_sampler: self.downsample_textures.albedo.sampler,
+                    material_view: self.downsample_textures.material.view,
+                    material_sampler: self.downsample_textures.material.sampler,
+                    irradiance_view: irradiance.cube_view,
+                    irradiance_sampler: irradiance.faces.sampler,
+                },
+            );
+            rc.draw(0, 3, 0, 1);
+        }
+    }
+
+    pub fn render_dof(&mut self, target: gpu::TextureView) {
+        let depth = &self.downsample_textures.textures[0].depth;
+        if let mut pass = self.command_encoder.render(
+            "dof",
+            gpu::RenderTargetSet {
+                colors: &[gpu::RenderTarget {
+                    view: target,
+                    init_op: gpu::InitOp::Clear(gpu::TextureColor::White),
+                    finish_op: gpu::FinishOp::Store,
+                }],
+                depth_stencil: None,
+            },
+        ) {
+            let mut rc = pass.with(&self.pipelines.dof);
+            rc.bind(
+                0,
+                &DofParams {
+                    globals: Globals::new(&self.scene.camera, &Transform::IDENTITY),
+                    dof: DofUniform::new(
+                        &self.input_state.dof,
+                        &self.scene.camera,
+                        self.dof_source.size.height,
+                    ),
+                    color_view: self.dof_source.view,
+                    color_sampler: self.dof_source.sampler,
+                    depth_view: depth.view,
+                },
+            );
+            rc.draw(0, 3, 0, 1);
+        }
+    }
+
+    // NOTE: blends the jittered light pass output with the reprojected history
+    pub fn render_taa_resolve(&mut self, target: gpu::TextureView) {
+        let taa = &self.taa_textures;
+        let history = &taa.history[taa.history_index];
+        let next_history = &taa.history[1 - taa.history_index];
+        let depth = &self.downsample_textures.textures[0].depth;
+        if let mut pass = self.command_encoder.render(
+            "taa resolve",
+            gpu::RenderTargetSet {
+                colors: &[
+                    gpu::RenderTarget {
+                        view: target,
+                        init_op: gpu::InitOp::Clear(gpu::TextureColor::White),
+                        finish_op: gpu::FinishOp::Store,
+                    },
+                    gpu::RenderTarget {
+                        view: next_history.view,
+                        init_op: gpu::InitOp::Clear(gpu::TextureColor::White),
+                        finish_op: gpu::FinishOp::Store,
+                    },
+                ],
+                depth_stencil: None,
+            },
+        ) {
+            let mut rc = pass.with(&self.pipelines.taa_resolve);
+            rc.bind(
+                0,
+                &TaaParams {
+                    globals: Globals::new(&self.scene.camera, &Transform::IDENTITY),
+                    taa: TaaUniform {
+                        history_weight: self.input_state.taa_history_weight,
+                        history_valid: taa.history_valid as u32,
+                        pad0: 0,
+                        pad1: 0,
+                    },
+                    current_view: taa.current.view,
+                    current_sampler: taa.current.sampler,
+                    history_view: history.view,
+                    history_sampler: history.sampler,
+                    depth_view: depth.view,
+                },
+            );
+            rc.draw(0, 3, 0, 1);
+        }
+    }
+
+    pub fn handle_input(&mut self, dt: f32) {
+        let [r, f, u] = self.scene.camera.right_forward_up();
+
+        use winit::keyboard::KeyCode;
+        let held = |keys: &[KeyCode]| {
+            keys.iter()
+                .any(|key| self.retained_input.held_keys.contains(key))
+        };
+        let mut speed = CAM_SPEED * self.move_speed;
+        if held(&[KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+            speed *= MOVE_SPEED_BOOST;
+        }
+        let precision_keys = [
+            KeyCode::ControlLeft,
+            KeyCode::ControlRight,
+            KeyCode::AltLeft,
+            KeyCode::AltRight,
+        ];
+        if held(&precision_keys) {
+            speed *= MOVE_SPEED_PRECISION;
+        }
+        let angle_speed = CAM_ANGLE_SPEED;
+
+        // NOTE: imgui gets the mouse from the platform, both are in logical pixels. Over a
+        // window the gizmos stay where they were
+        let io = self.imgui.io();
+        let gizmos = &mut self.input_state.ao_gizmos;
+        if !gizmos.at_mouse || self.cursor_grabbed {
+            gizmos.uv = Vec2::splat(0.5);
+        } else if !io.want_capture_mouse && io.display_size[0] > 0.0 && io.display_size[1] > 0.0 {
+            let uv = Vec2::from(io.mouse_pos) / Vec2::from(io.display_size);
+            gizmos.uv = uv.clamp(Vec2::ZERO, Vec2::ONE);
+        }
+
+        // NOTE: mouse right turns right like the gamepad stick, mouse up looks up
+        let mouse_delta = std::mem::take(&mut self.retained_input.mouse_delta);
+        if self.cursor_grabbed {
+            let sensitivity = self.input_state.mouse_sensitivity;
+            self.scene
+                .camera
+                .look(-mouse_delta.x * sensitivity, -mouse_delta.y * sensitivity);
+        }
+
+        for key in self.retained_input.held_keys.iter() {
+            match key {
+                winit::keyboard::KeyCode::KeyW => {
+                    self.scene.camera.pos += f * dt * speed;
+                }
+                winit::keyboard::KeyCode::KeyA => {
+                    self.scene.camera.pos -= r * dt * speed;
+                }
+                winit::keyboard::KeyCode::KeyS => {
+                    self.scene.camera.pos -= f * dt * speed;
+                }
+                winit::keyboard::KeyCode::KeyD => {
+                    self.scene.camera.pos += r * dt * speed;
+                }
+                winit::keyboard::KeyCode::KeyQ => {
+                    self.scene.camera.pos -= u * dt * speed;
+                }
+                winit::keyboard::KeyCode::KeyE => {
+                    self.scene.camera.pos += u * dt * speed;
+                }
+
+                // angle
+                winit::keyboard::KeyCode::KeyI => {
+                    self.scene.camera.look(0.0, dt * angle_speed);
+                }
+                winit::keyboard::KeyCode::KeyJ => {
+                    self.scene.camera.look(dt * angle_speed, 0.0);
+                }
+                winit::keyboard::KeyCode::KeyK => {
+                    self.scene.camera.look(0.0, -dt * angle_speed);
+                }
+                winit::keyboard::KeyCode::KeyL => {
+                    self.scene.camera.look(-dt * angle_speed, 0.0);
+                }
+
+                winit::keyboard::KeyCode::Digit1 => {
+                    self.input_state.ao_level = 0;
+                }
+                winit::keyboard::KeyCode::Digit2 => {
+                    self.input_state.ao_level = 1;
+                }
+                winit::keyboard::KeyCode::Digit3 => {
+                    self.input_state.ao_level = 2;
+                }
+                winit::keyboard::KeyCode::Digit4 => {
+                    self.input_state.ao_level = 3;
+                }
+                winit::keyboard::KeyCode::Digit5 => {
+                    self.input_state.ao_level = 4;
+                }
+
+                winit::keyboard::KeyCode::Digit6 => {
+                    self.input_state.ao_level = 5;
+                }
+                winit::keyboard::KeyCode::Digit7 => {
+                    self.input_state.ao_level = 6;
+                }
+
+                // NOTE: relative steps, the same key feels the same near and far
+                winit::keyboard::KeyCode::BracketLeft => {
+                    self.input_state.dof.focus_distance /= 1.0 + dt;
+                }
+                winit::keyboard::KeyCode::BracketRight => {
+                    self.input_state.dof.focus_distance *= 1.0 + dt;
+                }
+                winit::keyboard::KeyCode::Minus => {
+                    self.input_state.dof.aperture /= 1.0 + dt;
+                }
+                winit::keyboard::KeyCode::Equal => {
+                    self.input_state.dof.aperture *= 1.0 + dt;
+                }
+                // NOTE: a quarter of the screen per second
+                winit::keyboard::KeyCode::Comma => {
+                    let split = &mut self.input_state.ao_split;
+                    split.x = (split.x - 0.25 * dt).max(0.0);
+                }
+                winit::keyboard::KeyCode::Period => {
+                    let split = &mut self.input_state.ao_split;
+                    split.x = (split.x + 0.25 * dt).min(1.0);
+                }
+
+                _ => {}
+            }
+
+            self.input_state.ao_level = self.input_state.ao_level.min(NUM_AO_TEXTURES - 1);
+        }
+
+        // NOTE: taken out so handlers below are free to borrow self mutably
+        let just_pressed_keys = std::mem::take(&mut self.retained_input.just_pressed_keys);
+        for key in just_pressed_keys.iter() {
+            match key {
+                winit::keyboard::KeyCode::KeyB => {
+                    self.input_state.use_blur = !self.input_state.use_blur;
+
+                    dbg!(self.input_state.use_blur);
+                }
+                winit::keyboard::KeyCode::KeyH => {
+                    let settings = &mut self.input_state.ao_settings;
+                    let next = (settings.method.index() + 1) % AoMethod::ALL.len();
+                    settings.method = AoMethod::ALL[next];
+                    dbg!(settings.method);
+                }
+                winit::keyboard::KeyCode::KeyV => {
+                    self.input_state.debug_depth = !self.input_state.debug_depth;
+                }
+                winit::keyboard::KeyCode::KeyN => {
+                    self.input_state.debug_normals = !self.input_state.debug_normals;
+                }
+                winit::keyboard::KeyCode::F1 => {
+                    self.wireframe_mode = !self.wireframe_mode;
+                }
+                winit::keyboard::KeyCode::F2 => {
+                    self.topology_debug = !self.topology_debug;
+                }
+                winit::keyboard::KeyCode::F3 => {
+                    self.dump_gbuffer = true;
+                }
+                winit::keyboard::KeyCode::F4 => {
+                    self.input_state.dof.enabled = !self.input_state.dof.enabled;
+                    dbg!(self.input_state.dof.enabled);
+                }
+                winit::keyboard::KeyCode::F5 => {
+                    self.input_state.ssr.enabled = !self.input_state.ssr.enabled;
+                    dbg!(self.input_state.ssr.enabled);
+                }
+                winit::keyboard::KeyCode::F6 => {
+                    let gizmos = &mut self.input_state.ao_gizmos;
+                    gizmos.enabled = !gizmos.enabled;
+                    dbg!(gizmos.enabled);
+                }
+                winit::keyboard::KeyCode::F7 => {
+                    let scissor = &mut self.input_state.ao_scissor;
+                    scissor.enabled = !scissor.enabled;
+                    dbg!(scissor.enabled);
+                }
+                winit::keyboard::KeyCode::F8 => {
+                    let split = &mut self.input_state.ao_split;
+                    split.enabled = !split.enabled;
+                    dbg!(split.enabled);
+                }
+                winit::keyboard::KeyCode::KeyF => {
+                    self.autofocus = true;
+                }
+                // NOTE: the light buffer is rewritten from scene.lights every frame
+                winit::keyboard::KeyCode::KeyG => {
+                    self.scene.add_light(Light::point_at(self.scene.camera.pos));
+                }
+                winit::keyboard::KeyCode::KeyT => {
+                    let [_, forward, _] = self.scene.camera.right_forward_up();
+                    self.scene
+                        .add_light(Light::spot_at(self.scene.camera.pos, forward));
+                }
+                winit::keyboard::KeyCode::Backspace => {
+                    self.scene.remove_nearest_light(self.scene.camera.pos);
+                }
+                winit::keyboard::KeyCode::KeyM => {
+                    self.input_state.use_mipmaps = !self.input_state.use_mipmaps;
+                    dbg!(self.input_state.use_mipmaps);
+                }
+                winit::keyboard::KeyCode::KeyZ => {
+                    self.scene.camera.save_state();
+                }
+                winit::keyboard::KeyCode::KeyY => {
+                    self.scene.camera.load_state();
+                }
+                winit::keyboard::KeyCode::KeyR => {
+                    self.scene.camera.reset();
+                    if let Some(aabb) = self.scene_aabb {
+                        self.scene.camera.frame_aabb(&aabb);
+                    }
+                }
+
+                winit::keyboard::KeyCode::KeyC => {
+                    self.camera_keyframes
+                        .push(CameraKeyframe::from_camera(&self.scene.camera, 0.0));
+                    dbg!(self.camera_keyframes.len());
+                }
+                winit::keyboard::KeyCode::KeyX => {
+                    self.camera_keyframes.clear();
+                    self.camera_path = None;
+                    dbg!("cleared camera keyframes");
+                }
+                winit::keyboard::KeyCode::KeyP => {
+                    if self.camera_path.is_some() {
+                        self.camera_path = None;
+                    } else if self.camera_keyframes.len() >= 2 {
+                        let keyframes = self
+                            .camera_keyframes
+                            .iter()
+                            .enumerate()
+                            .map(|(i, key)| CameraKeyframe {
+                                time: i as f32 * self.keyframe_duration.max(0.01),
+                                ..*key
+                            })
+                            .collect();
+                        self.camera_path = Some(CameraPath {
+                            keyframes,
+                            looping: true,
+                        });
+                        self.camera_path_start = std::time::Instant::now();
+                    } else {
+                        dbg!("need at least 2 keyframes for a flythrough");
+                    }
+                }
+
+                winit::keyboard::KeyCode::ArrowLeft if !self.meshes.is_empty() => {
+                    let mut i = self.file_to_draw;
+                    let n = self.num_files;
+                    i += n;
+                    i -= 1;
+                    i %= n;
+                    self.select_file(i);
+                }
+                winit::keyboard::KeyCode::ArrowRight if !self.meshes.is_empty() => {
+                    let mut i = self.file_to_draw;
+                    let n = self.num_files;
+                    i += 1;
+                    i %= n;
+                    self.select_file(i);
+                }
+
+                _ => {}
+            }
+        }
+    }
+
+    // NOTE: view space distance of whatever the previous frame drew under screen_pos, in
+    // physical pixels of the depth buffer with 0,0 top left, far where nothing was drawn.
+    // Asks for the texel to be copied this frame, so a position that wasn't sampled the
+    // frame before gives None
+    pub fn sample_depth(&mut self, screen_pos: Vec2) -> Option<f32> {
+        self.depth_readback.request(screen_pos);
+        let raw = self.depth_readback.raw_depth(screen_pos)?;
+        Some(self.scene.camera.linear_depth(raw))
+    }
+
+    // NOTE: nearest visible node under screen_pos, both in the same pixels as viewport. The
+    // ray goes to model space so the aabb and triangles of the mesh are tested as they are
+    pub fn pick_node(&mut self, screen_pos: Vec2, viewport: Vec2) {
+        let ray = screen_to_ray(screen_pos, viewport, &self.scene.camera);
+        let mut nearest: Option<(usize, f32)> = None;
+        for (i, node) in self.scene.nodes.iter().enumerate() {
+            if !node.visible {
+                continue;
+            }
+            let mesh = &self.meshes[node.mesh_index];
+            let local_ray = ray.transformed(&node.transform.to_mat4().inverse());
+            let best = nearest.map_or(f32::MAX, |(_, t)| t);
+            match ray_aabb_intersect(&local_ray, mesh.aabb.min, mesh.aabb.max) {
+                Some(t) if t < best => {}
+                _ => continue,
+            }
+            let hit = mesh.bvh.as_ref().and_then(|bvh| bvh.intersect(&local_ray));
+            if let Some((_, t)) = hit {
+                if t < best {
+                    nearest = Some((i, t));
+                }
+            }
+        }
+        self.selected_node = nearest.map(|(i, _)| i);
+        match nearest {
+            Some((i, t)) => {
+                let name = &self.meshes[self.scene.nodes[i].mesh_index].name;
+                dbg!(format!("picked {name} at {:?}", ray.at(t)));
+            }
+            None => {
+                dbg!("picked nothing");
+            }
+        }
+    }
+
+    // NOTE: locked keeps the cursor in place, not every platform has it so confined is
+    // the fallback. Without either the cursor stays free and mouse look stays off
+    pub fn set_cursor_grab(&mut self, window: &winit::window::Window, grab: bool) {
+        if grab == self.cursor_grabbed {
+            return;
+        }
+        if grab {
+            let result = window
+                .set_cursor_grab(winit::window::CursorGrabMode::Locked)
+                .or_else(|_| window.set_cursor_grab(winit::window::CursorGrabMode::Confined));
+            if let Err(err) = result {
+                dbg!(format!("could not grab the cursor: {err}"));
+                return;
+            }
+        } else if let Err(err) = window.set_cursor_grab(winit::window::CursorGrabMode::None) {
+            dbg!(format!("could not release the cursor: {err}"));
+        }
+        window.set_cursor_visible(!grab);
+        self.cursor_grabbed = grab;
+        self.retained_input.mouse_delta = Vec2::ZERO;
+    }
+
+    pub fn handle_gamepad_input(&mut self, dt: f32) {
+        let Some(gilrs) = self.gilrs.as_mut() else {
+            return;
+        };
+        // NOTE: gilrs only updates gamepad state when events are pulled
+        while gilrs.next_event().is_some() {}
+
+        let [r, f, u] = self.scene.camera.right_forward_up();
+
+        let speed = CAM_SPEED * self.move_speed;
+        let angle_speed = CAM_ANGLE_SPEED;
+
+        for (_id, gamepad) in gilrs.gamepads() {
+            let move_x = apply_deadzone(gamepad.value(gilrs::Axis::LeftStickX));
+            let move_y = apply_deadzone(gamepad.value(gilrs::Axis::LeftStickY));
+            let look_x = apply_deadzone(gamepad.value(gilrs::Axis::RightStickX));
+            let look_y = apply_deadzone(gamepad.value(gilrs::Axis::RightStickY));
+
+            let trigger = |button| {
+                gamepad
+                    .button_data(button)
+                    .map(|data| data.value())
+                    .unwrap_or(0.0)
+            };
+            let up = apply_deadzone(trigger(gilrs::Button::RightTrigger2));
+            let down = apply_deadzone(trigger(gilrs::Button::LeftTrigger2));
+
+            self.scene.camera.pos += f * dt * speed * move_y;
+            self.scene.camera.pos += r * dt * speed * move_x;
+            self.scene.camera.pos += u * dt * speed * (up - down);
+
+            // NOTE: stick right should turn right, i.e negative yaw
+            self.scene
+                .camera
+                .look(-dt * angle_speed * look_x, dt * angle_speed * look_y);
+        }
+    }
+
+    // NOTE: called after input handling so the flythrough overrides any manual camera input
+    // NOTE: wall clock time instead of summed delta times, so a path takes the same time
+    // no matter how many frames got rendered on the way
+    pub fn update_camera_path(&mut self) {
+        let Some(path) = self.camera_path.as_ref() else {
+            return;
+        };
+        let elapsed = self.camera_path_start.elapsed().as_secs_f32();
+        let (pos, yaw, pitch) = path.sample(elapsed);
+        self.scene.camera.pos = pos;
+        self.scene.camera.yaw = yaw;
+        self.scene.camera.pitch = pitch;
+        if path.is_finished(elapsed) {
+            self.camera_path = None;
+        }
+    }
+
+    pub fn select_file(&mut self, file: usize) {
+        self.file_to_draw = file;
+        for node in self.scene.nodes.iter_mut() {
+            node.visible = node.file == file;
+        }
+    }
+
+    pub fn recreate_pipelines_if_required(&mut self) {
+        // let geometry_shader_source = std::fs::read_to_string().unwrap();
+        let shader_modified_time = last_time_shader_modified();
+        if self.pipelines.last_modified_shader_time != shader_modified_time {
+            self.pipelines.last_modified_shader_time = shader_modified_time;
+            self.reload_pipelines();
+        }
+    }
+
+    pub fn reload_pipelines(&mut self) {
+        match Pipelines::rebuild(
+            &self.ctx,
+            self.surface.info().format,
+            self.pipelines.vertex_format,
+            self.pipelines.normal_encoding,
+            self.pipelines.reverse_z,
+        ) {
+            Ok(new_pipelines) => {
+                dbg!("recompiled all shaders");
+                self.pipelines = new_pipelines;
+                self.light_variants.clear();
+            }
+            Err(err) => {
+                // NOTE: keep using the old pipelines until the shader is fixed
+                dbg!(format!("{err}"));
+            }
+        }
+    }
+
+    pub fn build_debug_ui(&mut self, window: &winit::window::Window) {
+        let io = self.imgui.io_mut();
+        // NOTE: imgui asserts on a zero delta time
+        io.update_delta_time(std::time::Duration::from_secs_f32(
+            self.delta_time.max(0.0001),
+        ));
+        if let Err(err) = self.imgui_platform.prepare_frame(io, window) {
+            dbg!(err);
+        }
+
+        let mut reload_shaders = false;
+        let mut anisotropy = self.material_samplers.anisotropy;
+        let loading = self.mesh_loader.is_some();
+        let center_depth = self.sample_depth(self.depth_readback.center());
+        let num_loaded_meshes = self.meshes.len();
+        let ui = self.imgui.new_frame();
+        if self.cursor_grabbed {
+            let center = Vec2::from(ui.io().display_size) * 0.5;
+            let draw_list = ui.get_foreground_draw_list();
+            for offset in [Vec2::X, Vec2::Y] {
+                let [a, b] = [center - 8.0 * offset, center + 8.0 * offset];
+                draw_list
+                    .add_line(a.to_array(), b.to_array(), [1.0, 1.0, 1.0, 0.8])
+                    .thickness(1.5)
+                    .build();
+            }
+        }
+        // NOTE: under the ring of dots from loading.wgsl
+        if loading {
+            let [width, height] = ui.io().display_size;
+            ui.window("loading")
+                .position([0.5 * width, 0.62 * height], imgui::Condition::Always)
+                .position_pivot([0.5, 0.5])
+                .flags(
+                    imgui::WindowFlags::NO_DECORATION
+                        | imgui::WindowFlags::NO_BACKGROUND
+                        | imgui::WindowFlags::NO_INPUTS
+                        | imgui::WindowFlags::ALWAYS_AUTO_RESIZE,
+                )
+                .build(|| {
+                    ui.text(format!("Loading... {num_loaded_meshes} meshes so far"));
+                });
+        }
+        ui.window("debug")
+            .position([10.0, 10.0], imgui::Condition::FirstUseEver)
+            .size([340.0, 520.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                ui.text(format!(
+                    "frame {:.2} ms ({:.0} fps)",
+                    self.delta_time * 1000.0,
+                    1.0 / self.delta_time.max(0.0001)
+                ));
+                // NOTE: every render pass gets a timestamp pair under its label with --timing
+                let gpu_total = self
+                    .command_encoder
+                    .timings()
+                    .iter()
+                    .map(|(_, duration)| duration.as_secs_f64() * 1000.0)
+                    .sum::<f64>();
+                if gpu_total > 0.0 {
+                    ui.text(format!("gpu {gpu_total:.2} ms"));
+                }
+                ui.text(format!(
+                    "{} / {} meshes culled",
+                    self.culled_nodes,
+                    self.scene.nodes.iter().filter(|node| node.visible).count()
+                ));
+                ui.text(format!(
+                    "triangles drawn: {} lod0, {} lod1",
+                    self.lod_triangles[0], self.lod_triangles[1]
+                ));
+                match center_depth {
+                    Some(depth) => ui.text(format!("center depth {depth:.2}")),
+                    None => ui.text("center depth -"),
+                }
+                if let Some(node) = self.selected_node.and_then(|i| self.scene.nodes.get(i)) {
+                    ui.text(format!("selected {}", self.meshes[node.mesh_index].name));
+                    if ui.button("clear selection") {
+                        self.selected_node = None;
+                    }
+                }
+
+                if ui.collapsing_header("camera", imgui::TreeNodeFlags::DEFAULT_OPEN) {
+                    let mut pos = self.scene.camera.pos.to_array();
+                    if ui.input_float3("pos", &mut pos).build() {
+                        self.scene.camera.pos = Vec3A::from_array(pos);
+                    }
+                    ui.input_float("yaw", &mut self.scene.camera.yaw).build();
+                    ui.input_float("pitch", &mut self.scene.camera.pitch)
+                        .build();
+                    ui.slider(
+                        "mouse sensitivity",
+                        0.0002,
+                        0.01,
+                        &mut self.input_state.mouse_sensitivity,
+                    );
+                    let camera = &mut self.scene.camera;
+                    ui.input_float("near", &mut camera.near).build();
+                    ui.input_float("far", &mut camera.far).build();
+                    // NOTE: a degenerate projection would nan the whole frame
+                    camera.near = camera.near.max(1e-4);
+                    camera.far = camera.far.max(2.0 * camera.near);
+                }
+
+                if ui.collapsing_header("depth of field", imgui::TreeNodeFlags::empty()) {
+                    let dof = &mut self.input_state.dof;
+                    ui.checkbox("dof", &mut dof.enabled);
+                    let (near, far) = (self.scene.camera.near, self.scene.camera.far);
+                    ui.slider("focus distance", near, far, &mut dof.focus_distance);
+                    ui.slider("aperture", 0.0, 1.0, &mut dof.aperture);
+                    ui.slider("max coc px", 1.0, 32.0, &mut dof.max_coc_px);
+                    if ui.button("autofocus") {
+                        self.autofocus = true;
+                    }
+                }
+
+                if ui.collapsing_header("reflections", imgui::TreeNodeFlags::empty()) {
+                    let ssr = &mut self.input_state.ssr;
+                    ui.checkbox("ssr", &mut ssr.enabled);
+                    ui.slider("steps", 1, 128, &mut ssr.steps);
+                    ui.slider("max distance", 0.1, 50.0, &mut ssr.max_distance);
+                    ui.slider("thickness", 0.01, 2.0, &mut ssr.thickness);
+                }
+
+                if ui.collapsing_header("flythrough", imgui::TreeNodeFlags::DEFAULT_OPEN) {
+                    ui.text(format!("{} keyframes", self.camera_keyframes.len()));
+                    ui.slider("seconds per key", 0.1, 10.0, &mut self.keyframe_duration);
+                    if let Some(path) = self.camera_path.as_ref() {
+                        let time = self.camera_path_start.elapsed().as_secs_f32();
+                        ui.text(format!("playing {time:.2} / {:.2} s", path.duration()));
+                    }
+                }
+
+                if ui.collapsing_header("ao", imgui::TreeNodeFlags::DEFAULT_OPEN) {
+                    ui.checkbox("ao enabled", &mut self.input_state.use_ao);
+                    let settings = &mut self.input_state.ao_settings;
+                    ui.slider("radius", 0.1, 10.0, &mut settings.d_max);
+                    ui.slider("max kernel px", 2.0, 64.0, &mut settings.r_max);
+                    ui.slider("bias", 0.0, 0.5, &mut settings.bias);
+                    let mut mip_selection_i = settings.hi_z_mip_selection.index();
+                    let names = HiZMipSelection::ALL.map(|selection| selection.name());
+                    if ui.combo_simple_string("hi-z mips", &mut mip_selection_i, &names) {
+                        settings.hi_z_mip_selection = HiZMipSelection::ALL[mip_selection_i];
+                    }
+                    if settings.hi_z_mip_selection != HiZMipSelection::Off {
+                        ui.slider(
+                            "hi-z mip bias",
+                            0,
+                            MAX_HI_Z_MIPS,
+                            &mut settings.hi_z_mip_bias,
+                        );
+                    }
+                    let mut method_i = settings.method.index();
+                    let names = AoMethod::ALL.map(|method| method.name());
+                    if ui.combo_simple_string("method", &mut method_i, &names) {
+                        settings.method = AoMethod::ALL[method_i];
+                    }
+                    if settings.method == AoMethod::Hbao {
+                        ui.slider(
+                            "directions",
+                            1,
+                            MAX_AO_SAMPLES as u32,
+                            &mut settings.hbao_directions,
+                        );
+                        ui.slider("steps", 1, MAX_MARCH_STEPS, &mut settings.hbao_steps);
+                    } else if settings.method == AoMethod::Gtao {
+                        ui.slider(
+                            "slices",
+                            1,
+                            MAX_AO_SAMPLES as u32,
+                            &mut settings.gtao_slices,
+                        );
+                        ui.slider("steps", 1, MAX_MARCH_STEPS, &mut settings.gtao_steps);
+                        ui.slider("thickness", 0.05, 5.0, &mut settings.gtao_thickness);
+                    } else {
+                        ui.slider(
+                            "samples",
+                            1,
+                            MAX_AO_SAMPLES as u32,
+                            &mut settings.num_samples,
+                        );
+                        let mut distribution_i = match settings.kernel_distribution {
+                            KernelDistribution::UniformHemisphere => 0,
+                            KernelDistribution::CosineWeighted => 1,
+                            KernelDistribution::Poisson { .. } => 2,
+                        };
+                        let names = ["uniform hemisphere", "cosine weighted", "poisson rings"];
+                        if ui.combo_simple_string("kernel", &mut distribution_i, &names) {
+                            settings.kernel_distribution = match distribution_i {
+                                0 => KernelDistribution::UniformHemisphere,
+                                1 => KernelDistribution::CosineWeighted,
+                                _ => KernelDistribution::Poisson { num_rings: 3 },
+                            };
+                        }
+                        if let KernelDistribution::Poisson { num_rings } =
+                            &mut settings.kernel_distribution
+                        {
+                            ui.slider("rings", 1, 4, num_rings);
+                        }
+                        ui.checkbox("kernel jitter", &mut settings.kernel_jitter);
+                    }
+                    ui.checkbox("bent normals", &mut settings.use_bent_normals);
+                    ui.slider("ambient sky", 0.0, 1.0, &mut settings.ambient_sky);
+                    ui.slider("ambient ground", 0.0, 1.0, &mut settings.ambient_ground);
+                    ui.slider("ambient pulse", 0.0, 1.0, &mut settings.ambient_pulse);
+                    if self.skybox.is_some() {
+                        ui.checkbox("ibl ambient", &mut settings.use_ibl);
+                        ui.slider("ibl intensity", 0.0, 2.0, &mut settings.ibl_intensity);
+                    }
+                    let gizmos = &mut self.input_state.ao_gizmos;
+                    ui.checkbox("kernel gizmos", &mut gizmos.enabled);
+                    if gizmos.enabled {
+                        ui.checkbox("gizmos at mouse", &mut gizmos.at_mouse);
+                        ui.slider("gizmo size", 0.005, 0.5, &mut gizmos.point_size);
+                    }
+                    let scissor = &mut self.input_state.ao_scissor;
+                    ui.checkbox("ao scissor", &mut scissor.enabled);
+                    if scissor.enabled {
+                        ui.slider("scissor min x", 0.0, 1.0, &mut scissor.min.x);
+                        ui.slider("scissor max x", 0.0, 1.0, &mut scissor.max.x);
+                        ui.slider("scissor min y", 0.0, 1.0, &mut scissor.min.y);
+                        ui.slider("scissor max y", 0.0, 1.0, &mut scissor.max.y);
+                    }
+                    let split = &mut self.input_state.ao_split;
+                    ui.checkbox("ao split", &mut split.enabled);
+                    if split.enabled {
+                        ui.slider("split x", 0.0, 1.0, &mut split.x);
+                    }
+                    ui.checkbox("blur", &mut self.input_state.use_blur);
+                    let mut ao_level = self.input_state.ao_level as u32;
+                    if ui.slider("level", 0, NUM_AO_TEXTURES as u32 - 1, &mut ao_level) {
+                        self.input_state.ao_level = ao_level as usize;
+                    }
+                }
+
+                if ui.collapsing_header("shadows", imgui::TreeNodeFlags::DEFAULT_OPEN) {
+                    let settings = &mut self.input_state.shadow_settings;
+                    ui.checkbox("enabled", &mut settings.enabled);
+                    ui.slider("shadow bias", 0.0, 0.02, &mut settings.bias);
+                    let mut cascade_count = settings.cascade_count as u32;
+                    if ui.slider(
+                        "cascades",
+                        1,
+                        MAX_SHADOW_CASCADES as u32,
+                        &mut cascade_count,
+                    ) {
+                        settings.cascade_count = cascade_count as usize;
+                    }
+                    ui.slider("split lambda", 0.0, 1.0, &mut settings.split_lambda);
+                    ui.slider(
+                        "shadow distance",
+                        1.0,
+                        self.scene.camera.far,
+                        &mut settings.max_distance,
+                    );
+                    ui.slider("cascade blend", 0.0, 0.5, &mut settings.blend_width);
+                    ui.checkbox("pcss", &mut settings.pcss_enabled);
+                    ui.slider("light size", 0.0, 1.0, &mut settings.light_size);
+                    ui.slider(
+                        "blocker samples",
+                        1,
+                        16,
+                        &mut settings.pcss_num_blocker_samples,
+                    );
+                }
+
+                if ui.collapsing_header("lights", imgui::TreeNodeFlags::empty()) {
+                    ui.text(format!("{} / {MAX_LIGHTS} lights", self.scene.lights.len()));
+                    let [_, forward, _] = self.scene.camera.right_forward_up();
+                    if ui.button("add point light at camera") {
+                        self.scene.add_light(Light::point_at(self.scene.camera.pos));
+                    }
+                    if ui.button("add spot light at camera") {
+                        self.scene
+                            .add_light(Light::spot_at(self.scene.camera.pos, forward));
+                    }
+                    if ui.button("remove nearest light") {
+                        self.scene.remove_nearest_light(self.scene.camera.pos);
+                    }
+                    if ui.button("remove added lights") {
+                        self.scene.lights.truncate(1);
+                    }
+                }
+
+                if ui.collapsing_header("particles", imgui::TreeNodeFlags::empty()) {
+                    let particles = &mut self.particles;
+                    ui.checkbox("emit particles", &mut particles.enabled);
+                    ui.slider("spawn rate", 0.0, 2000.0, &mut particles.spawn_rate);
+                    if ui.button("move emitter to camera") {
+                        particles.emitter = self.scene.camera.pos;
+                    }
+                    ui.text(format!(
+                        "{} / {} particles",
+                        particles.particles.len(),
+                        particles.max_particles
+                    ));
+                }
+
+                if ui.collapsing_header("debug views", imgui::TreeNodeFlags::DEFAULT_OPEN) {
+                    ui.checkbox("show depth", &mut self.input_state.debug_depth);
+                    ui.checkbox("show normals", &mut self.input_state.debug_normals);
+                    ui.checkbox("mipmaps", &mut self.input_state.use_mipmaps);
+                    ui.checkbox("lod", &mut self.input_state.use_lod);
+                    ui.checkbox("frustum culling", &mut self.input_state.use_frustum_culling);
+                    ui.checkbox("depth prepass", &mut self.input_state.use_depth_prepass);
+                    ui.checkbox("taa", &mut self.input_state.use_taa);
+                    ui.slider(
+                        "taa history",
+                        0.0,
+                        0.98,
+                        &mut self.input_state.taa_history_weight,
+                    );
+                    ui.checkbox("tssao", &mut self.input_state.use_tssao);
+                    ui.slider("tssao alpha", 0.02, 1.0, &mut self.input_state.tssao_alpha);
+                    ui.slider(
+                        "lod screen size",
+                        0.0,
+                        1.0,
+                        &mut self.input_state.lod_screen_size,
+                    );
+                    // NOTE: powers of two only
+                    let mut anisotropy_log2 = anisotropy.ilog2();
+                    let max_log2 = MAX_ANISOTROPY.ilog2();
+                    if ui.slider("anisotropy log2", 0, max_log2, &mut anisotropy_log2) {
+                        anisotropy = 1 << anisotropy_log2;
+                    }
+                    ui.checkbox("wireframe", &mut self.wireframe_mode);
+                    ui.checkbox("backface wireframe", &mut self.topology_debug);
+                    ui.checkbox("draw bounds", &mut self.input_state.debug_draw_bounds);
+                    ui.checkbox("draw lights", &mut self.input_state.debug_draw_lights);
+                    ui.checkbox("compute test", &mut self.input_state.compute_test);
+                    let mut frozen = self.frozen_camera.is_some();
+                    if ui.checkbox("freeze culling frustum", &mut frozen) {
+                        self.frozen_camera = frozen.then(|| self.scene.camera.clone());
+                    }
+                }
+
+                if ui.collapsing_header("meshes", imgui::TreeNodeFlags::DEFAULT_OPEN) {
+                    for (i, node) in self.scene.nodes.iter_mut().enumerate() {
+                        let mesh = &self.meshes[node.mesh_index];
+                        let label = format!("{i} {} ({} vertices)", mesh.name, mesh.num_vertices);
+                        ui.checkbox(label, &mut node.visible);
+                    }
+                }
+
+                if ui.collapsing_header("gpu timings", imgui::TreeNodeFlags::DEFAULT_OPEN) {
+                    let mut any_timings = false;
+                    // NOTE: compare this with the geometry pass alone with the prepass off
+                    // to see what skipping the overdraw saves
+                    let mut geometry_total = 0.0;
+                    for (name, duration) in self.command_encoder.timings() {
+                        any_timings = true;
+                        let ms = duration.as_secs_f64() * 1000.0;
+                        if name == "geometry" || name == "depth prepass" {
+                            geometry_total += ms;
+                        }
+                        ui.text(format!("{name}: {ms:.3} ms"));
+                    }
+                    if any_timings {
+                        ui.text(format!("geometry incl. prepass: {geometry_total:.3} ms"));
+                    }
+                    if !any_timings {
+                        ui.text("no timings, pass --timing to enable");
+                    }
+                }
+
+                if ui.button("reload shaders") {
+                    reload_shaders = true;
+                }
+            });
+        self.imgui_platform.prepare_render(ui, window);
+
+        if reload_shaders {
+            self.reload_pipelines();
+        }
+        if anisotropy != self.material_samplers.anisotropy {
+            self.set_anisotropy(anisotropy);
+        }
+    }
+
+    // NOTE: samplers are only used by the frame before, which is already waited for
+    pub fn set_anisotropy(&mut self, anisotropy: u32) {
+        self.material_samplers.destroy(&self.ctx);
+        self.material_samplers = MaterialSamplers::new(&self.ctx, anisotropy);
+        dbg!(self.material_samplers.anisotropy);
+    }
+}
+
+pub fn apply_deadzone(value: f32) -> f32 {
+    if value.abs() < GAMEPAD_DEADZONE {
+        return 0.0;
+    }
+    // NOTE: rescale so output still starts at 0 right outside the deadzone
+    value.signum() * (value.abs() - GAMEPAD_DEADZONE) / (1.0 - GAMEPAD_DEADZONE)
+}
+
+impl Camera {
+    pub fn transform(&self) -> Transform {
+        Transform::from_translation_rotation(self.pos, self.rot_quat())
+    }
+
+    pub fn view(&self) -> glam::Mat4 {
+        let view = self.transform().to_mat4().inverse();
+        view
+    }
+
+    pub fn view_rot_only(&self) -> glam::Mat4 {
+        let rot = self.rot_quat();
+        let mat = glam::Mat4::from_rotation_translation(rot, Vec3::ZERO).inverse();
+        mat
+    }
+
+    // NOTE: shared by the mouse, keyboard and gamepad. Pitch stops short of straight up
+    // or down where yaw would flip
+    pub fn look(&mut self, yaw: f32, pitch: f32) {
+        let max_pitch = 89.0_f32.to_radians();
+        self.yaw += yaw;
+        self.pitch = (self.pitch + pitch).clamp(-max_pitch, max_pitch);
+    }
+
+    pub fn rot_quat(&self) -> glam::Quat {
+        let rot_x = Quat::from_axis_angle(Vec3::X, self.pitch);
+        let rot_y = Quat::from_axis_angle(Vec3::Y, self.yaw);
+        let rot = rot_y * rot_x;
+        rot
+    }
+
+    pub fn projection(&self) -> glam::Mat4 {
+        // NOTE: translating after the projection shifts the whole image by jitter in ndc
+        let jitter = glam::Mat4::from_translation(Vec3::new(self.jitter.x, self.jitter.y, 0.0));
+        jitter * self.unjittered_projection()
+    }
+
+    pub fn unjittered_projection(&self) -> glam::Mat4 {
+        // NOTE: swapping near and far gives exactly 1 - depth of the regular projection
+        if self.reverse_z {
+            glam::Mat4::perspective_rh(self.vfov_rad, self.aspect, self.far, self.near)
+        } else {
+            glam::Mat4::perspective_rh(self.vfov_rad, self.aspect, self.near, self.far)
+        }
+    }
+
+    pub fn default_from_aspect(aspect: f32) -> Self {
+        Self {
+            pos: Vec3A::ZERO,
+            yaw: 0.0,
+            pitch: 0.0,
+            // vfov_rad: TAU / 4.0,
+            vfov_rad: 70.0_f32.to_radians(),
+            aspect,
+            reverse_z: false,
+            jitter: Vec2::ZERO,
+            prev_vp: Mat4::IDENTITY,
+            near: CAM_NEAR,
+            far: CAM_FAR,
+            viewport_size: Vec2::ONE,
+            time_secs: 0.0,
+            delta_time_secs: 0.0,
+        }
+    }
+
+    pub fn vp(&self) -> glam::Mat4 {
+        let v = self.view();
+        let p = self.projection();
+        // dbg!(v);
+        p * v
+    }
+
+    pub fn unjittered_vp(&self) -> glam::Mat4 {
+        self.unjittered_projection() * self.view()
+    }
+
+    // NOTE: view space distance for a depth buffer value, same as depth_debug.wgsl. Reverse
+    // z stores 1 - depth, the background comes out as far
+    pub fn linear_depth(&self, raw: f32) -> f32 {
+        let d = if self.reverse_z { 1.0 - raw } else { raw };
+        self.near * self.far / (self.far + d * (self.near - self.far))
+    }
+
+    // NOTE: world space left, right, bottom, top, near and far planes pointing inwards,
+    // from the rows of the unjittered vp. Clip z is in 0..w for both depth directions, with
+    // reverse z the near and far planes just swap places
+    pub fn frustum_planes(&self) -> [Plane; 6] {
+        let vp = self.unjittered_vp();
+        let [r0, r1, r2, r3] = [0, 1, 2, 3].map(|i| vp.row(i));
+        [r3 + r0, r3 - r0, r3 + r1, r3 - r1, r2, r3 - r2].map(Plane::from_coefficients)
+    }
+
+    pub fn right_forward_up(&self) -> [Vec3A; 3] {
+        let v = self.view();
+        let rot = v.to_scale_rotation_translation().1.inverse();
+
+        let r = rot * Vec3A::X;
+        let f = rot * -Vec3A::Z;
+        let u = rot * Vec3A::Y;
+
+        [r, f, u]
+    }
+    pub fn save_state(&self) {
+        let path = std::path::Path::new("src/assets/cam/cam.txt");
+        let Ok(file) = std::fs::File::create(path) else {
+            dbg!("coulf not write cam state");
+            return;
+        };
+        let mut w = std::io::BufWriter::new(file);
+
+        let _ = w.write_fmt(format_args!(" {}", self.pos.x));
+        let _ = w.write_fmt(format_args!(" {}", self.pos.y));
+        let _ = w.write_fmt(format_args!(" {}", self.pos.z));
+
+        let _ = w.write_fmt(format_args!(" {}", self.yaw));
+        let _ = w.write_fmt(format_args!(" {}", self.pitch));
+        let _ = w.write_fmt(format_args!(" {}", self.vfov_rad));
+        let _ = w.write_fmt(format_args!(" {}", self.aspect));
+        dbg!("saved cam state to file");
+        // self.
+    }
+    pub fn load_state(&mut self) {
+        let path = std::path::Path::new("src/assets/cam/cam.txt");
+        let Ok(file) = std::fs::File::open(path) else {
+            dbg!("coulf not read cam state");
+            return;
+        };
+        let reader = std::io::BufReader::new(file);
+        let Some(Ok(lines)) = reader.lines().next() else {
+            dbg!("coulf not load cam state");
+            return;
+        };
+        let mut args = lines.split_whitespace();
+
+        self.pos.x = args.next().unwrap().parse().unwrap();
+        self.pos.y = args.next().unwrap().parse().unwrap();
+        self.pos.z = args.next().unwrap().parse().unwrap();
+
+        self.yaw = args.next().unwrap().parse().unwrap();
+        self.pitch = args.next().unwrap().parse().unwrap();
+        self.vfov_rad = args.next().unwrap().parse().unwrap();
+        self.aspect = args.next().unwrap().parse().unwrap();
+    }
+
+    // NOTE: only the pose, the projection settings stay
+    pub fn reset(&mut self) {
+        *self = Self {
+            reverse_z: self.reverse_z,
+            near: self.near,
+            far: self.far,
+            viewport_size: self.viewport_size,
+            time_secs: self.time_secs,
+            delta_time_secs: self.delta_time_secs,
+            ..Self::default_from_aspect(self.aspect)
+        };
+    }
+
+    // NOTE: looks down -z at the aabb from far enough back for its bounding sphere to fit
+    // the vertical fov
+    pub fn frame_aabb(&mut self, aabb: &Aabb) {
+        let radius = 0.5 * aabb.size().length();
+        let distance = radius / (0.5 * self.vfov_rad).tan();
+        self.pos = aabb.center() + Vec3A::new(0.0, 0.0, distance.max(self.near));
+        self.yaw = 0.0;
+        self.pitch = 0.0;
+    }
+}
+pub const SPONZA_PATH: &str = "src/assets/sponza/sponza.obj";
+pub const SPONZA_DOWNLOAD_HINT: &str = "Crytek sponza is in the mcguire computer graphics \
+     archive at https://casual-effects.com/data/, unpack it to src/assets/sponza";
+
+pub fn load_sponza(options: MeshLoadOptions) -> Result<Vec<CpuMesh>, ObjError> {
+    dbg!("loading sponza");
+    let path = std::path::Path::new(SPONZA_PATH);
+    let meshes = load_meshes(path, options)?;
+    // let vertices = turn_mesh_into_pure_vertex_list(mesh);
+
+    Ok(meshes)
+    // vertices
+}
+
+pub fn load_cathedral(options: MeshLoadOptions) -> Result<Vec<CpuMesh>, ObjError> {
+    dbg!("loading sibenik cathedral");
+    let path = std::path::Path::new("src/assets/sibenik_cathedral/sibenik.obj");
+    let meshes = load_meshes(path, options)?;
+    // let vertices = turn_mesh_into_pure_vertex_list(mesh);
+
+    // vertices
+    Ok(meshes)
+}
+
+// NOTE: unit cube around the origin, stands in for meshes that failed to load
+pub fn cube_mesh() -> CpuMesh {
+    // NOTE: corner i has x, y and z from bits 0, 1 and 2
+    let vertices = (0..8)
+        .map(|i: u32| {
+            Vec3A::new(
+                (i & 1) as f32 - 0.5,
+                ((i >> 1) & 1) as f32 - 0.5,
+                ((i >> 2) & 1) as f32 - 0.5,
+            )
+        })
+        .collect::<Vec<_>>();
+    // NOTE: ccw seen from outside, two triangles per face
+    let indices = vec![
+        0, 4, 6, 0, 6, 2, // -x
+        1, 3, 7, 1, 7, 5, // +x
+        0, 1, 5, 0, 5, 4, // -y
+        2, 6, 7, 2, 7, 3, // +y
+        0, 2, 3, 0, 3, 1, // -z
+        4, 5, 7, 4, 7, 6, // +z
+    ];
+    CpuMesh {
+        name: "cube".to_string(),
+        uv_indices: vec![None; indices.len()],
+        vertices,
+        indices,
+        ..Default::default()
+    }
+}
+
+// NOTE: stands in for sponza when it is missing, or on its own with "--scene procedural",
+// so the renderer can be tried without downloading anything. Built to show off the ao:
+// boxes sitting close together, a narrow trench, an inside corner, tightly spaced
+// pillars and a fence of thin slats for the thickness falloff
+pub fn procedural_scene() -> Vec<CpuMesh> {
+    let ground = plane_mesh(Vec2::new(24.0, 24.0), 8);
+
+    // NOTE: 0.4 gaps, the ground and the neighbours darken every side of every box
+    let mut boxes = CpuMesh::default();
+    for i in 0..4 {
+        for j in 0..4 {
+            let center = Vec3A::new(-9.0 + 1.4 * i as f32, 0.5, -9.0 + 1.4 * j as f32);
+            append_mesh(&mut boxes, &box_mesh(center, Vec3A::ONE));
+        }
+    }
+
+    // NOTE: two long walls with a 0.6 wide gap between them
+    let mut trench = CpuMesh::default();
+    for z in [3.0, 4.6] {
+        let center = Vec3A::new(-5.0, 0.75, z);
+        append_mesh(&mut trench, &box_mesh(center, Vec3A::new(8.0, 1.5, 1.0)));
+    }
+
+    let mut pillars = CpuMesh::default();
+    for i in 0..6 {
+        for j in 0..2 {
+            let center = Vec3A::new(2.0 + 0.6 * i as f32, 2.0, -9.0 + 0.6 * j as f32);
+            append_mesh(&mut pillars, &box_mesh(center, Vec3A::new(0.3, 4.0, 0.3)));
+        }
+    }
+
+    // NOTE: resting on the ground, contact shadows under each and one right against a wall
+    let mut spheres = CpuMesh::default();
+    for (center, radius) in [
+        (Vec3A::new(4.0, 1.0, 0.0), 1.0),
+        (Vec3A::new(6.5, 0.5, 1.0), 0.5),
+        (Vec3A::new(7.8, 0.75, 6.95), 0.75),
+    ] {
+        append_mesh(&mut spheres, &uv_sphere_mesh(center, radius, 32, 16));
+    }
+
+    // NOTE: inside corner of two walls meeting at a right angle
+    let mut walls = CpuMesh::default();
+    append_mesh(
+        &mut walls,
+        &box_mesh(Vec3A::new(6.0, 1.5, 7.9), Vec3A::new(6.0, 3.0, 0.2)),
+    );
+    append_mesh(
+        &mut walls,
+        &box_mesh(Vec3A::new(8.9, 1.5, 5.0), Vec3A::new(0.2, 3.0, 6.0)),
+    );
+
+    // NOTE: slats much thinner than the ao radius, their back side must not darken the
+    // ground in front of them
+    let mut fence = CpuMesh::default();
+    for i in 0..24 {
+        let center = Vec3A::new(-8.0 + 0.25 * i as f32, 0.75, 8.0);
+        append_mesh(&mut fence, &box_mesh(center, Vec3A::new(0.05, 1.5, 0.6)));
+    }
+
+    let mut meshes = vec![ground, boxes, trench, pillars, spheres, walls, fence];
+    let names = [
+        "ground", "boxes", "trench", "pillars", "spheres", "walls", "fence",
+    ];
+    for (mesh, name) in meshes.iter_mut().zip(names) {
+        mesh.name = format!("procedural/{name}");
+    }
+    meshes
+}
+
+pub fn load_procedural(options: MeshLoadOptions) -> Result<Vec<CpuMesh>, ObjError> {
+    dbg!("generating procedural scene");
+    let mut meshes = procedural_scene();
+    for mesh in meshes.iter_mut() {
+        apply_normal_options(mesh, options.normals);
+    }
+    options.transform.apply(&mut meshes);
+    Ok(meshes)
+}
+
+// NOTE: adds the geometry of other to mesh, materials and groups of other are dropped
+pub fn append_mesh(mesh: &mut CpuMesh, other: &CpuMesh) {
+    let vertex_offset = mesh.vertices.len();
+    let uv_offset = mesh.uvs.len();
+    let normal_offset = mesh.normals.len();
+    mesh.vertices.extend_from_slice(&other.vertices);
+    mesh.uvs.extend_from_slice(&other.uvs);
+    mesh.normals.extend_from_slice(&other.normals);
+    mesh.colors.extend_from_slice(&other.colors);
+    mesh.indices
+        .extend(other.indices.iter().map(|idx| idx + vertex_offset));
+    mesh.uv_indices.extend(
+        other
+            .uv_indices
+            .iter()
+            .map(|idx| idx.map(|idx| idx + uv_offset)),
+    );
+    mesh.normal_indices.extend(
+        other
+            .normal_indices
+            .iter()
+            .map(|idx| idx.map(|idx| idx + normal_offset)),
+    );
+}
+
+// NOTE: y up plane centered on the origin, subdivisions quads along each side so the
+// ao and lighting have vertices to work with. (n + 1)^2 vertices and 6 n^2 indices
+pub fn plane_mesh(size: Vec2, subdivisions: u32) -> CpuMesh {
+    let n = subdivisions.max(1) as usize;
+    let mut vertices = vec![];
+    let mut uvs = vec![];
+    for j in 0..=n {
+        for i in 0..=n {
+            let uv = Vec2::new(i as f32, j as f32) / n as f32;
+            vertices.push(Vec3A::new(
+                (uv.x - 0.5) * size.x,
+                0.0,
+                (uv.y - 0.5) * size.y,
+            ));
+            uvs.push(uv);
+        }
+    }
+    let mut indices = vec![];
+    for j in 0..n {
+        for i in 0..n {
+            let v00 = j * (n + 1) + i;
+            let v10 = v00 + 1;
+            let v01 = v00 + n + 1;
+            let v11 = v01 + 1;
+            // NOTE: ccw seen from above
+            indices.extend_from_slice(&[v00, v01, v10, v10, v01, v11]);
+        }
+    }
+    CpuMesh {
+        name: "plane".to_string(),
+        uv_indices: indices.iter().map(|idx| Some(*idx)).collect(),
+        normals: vec![Vec3A::Y],
+        normal_indices: vec![Some(0); indices.len()],
+        vertices,
+        uvs,
+        indices,
+        ..Default::default()
+    }
+}
+
+// NOTE: separate vertices per face so every face keeps its own normal, 24 vertices and
+// 36 indices
+pub fn box_mesh(center: Vec3A, size: Vec3A) -> CpuMesh {
+    let half = 0.5 * size;
+    // NOTE: normal and the two axes spanning the face, u cross v is the normal
+    let faces = [
+        (Vec3A::X, Vec3A::Y, Vec3A::Z),
+        (-Vec3A::X, Vec3A::Z, Vec3A::Y),
+        (Vec3A::Y, Vec3A::Z, Vec3A::X),
+        (-Vec3A::Y, Vec3A::X, Vec3A::Z),
+        (Vec3A::Z, Vec3A::X, Vec3A::Y),
+        (-Vec3A::Z, Vec3A::Y, Vec3A::X),
+    ];
+    let corners = [
+        Vec2::new(-1.0, -1.0),
+        Vec2::new(1.0, -1.0),
+        Vec2::new(1.0, 1.0),
+        Vec2::new(-1.0, 1.0),
+    ];
+    let mut vertices = vec![];
+    let mut uvs = vec![];
+    let mut normals = vec![];
+    let mut indices = vec![];
+    let mut normal_indices = vec![];
+    for (face_i, (normal, u, v)) in faces.into_iter().enumerate() {
+        let first = vertices.len();
+        for corner in corners {
+            vertices.push(center + half * (normal + corner.x * u + corner.y * v));
+            uvs.push(0.5 * (corner + 1.0));
+        }
+        normals.push(normal);
+        // NOTE: ccw around the normal
+        indices.extend([0, 1, 2, 0, 2, 3].map(|i| first + i));
+        normal_indices.extend([Some(face_i); 6]);
+    }
+    CpuMesh {
+        name: "box".to_string(),
+        uv_indices: indices.iter().map(|idx| Some(*idx)).collect(),
+        vertices,
+        uvs,
+        normals,
+        normal_indices,
+        indices,
+        ..Default::default()
+    }
+}
+
+// NOTE: rings from the top pole to the bottom one, the seam and pole vertices are
+// duplicated for the uvs. (rings + 1) * (segments + 1) vertices and
+// 6 * segments * (rings - 1) indices, the pole rows are single triangles
+pub fn uv_sphere_mesh(center: Vec3A, radius: f32, segments: u32, rings: u32) -> CpuMesh {
+    let segments = segments.max(3) as usize;
+    let rings = rings.max(2) as usize;
+    let mut vertices = vec![];
+    let mut uvs = vec![];
+    let mut normals = vec![];
+    for r in 0..=rings {
+        let theta = PI * r as f32 / rings as f32;
+        for s in 0..=segments {
+            let phi = TAU * s as f32 / segments as f32;
+            let normal = Vec3A::new(
+                theta.sin() * phi.cos(),
+                theta.cos(),
+                theta.sin() * phi.sin(),
+            );
+            vertices.push(center + radius * normal);
+            normals.push(normal);
+            uvs.push(Vec2::new(
+                s as f32 / segments as f32,
+                r as f32 / rings as f32,
+            ));
+        }
+    }
+    let mut indices = vec![];
+    for r in 0..rings {
+        for s in 0..segments {
+            let v00 = r * (segments + 1) + s;
+            let v01 = v00 + 1;
+            let v10 = v00 + segments + 1;
+            let v11 = v10 + 1;
+            // NOTE: ccw seen from outside, skipping the triangle that collapses at a pole
+            if r != 0 {
+                indices.extend_from_slice(&[v00, v01, v10]);
+            }
+            if r != rings - 1 {
+                indices.extend_from_slice(&[v01, v11, v10]);
+            }
+        }
+    }
+    CpuMesh {
+        name: "sphere".to_string(),
+        uv_indices: indices.iter().map(|idx| Some(*idx)).collect(),
+        normal_indices: indices.iter().map(|idx| Some(*idx)).collect(),
+        vertices,
+        uvs,
+        normals,
+        indices,
+        ..Default::default()
+    }
+}
+
+// NOTE: what meshes without any vn get for normals, flat uses the screen space derivative
+// face normal in the geometry shader. Smooth splits a vertex between faces that are more
+// than threshold_deg apart, "--smooth-normals DEG" on the command line
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NormalMode {
+    Flat,
+    Smooth { threshold_deg: f32 },
+}
+
+impl NormalMode {
+    pub fn parse(cmd: &mut CommandLine) -> Self {
+        match cmd
+            .value("--smooth-normals")
+            .map(|value| value.parse::<f32>())
+        {
+            Some(Ok(threshold_deg)) => Self::Smooth { threshold_deg },
+            Some(Err(err)) => {
+                dbg!(format!("invalid --smooth-normals: {err}"));
+                Self::Flat
+            }
+            None => Self::Flat,
+        }
+    }
+}
+
+// NOTE: what happens to the vn of the file, for files with inverted or broken normals.
+// "--flip-normals" negates them, "--recompute-normals" drops them so the mesh gets the
+// generated ones from NormalMode like a file without vn
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileNormals {
+    Keep,
+    Flip,
+    Recompute,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NormalOptions {
+    pub file: FileNormals,
+    pub generated: NormalMode,
+}
+
+impl NormalOptions {
+    pub fn parse(cmd: &mut CommandLine) -> Self {
+        // NOTE: both looked up so neither is reported as unknown, recompute wins
+        let file = match (cmd.flag("--recompute-normals"), cmd.flag("--flip-normals")) {
+            (true, _) => FileNormals::Recompute,
+            (_, true) => FileNormals::Flip,
+            _ => FileNormals::Keep,
+        };
+        Self {
+            file,
+            generated: NormalMode::parse(cmd),
+        }
+    }
+}
+
+// NOTE: each corner averages the area weighted normals of the faces around its position that
+// are within angle_threshold_deg of its own face. Corners that end up with the same set of
+// faces share a normal. Returns the normals and a normal index per entry in indices. A
+// threshold of 180 counts every face, so each position gets one normal (gouraud smooth)
+pub fn compute_smooth_normals(
+    vertices: &[Vec3A],
+    indices: &[usize],
+    angle_threshold_deg: f32,
+) -> (Vec<Vec3A>, Vec<usize>) {
+    // NOTE: not normalized, the length of the cross product is twice the face area
+    let face_normals = indices
+        .chunks_exact(3)
+        .map(|idxs| {
+            let v0 = vertices[idxs[0]];
+            let v1 = vertices[idxs[1]];
+            let v2 = vertices[idxs[2]];
+            (v1 - v0).cross(v2 - v0)
+        })
+        .collect::<Vec<_>>();
+    let num_corners = 3 * face_normals.len();
+
+    let mut corners_at = vec![vec![]; vertices.len()];
+    for (corner, pos_i) in indices.iter().enumerate().take(num_corners) {
+        corners_at[*pos_i].push(corner);
+    }
+
+    let cos_threshold = angle_threshold_deg.to_radians().cos();
+    let mut normals = vec![];
+    let mut normal_indices = vec![0; num_corners];
+    let mut unique = std::collections::HashMap::new();
+    for corners in &corners_at {
+        unique.clear();
+        for &corner in corners {
+            let own = face_normals[corner / 3].normalize_or_zero();
+            let faces = corners
+                .iter()
+                .map(|c| c / 3)
+                .filter(|face| face_normals[*face].normalize_or_zero().dot(own) >= cos_threshold)
+                .collect::<Vec<_>>();
+            let index = *unique.entry(faces).or_insert_with_key(|faces| {
+                let sum = faces.iter().map(|face| face_normals[*face]).sum::<Vec3A>();
+                normals.push(sum.normalize_or_zero());
+                normals.len() - 1
+            });
+            normal_indices[corner] = index;
+        }
+    }
+    (normals, normal_indices)
+}
+
+pub fn apply_normal_options(mesh: &mut CpuMesh, options: NormalOptions) {
+    match options.file {
+        FileNormals::Keep => {}
+        FileNormals::Flip => {
+            for normal in mesh.normals.iter_mut() {
+                *normal = -*normal;
+            }
+        }
+        FileNormals::Recompute => {
+            mesh.normals.clear();
+            mesh.normal_indices = vec![None; mesh.indices.len()];
+        }
+    }
+    apply_normal_mode(mesh, options.generated);
+}
+
+// NOTE: meshes that came with normals keep them whatever the mode
+pub fn apply_normal_mode(mesh: &mut CpuMesh, normal_mode: NormalMode) {
+    let NormalMode::Smooth { threshold_deg } = normal_mode else {
+        return;
+    };
+    if mesh.has_vertex_normals() {
+        return;
+    }
+    let (normals, normal_indices) =
+        compute_smooth_normals(&mesh.vertices, &mesh.indices, threshold_deg);
+    dbg!(format!(
+        "{}: {} smooth normals at {threshold_deg} degrees",
+        mesh.name,
+        normals.len()
+    ));
+    mesh.normals = normals;
+    mesh.normal_indices = normal_indices.into_iter().map(Some).collect();
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpAxis {
+    Y,
+    Z,
+}
+
+// NOTE: baked into the vertices of the loaded mesh, "--scale f", "--y-up"/"--z-up" and
+// "--flip-winding"
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SceneTransform {
+    pub scale: f32,
+    pub up: UpAxis,
+    // NOTE: for files with clockwise front faces, or mirrored by their exporter, which
+    // otherwise get their fronts culled. Done by load_meshes before generating normals
+    pub flip_winding: bool,
+    // NOTE: recenter at the origin and scale the largest aabb dimension to this size before
+    // applying scale, on for models from the command line unless "--no-normalize" is passed
+    pub normalize_size: Option<f32>,
+}
+
+impl Default for SceneTransform {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            up: UpAxis::Y,
+            flip_winding: false,
+            normalize_size: None,
+        }
+    }
+}
+
+// NOTE: roughly the scale the camera speed, near plane and ao radius are tuned for
+pub const NORMALIZED_SCENE_SIZE: f32 = 20.0;
+
+impl SceneTransform {
+    // NOTE: the meshes of one file move together, normalizing uses their combined aabb
+    pub fn apply(&self, meshes: &mut [CpuMesh]) {
+        if *self == Self::default() {
+            return;
+        }
+        // NOTE: z up to y up turns -90 degrees around x, the winding stays the same
+        let rotate = |v: Vec3A| match self.up {
+            UpAxis::Y => v,
+            UpAxis::Z => Vec3A::new(v.x, v.z, -v.y),
+        };
+        for v in meshes.iter_mut().flat_map(|mesh| mesh.vertices.iter_mut()) {
+            *v = rotate(*v);
+        }
+        let aabb = meshes
+            .iter()
+            .filter(|mesh| !mesh.vertices.is_empty())
+            .map(|mesh| Aabb::from_points(&mesh.vertices))
+            .reduce(|a, b| a.union(&b));
+        let (offset, scale) = match (self.normalize_size, aabb) {
+            (Some(size), Some(aabb)) => {
+                let largest = aabb.size().max_element();
+                let normalize = if largest > 0.0 { size / largest } else { 1.0 };
+                (-aabb.center(), normalize * self.scale)
+            }
+            _ => (Vec3A::ZERO, self.scale),
+        };
+        for mesh in meshes.iter_mut() {
+            for v in mesh.vertices.iter_mut() {
+                *v = (*v + offset) * scale;
+            }
+            // NOTE: the scale is uniform so normals only need the rotation
+            for n in mesh.normals.iter_mut() {
+                *n = rotate(*n);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeshLoadOptions {
+    pub normals: NormalOptions,
+    pub transform: SceneTransform,
+    // NOTE: "--split-materials", one mesh per material instead of per o/g group, see
+    // split_mesh_materials
+    pub split_materials: bool,
+}
+
+// NOTE: the arguments after the program name. Every lookup marks the arguments it used, so
+// finish can reject whatever no parser asked for instead of silently ignoring a typo
+pub struct CommandLine {
+    args: Vec<String>,
+    used: Vec<bool>,
+    errors: Vec<String>,
+}
+
+impl CommandLine {
+    pub fn new(args: impl IntoIterator<Item = String>) -> Self {
+        let args = args.into_iter().collect::<Vec<_>>();
+        Self {
+            used: vec![false; args.len()],
+            args,
+            errors: vec![],
+        }
+    }
+
+    pub fn flag(&mut self, name: &str) -> bool {
+        let mut found = false;
+        for (arg, used) in self.args.iter().zip(self.used.iter_mut()) {
+            if arg == name {
+                *used = true;
+                found = true;
+            }
+        }
+        found
+    }
+
+    // NOTE: value following name, e.g. "--anisotropy 8", the first one if name is repeated
+    pub fn value(&mut self, name: &str) -> Option<String> {
+        let mut value = None;
+        let mut i = 0;
+        while i < self.args.len() {
+            if self.args[i] == name {
+                self.used[i] = true;
+                match self.args.get(i + 1) {
+                    Some(next) => {
+                        self.used[i + 1] = true;
+                        value.get_or_insert_with(|| next.clone());
+                        i += 1;
+                    }
+                    None => self.errors.push(format!("{name} needs a value")),
+                }
+            }
+            i += 1;
+        }
+        value
+    }
+
+    // NOTE: first argument with a mesh extension that isn't the value of a flag, "cargo run
+    // -- scene.gltf"
+    pub fn mesh_path(&mut self) -> Option<std::path::PathBuf> {
+        let i = (0..self.args.len())
+            .find(|i| !self.used[*i] && is_mesh_path(std::path::Path::new(&self.args[*i])))?;
+        self.used[i] = true;
+        Some(std::path::PathBuf::from(&self.args[i]))
+    }
+
+    // NOTE: after all lookups, the first argument nobody used is an error
+    pub fn finish(self) -> Result<(), String> {
+        if let Some(err) = self.errors.into_iter().next() {
+            return Err(err);
+        }
+        let Some(arg) = self
+            .args
+            .iter()
+            .zip(self.used)
+            .find(|(_, used)| !used)
+            .map(|(arg, _)| arg)
+        else {
+            return Ok(());
+        };
+        Err(if arg.starts_with("--") {
+            format!("unknown flag {arg}")
+        } else if is_mesh_path(std::path::Path::new(arg)) {
+            format!("only one mesh path can be passed, {arg} is a second one")
+        } else {
+            format!("{arg} is not a mesh file, expected .obj, .ply, .gltf or .glb")
+        })
+    }
+}
+
+// NOTE: the whole command line, parsed once in main before the window opens. The render
+// flags only set what State::new starts with, the debug ui can change them after
+pub struct Args {
+    pub scene: SceneArgs,
+    // NOTE: "--timing", off by default, the timestamp queries perturb the timings
+    // themselves a bit
+    pub timing: bool,
+    // NOTE: "--packed-normals" and "--full-normals"
+    pub vertex_format: VertexFormat,
+    pub normal_encoding: NormalEncoding,
+    // NOTE: "--anisotropy n", see MaterialSamplers
+    pub anisotropy: u32,
+    pub reverse_z: bool,
+    pub use_taa: bool,
+    pub use_tssao: bool,
+    pub compute_test: bool,
+    pub dof: bool,
+    pub ssr: bool,
+    pub ao_gizmos: bool,
+    pub ao_scissor: bool,
+    pub ao_split: bool,
+}
+
+impl Args {
+    pub fn parse(mut cmd: CommandLine) -> Result<Self, String> {
+        let scene = SceneArgs::parse(&mut cmd)?;
+        let anisotropy = match cmd.value("--anisotropy").map(|value| value.parse::<u32>()) {
+            Some(Ok(anisotropy)) => anisotropy,
+            Some(Err(err)) => {
+                dbg!(format!("invalid --anisotropy: {err}"));
+                1
+            }
+            None => 1,
+        };
+        let args = Self {
+            scene,
+            timing: cmd.flag("--timing"),
+            vertex_format: if cmd.flag("--packed-normals") {
+                VertexFormat::PackedNormals
+            } else {
+                VertexFormat::Full
+            },
+            normal_encoding: if cmd.flag("--full-normals") {
+                NormalEncoding::Full
+            } else {
+                NormalEncoding::Octahedral
+            },
+            anisotropy,
+            reverse_z: cmd.flag("--reverse-z"),
+            use_taa: cmd.flag("--taa"),
+            use_tssao: cmd.flag("--tssao"),
+            compute_test: cmd.flag("--compute-test"),
+            dof: cmd.flag("--dof"),
+            ssr: cmd.flag("--ssr"),
+            ao_gizmos: cmd.flag("--ao-gizmos"),
+            ao_scissor: cmd.flag("--ao-scissor"),
+            ao_split: cmd.flag("--ao-split"),
+        };
+        cmd.finish()?;
+        Ok(args)
+    }
+}
+
+// NOTE: "mssao [path] [--scale f] [--y-up|--z-up] [--flip-winding]" and the other mesh
+// loading flags. Without a path the default scenes are loaded
+pub struct SceneArgs {
+    pub path: Option<std::path::PathBuf>,
+    pub load_options: MeshLoadOptions,
+    // NOTE: for files dropped on the window, normalized like a path on the command line
+    pub drop_options: MeshLoadOptions,
+    // NOTE: folder of cube faces or an equirectangular image, see load_skybox
+    pub skybox: Option<std::path::PathBuf>,
+    // NOTE: "--scene procedural", only the generated scene, see procedural_scene
+    pub procedural: bool,
+}
+
+impl SceneArgs {
+    pub fn parse(cmd: &mut CommandLine) -> Result<Self, String> {
+        let scale = match cmd.value("--scale").map(|value| value.parse::<f32>()) {
+            Some(Ok(scale)) if scale > 0.0 && scale.is_finite() => scale,
+            Some(Ok(scale)) => return Err(format!("--scale has to be positive, got {scale}")),
+            Some(Err(err)) => return Err(format!("invalid --scale: {err}")),
+            None => 1.0,
+        };
+        let skybox = cmd.value("--skybox").map(std::path::PathBuf::from);
+        if let Some(skybox) = skybox.as_ref().filter(|skybox| !skybox.exists()) {
+            return Err(format!(
+                "could not find skybox {}",
+                absolute_path(skybox).display()
+            ));
+        }
+        let scene = cmd.value("--scene");
+        // NOTE: after the flags with values so a value is never taken for the path
+        let path = cmd.mesh_path();
+        if let Some(path) = path.as_ref().filter(|path| !path.exists()) {
+            return Err(format!("could not find {}", absolute_path(path).display()));
+        }
+        let procedural = match scene.as_deref() {
+            Some("procedural") if path.is_some() => {
+                return Err("pass either a mesh path or --scene procedural".to_string())
+            }
+            Some("procedural") => true,
+            Some(scene) => return Err(format!("unknown --scene {scene}, only procedural")),
+            None => false,
+        };
+        let up = match (cmd.flag("--y-up"), cmd.flag("--z-up")) {
+            (true, true) => return Err("pass only one of --y-up and --z-up".to_string()),
+            (_, true) => UpAxis::Z,
+            _ => UpAxis::Y,
+        };
+        let normalize_size = (!cmd.flag("--no-normalize")).then_some(NORMALIZED_SCENE_SIZE);
+        let drop_options = MeshLoadOptions {
+            normals: NormalOptions::parse(cmd),
+            split_materials: cmd.flag("--split-materials"),
+            transform: SceneTransform {
+                scale,
+                up,
+                flip_winding: cmd.flag("--flip-winding"),
+                normalize_size,
+            },
+        };
+        let mut load_options = drop_options;
+        if path.is_none() {
+            load_options.transform.normalize_size = None;
+        }
+        Ok(Self {
+            path,
+            load_options,
+            drop_options,
+            skybox,
+            procedural,
+        })
+    }
+}
+
+// NOTE: relative paths resolved against the working directory, for error messages, the
+// path doesn't have to exist unlike with canonicalize
+pub fn absolute_path(path: &std::path::Path) -> std::path::PathBuf {
+    if path.is_absolute() {
+        return path.to_path_buf();
+    }
+    std::env::current_dir()
+        .map(|dir| dir.join(path))
+        .unwrap_or_else(|_| path.to_path_buf())
+}
+
+// NOTE: goes through the mesh cache, parses and refreshes the cache on a miss. The cache
+// holds the meshes as parsed, the normal options and transform are applied after. What the
+// loader thread builds from the result has a cache of its own, see LoadedMesh::cached
+pub fn load_meshes<P: AsRef<std::path::Path>>(
+    path: P,
+    options: MeshLoadOptions,
+) -> Result<Vec<CpuMesh>, ObjError> {
+    let mut meshes = load_parsed_meshes(path)?;
+    if options.split_materials {
+        meshes = meshes
+            .iter()
+            .flat_map(|mesh| split_mesh_materials(mesh).0)
+            .map(|(mesh, _)| mesh)
+            .collect();
+    }
+    for mesh in meshes.iter_mut() {
+        // NOTE: before the normal options so generated normals face out of the new fronts
+        if options.transform.flip_winding {
+            mesh.flip_winding();
+        }
+        apply_normal_options(mesh, options.normals);
+    }
+    options.transform.apply(&mut meshes);
+    Ok(meshes)
+}
+
+pub fn load_parsed_meshes<P: AsRef<std::path::Path>>(path: P) -> Result<Vec<CpuMesh>, ObjError> {
+    let path = path.as_ref();
+    if !path.exists() {
+        return Err(ObjError::Missing {
+            path: absolute_path(path),
+        });
+    }
+    let cache_path = mesh_cache_path(path);
+    if let Some(cache_path) = cache_path.as_ref() {
+        if let Some(meshes) = read_mesh_cache(cache_path, path) {
+            dbg!(format!(
+                "loaded {} from {}",
+                path.display(),
+                cache_path.display()
+            ));
+            return Ok(meshes);
+        }
+    }
+    let meshes = parse_mesh_file(path)?;
+    for mesh in meshes.iter() {
+        mesh.validate().map_err(|message| ObjError::InvalidMesh {
+            mesh: mesh.name.clone(),
+            message,
+        })?;
+    }
+    // NOTE: a file without any vertices, e.g. a ply with only a header, isn't worth caching
+    let any_vertices = meshes.iter().any(|mesh| !mesh.vertices.is_empty());
+    if let Some(cache_path) = cache_path.filter(|_| any_vertices) {
+        if let Err(err) = write_mesh_cache(&cache_path, &meshes) {
+            dbg!(format!(
+                "could not write mesh cache {}: {err}",
+                cache_path.display()
+            ));
+        }
+    }
+    Ok(meshes)
+}
+
+pub const MESH_CACHE_DIR: &str = "target/mesh_cache";
+pub const MESH_CACHE_MAGIC: [u8; 4] = *b"MSHC";
+// NOTE: bump whenever the layouts below, what the parsers produce or what LoadedMesh::new
+// builds changes, the parsed and the processed caches share it
+pub const MESH_CACHE_VERSION: u32 = 6;
+pub const MESH_CACHE_NONE: u32 = u32::MAX;
+
+// NOTE: the source mtime is part of the name so an edited file never hits an old entry.
+// Only the mesh file itself is tracked, touch it after editing an mtl
+pub fn mesh_cache_path(path: &std::path::Path) -> Option<std::path::PathBuf> {
+    use std::hash::{Hash, Hasher};
+    let modified = path.metadata().and_then(|m| m.modified()).ok()?;
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    path.hash(&mut hasher);
+    modified.hash(&mut hasher);
+    let name = format!("{}_{:016x}.bin", mesh_name_from_path(path), hasher.finish());
+    Some(std::path::Path::new(MESH_CACHE_DIR).join(name))
+}
+
+fn cache_put_slice<T: Pod>(out: &mut Vec<u8>, items: &[T]) {
+    out.extend_from_slice(&(items.len() as u64).to_le_bytes());
+    out.extend_from_slice(bytemuck::cast_slice(items));
+}
+
+fn cache_put_str(out: &mut Vec<u8>, s: &str) {
+    cache_put_slice(out, s.as_bytes());
+}
+
+fn cache_index(idx: Option<usize>) -> u32 {
+    idx.map(|idx| idx as u32).unwrap_or(MESH_CACHE_NONE)
+}
+
+// NOTE: magic, version, mesh count, then for every mesh every field as a u64 count plus
+// the raw pod data
+pub fn write_mesh_cache(cache_path: &std::path::Path, meshes: &[CpuMesh]) -> std::io::Result<()> {
+    let mut out = vec![];
+    out.extend_from_slice(&MESH_CACHE_MAGIC);
+    out.extend_from_slice(&MESH_CACHE_VERSION.to_le_bytes());
+    out.extend_from_slice(&(meshes.len() as u64).to_le_bytes());
+    for mesh in meshes {
+        cache_put_mesh(&mut out, mesh);
+    }
+    write_cache_file(cache_path, &out)
+}
+
+fn write_cache_file(cache_path: &std::path::Path, data: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = cache_path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    // NOTE: write then rename so a crash mid write never leaves a truncated cache behind
+    let tmp_path = cache_path.with_extension("tmp");
+    std::fs::write(&tmp_path, data)?;
+    std::fs::rename(&tmp_path, cache_path)
+}
+
+fn cache_put_mesh(out: &mut Vec<u8>, mesh: &CpuMesh) {
+    cache_put_str(out, &mesh.name);
+    let vec3s = |v: &[Vec3A]| v.iter().map(|v| v.to_array()).collect::<Vec<_>>();
+    cache_put_slice(out, &vec3s(&mesh.vertices));
+    let indices = mesh.indices.iter().map(|i| *i as u32).collect::<Vec<_>>();
+    cache_put_slice(out, &indices);
+    let uvs = mesh.uvs.iter().map(|uv| uv.to_array()).collect::<Vec<_>>();
+    cache_put_slice(out, &uvs);
+    let uv_indices = mesh
+        .uv_indices
+        .iter()
+        .map(|i| cache_index(*i))
+        .collect::<Vec<_>>();
+    cache_put_slice(out, &uv_indices);
+    cache_put_slice(out, &vec3s(&mesh.normals));
+    let normal_indices = mesh
+        .normal_indices
+        .iter()
+        .map(|i| cache_index(*i))
+        .collect::<Vec<_>>();
+    cache_put_slice(out, &normal_indices);
+    let colors = mesh.colors.iter().map(|c| c.to_array()).collect::<Vec<_>>();
+    cache_put_slice(out, &colors);
+
+    out.extend_from_slice(&(mesh.materials.len() as u64).to_le_bytes());
+    for material in mesh.materials.iter() {
+        cache_put_str(out, &material.name);
+        let texture = material
+            .diffuse_texture
+            .as_ref()
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        cache_put_str(out, &texture);
+        let mut values = material.diffuse.to_array().to_vec();
+        values.extend(material.specular.to_array());
+        values.extend([material.shininess, material.roughness, material.metalness]);
+        cache_put_slice(out, &values);
+    }
+    let runs = mesh
+        .material_runs
+        .iter()
+        .flat_map(|run| [run.first_index as u32, run.material as u32])
+        .collect::<Vec<_>>();
+    cache_put_slice(out, &runs);
+
+    out.extend_from_slice(&(mesh.groups.len() as u64).to_le_bytes());
+    for (group, range) in mesh.groups.iter() {
+        cache_put_str(out, group);
+        cache_put_slice(out, &[range.start as u32, range.end as u32]);
+    }
+}
+
+struct CacheReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl CacheReader<'_> {
+    fn bytes(&mut self, len: usize) -> Option<&[u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.bytes(8)?.try_into().ok()?))
+    }
+
+    // NOTE: copies since the file data has no alignment guarantee for T
+    fn slice<T: Pod>(&mut self) -> Option<Vec<T>> {
+        let count = usize::try_from(self.u64()?).ok()?;
+        let bytes = self.bytes(count.checked_mul(std::mem::size_of::<T>())?)?;
+        Some(
+            bytes
+                .chunks_exact(std::mem::size_of::<T>())
+                .map(bytemuck::pod_read_unaligned)
+                .collect(),
+        )
+    }
+
+    fn string(&mut self) -> Option<String> {
+        String::from_utf8(self.slice::<u8>()?).ok()
+    }
+}
+
+// NOTE: None for anything that is missing, stale, corrupt or from another version,
+// the caller just parses the source again and overwrites the cache
+pub fn read_mesh_cache(
+    cache_path: &std::path::Path,
+    source: &std::path::Path,
+) -> Option<Vec<CpuMesh>> {
+    let cache_modified = cache_path.metadata().and_then(|m| m.modified()).ok()?;
+    let source_modified = source.metadata().and_then(|m| m.modified()).ok()?;
+    if cache_modified < source_modified {
+        return None;
+    }
+    let data = std::fs::read(cache_path).ok()?;
+    let mut r = CacheReader {
+        data: &data,
+        pos: 0,
+    };
+    if r.bytes(4)? != MESH_CACHE_MAGIC {
+        return None;
+    }
+    if u32::from_le_bytes(r.bytes(4)?.try_into().ok()?) != MESH_CACHE_VERSION {
+        return None;
+    }
+    let num_meshes = r.u64()?;
+    let mut meshes = vec![];
+    for _ in 0..num_meshes {
+        meshes.push(cache_read_mesh(&mut r)?);
+    }
+    if r.pos != data.len() {
+        return None;
+    }
+    Some(meshes)
+}
+
+fn cache_read_mesh(r: &mut CacheReader) -> Option<CpuMesh> {
+    let index = |i: u32| (i != MESH_CACHE_NONE).then_some(i as usize);
+    let name = r.string()?;
+    let vertices = r.slice::<[f32; 3]>()?;
+    let indices = r.slice::<u32>()?;
+    let uvs = r.slice::<[f32; 2]>()?;
+    let uv_indices = r.slice::<u32>()?;
+    let normals = r.slice::<[f32; 3]>()?;
+    let normal_indices = r.slice::<u32>()?;
+    let colors = r.slice::<[f32; 4]>()?;
+
+    let num_materials = r.u64()?;
+    let mut materials = vec![];
+    for _ in 0..num_materials {
+        let mut material = Material::new(&r.string()?);
+        let texture = r.string()?;
+        material.diffuse_texture = (!texture.is_empty()).then(|| texture.into());
+        let values = r.slice::<f32>()?;
+        let [dr, dg, db, sr, sg, sb, shininess, roughness, metalness] = values[..] else {
+            return None;
+        };
+        material.diffuse = Vec3A::new(dr, dg, db);
+        material.specular = Vec3A::new(sr, sg, sb);
+        material.shininess = shininess;
+        material.roughness = roughness;
+        material.metalness = metalness;
+        materials.push(material);
+    }
+    let runs = r.slice::<u32>()?;
+    let num_groups = r.u64()?;
+    let mut groups = vec![];
+    for _ in 0..num_groups {
+        let group = r.string()?;
+        let [start, end] = r.slice::<u32>()?[..] else {
+            return None;
+        };
+        groups.push((group, start as usize..end as usize));
+    }
+
+    let mesh = CpuMesh {
+        name,
+        vertices: vertices.into_iter().map(Vec3A::from_array).collect(),
+        indices: indices.into_iter().map(|i| i as usize).collect(),
+        uvs: uvs.into_iter().map(Vec2::from_array).collect(),
+        uv_indices: uv_indices.into_iter().map(index).collect(),
+        normals: normals.into_iter().map(Vec3A::from_array).collect(),
+        normal_indices: normal_indices.into_iter().map(index).collect(),
+        colors: colors.into_iter().map(Vec4::from_array).collect(),
+        materials,
+        material_runs: runs
+            .chunks_exact(2)
+            .map(|run| MaterialRun {
+                first_index: run[0] as usize,
+                material: run[1] as usize,
+            })
+            .collect(),
+        groups,
+    };
+    // NOTE: same checks as after parsing, a cache that passes the magic but breaks these
+    // would crash the upload
+    mesh.validate().is_ok().then_some(mesh)
+}
+
+// NOTE: the gpu buffers, lod and bvh LoadedMesh::new builds for a mesh. Named after a hash
+// of the mesh itself rather than a file, so procedural meshes are cached too and a change
+// to the load options (normals, transform, ...) gives a new entry
+pub fn processed_cache_path(mesh: &CpuMesh) -> std::path::PathBuf {
+    use std::hash::{Hash, Hasher};
+    let mut bytes = vec![];
+    cache_put_mesh(&mut bytes, mesh);
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    let name = format!("processed_{:016x}.bin", hasher.finish());
+    std::path::Path::new(MESH_CACHE_DIR).join(name)
+}
+
+fn cache_put_indexed(out: &mut Vec<u8>, mesh: &IndexedMesh) {
+    cache_put_slice(out, &mesh.vertices);
+    cache_put_slice(out, &mesh.indices);
+}
+
+// NOTE: magic, version, the vertices and indices of the mesh, a lod count of 0 or 1 with
+// the lod mesh and its buffers, then the bvh nodes split into bounds and links
+pub fn write_processed_cache(
+    cache_path: &std::path::Path,
+    loaded: &LoadedMesh,
+) -> std::io::Result<()> {
+    let mut out = vec![];
+    out.extend_from_slice(&MESH_CACHE_MAGIC);
+    out.extend_from_slice(&MESH_CACHE_VERSION.to_le_bytes());
+    cache_put_indexed(&mut out, &loaded.mesh);
+    out.extend_from_slice(&(loaded.lod.is_some() as u64).to_le_bytes());
+    if let Some(lod) = &loaded.lod {
+        cache_put_mesh(&mut out, &lod.cpu);
+        cache_put_indexed(&mut out, lod);
+    }
+
+    let bvh = &loaded.bvh;
+    let bounds = bvh
+        .nodes
+        .iter()
+        .map(|node| [node.aabb_min.to_array(), node.aabb_max.to_array()])
+        .collect::<Vec<_>>();
+    cache_put_slice(&mut out, &bounds);
+    let links = bvh
+        .nodes
+        .iter()
+        .map(|node| [node.left, node.right, node.first_tri, node.tri_count])
+        .collect::<Vec<_>>();
+    cache_put_slice(&mut out, &links);
+    let triangles = bvh
+        .triangles
+        .iter()
+        .map(|tri| tri.map(|v| v.to_array()))
+        .collect::<Vec<_>>();
+    cache_put_slice(&mut out, &triangles);
+    cache_put_slice(&mut out, &bvh.tri_ids);
+    write_cache_file(cache_path, &out)
+}
+
+// NOTE: the vertices and indices of an IndexedMesh whose CpuMesh has num_indices corners
+fn cache_read_buffers(r: &mut CacheReader, num_indices: usize) -> Option<(Vec<Vertex>, Vec<u32>)> {
+    let vertices = r.slice::<Vertex>()?;
+    let indices = r.slice::<u32>()?;
+    let in_range = indices.iter().all(|idx| (*idx as usize) < vertices.len());
+    (in_range && indices.len() == num_indices).then_some((vertices, indices))
+}
+
+// NOTE: hands the mesh back on any miss so the caller can build the buffers itself. The
+// entry is named after the content, so unlike read_mesh_cache there is no mtime to check
+pub fn read_processed_cache(
+    cache_path: &std::path::Path,
+    mesh: CpuMesh,
+    file: usize,
+) -> Result<LoadedMesh, CpuMesh> {
+    let loaded = std::fs::read(cache_path)
+        .ok()
+        .and_then(|data| cache_read_processed(&data, &mesh, file));
+    match loaded {
+        Some(mut loaded) => {
+            loaded.mesh.cpu = mesh;
+            Ok(loaded)
+        }
+        None => Err(mesh),
+    }
+}
+
+// NOTE: the mesh itself is left empty for read_processed_cache to move in, it only has to
+// match the entry
+fn cache_read_processed(data: &[u8], mesh: &CpuMesh, file: usize) -> Option<LoadedMesh> {
+    let mut r = CacheReader { data, pos: 0 };
+    if r.bytes(4)? != MESH_CACHE_MAGIC {
+        return None;
+    }
+    if u32::from_le_bytes(r.bytes(4)?.try_into().ok()?) != MESH_CACHE_VERSION {
+        return None;
+    }
+    let (vertices, indices) = cache_read_buffers(&mut r, mesh.indices.len())?;
+    let lod = match r.u64()? {
+        0 => None,
+        1 => {
+            let cpu = cache_read_mesh(&mut r)?;
+            let (vertices, indices) = cache_read_buffers(&mut r, cpu.indices.len())?;
+            Some(IndexedMesh {
+                cpu,
+                vertices,
+                indices,
+            })
+        }
+        _ => return None,
+    };
+
+    let bounds = r.slice::<[[f32; 3]; 2]>()?;
+    let links = r.slice::<[u32; 4]>()?;
+    let triangles = r.slice::<[[f32; 3]; 3]>()?;
+    let tri_ids = r.slice::<u32>()?;
+    let num_triangles = mesh.indices.len() / 3;
+    if bounds.len() != links.len()
+        || triangles.len() != tri_ids.len()
+        || tri_ids.iter().any(|id| *id as usize >= num_triangles)
+        || r.pos != data.len()
+    {
+        return None;
+    }
+    // NOTE: picking walks the links without further checks. Children come after their
+    // parent and have no other parent, like Bvh::new builds them, so the walk can't loop
+    // or visit a subtree twice
+    let mut nodes = vec![];
+    let mut has_parent = vec![false; bounds.len()];
+    for (i, ([min, max], [left, right, first_tri, tri_count])) in
+        bounds.iter().zip(links.iter()).enumerate()
+    {
+        let leaf_end = (*first_tri as usize).checked_add(*tri_count as usize)?;
+        if leaf_end > triangles.len() {
+            return None;
+        }
+        if *tri_count == 0 {
+            let (left, right) = (*left as usize, *right as usize);
+            if left <= i || right <= i || left == right {
+                return None;
+            }
+            for child in [left, right] {
+                if has_parent.get(child) != Some(&false) {
+                    return None;
+                }
+                has_parent[child] = true;
+            }
+        }
+        nodes.push(BvhNode {
+            aabb_min: Vec3A::from_array(*min),
+            aabb_max: Vec3A::from_array(*max),
+            left: *left,
+            right: *right,
+            first_tri: *first_tri,
+            tri_count: *tri_count,
+        });
+    }
+    let bvh = Bvh {
+        nodes,
+        triangles: triangles
+            .iter()
+            .map(|tri| tri.map(Vec3A::from_array))
+            .collect(),
+        tri_ids,
+    };
+
+    Some(LoadedMesh {
+        mesh: IndexedMesh {
+            cpu: CpuMesh::default(),
+            vertices,
+            indices,
+        },
+        lod,
+        bvh,
+        file,
+    })
+}
+
+// NOTE: picks the parser based on file extension
+// NOTE: obj files give one mesh per o/g group, gltf files one per material and ply files
+// a single mesh
+pub fn parse_mesh_file<P: AsRef<std::path::Path>>(path: P) -> Result<Vec<CpuMesh>, ObjError> {
+    let path = path.as_ref();
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+    let obj_meshes = |path: &std::path::Path| -> Result<Vec<CpuMesh>, ObjError> {
+        let groups = parse_obj_file_groups(path)?;
+        Ok(groups.into_iter().map(|(_, mesh)| mesh).collect())
+    };
+    match extension.as_deref() {
+        Some("obj") => obj_meshes(path),
+        Some("ply") => Ok(vec![parse_ply_file(path)?]),
+        Some("gltf") | Some("glb") => {
+            Ok(load_gltf(path)?.into_iter().map(|(mesh, _)| mesh).collect())
+        }
+        ext => {
+            dbg!(format!(
+                "unknown mesh extension {ext:?}, trying to parse as obj"
+            ));
+            obj_meshes(path)
+        }
+    }
+}
+
+// NOTE: by extension only, whether load_meshes can parse the file is only known after
+pub fn is_mesh_path(path: &std::path::Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase())
+        .is_some_and(|ext| matches!(ext.as_str(), "obj" | "ply" | "gltf" | "glb"))
+}
+
+pub fn mesh_name_from_path(path: &std::path::Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "mesh".to_string())
+}
+
+// NOTE: meshes below this many triangles are cheap enough to not bother with a lod
+pub const LOD_MIN_TRIANGLES: usize = 10_000;
+// NOTE: the simplified lod aims for this fraction of the full triangle count
+pub const LOD_TARGET_RATIO: f32 = 0.125;
+
+// NOTE: vertex clustering, every vertex snaps to the center of its cell in a uniform
+// grid over the aabb and triangles that collapse or duplicate another are dropped,
+// the grid resolution is binary searched for the most triangles at or below
+// target_ratio of the full count
+pub fn simplify_mesh(mesh: &CpuMesh, target_ratio: f32) -> CpuMesh {
+    let num_triangles = mesh.indices.len() / 3;
+    let target_triangle_count = (num_triangles as f32 * target_ratio.clamp(0.0, 1.0)) as usize;
+    if num_triangles <= target_triangle_count || mesh.vertices.is_empty() {
+        return mesh.clone();
+    }
+
+    let min = mesh
+        .vertices
+        .iter()
+        .copied()
+        .fold(Vec3A::splat(f32::MAX), Vec3A::min);
+    let max = mesh
+        .vertices
+        .iter()
+        .copied()
+        .fold(Vec3A::splat(f32::MIN), Vec3A::max);
+
+    let mut lo = 1;
+    let mut hi = 1024;
+    while lo < hi {
+        let mid = (lo + hi + 1) / 2;
+        let count = cluster_mesh(mesh, min, max, mid).indices.len() / 3;
+        if count <= target_triangle_count {
+            lo = mid;
+        } else {
+            hi = mid - 1;
+        }
+    }
+    let simplified = cluster_mesh(mesh, min, max, lo);
+    dbg!(format!(
+        "simplified {}: {} -> {} triangles with {lo}^3 cells",
+        mesh.name,
+        num_triangles,
+        simplified.indices.len() / 3
+    ));
+    simplified
+}
+
+fn cluster_mesh(mesh: &CpuMesh, min: Vec3A, max: Vec3A, cells: u32) -> CpuMesh {
+    let cell_size = ((max - min) / cells as f32).max(Vec3A::splat(1e-6));
+    let mut cluster_of_cell = std::collections::HashMap::new();
+    let mut vertices = vec![];
+    let mut colors = vec![];
+    let clusters = mesh
+        .vertices
+        .iter()
+        .enumerate()
+        .map(|(i, v)| {
+            let cell = ((*v - min) / cell_size)
+                .floor()
+                .clamp(Vec3A::ZERO, Vec3A::splat(cells as f32 - 1.0));
+            let key = (cell.x as u32, cell.y as u32, cell.z as u32);
+            *cluster_of_cell.entry(key).or_insert_with(|| {
+                vertices.push(min + (cell + 0.5) * cell_size);
+                if !mesh.colors.is_empty() {
+                    colors.push(mesh.vertex_color(i));
+                }
+                vertices.len() - 1
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut seen = std::collections::HashSet::new();
+    let mut indices = vec![];
+    let mut uv_indices = vec![];
+    let mut normal_indices = vec![];
+    let mut material_runs: Vec<MaterialRun> = vec![];
+    let mut next_run = 0;
+    let mut material = None;
+    for (tri_i, idxs) in mesh.indices.chunks_exact(3).enumerate() {
+        while next_run < mesh.material_runs.len()
+            && mesh.material_runs[next_run].first_index <= 3 * tri_i
+        {
+            material = Some(mesh.material_runs[next_run].material);
+            next_run += 1;
+        }
+
+        let c = [clusters[idxs[0]], clusters[idxs[1]], clusters[idxs[2]]];
+        if c[0] == c[1] || c[1] == c[2] || c[2] == c[0] {
+            continue;
+        }
+        let mut key = c;
+        key.sort();
+        if !seen.insert(key) {
+            continue;
+        }
+
+        if let Some(material) = material {
+            if material_runs.last().map(|run| run.material) != Some(material) {
+                material_runs.push(MaterialRun {
+                    first_index: indices.len(),
+                    material,
+                });
+            }
+        }
+        for corner in 0..3 {
+            let old_corner = 3 * tri_i + corner;
+            indices.push(c[corner]);
+            uv_indices.push(mesh.uv_indices.get(old_corner).copied().flatten());
+            normal_indices.push(mesh.normal_indices.get(old_corner).copied().flatten());
+        }
+    }
+
+    CpuMesh {
+        name: format!("{} lod", mesh.name),
+        vertices,
+        indices,
+        uvs: mesh.uvs.clone(),
+        uv_indices,
+        normals: mesh.normals.clone(),
+        normal_indices,
+        colors,
+        materials: mesh.materials.clone(),
+        material_runs,
+        // NOTE: the clustering reorders the faces, the ranges wouldn't match
+        groups: vec![],
+    }
+}
+
+// NOTE: None for meshes too small to be worth it
+pub fn simplified_lod(mesh: &CpuMesh) -> Option<CpuMesh> {
+    let num_triangles = mesh.indices.len() / 3;
+    if num_triangles < LOD_MIN_TRIANGLES {
+        return None;
+    }
+    Some(simplify_mesh(mesh, LOD_TARGET_RATIO))
+}
+
+// NOTE: one gpu vertex per unique (position, normal, uv) corner, the index buffer keeps the
+// corner order so material runs still line up. Corners without a vn in a mesh that has them
+// elsewhere keep their flat face normal and are never shared across faces, in meshes without
+// any vn the normal only matters for the wireframe view and the first face's normal wins
+pub fn build_indexed_mesh(cpu: &CpuMesh) -> (Vec<Vertex>, Vec<u32>) {
+    let vertex_normals = cpu.has_vertex_normals();
+    let mut unique = std::collections::HashMap::new();
+    let mut vertices = vec![];
+    let mut indices = Vec::with_capacity(cpu.indices.len());
+
+    for (tri_i, idxs) in cpu.indices.chunks_exact(3).enumerate() {
+        let v0 = cpu.vertices[idxs[0]];
+        let v1 = cpu.vertices[idxs[1]];
+        let v2 = cpu.vertices[idxs[2]];
+        let face_normal = (v1 - v0).cross(v2 - v0).normalize_or_zero();
+
+        for (c, pos_i) in idxs.iter().enumerate() {
+            let corner = 3 * tri_i + c;
+            let normal_i = cpu.normal_indices.get(corner).copied().flatten();
+            let uv_i = cpu.uv_indices.get(corner).copied().flatten();
+            let flat_face = (vertex_normals && normal_i.is_none()).then_some(tri_i);
+            let key = (*pos_i, normal_i, uv_i, flat_face);
+            let index = *unique.entry(key).or_insert_with(|| {
+                vertices.push(Vertex {
+                    ws_pos: cpu.vertices[*pos_i].to_array(),
+                    ws_normal: cpu.corner_normal(corner).unwrap_or(face_normal).to_array(),
+                    uv: cpu.corner_uv(corner).to_array(),
+                    color: cpu.vertex_color(*pos_i).to_array(),
+                });
+                // NOTE: fits, CpuMesh::validate caps the corner count at u32::MAX
+                (vertices.len() - 1) as u32
+            });
+            indices.push(index);
+        }
+    }
+
+    dbg!(format!(
+        "{}: {} corners -> {} unique vertices ({:.2} corners per vertex)",
+        cpu.name,
+        indices.len(),
+        vertices.len(),
+        indices.len() as f32 / vertices.len().max(1) as f32
+    ));
+    (vertices, indices)
+}
+
+pub fn create_vertex_buffer(
+    ctx: &gpu::Context,
+    upload_batch: &mut UploadBatch,
+    name: &str,
+    vertices: &[Vertex],
+    vertex_format: VertexFormat,
+) -> gpu::Buffer {
+    let name = format!("{name} vb");
+    let vertex_buf = match vertex_format {
+        VertexFormat::Full => {
+            upload_batch.create_buffer(ctx, &name, bytemuck::cast_slice(vertices))
+        }
+        VertexFormat::PackedNormals => {
+            let packed = vertices.iter().map(PackedVertex::from).collect::<Vec<_>>();
+            upload_batch.create_buffer(ctx, &name, bytemuck::cast_slice(&packed))
+        }
+    };
+
+    let full_size = vertices.len() * std::mem::size_of::<Vertex>();
+    let uploaded_size = vertices.len() * vertex_format.vertex_size();
+    dbg!(format!(
+        "{vertex_format:?} vertex upload: {:.2} MB (full precision {:.2} MB, saved {:.2} MB)",
+        uploaded_size as f32 / 1e6,
+        full_size as f32 / 1e6,
+        (full_size - uploaded_size) as f32 / 1e6,
+    ));
+
+    vertex_buf
+}
+
+pub fn upload_vertices(
+    name: &str,
+    vertices: Vec<Vertex>,
+    ctx: &gpu::Context,
+    upload_batch: &mut UploadBatch,
+    vertex_format: VertexFormat,
+) -> Mesh {
+    let vertex_buf = create_vertex_buffer(ctx, upload_batch, name, &vertices, vertex_format);
+    let positions = vertices
+        .iter()
+        .map(|v| Vec3A::from_array(v.ws_pos))
+        .collect::<Vec<_>>();
+    let (bounds_center, bounds_radius) = bounding_sphere(&positions);
+    let aabb = Aabb::from_points(&positions);
+    let mesh = Mesh {
+        name: name.to_string(),
+        vertex_buf: vertex_buf.into(),
+        index_buf: None,
+        num_vertices: vertices.len(),
+        num_indices: 0,
+        materials: vec![],
+        material_textures: None,
+        draws: single_draw(vertices.len()),
+        vertex_normals: false,
+        bounds_center,
+        bounds_radius,
+        aabb,
+        lod: None,
+        bvh: Some(Bvh::new(
+            &positions,
+            &(0..positions.len()).collect::<Vec<_>>(),
+        )),
+    };
+
+    mesh
+}
+
+// NOTE: tuning constants from Forsyth's "Linear-Speed Vertex Cache Optimisation" (2006)
+const VERTEX_CACHE_SIZE: usize = 32;
+const CACHE_DECAY_POWER: f32 = 1.5;
+const LAST_TRI_SCORE: f32 = 0.75;
+const VALENCE_BOOST_SCALE: f32 = 2.0;
+const VALENCE_BOOST_POWER: f32 = 0.5;
+
+fn forsyth_vertex_score(cache_pos: Option<usize>, remaining_tris: u32) -> f32 {
+    if remaining_tris == 0 {
+        return -1.0;
+    }
+    let cache_score = match cache_pos {
+        None => 0.0,
+        // NOTE: the last triangle's vertices get a fixed score so the next triangle
+        // doesn't just reuse the same edge over and over
+        Some(pos) if pos < 3 => LAST_TRI_SCORE,
+        Some(pos) => {
+            let t = (pos - 3) as f32 / (VERTEX_CACHE_SIZE - 3) as f32;
+            (1.0 - t).powf(CACHE_DECAY_POWER)
+        }
+    };
+    // NOTE: prefer vertices with few triangles left so they get finished off
+    let valence_score = VALENCE_BOOST_SCALE * (remaining_tris as f32).powf(-VALENCE_BOOST_POWER);
+    cache_score + valence_score
+}
+
+// NOTE: reorders whole triangles for the gpu post transform cache, the triangles and
+// their winding are unchanged. Greedily emits the best scoring triangle among the ones
+// touching the simulated lru cache, falling back to the next unemitted triangle in
+// order when none of them has any left
+pub fn optimize_vertex_cache(indices: &[u32], vertex_count: usize) -> Vec<u32> {
+    let num_tris = indices.len() / 3;
+    if num_tris == 0 {
+        return indices.to_vec();
+    }
+
+    // NOTE: triangles of each vertex as one flat list with per vertex offsets
+    let mut remaining = vec![0u32; vertex_count];
+    for &idx in indices[..3 * num_tris].iter() {
+        remaining[idx as usize] += 1;
+    }
+    let mut offsets = vec![0usize; vertex_count + 1];
+    for v in 0..vertex_count {
+        offsets[v + 1] = offsets[v] + remaining[v] as usize;
+    }
+    let mut vertex_tris = vec![0usize; offsets[vertex_count]];
+    let mut fill = offsets.clone();
+    for tri in 0..num_tris {
+        for &idx in indices[3 * tri..3 * tri + 3].iter() {
+            vertex_tris[fill[idx as usize]] = tri;
+            fill[idx as usize] += 1;
+        }
+    }
+
+    let mut cache_pos: Vec<Option<usize>> = vec![None; vertex_count];
+    let mut vertex_score = (0..vertex_count)
+        .map(|v| forsyth_vertex_score(None, remaining[v]))
+        .collect::<Vec<_>>();
+    let mut tri_added = vec![false; num_tris];
+    let tri_vertices = |tri: usize| &indices[3 * tri..3 * tri + 3];
+    let mut tri_score = (0..num_tris)
+        .map(|tri| {
+            tri_vertices(tri)
+                .iter()
+                .map(|&v| vertex_score[v as usize])
+                .sum::<f32>()
+        })
+        .collect::<Vec<_>>();
+
+    let mut cache: Vec<u32> = Vec::with_capacity(VERTEX_CACHE_SIZE + 3);
+    let mut output = Vec::with_capacity(3 * num_tris);
+    let mut best_tri = (0..num_tris)
+        .max_by(|a, b| tri_score[*a].total_cmp(&tri_score[*b]))
+        .unwrap();
+    let mut scan_cursor = 0;
+
+    for _ in 0..num_tris {
+        tri_added[best_tri] = true;
+        let tri = tri_vertices(best_tri);
+        output.extend_from_slice(tri);
+
+        for &v in tri.iter() {
+            let v = v as usize;
+            remaining[v] -= 1;
+            // NOTE: swap remove the emitted triangle so the first remaining[v]
+            // entries are the ones still to go
+            let list = &mut vertex_tris[offsets[v]..offsets[v + 1]];
+            let n = remaining[v] as usize;
+            if let Some(i) = list[..=n].iter().position(|t| *t == best_tri) {
+                list.swap(i, n);
+            }
+        }
+
+        // NOTE: lru update, the triangle's vertices move to the front
+        let mut new_cache = tri.to_vec();
+        new_cache.extend(cache.iter().copied().filter(|v| !tri.contains(v)));
+        for (pos, &v) in new_cache.iter().enumerate() {
+            let v = v as usize;
+            cache_pos[v] = (pos < VERTEX_CACHE_SIZE).then_some(pos);
+            vertex_score[v] = forsyth_vertex_score(cache_pos[v], remaining[v]);
+        }
+
+        let mut next_best = None;
+        let mut next_best_score = -1.0;
+        for &v in new_cache.iter() {
+            let v = v as usize;
+            for &t in vertex_tris[offsets[v]..offsets[v] + remaining[v] as usize].iter() {
+                let score = tri_vertices(t)
+                    .iter()
+                    .map(|&tv| vertex_score[tv as usize])
+                    .sum::<f32>();
+                tri_score[t] = score;
+                if score > next_best_score {
+                    next_best_score = score;
+                    next_best = Some(t);
+                }
+            }
+        }
+        new_cache.truncate(VERTEX_CACHE_SIZE);
+        cache = new_cache;
+
+        best_tri = match next_best {
+            Some(t) => t,
+            None => {
+                while scan_cursor < num_tris && tri_added[scan_cursor] {
+                    scan_cursor += 1;
+                }
+                if scan_cursor == num_tris {
+                    break;
+                }
+                scan_cursor
+            }
+        };
+    }
+
+    // NOTE: a trailing partial triangle is kept as is
+    output.extend_from_slice(&indices[3 * num_tris..]);
+    output
+}
+
+// NOTE: average cache miss ratio, vertex shader runs per triangle for a fifo cache
+pub fn fifo_acmr(indices: &[u32], cache_size: usize) -> f32 {
+    let mut cache = std::collections::VecDeque::with_capacity(cache_size);
+    let mut misses = 0;
+    for &idx in indices.iter() {
+        if !cache.contains(&idx) {
+            misses += 1;
+            if cache.len() == cache_size {
+                cache.pop_front();
+            }
+            cache.push_back(idx);
+        }
+    }
+    misses as f32 / (indices.len() / 3).max(1) as f32
+}
+
+// NOTE: index into the Vec returned by Uploader::flush
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MeshHandle(pub usize);
+
+// NOTE: uploads any number of meshes through one UploadBatch, so a whole scene costs one
+// staging buffer, one transfer pass and one wait. The meshes' device buffers exist as soon
+// as they are queued but only hold data after flush
+pub struct Uploader<'a> {
+    ctx: &'a gpu::Context,
+    vertex_format: VertexFormat,
+    batch: UploadBatch,
+    meshes: Vec<Mesh>,
+}
+
+impl<'a> Uploader<'a> {
+    pub fn new(ctx: &'a gpu::Context, vertex_format: VertexFormat) -> Self {
+        Self {
+            ctx,
+            vertex_format,
+            batch: UploadBatch::default(),
+            meshes: vec![],
+        }
+    }
+
+    pub fn queue(&mut self, mesh: IndexedMesh, bvh: Bvh) -> MeshHandle {
+        let mut mesh = upload_mesh(self.ctx, &mut self.batch, mesh, self.vertex_format);
+        mesh.bvh = Some(bvh);
+        self.meshes.push(mesh);
+        MeshHandle(self.meshes.len() - 1)
+    }
+
+    // NOTE: same material list as the full mesh, share its textures instead of
+    // uploading them twice
+    pub fn queue_lod(&mut self, handle: MeshHandle, mut lod: IndexedMesh) {
+        lod.cpu.materials.clear();
+        let mut lod = upload_mesh(self.ctx, &mut self.batch, lod, self.vertex_format);
+        let mesh = &mut self.meshes[handle.0];
+        lod.materials = mesh.materials.clone();
+        lod.material_textures = mesh.material_textures;
+        mesh.lod = Some(Box::new(lod));
+    }
+
+    pub fn flush(self, encoder: &mut gpu::CommandEncoder) -> Vec<Mesh> {
+        self.batch.flush(self.ctx, encoder);
+        self.meshes
+    }
+}
+
+// NOTE: a mesh with its gpu vertices and vertex cache ordered index buffer, built on the
+// loader thread so upload_mesh only copies them into buffers
+pub struct IndexedMesh {
+    pub cpu: CpuMesh,
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+
+impl IndexedMesh {
+    pub fn new(cpu: CpuMesh) -> Self {
+        let (vertices, mut indices) = build_indexed_mesh(&cpu);
+        // NOTE: per draw so the material ranges stay where they are
+        let draws = draws_from_material_runs(&cpu.material_runs, cpu.indices.len());
+        let acmr_before = fifo_acmr(&indices, VERTEX_CACHE_SIZE);
+        for draw in draws.iter() {
+            let range = draw.start..draw.start + draw.count;
+            let optimized = optimize_vertex_cache(&indices[range.clone()], vertices.len());
+            indices[range].copy_from_slice(&optimized);
+        }
+        dbg!(format!(
+            "{} vertex cache acmr {acmr_before:.3} -> {:.3}",
+            cpu.name,
+            fifo_acmr(&indices, VERTEX_CACHE_SIZE)
+        ));
+        Self {
+            cpu,
+            vertices,
+            indices,
+        }
+    }
+}
+
+pub fn upload_mesh(
+    ctx: &gpu::Context,
+    upload_batch: &mut UploadBatch,
+    mesh: IndexedMesh,
+    vertex_format: VertexFormat,
+) -> Mesh {
+    let IndexedMesh {
+        cpu,
+        vertices: gpu_vertices,
+        indices,
+    } = mesh;
+    let (materials, material_textures) =
+        upload_materials(ctx, upload_batch, &cpu.name, &cpu.materials);
+    let draws = draws_from_material_runs(&cpu.material_runs, cpu.indices.len());
+    let vertex_normals = cpu.has_vertex_normals();
+    let CpuMesh { name, vertices, .. } = cpu;
+
+    let vertex_buf = create_vertex_buffer(ctx, upload_batch, &name, &gpu_vertices, vertex_format);
+    let (bounds_center, bounds_radius) = bounding_sphere(&vertices);
+    let aabb = Aabb::from_points(&vertices);
+    let index_buf = upload_batch.create_buffer(
+        ctx,
+        format!("{name} ib").as_str(),
+        bytemuck::cast_slice(&indices),
+    );
+
+    let mesh = Mesh {
+        name,
+        vertex_buf: vertex_buf.into(),
+        index_buf: Some(index_buf.into()),
+        num_vertices: gpu_vertices.len(),
+        num_indices: indices.len(),
+        materials,
+        material_textures,
+        draws,
+        vertex_normals,
+        bounds_center,
+        bounds_radius,
+        aabb,
+        lod: None,
+        bvh: None,
+    };
+
+    mesh
+}
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(std::io::Error),
+    // NOTE: line is 1-based like in an editor
+    BadIndex { line: usize, token: String },
+    NonNumeric { line: usize },
+    // NOTE: gltf files go through the same loading path, message says what was wrong
+    Gltf { message: String },
+    // NOTE: path is made absolute so it is clear where the file was looked for
+    Missing { path: std::path::PathBuf },
+    // NOTE: a parsed mesh failed CpuMesh::validate, message names the triangle
+    InvalidMesh { mesh: String, message: String },
+    Ply(PlyError),
+}
+
+impl std::fmt::Display for ObjError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ObjError::Io(err) => write!(f, "io error: {err}"),
+            ObjError::BadIndex { line, token } => {
+                write!(f, "bad face index {token} on line {line}")
+            }
+            ObjError::NonNumeric { line } => write!(f, "non numeric value on line {line}"),
+            ObjError::Gltf { message } => write!(f, "gltf error: {message}"),
+            ObjError::Missing { path } => write!(f, "could not find {}", path.display()),
+            ObjError::InvalidMesh { mesh, message } => write!(f, "invalid mesh {mesh}: {message}"),
+            ObjError::Ply(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+impl From<std::io::Error> for ObjError {
+    fn from(err: std::io::Error) -> Self {
+        ObjError::Io(err)
+    }
+}
+
+impl From<PlyError> for ObjError {
+    fn from(err: PlyError) -> Self {
+        ObjError::Ply(err)
+    }
+}
+
+pub fn parse_obj_file<P: AsRef<std::path::Path>>(path: P) -> Result<CpuMesh, ObjError> {
+    let path = path.as_ref();
+    let file = std::fs::File::open(path)?;
+    // NOTE: mtllib paths are relative to the obj file
+    let dir = path.parent().unwrap_or(std::path::Path::new(""));
+    parse_obj(
+        std::io::BufReader::new(file),
+        dir,
+        &mesh_name_from_path(path),
+    )
+}
+
+pub fn parse_obj_file_groups<P: AsRef<std::path::Path>>(
+    path: P,
+) -> Result<Vec<(String, CpuMesh)>, ObjError> {
+    let path = path.as_ref();
+    let file = std::fs::File::open(path)?;
+    let dir = path.parent().unwrap_or(std::path::Path::new(""));
+    parse_obj_groups(
+        std::io::BufReader::new(file),
+        dir,
+        &mesh_name_from_path(path),
+    )
+}
+
+// NOTE: one mesh per usemtl material with the material table of the file, see
+// split_mesh_materials
+pub fn parse_obj_file_materials<P: AsRef<std::path::Path>>(
+    path: P,
+) -> Result<(Vec<(CpuMesh, MaterialId)>, Vec<Material>), ObjError> {
+    Ok(split_mesh_materials(&parse_obj_file(path)?))
+}
+
+// NOTE: name is the mesh name, "name/object" if the file has exactly one named object
+// NOTE: for obj data that isn't a file on disk, e.g. include_bytes! or a download,
+// mtllib and texture paths are resolved against the working directory
+pub fn parse_obj_file_from_reader<R: BufRead>(reader: R) -> Result<CpuMesh, ObjError> {
+    parse_obj(reader, std::path::Path::new(""), "obj")
+}
+
+pub fn parse_obj_file_from_bytes(data: &[u8]) -> Result<CpuMesh, ObjError> {
+    parse_obj_file_from_reader(data)
+}
+
+// NOTE: one mesh per o/g group, each named "name/group" and paired with the group name.
+// Faces before the first group are named after the file, a file with a single group
+// gives the same mesh parse_obj does
+pub fn parse_obj_groups<R: BufRead>(
+    reader: R,
+    dir: &std::path::Path,
+    name: &str,
+) -> Result<Vec<(String, CpuMesh)>, ObjError> {
+    let mesh = parse_obj(reader, dir, name)?;
+    let mut groups = split_mesh_groups(&mesh);
+    if groups.len() <= 1 {
+        let group = groups.pop().map_or(name.to_string(), |(group, _)| group);
+        return Ok(vec![(group, mesh)]);
+    }
+    for (group, mesh) in groups.iter_mut() {
+        if group.is_empty() {
+            *group = name.to_string();
+            mesh.name = name.to_string();
+        } else {
+            mesh.name = format!("{name}/{group}");
+        }
+    }
+    Ok(groups)
+}
+
+// NOTE: "o name" starts an object and "g name" a group in it, the two together name a
+// group "object/group". A submesh without faces is replaced by the next one like material
+// runs are
+pub fn start_obj_group(
+    submeshes: &mut Vec<Submesh>,
+    object: &str,
+    group: &str,
+    index_start: usize,
+) {
+    let name = match (object.is_empty(), group.is_empty()) {
+        (false, false) => format!("{object}/{group}"),
+        (false, true) => object.to_string(),
+        _ => group.to_string(),
+    };
+    if submeshes.last().is_some_and(|last| last.name == name) {
+        return;
+    }
+    if submeshes
+        .last()
+        .is_some_and(|last| last.index_start == index_start)
+    {
+        submeshes.pop();
+    }
+    submeshes.push(Submesh { name, index_start });
+}
+
+pub fn parse_obj<R: BufRead>(
+    reader: R,
+    dir: &std::path::Path,
+    name: &str,
+) -> Result<CpuMesh, ObjError> {
+    // NOTE: faces before any o or g are in the unnamed submesh
+    let mut submeshes = vec![Submesh {
+        name: String::new(),
+        index_start: 0,
+    }];
+    let mut object = String::new();
+    let mut group = String::new();
+    let mut materials: Vec<Material> = vec![];
+    let mut material_runs: Vec<MaterialRun> = vec![];
+    let mut object_names: Vec<String> = vec![];
+    let mut vertices = vec![];
+    let mut normals = vec![];
+    let mut indices = vec![];
+    let mut uvs = vec![];
+    let mut uv_indices = vec![];
+    let mut normal_indices = vec![];
+    let mut colors = vec![];
+    // NOTE: faces with less than 3 distinct positions, skipped
+    let mut degenerate_faces = 0;
+    for (line_i, line) in reader.lines().enumerate() {
+        let line = line?;
+        // NOTE: lines() only strips the \n of crlf files, trim takes the \r with it. Same
+        // keyword split as parse_mtl, so tabs and indented lines work too
+        let line = line.trim();
+        // NOTE: checked up front instead of relying on "#" and "" not matching a keyword
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line_number = line_i + 1;
+        let parse_f32 = |x: &str| {
+            x.parse::<f32>()
+                .map_err(|_| ObjError::NonNumeric { line: line_number })
+        };
+        if let Some((pre, rest)) = line.split_once(char::is_whitespace) {
+            match pre {
+                "v" => {
+                    // NOTE: "v x y z" optionally followed by "r g b" vertex colors
+                    let mut vals = [0.0; 6];
+                    let mut num_vals = 0;
+                    for (i, x) in rest.split_whitespace().enumerate() {
+                        if i >= vals.len() {
+                            dbg!(&line);
+                            break;
+                        }
+                        vals[i] = parse_f32(x)?;
+                        num_vals = i + 1;
+                    }
+                    vertices.push(Vec3A::new(vals[0], vals[1], vals[2]));
+                    if num_vals == 6 {
+                        // NOTE: vertices before the first colored one default to white
+                        colors.resize(vertices.len() - 1, Vec4::ONE);
+                        colors.push(Vec4::new(vals[3], vals[4], vals[5], 1.0));
+                    }
+                }
+                "vn" => {
+                    let mut v = Vec3A::ZERO;
+                    for (i, x) in rest.split_whitespace().take(3).enumerate() {
+                        v[i] = parse_f32(x)?;
+                    }
+                    normals.push(v.normalize_or_zero());
+                }
+                "vt" => {
+                    let mut uv = Vec2::ZERO;
+                    // NOTE: optional third coordinate is ignored
+                    for (i, x) in rest.split_whitespace().take(2).enumerate() {
+                        uv[i] = parse_f32(x)?;
+                    }
+                    uvs.push(uv);
+                }
+                "f" => {
+                    let mut these_indices = vec![];
+                    let mut these_uv_indices = vec![];
+                    let mut these_normal_indices = vec![];
+                    let counts = [vertices.len(), uvs.len(), normals.len()];
+                    for token in rest.split_whitespace() {
+                        let corner = parse_face_corner(token, counts).map_err(|err| {
+                            dbg!(err);
+                            ObjError::BadIndex {
+                                line: line_number,
+                                token: token.to_string(),
+                            }
+                        })?;
+                        these_indices.push(corner.pos);
+                        these_uv_indices.push(corner.uv);
+                        these_normal_indices.push(corner.normal);
+                    }
+                    let mut distinct = these_indices.clone();
+                    distinct.sort();
+                    distinct.dedup();
+                    if distinct.len() < 3 {
+                        degenerate_faces += 1;
+                        continue;
+                    }
+                    // NOTE: fan around the first corner, (v0, vi, vi+1) keeps the winding
+                    // of the face, quads come out as (0, 1, 2) and (0, 2, 3)
+                    for i in 1..these_indices.len() - 1 {
+                        for c in [0, i, i + 1] {
+                            indices.push(these_indices[c]);
+                            uv_indices.push(these_uv_indices[c]);
+                            normal_indices.push(these_normal_indices[c]);
+                        }
+                    }
+                }
+                "o" => {
+                    object_names.push(rest.trim().to_string());
+                    object = rest.trim().to_string();
+                    group.clear();
+                    start_obj_group(&mut submeshes, &object, &group, indices.len());
+                }
+                "g" => {
+                    group = rest.trim().to_string();
+                    start_obj_group(&mut submeshes, &object, &group, indices.len());
+                }
+                "mtllib" => {
+                    materials.extend(parse_mtl_file(dir.join(rest.trim())));
+                }
+                "usemtl" => {
+                    let name = rest.trim();
+                    let material = match materials.iter().position(|m| m.name == name) {
+                        Some(material) => material,
+                        None => {
+                            dbg!(format!("unknown material {name}"));
+                            materials.push(Material::new(name));
+                            materials.len() - 1
+                        }
+                    };
+                    // NOTE: a run without any faces is replaced by the next one
+                    if material_runs
+                        .last()
+                        .is_some_and(|run| run.first_index == indices.len())
+                    {
+                        material_runs.pop();
+                    }
+                    material_runs.push(MaterialRun {
+                        first_index: indices.len(),
+                        material,
+                    });
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if degenerate_faces > 0 {
+        dbg!(format!("skipped {degenerate_faces} degenerate faces"));
+    }
+
+    if !colors.is_empty() {
+        colors.resize(vertices.len(), Vec4::ONE);
+    }
+
+    let name = match object_names.as_slice() {
+        [object_name] => format!("{name}/{object_name}"),
+        _ => name.to_string(),
+    };
+
+    // NOTE: the unnamed faces before the first o or g don't make a group
+    let groups = submeshes
+        .iter()
+        .enumerate()
+        .filter(|(_, submesh)| !submesh.name.is_empty())
+        .map(|(i, submesh)| {
+            let end = submeshes
+                .get(i + 1)
+                .map_or(indices.len(), |next| next.index_start);
+            (submesh.name.clone(), submesh.index_start..end)
+        })
+        .collect();
+
+    Ok(CpuMesh {
+        name,
+        vertices,
+        indices,
+        uvs,
+        uv_indices,
+        normals,
+        normal_indices,
+        colors,
+        materials,
+        material_runs,
+        groups,
+    })
+}
+
+// NOTE: one mesh per group name in order of first appearance, groups without faces give
+// no mesh at all. The faces before the first group come first under an empty name, the
+// caller picks a name for it
+pub fn split_mesh_groups(mesh: &CpuMesh) -> Vec<(String, CpuMesh)> {
+    let mut split = vec![];
+    let first_start = mesh
+        .groups
+        .first()
+        .map_or(mesh.indices.len(), |(_, range)| range.start);
+    if first_start > 0 {
+        split.push((String::new(), extract_mesh_ranges(mesh, &[0..first_start])));
+    }
+    let mut names: Vec<&str> = vec![];
+    for (group, range) in mesh.groups.iter() {
+        if !range.is_empty() && !names.contains(&group.as_str()) {
+            names.push(group);
+        }
+    }
+    for name in names {
+        if let Some(group_mesh) = mesh.extract_group(name) {
+            split.push((name.to_string(), group_mesh));
+        }
+    }
+    split
+}
+
+// NOTE: one mesh per material in order of first use, each with only that material and
+// the vertices it draws, paired with its index into the returned table. The table is
+// the mesh's own materials, plus a white "default" one at the end if faces come before
+// the first usemtl
+pub fn split_mesh_materials(mesh: &CpuMesh) -> (Vec<(CpuMesh, MaterialId)>, Vec<Material>) {
+    let mut materials = mesh.materials.clone();
+    let mut runs = mesh
+        .material_runs
+        .iter()
+        .enumerate()
+        .map(|(run_i, run)| {
+            let end = mesh
+                .material_runs
+                .get(run_i + 1)
+                .map_or(mesh.indices.len(), |next| next.first_index);
+            (run.material, run.first_index..end)
+        })
+        .collect::<Vec<_>>();
+    let first_start = runs
+        .first()
+        .map_or(mesh.indices.len(), |(_, range)| range.start);
+    if first_start > 0 {
+        materials.push(Material::new("default"));
+        runs.insert(0, (materials.len() - 1, 0..first_start));
+    }
+
+    let mut ids: Vec<MaterialId> = vec![];
+    for (material, range) in runs.iter() {
+        if !range.is_empty() && !ids.contains(material) {
+            ids.push(*material);
+        }
+    }
+    let split = ids
+        .into_iter()
+        .map(|id| {
+            let ranges = runs
+                .iter()
+                .filter(|(material, range)| *material == id && !range.is_empty())
+                .map(|(_, range)| range.clone())
+                .collect::<Vec<_>>();
+            let mut sub_mesh = extract_mesh_ranges(mesh, &ranges);
+            // NOTE: the faces before the first usemtl had no run to carry a material
+            if sub_mesh.materials.is_empty() {
+                sub_mesh.materials.push(materials[id].clone());
+                sub_mesh.material_runs.push(MaterialRun {
+                    first_index: 0,
+                    material: 0,
+                });
+            }
+            sub_mesh.name = format!("{}/{}", mesh.name, materials[id].name);
+            (sub_mesh, id)
+        })
+        .collect();
+    (split, materials)
+}
+
+// NOTE: the faces in the index ranges as a mesh of their own, vertices, uvs, normals and
+// materials are renumbered in order of first use so a group only keeps what it draws
+pub fn extract_mesh_ranges(mesh: &CpuMesh, ranges: &[std::ops::Range<usize>]) -> CpuMesh {
+    use std::collections::HashMap;
+    let mut out = CpuMesh {
+        name: mesh.name.clone(),
+        ..Default::default()
+    };
+    let mut vertex_map = HashMap::new();
+    let mut uv_map = HashMap::new();
+    let mut normal_map = HashMap::new();
+    let mut material_map = HashMap::new();
+    for range in ranges {
+        // NOTE: every run overlapping the range, the first one may start before it
+        for (run_i, run) in mesh.material_runs.iter().enumerate() {
+            let run_end = mesh
+                .material_runs
+                .get(run_i + 1)
+                .map_or(mesh.indices.len(), |next| next.first_index);
+            if run_end <= range.start || run.first_index >= range.end {
+                continue;
+            }
+            let material = *material_map.entry(run.material).or_insert_with(|| {
+                out.materials.push(mesh.materials[run.material].clone());
+                out.materials.len() - 1
+            });
+            if out
+                .material_runs
+                .last()
+                .is_some_and(|last| last.material == material)
+            {
+                continue;
+            }
+            out.material_runs.push(MaterialRun {
+                first_index: out.indices.len() + run.first_index.saturating_sub(range.start),
+                material,
+            });
+        }
+        for corner in range.clone() {
+            let idx = mesh.indices[corner];
+            let new_idx = *vertex_map.entry(idx).or_insert_with(|| {
+                out.vertices.push(mesh.vertices[idx]);
+                if !mesh.colors.is_empty() {
+                    out.colors.push(mesh.colors[idx]);
+                }
+                out.vertices.len() - 1
+            });
+            out.indices.push(new_idx);
+            let uv = mesh.uv_indices[corner].map(|uv| {
+                *uv_map.entry(uv).or_insert_with(|| {
+                    out.uvs.push(mesh.uvs[uv]);
+                    out.uvs.len() - 1
+                })
+            });
+            out.uv_indices.push(uv);
+            if !mesh.normal_indices.is_empty() {
+                let normal = mesh.normal_indices[corner].map(|normal| {
+                    *normal_map.entry(normal).or_insert_with(|| {
+                        out.normals.push(mesh.normals[normal]);
+                        out.normals.len() - 1
+                    })
+                });
+                out.normal_indices.push(normal);
+            }
+        }
+    }
+    out
+}
+
+// NOTE: 0-based indices of one face corner, obj itself is 1-based
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FaceCorner {
+    pub pos: usize,
+    pub uv: Option<usize>,
+    pub normal: Option<usize>,
+}
+
+// NOTE: handles all of "v", "v/vt", "v//vn" and "v/vt/vn"
+// NOTE: counts are the number of v, vt and vn lines seen so far, negative indices count
+// back from the last one and anything outside of them is an error instead of a later
+// out of bounds panic
+pub fn parse_face_corner(token: &str, counts: [usize; 3]) -> Result<FaceCorner, String> {
+    let resolve = |s: &str, count: usize, kind: &str| {
+        let idx = s
+            .parse::<i64>()
+            .map_err(|_| format!("bad {kind} index {s} in {token}"))?;
+        let resolved = if idx < 0 { count as i64 + idx } else { idx - 1 };
+        if idx == 0 || resolved < 0 || resolved >= count as i64 {
+            return Err(format!(
+                "{kind} index {idx} out of range in {token}, {count} defined"
+            ));
+        }
+        Ok(resolved as usize)
+    };
+
+    let [num_positions, num_uvs, num_normals] = counts;
+    let mut parts = token.split('/');
+    let pos = match parts.next() {
+        None | Some("") => return Err(format!("missing position in {token}")),
+        Some(s) => resolve(s, num_positions, "position")?,
+    };
+    let uv = match parts.next() {
+        None | Some("") => None,
+        Some(s) => Some(resolve(s, num_uvs, "uv")?),
+    };
+    let normal = match parts.next() {
+        None | Some("") => None,
+        Some(s) => Some(resolve(s, num_normals, "normal")?),
+    };
+    if parts.next().is_some() {
+        return Err(format!("too many parts in {token}"));
+    }
+    Ok(FaceCorner { pos, uv, normal })
+}
+
+pub fn parse_mtl_file<P: AsRef<std::path::Path>>(path: P) -> Vec<Material> {
+    let path = path.as_ref();
+    // NOTE: texture paths are relative to the mtl file
+    let dir = path.parent().unwrap_or(std::path::Path::new(""));
+    let mut materials: Vec<Material> = vec![];
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(err) => {
+            dbg!(format!("could not open mtl {}: {err}", path.display()));
+            return materials;
+        }
+    };
+    let reader = std::io::BufReader::new(file);
+    for line in reader.lines().map_while(Result::ok) {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((pre, rest)) = line.split_once(char::is_whitespace) else {
+            continue;
+        };
+        let rest = rest.trim();
+        if pre == "newmtl" {
+            materials.push(Material::new(rest));
+            continue;
+        }
+        let Some(material) = materials.last_mut() else {
+            continue;
+        };
+        let parse_rgb = |default: Vec3A| {
+            let mut rgb = default;
+            for (i, x) in rest.split_whitespace().take(3).enumerate() {
+                if let Ok(x) = x.parse() {
+                    rgb[i] = x;
+                }
+            }
+            rgb
+        };
+        match pre {
+            "Kd" => {
+                material.diffuse = parse_rgb(Vec3A::ONE);
+            }
+            "Ks" => {
+                material.specular = parse_rgb(Vec3A::ZERO);
+            }
+            "Ns" => {
+                if let Ok(ns) = rest.parse() {
+                    material.shininess = ns;
+                    material.roughness = shininess_to_roughness(ns);
+                }
+            }
+            // NOTE: pbr extension of mtl, exporters that write it put it after Ns so it wins
+            "Pr" => {
+                if let Ok(pr) = rest.parse() {
+                    material.roughness = pr;
+                }
+            }
+            "Pm" => {
+                if let Ok(pm) = rest.parse() {
+                    material.metalness = pm;
+                }
+            }
+            "map_Kd" => {
+                // NOTE: options like -bm come before the file name, some exporters write windows separators
+                if let Some(file_name) = rest.split_whitespace().last() {
+                    material.diffuse_texture = Some(dir.join(file_name.replace('\\', "/")));
+                }
+            }
+            _ => {}
+        }
+    }
+    materials
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum PlyScalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl PlyScalar {
+    pub fn from_name(name: &str) -> Option<Self> {
+        let scalar = match name {
+            "char" | "int8" => Self::I8,
+            "uchar" | "uint8" => Self::U8,
+            "short" | "int16" => Self::I16,
+            "ushort" | "uint16" => Self::U16,
+            "int" | "int32" => Self::I32,
+            "uint" | "uint32" => Self::U32,
+            "float" | "float32" => Self::F32,
+            "double" | "float64" => Self::F64,
+            _ => return None,
+        };
+        Some(scalar)
+    }
+
+    pub fn size(self) -> usize {
+        match self {
+            Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+
+    // NOTE: bytes must be exactly self.size() long
+    pub fn read_le(self, bytes: &[u8]) -> f64 {
+        match self {
+            Self::I8 => bytes[0] as i8 as f64,
+            Self::U8 => bytes[0] as f64,
+            Self::I16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            Self::U16 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            Self::I32 => i32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            Self::U32 => u32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            Self::F32 => f32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            Self::F64 => f64::from_le_bytes(bytes.try_into().unwrap()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum PlyProperty {
+    Scalar {
+        name: String,
+        ty: PlyScalar,
+    },
+    List {
+        name: String,
+        count_ty: PlyScalar,
+        item_ty: PlyScalar,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct PlyElement {
+    pub name: String,
+    pub count: usize,
+    pub properties: Vec<PlyProperty>,
+}
+
+// NOTE: source of values for the ply body, ascii and binary bodies are read the same way
+pub enum PlyValues<'a> {
+    Ascii(std::str::SplitWhitespace<'a>),
+    BinaryLittleEndian { data: &'a [u8], pos: usize },
+}
+
+impl PlyValues<'_> {
+    pub fn next(&mut self, ty: PlyScalar) -> Option<f64> {
+        match self {
+            Self::Ascii(tokens) => tokens.next()?.parse().ok(),
+            Self::BinaryLittleEndian { data, pos } => {
+                let size = ty.size();
+                let bytes = data.get(*pos..*pos + size)?;
+                *pos += size;
+                Some(ty.read_le(bytes))
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PlyError {
+    Io(std::io::Error),
+    // NOTE: message says what was wrong with the header line
+    Header { message: String },
+    // NOTE: fewer values in the body than the header declared
+    Truncated,
+    BadIndex { index: usize, num_vertices: usize },
+    // NOTE: a face list count or index that is negative or has a fraction, float list
+    // types and signed ones can hold those
+    NonIntegerIndex { value: f64 },
+}
+
+impl std::fmt::Display for PlyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlyError::Io(err) => write!(f, "io error: {err}"),
+            PlyError::Header { message } => write!(f, "bad ply header: {message}"),
+            PlyError::Truncated => write!(f, "ply body ended early"),
+            PlyError::BadIndex {
+                index,
+                num_vertices,
+            } => write!(
+                f,
+                "face index {index} out of range for {num_vertices} vertices"
+            ),
+            PlyError::NonIntegerIndex { value } => {
+                write!(f, "face list value {value} is not a non negative integer")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PlyError {}
+
+impl From<std::io::Error> for PlyError {
+    fn from(err: std::io::Error) -> Self {
+        PlyError::Io(err)
+    }
+}
+
+pub fn parse_ply_file<P: AsRef<std::path::Path>>(path: P) -> Result<CpuMesh, PlyError> {
+    let path = path.as_ref();
+    let mut mesh = parse_ply(path)?;
+    mesh.name = mesh_name_from_path(path);
+    dbg!(format!(
+        "loaded ply {} with {} vertices and {} triangles",
+        mesh.name,
+        mesh.vertices.len(),
+        mesh.indices.len() / 3
+    ));
+    Ok(mesh)
+}
+
+fn parse_ply_header<R: BufRead>(reader: &mut R) -> Result<(PlyFormat, Vec<PlyElement>), PlyError> {
+    let header_err = |message: &str| PlyError::Header {
+        message: message.to_string(),
+    };
+    let mut line = String::new();
+    let mut read_line = |line: &mut String| -> Result<(), PlyError> {
+        line.clear();
+        match reader.read_line(line)? {
+            0 => Err(header_err("unexpected end of header")),
+            _ => Ok(()),
+        }
+    };
+
+    read_line(&mut line)?;
+    if line.trim() != "ply" {
+        return Err(header_err("missing ply magic"));
+    }
+
+    let mut format = None;
+    let mut elements: Vec<PlyElement> = vec![];
+    loop {
+        read_line(&mut line)?;
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("format") => {
+                format = match tokens.next() {
+                    Some("ascii") => Some(PlyFormat::Ascii),
+                    Some("binary_little_endian") => Some(PlyFormat::BinaryLittleEndian),
+                    other => return Err(header_err(&format!("unsupported format {other:?}"))),
+                };
+            }
+            Some("element") => {
+                let name = tokens
+                    .next()
+                    .ok_or_else(|| header_err("element without name"))?;
+                let count = tokens
+                    .next()
+                    .and_then(|count| count.parse().ok())
+                    .ok_or_else(|| header_err("element without count"))?;
+                elements.push(PlyElement {
+                    name: name.to_string(),
+                    count,
+                    properties: vec![],
+                });
+            }
+            Some("property") => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| header_err("property before element"))?;
+                let ty = tokens
+                    .next()
+                    .ok_or_else(|| header_err("property without type"))?;
+                let property = if ty == "list" {
+                    let count_ty = tokens.next().and_then(PlyScalar::from_name);
+                    let item_ty = tokens.next().and_then(PlyScalar::from_name);
+                    let name = tokens.next();
+                    match (count_ty, item_ty, name) {
+                        (Some(count_ty), Some(item_ty), Some(name)) => PlyProperty::List {
+                            name: name.to_string(),
+                            count_ty,
+                            item_ty,
+                        },
+                        _ => return Err(header_err(&format!("bad list property {}", line.trim()))),
+                    }
+                } else {
+                    // NOTE: unknown properties are still read with their declared size,
+                    // only an unknown type makes the body unreadable
+                    let ty = PlyScalar::from_name(ty)
+                        .ok_or_else(|| header_err(&format!("unknown property type {ty}")))?;
+                    let name = tokens
+                        .next()
+                        .ok_or_else(|| header_err("property without name"))?;
+                    PlyProperty::Scalar {
+                        name: name.to_string(),
+                        ty,
+                    }
+                };
+                element.properties.push(property);
+            }
+            Some("end_header") => break,
+            // NOTE: comment, obj_info and blank lines
+            _ => {}
+        }
+    }
+
+    let format = format.ok_or_else(|| header_err("missing format line"))?;
+    Ok((format, elements))
+}
+
+fn parse_ply(path: &std::path::Path) -> Result<CpuMesh, PlyError> {
+    let file = std::fs::File::open(path)?;
+    parse_ply_from_reader(std::io::BufReader::new(file))
+}
+
+// NOTE: `as usize` would turn -1 into 0 and 1.5 into 1, a silently wrong face instead of
+// an error
+fn ply_list_value(value: f64) -> Result<usize, PlyError> {
+    if value < 0.0 || value.fract() != 0.0 || value > u32::MAX as f64 {
+        return Err(PlyError::NonIntegerIndex { value });
+    }
+    Ok(value as usize)
+}
+
+pub fn parse_ply_from_reader<R: BufRead>(mut reader: R) -> Result<CpuMesh, PlyError> {
+    let (format, elements) = parse_ply_header(&mut reader)?;
+
+    let mut body = vec![];
+    reader.read_to_end(&mut body)?;
+    let ascii_body;
+    let mut values = match format {
+        PlyFormat::Ascii => {
+            ascii_body = String::from_utf8_lossy(&body);
+            PlyValues::Ascii(ascii_body.split_whitespace())
+        }
+        PlyFormat::BinaryLittleEndian => PlyValues::BinaryLittleEndian {
+            data: &body,
+            pos: 0,
+        },
+    };
+
+    let mut vertices = vec![];
+    let mut normals = vec![];
+    let mut colors = vec![];
+    let mut indices = vec![];
+    let mut face_indices = vec![];
+
+    for element in elements.iter() {
+        for _ in 0..element.count {
+            let mut pos = Vec3A::ZERO;
+            let mut normal = Vec3A::ZERO;
+            let mut has_normal = false;
+            let mut color = Vec4::ONE;
+            let mut has_color = false;
+            for property in element.properties.iter() {
+                match property {
+                    PlyProperty::Scalar { name, ty } => {
+                        let value = values.next(*ty).ok_or(PlyError::Truncated)?;
+                        // NOTE: integer colors are 0-255, float colors 0-1
+                        let color_value = match ty {
+                            PlyScalar::F32 | PlyScalar::F64 => value as f32,
+                            _ => value as f32 / 255.0,
+                        };
+                        if element.name == "vertex" {
+                            match name.as_str() {
+                                "x" => pos.x = value as f32,
+                                "y" => pos.y = value as f32,
+                                "z" => pos.z = value as f32,
+                                "nx" => normal.x = value as f32,
+                                "ny" => normal.y = value as f32,
+                                "nz" => normal.z = value as f32,
+                                "red" => color.x = color_value,
+                                "green" => color.y = color_value,
+                                "blue" => color.z = color_value,
+                                "alpha" => color.w = color_value,
+                                _ => {}
+                            }
+                            has_normal |= matches!(name.as_str(), "nx" | "ny" | "nz");
+                            has_color |= matches!(name.as_str(), "red" | "green" | "blue");
+                        }
+                    }
+                    PlyProperty::List {
+                        name,
+                        count_ty,
+                        item_ty,
+                    } => {
+                        let n = ply_list_value(values.next(*count_ty).ok_or(PlyError::Truncated)?)?;
+                        face_indices.clear();
+                        let is_face_indices = element.name == "face"
+                            && (name == "vertex_indices" || name == "vertex_index");
+                        for _ in 0..n {
+                            let value = values.next(*item_ty).ok_or(PlyError::Truncated)?;
+                            // NOTE: other lists, e.g. per face texcoords, are floats
+                            if is_face_indices {
+                                face_indices.push(ply_list_value(value)?);
+                            }
+                        }
+                        if is_face_indices {
+                            // NOTE: fan triangulation, keeps winding of the polygon
+                            for i in 1..n.saturating_sub(1) {
+                                indices.push(face_indices[0]);
+                                indices.push(face_indices[i]);
+                                indices.push(face_indices[i + 1]);
+                            }
+                        }
+                    }
+                }
+            }
+            if element.name == "vertex" {
+                vertices.push(pos);
+                if has_normal {
+                    normals.push(normal.normalize_or_zero());
+                }
+                if has_color {
+                    colors.push(color);
+                }
+            }
+        }
+    }
+
+    if let Some(bad_idx) = indices.iter().find(|idx| **idx >= vertices.len()) {
+        return Err(PlyError::BadIndex {
+            index: *bad_idx,
+            num_vertices: vertices.len(),
+        });
+    }
+
+    if colors.len() != vertices.len() {
+        colors.clear();
+    }
+
+    // NOTE: normals are per vertex like the positions, so a corner uses its vertex index.
+    // Without them the indexed mesh computes face normals as for the other formats
+    let normal_indices = if normals.len() == vertices.len() {
+        indices.iter().map(|idx| Some(*idx)).collect()
+    } else {
+        normals.clear();
+        vec![None; indices.len()]
+    };
+
+    Ok(CpuMesh {
+        uv_indices: vec![None; indices.len()],
+        normal_indices,
+        vertices,
+        normals,
+        indices,
+        colors,
+        ..Default::default()
+    })
+}
+
+// NOTE: just enough json for gltf, objects keep their key order
+#[derive(Clone, Debug)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    // NOTE: empty for anything that is not an array so missing lists just iterate nothing
+    pub fn items(&self) -> &[Json] {
+        match self {
+            Json::Array(items) => items,
+            _ => &[],
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_usize(&self) -> Option<usize> {
+        self.as_f64()
+            .filter(|n| *n >= 0.0 && n.fract() == 0.0)
+            .map(|n| n as usize)
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn get_usize(&self, key: &str) -> Option<usize> {
+        self.get(key).and_then(Json::as_usize)
+    }
+
+    pub fn get_f32s(&self, key: &str) -> Option<Vec<f32>> {
+        let items = self.get(key)?.items();
+        items.iter().map(|v| v.as_f64().map(|n| n as f32)).collect()
+    }
+}
+
+pub fn parse_json(text: &str) -> Result<Json, String> {
+    let mut parser = JsonParser {
+        bytes: text.as_bytes(),
+        pos: 0,
+        depth: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos != parser.bytes.len() {
+        return Err(format!("trailing characters at byte {}", parser.pos));
+    }
+    Ok(value)
+}
+
+// NOTE: the parser recurses per array or object, a hostile gltf could otherwise
+// overflow the stack with a few kilobytes of brackets
+const JSON_MAX_DEPTH: usize = 128;
+
+struct JsonParser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    depth: usize,
+}
+
+impl JsonParser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self
+            .bytes
+            .get(self.pos)
+            .is_some_and(|b| b.is_ascii_whitespace())
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &str) -> Result<(), String> {
+        if self.bytes[self.pos..].starts_with(literal.as_bytes()) {
+            self.pos += literal.len();
+            Ok(())
+        } else {
+            Err(format!("expected {literal} at byte {}", self.pos))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.bytes.get(self.pos) {
+            None => Err("unexpected end of json".to_string()),
+            Some(b'n') => self.expect("null").map(|_| Json::Null),
+            Some(b't') => self.expect("true").map(|_| Json::Bool(true)),
+            Some(b'f') => self.expect("false").map(|_| Json::Bool(false)),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'[' | b'{') => {
+                if self.depth == JSON_MAX_DEPTH {
+                    return Err("json nested too deeply".to_string());
+                }
+                self.depth += 1;
+                let value = if self.bytes[self.pos] == b'[' {
+                    self.array()
+                } else {
+                    self.object()
+                };
+                self.depth -= 1;
+                value
             }
+            Some(_) => self.number(),
+        }
+    }
 
-            // NOTE: blur ao pass
+    fn array(&mut self) -> Result<Json, String> {
+        self.pos += 1;
+        let mut items = vec![];
+        self.skip_whitespace();
+        if self.bytes.get(self.pos) == Some(&b']') {
+            self.pos += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.bytes.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                _ => return Err(format!("expected , or ] at byte {}", self.pos)),
+            }
+        }
+    }
 
-            let ao_blur_target = &self.ao_textures.textures_after_blur[i];
-            let dpn = &self.downsample_textures.textures[i];
-            if let mut blur_ao_pass = self.command_encoder.render(
-                format!("blur ao {i}").as_str(),
-                gpu::RenderTargetSet {
-                    colors: &[gpu::RenderTarget {
-                        view: ao_blur_target.view,
-                        init_op: gpu::InitOp::Clear(gpu::TextureColor::White),
-                        finish_op: gpu::FinishOp::Store,
-                    }],
-                    depth_stencil: None,
-                },
-            ) {
-                // NOTE: these textures have same size as render target
-                let mut rc = blur_ao_pass.with(&self.pipelines.blur_ao);
+    fn object(&mut self) -> Result<Json, String> {
+        self.pos += 1;
+        let mut entries = vec![];
+        self.skip_whitespace();
+        if self.bytes.get(self.pos) == Some(&b'}') {
+            self.pos += 1;
+            return Ok(Json::Object(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(":")?;
+            entries.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.bytes.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Json::Object(entries));
+                }
+                _ => return Err(format!("expected , or }} at byte {}", self.pos)),
+            }
+        }
+    }
 
-                rc.bind(
-                    0,
-                    &BlurParams {
-                        ao_view: ao_target.view,
-                        ao_sampler: ao_target.sampler,
-                        ao_params: AOParams::from(
-                            i,
-                            1.0,
-                            self.camera.vfov_rad,
-                            ao_target.size.width,
-                            ao_target.size.height,
-                        ),
-                        pos_view: dpn.pos.view,
-                        pos_sampler: dpn.pos.sampler,
-                        normal_view: dpn.normal.view,
-                        normal_sampler: dpn.normal.sampler,
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+        while self
+            .bytes
+            .get(self.pos)
+            .is_some_and(|b| b.is_ascii_digit() || matches!(b, b'-' | b'+' | b'.' | b'e' | b'E'))
+        {
+            self.pos += 1;
+        }
+        let text = std::str::from_utf8(&self.bytes[start..self.pos]).unwrap_or("");
+        text.parse::<f64>()
+            .map(Json::Number)
+            .map_err(|_| format!("bad number at byte {start}"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let hex = self
+            .bytes
+            .get(self.pos..self.pos + 4)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| format!("bad \\u escape at byte {}", self.pos))?;
+        self.pos += 4;
+        Ok(hex)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect("\"")?;
+        let mut s = String::new();
+        loop {
+            let start = self.pos;
+            while self
+                .bytes
+                .get(self.pos)
+                .is_some_and(|b| *b != b'"' && *b != b'\\')
+            {
+                self.pos += 1;
+            }
+            // NOTE: the input is a &str and we only split at ascii, so this stays valid utf8
+            s.push_str(std::str::from_utf8(&self.bytes[start..self.pos]).unwrap());
+            match self.bytes.get(self.pos) {
+                None => return Err("unterminated string".to_string()),
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(s);
+                }
+                Some(_) => {
+                    self.pos += 1;
+                    let escape = *self
+                        .bytes
+                        .get(self.pos)
+                        .ok_or_else(|| "unterminated string".to_string())?;
+                    self.pos += 1;
+                    match escape {
+                        b'"' => s.push('"'),
+                        b'\\' => s.push('\\'),
+                        b'/' => s.push('/'),
+                        b'b' => s.push('\u{8}'),
+                        b'f' => s.push('\u{c}'),
+                        b'n' => s.push('\n'),
+                        b'r' => s.push('\r'),
+                        b't' => s.push('\t'),
+                        b'u' => {
+                            let mut code = self.hex4()?;
+                            // NOTE: utf16 surrogate pair. A high surrogate without a low
+                            // one after it becomes U+FFFD and the next escape is read as is
+                            if (0xd800..0xdc00).contains(&code)
+                                && self.bytes[self.pos..].starts_with(b"\\u")
+                            {
+                                let high_end = self.pos;
+                                self.pos += 2;
+                                let low = self.hex4()?;
+                                if (0xdc00..0xe000).contains(&low) {
+                                    code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                                } else {
+                                    self.pos = high_end;
+                                }
+                            }
+                            s.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+                        }
+                        _ => return Err(format!("bad escape at byte {}", self.pos - 1)),
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn decode_base64(text: &str) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(text.len() / 4 * 3);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for c in text.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' => break,
+            c if c.is_ascii_whitespace() => continue,
+            _ => return Err(format!("bad base64 character {:?}", c as char)),
+        };
+        acc = (acc << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    Ok(out)
+}
+
+pub const GLB_MAGIC: u32 = 0x4654_6c67;
+pub const GLB_CHUNK_JSON: u32 = 0x4e4f_534a;
+pub const GLB_CHUNK_BIN: u32 = 0x004e_4942;
+
+// NOTE: glb is a 12 byte header then chunks of (length, type, data), the json chunk
+// comes first and the optional bin chunk is buffer 0
+fn split_glb(bytes: &[u8]) -> Result<(String, Option<Vec<u8>>), String> {
+    let read_u32 = |offset: usize| -> Result<u32, String> {
+        bytes
+            .get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or_else(|| "glb ended early".to_string())
+    };
+    if read_u32(0)? != GLB_MAGIC {
+        return Err("not a glb file".to_string());
+    }
+    let mut json = None;
+    let mut bin = None;
+    let mut offset = 12;
+    while offset + 8 <= bytes.len() {
+        let length = read_u32(offset)? as usize;
+        let ty = read_u32(offset + 4)?;
+        let data = bytes
+            .get(offset + 8..offset + 8 + length)
+            .ok_or_else(|| "glb chunk out of range".to_string())?;
+        match ty {
+            GLB_CHUNK_JSON => json = Some(String::from_utf8_lossy(data).into_owned()),
+            GLB_CHUNK_BIN => bin = Some(data.to_vec()),
+            _ => {}
+        }
+        offset += 8 + length;
+    }
+    let json = json.ok_or_else(|| "glb has no json chunk".to_string())?;
+    Ok((json, bin))
+}
+
+fn load_gltf_buffers(
+    doc: &Json,
+    dir: &std::path::Path,
+    glb_bin: Option<Vec<u8>>,
+) -> Result<Vec<Vec<u8>>, String> {
+    let mut glb_bin = glb_bin;
+    let mut buffers = vec![];
+    for (i, buffer) in doc
+        .get("buffers")
+        .map(Json::items)
+        .unwrap_or(&[])
+        .iter()
+        .enumerate()
+    {
+        let data = match buffer.get("uri").and_then(Json::as_str) {
+            Some(uri) if uri.starts_with("data:") => {
+                let (_, encoded) = uri
+                    .split_once(";base64,")
+                    .ok_or_else(|| format!("buffer {i} data uri is not base64"))?;
+                decode_base64(encoded)?
+            }
+            Some(uri) => std::fs::read(dir.join(uri))
+                .map_err(|err| format!("could not read buffer {uri}: {err}"))?,
+            // NOTE: only the first buffer of a glb may leave out the uri
+            None => glb_bin
+                .take()
+                .ok_or_else(|| format!("buffer {i} has no uri and there is no glb bin chunk"))?,
+        };
+        if buffer
+            .get_usize("byteLength")
+            .is_some_and(|len| data.len() < len)
+        {
+            return Err(format!("buffer {i} is shorter than its byteLength"));
+        }
+        buffers.push(data);
+    }
+    Ok(buffers)
+}
+
+// NOTE: every accessor read as f64, exact for all the integer types gltf allows,
+// returns the flat values and the number of components per element
+fn read_gltf_accessor(
+    doc: &Json,
+    buffers: &[Vec<u8>],
+    accessor_i: usize,
+) -> Result<(Vec<f64>, usize), String> {
+    let accessor = doc
+        .get("accessors")
+        .and_then(|accessors| accessors.items().get(accessor_i))
+        .ok_or_else(|| format!("accessor {accessor_i} out of range"))?;
+    let count = accessor.get_usize("count").unwrap_or(0);
+    let components = match accessor.get("type").and_then(Json::as_str) {
+        Some("SCALAR") => 1,
+        Some("VEC2") => 2,
+        Some("VEC3") => 3,
+        Some("VEC4") => 4,
+        Some("MAT2") => 4,
+        Some("MAT3") => 9,
+        Some("MAT4") => 16,
+        ty => return Err(format!("accessor {accessor_i} has unknown type {ty:?}")),
+    };
+    let component_type = accessor.get_usize("componentType").unwrap_or(0);
+    let component_size = match component_type {
+        5120 | 5121 => 1,
+        5122 | 5123 => 2,
+        5125 | 5126 => 4,
+        ty => {
+            return Err(format!(
+                "accessor {accessor_i} has unknown component type {ty}"
+            ))
+        }
+    };
+    if accessor.get("sparse").is_some() {
+        dbg!(format!(
+            "gltf accessor {accessor_i} is sparse, ignoring the sparse values"
+        ));
+    }
+    let normalized = matches!(accessor.get("normalized"), Some(Json::Bool(true)));
+
+    let num_values = count
+        .checked_mul(components)
+        .ok_or_else(|| format!("accessor {accessor_i} count {count} is too large"))?;
+
+    // NOTE: no buffer view means all zeros
+    let Some(view_i) = accessor.get_usize("bufferView") else {
+        return Ok((vec![0.0; num_values], components));
+    };
+    let view = doc
+        .get("bufferViews")
+        .and_then(|views| views.items().get(view_i))
+        .ok_or_else(|| format!("buffer view {view_i} out of range"))?;
+    let buffer = view
+        .get_usize("buffer")
+        .and_then(|buffer_i| buffers.get(buffer_i))
+        .ok_or_else(|| format!("buffer view {view_i} has no valid buffer"))?;
+    let element_size = components * component_size;
+    let stride = view.get_usize("byteStride").unwrap_or(element_size);
+    let past_end = || format!("accessor {accessor_i} reads past the end of its buffer");
+    let start = view
+        .get_usize("byteOffset")
+        .unwrap_or(0)
+        .checked_add(accessor.get_usize("byteOffset").unwrap_or(0))
+        .ok_or_else(past_end)?;
+    // NOTE: checked before allocating, a broken count would otherwise reserve gigabytes
+    // for an accessor that fails on its first element past the end anyway
+    if count > 0 {
+        let end = (count - 1)
+            .checked_mul(stride)
+            .and_then(|last| last.checked_add(start))
+            .and_then(|last| last.checked_add(element_size))
+            .ok_or_else(past_end)?;
+        if end > buffer.len() {
+            return Err(past_end());
+        }
+    }
+
+    let mut values = Vec::with_capacity(num_values);
+    for element in 0..count {
+        let offset = start + element * stride;
+        let bytes = &buffer[offset..offset + element_size];
+        for c in bytes.chunks_exact(component_size) {
+            let value = match component_type {
+                5120 => (c[0] as i8) as f64,
+                5121 => c[0] as f64,
+                5122 => i16::from_le_bytes([c[0], c[1]]) as f64,
+                5123 => u16::from_le_bytes([c[0], c[1]]) as f64,
+                5125 => u32::from_le_bytes([c[0], c[1], c[2], c[3]]) as f64,
+                _ => f32::from_le_bytes([c[0], c[1], c[2], c[3]]) as f64,
+            };
+            let value = match (normalized, component_type) {
+                (true, 5120) => (value / 127.0).max(-1.0),
+                (true, 5121) => value / 255.0,
+                (true, 5122) => (value / 32767.0).max(-1.0),
+                (true, 5123) => value / 65535.0,
+                _ => value,
+            };
+            values.push(value);
+        }
+    }
+    Ok((values, components))
+}
+
+fn gltf_node_matrix(node: &Json) -> Mat4 {
+    if let Some(m) = node.get_f32s("matrix").filter(|m| m.len() == 16) {
+        // NOTE: column major, same as glam
+        return Mat4::from_cols_slice(&m);
+    }
+    let translation = node
+        .get_f32s("translation")
+        .filter(|t| t.len() == 3)
+        .map(|t| Vec3::from_slice(&t))
+        .unwrap_or(Vec3::ZERO);
+    let rotation = node
+        .get_f32s("rotation")
+        .filter(|r| r.len() == 4)
+        .map(|r| Quat::from_slice(&r).normalize())
+        .unwrap_or(Quat::IDENTITY);
+    let scale = node
+        .get_f32s("scale")
+        .filter(|s| s.len() == 3)
+        .map(|s| Vec3::from_slice(&s))
+        .unwrap_or(Vec3::ONE);
+    Mat4::from_scale_rotation_translation(scale, rotation, translation)
+}
+
+fn gltf_material(doc: &Json, material: &Json, i: usize, dir: &std::path::Path) -> Material {
+    let name = material
+        .get("name")
+        .and_then(Json::as_str)
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("material {i}"));
+    let mut result = Material::new(&name);
+    let Some(pbr) = material.get("pbrMetallicRoughness") else {
+        return result;
+    };
+    // NOTE: both factors default to 1 in gltf, the metallic roughness texture is ignored
+    result.roughness = pbr
+        .get("roughnessFactor")
+        .and_then(Json::as_f64)
+        .unwrap_or(1.0) as f32;
+    result.metalness = pbr
+        .get("metallicFactor")
+        .and_then(Json::as_f64)
+        .unwrap_or(1.0) as f32;
+    if let Some(factor) = pbr.get_f32s("baseColorFactor").filter(|f| f.len() == 4) {
+        result.diffuse = Vec3A::new(factor[0], factor[1], factor[2]);
+    }
+    let image = pbr
+        .get("baseColorTexture")
+        .and_then(|texture| texture.get_usize("index"))
+        .and_then(|texture_i| doc.get("textures")?.items().get(texture_i))
+        .and_then(|texture| texture.get_usize("source"))
+        .and_then(|image_i| doc.get("images")?.items().get(image_i));
+    if let Some(image) = image {
+        match image.get("uri").and_then(Json::as_str) {
+            Some(uri) if !uri.starts_with("data:") => {
+                // NOTE: gltf uris are percent encoded, spaces are the only common case
+                result.diffuse_texture = Some(dir.join(uri.replace("%20", " ")));
+            }
+            _ => {
+                dbg!(format!(
+                    "gltf material {name} has an embedded base color image, only image files are supported"
+                ));
+            }
+        }
+    }
+    result
+}
+
+// NOTE: appends one triangle primitive with its positions and normals already in world space
+fn append_gltf_primitive(
+    doc: &Json,
+    buffers: &[Vec<u8>],
+    primitive: &Json,
+    world: Mat4,
+    material: usize,
+    mesh: &mut CpuMesh,
+) -> Result<(), String> {
+    let mode = primitive.get_usize("mode").unwrap_or(4);
+    if mode != 4 {
+        dbg!(format!(
+            "skipping gltf primitive with mode {mode}, only triangle lists are supported"
+        ));
+        return Ok(());
+    }
+    let attributes = primitive
+        .get("attributes")
+        .ok_or_else(|| "primitive has no attributes".to_string())?;
+    let position_i = attributes
+        .get_usize("POSITION")
+        .ok_or_else(|| "primitive has no POSITION".to_string())?;
+    let (positions, components) = read_gltf_accessor(doc, buffers, position_i)?;
+    if components != 3 {
+        return Err("POSITION is not a VEC3".to_string());
+    }
+    let vertex_count = positions.len() / 3;
+    let indices = match primitive.get_usize("indices") {
+        Some(indices_i) => read_gltf_accessor(doc, buffers, indices_i)?
+            .0
+            .into_iter()
+            .map(|idx| idx as usize)
+            .collect::<Vec<_>>(),
+        None => (0..vertex_count).collect(),
+    };
+    if let Some(bad_idx) = indices.iter().find(|idx| **idx >= vertex_count) {
+        return Err(format!(
+            "index {bad_idx} out of range for {vertex_count} vertices"
+        ));
+    }
+
+    let normal_mat = Mat3::from_mat4(world).inverse().transpose();
+    // NOTE: a mirroring transform flips the winding, swap two corners to keep it ccw
+    let flip_winding = world.determinant() < 0.0;
+
+    let vertex_base = mesh.vertices.len();
+    mesh.vertices.extend(
+        positions
+            .chunks_exact(3)
+            .map(|p| world.transform_point3a(Vec3A::new(p[0] as f32, p[1] as f32, p[2] as f32))),
+    );
+
+    let normal_base = mesh.normals.len();
+    let has_normals = match attributes.get_usize("NORMAL") {
+        Some(normal_i) => {
+            let (normals, _) = read_gltf_accessor(doc, buffers, normal_i)?;
+            mesh.normals.extend(normals.chunks_exact(3).map(|n| {
+                (normal_mat * Vec3A::new(n[0] as f32, n[1] as f32, n[2] as f32)).normalize_or_zero()
+            }));
+            mesh.normals.len() - normal_base == vertex_count
+        }
+        None => false,
+    };
+
+    let uv_base = mesh.uvs.len();
+    let has_uvs = match attributes.get_usize("TEXCOORD_0") {
+        Some(uv_i) => {
+            let (uvs, _) = read_gltf_accessor(doc, buffers, uv_i)?;
+            // NOTE: gltf v points down, flip it to the obj convention the shader expects
+            mesh.uvs.extend(
+                uvs.chunks_exact(2)
+                    .map(|uv| Vec2::new(uv[0] as f32, 1.0 - uv[1] as f32)),
+            );
+            mesh.uvs.len() - uv_base == vertex_count
+        }
+        None => false,
+    };
+
+    mesh.material_runs.push(MaterialRun {
+        first_index: mesh.indices.len(),
+        material,
+    });
+    for tri in indices.chunks_exact(3) {
+        let tri = if flip_winding {
+            [tri[0], tri[2], tri[1]]
+        } else {
+            [tri[0], tri[1], tri[2]]
+        };
+        for idx in tri {
+            mesh.indices.push(vertex_base + idx);
+            mesh.uv_indices.push(has_uvs.then_some(uv_base + idx));
+            mesh.normal_indices
+                .push(has_normals.then_some(normal_base + idx));
+        }
+    }
+    Ok(())
+}
+
+fn parse_gltf(path: &std::path::Path) -> Result<CpuMesh, String> {
+    let bytes = std::fs::read(path).map_err(|err| err.to_string())?;
+    let dir = path.parent().unwrap_or(std::path::Path::new("."));
+    parse_gltf_from_bytes(&bytes, dir)
+}
+
+// NOTE: gltf or glb contents, buffer and image uris are resolved against dir
+pub fn parse_gltf_from_bytes(bytes: &[u8], dir: &std::path::Path) -> Result<CpuMesh, String> {
+    let is_glb = bytes.starts_with(&GLB_MAGIC.to_le_bytes());
+    let (json, glb_bin) = if is_glb {
+        split_glb(bytes)?
+    } else {
+        (String::from_utf8_lossy(bytes).into_owned(), None)
+    };
+    let doc = parse_json(&json)?;
+
+    for unsupported in ["skins", "animations", "cameras"] {
+        if doc.get(unsupported).is_some_and(|v| !v.items().is_empty()) {
+            dbg!(format!(
+                "skipping gltf {unsupported}, they are not supported"
+            ));
+        }
+    }
+    for extension in doc
+        .get("extensionsRequired")
+        .map(Json::items)
+        .unwrap_or(&[])
+    {
+        dbg!(format!(
+            "gltf requires unsupported extension {:?}, loading anyway",
+            extension.as_str()
+        ));
+    }
+
+    let buffers = load_gltf_buffers(&doc, dir, glb_bin)?;
+    let mut mesh = CpuMesh {
+        materials: doc
+            .get("materials")
+            .map(Json::items)
+            .unwrap_or(&[])
+            .iter()
+            .enumerate()
+            .map(|(i, material)| gltf_material(&doc, material, i, dir))
+            .collect(),
+        ..Default::default()
+    };
+    // NOTE: primitives without a material get a white one at the end
+    let default_material = mesh.materials.len();
+    let mut uses_default_material = false;
+
+    let nodes = doc.get("nodes").map(Json::items).unwrap_or(&[]);
+    let scene = doc
+        .get("scenes")
+        .and_then(|scenes| scenes.items().get(doc.get_usize("scene").unwrap_or(0)));
+    let mut stack = match scene {
+        Some(scene) => scene
+            .get("nodes")
+            .map(Json::items)
+            .unwrap_or(&[])
+            .iter()
+            .filter_map(Json::as_usize)
+            .map(|node_i| (node_i, Mat4::IDENTITY))
+            .collect::<Vec<_>>(),
+        // NOTE: no scene, every node that is nobody's child is a root
+        None => {
+            let children = nodes
+                .iter()
+                .flat_map(|node| node.get("children").map(Json::items).unwrap_or(&[]))
+                .filter_map(Json::as_usize)
+                .collect::<std::collections::HashSet<_>>();
+            (0..nodes.len())
+                .filter(|node_i| !children.contains(node_i))
+                .map(|node_i| (node_i, Mat4::IDENTITY))
+                .collect()
+        }
+    };
+
+    // NOTE: the node hierarchy is a forest, the visit count guards against broken files
+    let mut visits = 0;
+    while let Some((node_i, parent)) = stack.pop() {
+        visits += 1;
+        if visits > nodes.len().max(1) * 64 {
+            return Err("gltf node hierarchy has a cycle".to_string());
+        }
+        let node = nodes
+            .get(node_i)
+            .ok_or_else(|| format!("node {node_i} out of range"))?;
+        let world = parent * gltf_node_matrix(node);
+        if node.get("skin").is_some() {
+            dbg!(format!(
+                "gltf node {node_i} is skinned, drawing it in its bind pose"
+            ));
+        }
+        if let Some(gltf_mesh) = node
+            .get_usize("mesh")
+            .and_then(|mesh_i| doc.get("meshes")?.items().get(mesh_i))
+        {
+            for primitive in gltf_mesh.get("primitives").map(Json::items).unwrap_or(&[]) {
+                let material = match primitive.get_usize("material") {
+                    Some(material) if material < default_material => material,
+                    _ => {
+                        uses_default_material = true;
+                        default_material
+                    }
+                };
+                append_gltf_primitive(&doc, &buffers, primitive, world, material, &mut mesh)?;
+            }
+        }
+        for child in node.get("children").map(Json::items).unwrap_or(&[]) {
+            if let Some(child_i) = child.as_usize() {
+                stack.push((child_i, world));
+            }
+        }
+    }
+    if uses_default_material {
+        mesh.materials.push(Material::new("default"));
+    }
+    if !mesh.has_vertex_normals() {
+        mesh.normals.clear();
+    }
+    Ok(mesh)
+}
+
+pub fn parse_gltf_file<P: AsRef<std::path::Path>>(path: P) -> Result<CpuMesh, ObjError> {
+    let path = path.as_ref();
+    let mut mesh = parse_gltf(path).map_err(|message| ObjError::Gltf { message })?;
+    mesh.name = mesh_name_from_path(path);
+    dbg!(format!(
+        "loaded gltf {} with {} triangles and {} materials",
+        mesh.name,
+        mesh.indices.len() / 3,
+        mesh.materials.len()
+    ));
+    Ok(mesh)
+}
+
+// NOTE: the primitives of the whole scene grouped by material, each mesh paired with the
+// one material it draws. Primitives without a material share a white "default" one
+pub fn load_gltf<P: AsRef<std::path::Path>>(path: P) -> Result<Vec<(CpuMesh, Material)>, ObjError> {
+    let (split, materials) = split_mesh_materials(&parse_gltf_file(path)?);
+    Ok(split
+        .into_iter()
+        .map(|(mesh, id)| (mesh, materials[id].clone()))
+        .collect())
+}
+
+fn main() {
+    // NOTE: before the window opens so a mistyped path doesn't flash a fullscreen window
+    let args = match Args::parse(CommandLine::new(std::env::args().skip(1))) {
+        Ok(args) => args,
+        Err(err) => {
+            dbg!(err);
+            std::process::exit(1);
+        }
+    };
+
+    let event_loop = winit::event_loop::EventLoop::new().unwrap();
+    let window_attributes = winit::window::Window::default_attributes()
+        .with_title("ssao")
+        // .with_inner_size(winit::dpi::PhysicalSize::new(1024, 1024))
+        // .with_inner_size(winit::dpi::PhysicalSize::new(512, 512))
+        // .with_inner_size(winit::dpi::PhysicalSize::new(2048,2048))
+        .with_fullscreen(Some(winit::window::Fullscreen::Borderless(None)))
+        // .with_inner_size(winit::dpi::PhysicalSize::new(2048,2048))
+        ;
+
+    let window = event_loop.create_window(window_attributes).unwrap();
+
+    let mut state = State::new(&window, args);
+    window.set_title(&state.title());
+
+    event_loop
+        .run(|event, target| {
+            target.set_control_flow(winit::event_loop::ControlFlow::Poll);
+            state
+                .imgui_platform
+                .handle_event(state.imgui.io_mut(), &window, &event);
+            match event {
+                winit::event::Event::AboutToWait => window.request_redraw(),
+                winit::event::Event::WindowEvent { event, .. } => match event {
+                    winit::event::WindowEvent::Resized(_) => {}
+                    winit::event::WindowEvent::DroppedFile(path) => match state.drop_file(path) {
+                        Ok(()) => window.set_title(&state.title()),
+                        Err(err) => {
+                            dbg!(&err);
+                            window.set_title(&format!("ssao - {err}"));
+                        }
                     },
-                );
-                rc.bind_vertex(0, self.screen_quad_buf);
-                let num_quad_vertices = 6;
-                rc.draw(0, num_quad_vertices as _, 0, 1);
+                    winit::event::WindowEvent::MouseInput {
+                        state: winit::event::ElementState::Pressed,
+                        button: winit::event::MouseButton::Left,
+                        ..
+                    } if !state.imgui.io().want_capture_mouse => {
+                        // NOTE: logical pixels for both like the gizmo pick, while grabbed the
+                        // screen center under the crosshair is picked
+                        let io = state.imgui.io();
+                        let viewport = Vec2::from(io.display_size);
+                        let screen_pos = if state.cursor_grabbed {
+                            0.5 * viewport
+                        } else {
+                            Vec2::from(io.mouse_pos)
+                        };
+                        state.pick_node(screen_pos, viewport);
+                    }
+                    // NOTE: a button of its own so grabbing never changes the selection
+                    winit::event::WindowEvent::MouseInput {
+                        state: winit::event::ElementState::Pressed,
+                        button: winit::event::MouseButton::Right,
+                        ..
+                    } if !state.imgui.io().want_capture_mouse => {
+                        state.set_cursor_grab(&window, true);
+                    }
+                    winit::event::WindowEvent::Focused(false) => {
+                        state.set_cursor_grab(&window, false);
+                    }
+                    winit::event::WindowEvent::KeyboardInput {
+                        event:
+                            winit::event::KeyEvent {
+                                physical_key:
+                                    winit::keyboard::PhysicalKey::Code(
+                                        winit::keyboard::KeyCode::Escape,
+                                    ),
+                                state: winit::event::ElementState::Pressed,
+                                ..
+                            },
+                        ..
+                    } if state.cursor_grabbed => {
+                        state.set_cursor_grab(&window, false);
+                    }
+                    winit::event::WindowEvent::KeyboardInput {
+                        event:
+                            winit::event::KeyEvent {
+                                physical_key: winit::keyboard::PhysicalKey::Code(key_code),
+                                state: key_state,
+                                ..
+                            },
+                        ..
+                    } if !state.imgui.io().want_capture_keyboard => match key_state {
+                        winit::event::ElementState::Pressed => {
+                            if state.retained_input.held_keys.insert(key_code) {
+                                state.retained_input.just_pressed_keys.insert(key_code);
+                            }
+                        }
+                        winit::event::ElementState::Released => {
+                            state.retained_input.held_keys.remove(&key_code);
+                        }
+                    },
+                    // NOTE: pixel deltas come from touchpads, roughly 40 pixels to a line
+                    winit::event::WindowEvent::MouseWheel { delta, .. }
+                        if !state.imgui.io().want_capture_mouse =>
+                    {
+                        let lines = match delta {
+                            winit::event::MouseScrollDelta::LineDelta(_, y) => y,
+                            winit::event::MouseScrollDelta::PixelDelta(pos) => pos.y as f32 / 40.0,
+                        };
+                        state.scroll_move_speed(lines);
+                        window.set_title(&state.title());
+                    }
+                    winit::event::WindowEvent::CloseRequested => {
+                        dbg!("closing");
+                        Settings::from_state(&state.input_state, &state.scene).save(SETTINGS_PATH);
+                        state.imgui_renderer.destroy(&state.ctx);
+                        state.particles.destroy(&state.ctx);
+                        state.ao_gizmos.destroy(&state.ctx);
+                        state.debug_draw.destroy(&state.ctx);
+                        if let Some(skybox) = state.skybox.as_ref() {
+                            skybox.destroy(&state.ctx);
+                        }
+                        state.dummy_irradiance.destroy(&state.ctx);
+                        state.ctx.destroy_buffer(state.light_buffer);
+                        state.depth_readback.destroy(&state.ctx);
+                        target.exit();
+                    }
+                    winit::event::WindowEvent::RedrawRequested => {
+                        let now = std::time::Instant::now();
+                        state.delta_time = now.duration_since(state.prev_time).as_secs_f32();
+                        state.prev_time = now;
+                        state.recreate_pipelines_if_required();
+                        // NOTE: the fps counter still shows the unclamped frame time
+                        let input_dt = state.delta_time.min(MAX_INPUT_DELTA_TIME);
+                        state.handle_input(input_dt);
+                        state.handle_gamepad_input(input_dt);
+                        state.update_camera_path();
+                        state.load_dropped_files();
+                        state
+                            .particles
+                            .update(&state.ctx, state.delta_time, &state.scene.camera);
+                        if state.receive_loaded_meshes() {
+                            window.set_title(&state.title());
+                        }
+                        state.build_debug_ui(&window);
+                        state.render();
+                    }
+                    _ => {}
+                },
+                winit::event::Event::DeviceEvent {
+                    event: winit::event::DeviceEvent::MouseMotion { delta },
+                    ..
+                } if state.cursor_grabbed => {
+                    state.retained_input.mouse_delta += Vec2::new(delta.0 as f32, delta.1 as f32);
+                }
+                _ => {}
+            }
+        })
+        .unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // NOTE: the obj error variants all come out of parse_obj_file_from_reader, an in
+    // memory reader is enough to hit each of them
+    #[test]
+    fn obj_invalid_utf8_is_io_error() {
+        let data: &[u8] = b"v 0 0 0\n\xff\xfe\n";
+        let err = parse_obj_file_from_reader(data).unwrap_err();
+        assert!(matches!(err, ObjError::Io(_)), "{err:?}");
+    }
+
+    #[test]
+    fn obj_out_of_range_index_is_bad_index() {
+        let data: &[u8] = b"v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n";
+        let err = parse_obj_file_from_reader(data).unwrap_err();
+        match err {
+            ObjError::BadIndex { line, token } => {
+                assert_eq!(line, 4);
+                assert_eq!(token, "4");
+            }
+            err => panic!("expected BadIndex, got {err:?}"),
+        }
+    }
+
+    #[test]
+    fn obj_unparsable_index_is_bad_index() {
+        let data: &[u8] = b"v 0 0 0\nv 1 0 0\nv 0 1 0\n\nf 1 x/1 3\n";
+        let err = parse_obj_file_from_reader(data).unwrap_err();
+        match err {
+            ObjError::BadIndex { line, token } => {
+                assert_eq!(line, 5);
+                assert_eq!(token, "x/1");
             }
+            err => panic!("expected BadIndex, got {err:?}"),
         }
     }
 
-    pub fn render(&mut self) {
-        self.command_encoder.start();
-        for texture in self.downsample_textures.textures.iter() {
-            self.command_encoder.init_texture(texture.depth.texture);
-            self.command_encoder.init_texture(texture.pos.texture);
-            self.command_encoder.init_texture(texture.normal.texture);
+    #[test]
+    fn obj_non_numeric_value() {
+        let data: &[u8] = b"# comment\nv 0 0 0\nv 1 zero 0\n";
+        let err = parse_obj_file_from_reader(data).unwrap_err();
+        assert!(matches!(err, ObjError::NonNumeric { line: 3 }), "{err:?}");
+        let data: &[u8] = b"v 0 0 0\nvn 0 1 0\nvt 0.5 half\n";
+        let err = parse_obj_file_from_reader(data).unwrap_err();
+        assert!(matches!(err, ObjError::NonNumeric { line: 3 }), "{err:?}");
+    }
+
+    #[test]
+    fn face_corner_forms() {
+        let counts = [4, 3, 2];
+        let corner = |token| parse_face_corner(token, counts).unwrap();
+        assert_eq!(
+            corner("2"),
+            FaceCorner {
+                pos: 1,
+                uv: None,
+                normal: None
+            }
+        );
+        assert_eq!(
+            corner("2/3"),
+            FaceCorner {
+                pos: 1,
+                uv: Some(2),
+                normal: None
+            }
+        );
+        assert_eq!(
+            corner("4//2"),
+            FaceCorner {
+                pos: 3,
+                uv: None,
+                normal: Some(1)
+            }
+        );
+        assert_eq!(
+            corner("1/1/1"),
+            FaceCorner {
+                pos: 0,
+                uv: Some(0),
+                normal: Some(0)
+            }
+        );
+        assert!(parse_face_corner("", counts).is_err());
+        assert!(parse_face_corner("/1/1", counts).is_err());
+        assert!(parse_face_corner("1/1/1/1", counts).is_err());
+        assert!(parse_face_corner("1/4", counts).is_err());
+    }
+
+    // NOTE: every corner of a v//vn face keeps the normal it was written with
+    #[test]
+    fn obj_position_normal_faces_round_trip() {
+        let data: &[u8] = b"v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nvn 1 0 0\nvn 0 1 0\n\
+            f 1//3 2//1 3//2\n";
+        let mesh = parse_obj_file_from_reader(data).unwrap();
+        assert_eq!(mesh.indices, vec![0, 1, 2]);
+        assert_eq!(mesh.uv_indices, vec![None, None, None]);
+        assert_eq!(mesh.normal_indices, vec![Some(2), Some(0), Some(1)]);
+        let normals: Vec<Vec3A> = mesh
+            .normal_indices
+            .iter()
+            .map(|normal| mesh.normals[normal.unwrap()])
+            .collect();
+        assert_eq!(normals, vec![Vec3A::Y, Vec3A::Z, Vec3A::X]);
+    }
+
+    // NOTE: a negative index counts back from the vertices defined so far, not from the
+    // end of the file, so the second face here sees one more vertex than the first
+    #[test]
+    fn obj_mixed_negative_and_positive_indices() {
+        let data: &[u8] = b"v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 0 1\n\
+            f 1/-3 -2/2 -1/-1\n\
+            v 1 1 0\n\
+            f -4/1 3/-2 -1/3\n";
+        let mesh = parse_obj_file_from_reader(data).unwrap();
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(
+            mesh.uv_indices,
+            vec![Some(0), Some(1), Some(2), Some(0), Some(1), Some(2)]
+        );
+        let data: &[u8] = b"v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 -4\n";
+        let err = parse_obj_file_from_reader(data).unwrap_err();
+        assert!(
+            matches!(&err, ObjError::BadIndex { line: 4, token } if token == "-4"),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn obj_hexagon_fans_around_first_corner() {
+        let mut data = String::new();
+        for i in 0..6 {
+            let angle = i as f32 * std::f32::consts::TAU / 6.0;
+            data.push_str(&format!("v {} {} 0\n", angle.cos(), angle.sin()));
         }
+        data.push_str("f 1 2 3 4 5 6\n");
+        let mesh = parse_obj_file_from_reader(data.as_bytes()).unwrap();
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3, 0, 3, 4, 0, 4, 5]);
+        // NOTE: counter clockwise in the file, so every triangle faces +z
+        for tri in mesh.indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| mesh.vertices[tri[i]]);
+            assert!((b - a).cross(c - a).z > 0.0);
+        }
+    }
 
-        for t in self.ao_textures.textures.iter() {
-            self.command_encoder.init_texture(t.texture);
+    // NOTE: the same triangle written with crlf line endings, tabs and comment lines all
+    // has to parse to the same mesh as the plain version
+    #[test]
+    fn obj_line_ending_whitespace_and_comments() {
+        let plain: &[u8] = b"v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nf 1//1 2//1 3//1\n";
+        let crlf: &[u8] = b"v 0 0 0\r\nv 1 0 0\r\nv 0 1 0\r\nvn 0 0 1\r\nf 1//1 2//1 3//1\r\n";
+        let tabs: &[u8] = b"v\t0\t0\t0\nv\t1 0\t0\n\tv 0 1 0\nvn\t0 0 1\nf\t1//1\t2//1\t3//1\t\n";
+        let comments: &[u8] = b"# exported by hand\n\nv 0 0 0\n  # indented comment\n\
+            v 1 0 0\n#v 5 5 5\nv 0 1 0\r\n#\nvn 0 0 1\n# f 1 1 1\nf 1//1 2//1 3//1\n\n";
+        let expected = parse_obj_file_from_reader(plain).unwrap();
+        for data in [crlf, tabs, comments] {
+            let mesh = parse_obj_file_from_reader(data).unwrap();
+            assert_eq!(mesh.vertices, expected.vertices);
+            assert_eq!(mesh.normals, expected.normals);
+            assert_eq!(mesh.indices, expected.indices);
+            assert_eq!(mesh.normal_indices, expected.normal_indices);
         }
-        for t in self.ao_textures.textures_after_blur.iter() {
-            self.command_encoder.init_texture(t.texture);
+        assert_eq!(expected.vertices.len(), 3);
+        assert_eq!(expected.indices, vec![0, 1, 2]);
+    }
+
+    // NOTE: a (subdivisions + 1)^2 vertex grid, two triangles per cell in row order
+    fn grid_indices(subdivisions: u32) -> (Vec<u32>, usize) {
+        let row = subdivisions + 1;
+        let mut indices = vec![];
+        for y in 0..subdivisions {
+            for x in 0..subdivisions {
+                let i = y * row + x;
+                indices.extend_from_slice(&[i, i + row, i + 1, i + 1, i + row, i + row + 1]);
+            }
         }
-        self.command_encoder
-            .init_texture(self.ao_textures.dummy_texture.texture);
+        (indices, (row * row) as usize)
+    }
 
-        let geometry_target = &self.downsample_textures.textures[0];
+    fn sorted_triangles(indices: &[u32]) -> Vec<[u32; 3]> {
+        let mut triangles = indices
+            .chunks_exact(3)
+            .map(|t| [t[0], t[1], t[2]])
+            .collect::<Vec<_>>();
+        triangles.sort();
+        triangles
+    }
 
-        if let mut geometry_pass = self.command_encoder.render(
-            "geometry",
-            gpu::RenderTargetSet {
-                colors: &[
-                    gpu::RenderTarget {
-                        view: geometry_target.pos.view,
-                        init_op: gpu::InitOp::Clear(gpu::TextureColor::White),
-                        finish_op: gpu::FinishOp::Store,
-                    },
-                    gpu::RenderTarget {
-                        view: geometry_target.normal.view,
-                        init_op: gpu::InitOp::Clear(gpu::TextureColor::White),
-                        finish_op: gpu::FinishOp::Store,
-                    },
-                ],
-                depth_stencil: Some(gpu::RenderTarget {
-                    view: geometry_target.depth.view,
-                    init_op: gpu::InitOp::Clear(gpu::TextureColor::White),
-                    finish_op: gpu::FinishOp::Store,
-                }),
-            },
-        ) {
-            let mut rc = geometry_pass.with(&self.pipelines.geometry);
-            rc.bind(
-                0,
-                &GeometryParams {
-                    globals: Globals {
-                        mvp_transform: self.camera.vp().to_cols_array_2d(),
-                        mv_transform: self.camera.view().to_cols_array_2d(),
-                        cam_pos: self.camera.pos.to_array(),
-                        cam_dir: self.camera.right_forward_up()[1].to_array(),
-                        pad: [0; 2],
-                        mv_rot: self.camera.view_rot_only().to_cols_array_2d(),
-                    },
-                },
-            );
+    // NOTE: whole triangles are reordered with their corners in the same order, so the
+    // sorted triangles match exactly and the winding is untouched
+    #[test]
+    fn vertex_cache_output_is_triangle_permutation() {
+        let (indices, vertex_count) = grid_indices(16);
+        let optimized = optimize_vertex_cache(&indices, vertex_count);
+        assert_eq!(optimized.len(), indices.len());
+        assert_eq!(sorted_triangles(&optimized), sorted_triangles(&indices));
+
+        let mut partial = indices.clone();
+        partial.extend_from_slice(&[0, 1]);
+        let optimized = optimize_vertex_cache(&partial, vertex_count);
+        assert_eq!(&optimized[optimized.len() - 2..], &[0, 1]);
+        assert_eq!(optimize_vertex_cache(&[], 0), Vec::<u32>::new());
+    }
 
-            let mesh = &self.meshes[self.mesh_to_draw];
-            rc.bind_vertex(0, mesh.vertex_buf);
-            if let Some(index_buf) = mesh.index_buf {
-                rc.draw_indexed(
-                    index_buf,
-                    gpu::IndexType::U32,
-                    mesh.num_indices as _,
-                    0,
-                    0,
-                    1,
+    #[test]
+    fn vertex_cache_acmr_not_worse_on_grid() {
+        let (indices, vertex_count) = grid_indices(32);
+        let mut rng = nanorand::WyRand::new_seed(7);
+        let mut triangles = indices.chunks_exact(3).collect::<Vec<_>>();
+        rng.shuffle(&mut triangles);
+        let shuffled = triangles.concat();
+        for input in [indices, shuffled] {
+            let optimized = optimize_vertex_cache(&input, vertex_count);
+            for cache_size in [16, VERTEX_CACHE_SIZE] {
+                let before = fifo_acmr(&input, cache_size);
+                let after = fifo_acmr(&optimized, cache_size);
+                assert!(
+                    after <= before,
+                    "acmr {before} -> {after} with a {cache_size} entry cache"
                 );
-            } else {
-                rc.draw(0, mesh.num_vertices as _, 0, 1);
             }
         }
+    }
 
-        self.render_downsample();
-        self.render_calc_ao();
+    // NOTE: a 2 unit cube around the origin with each of its 8 corners shared by the 3
+    // faces meeting there, faces in -x, x, -y, y, -z, z order and ccw seen from outside
+    fn welded_cube() -> (Vec<Vec3A>, Vec<usize>) {
+        let vertices = (0..8)
+            .map(|i| {
+                let [x, y, z] = [i & 1, (i >> 1) & 1, i >> 2].map(|bit| [-1.0, 1.0][bit]);
+                Vec3A::new(x, y, z)
+            })
+            .collect();
+        let quads = [
+            [0, 4, 6, 2],
+            [1, 3, 7, 5],
+            [0, 1, 5, 4],
+            [2, 6, 7, 3],
+            [0, 2, 3, 1],
+            [4, 5, 7, 6],
+        ];
+        let indices = quads
+            .iter()
+            .flat_map(|[a, b, c, d]| [*a, *b, *c, *a, *c, *d])
+            .collect();
+        (vertices, indices)
+    }
 
-        let textures_for_light_pass = &self.downsample_textures.textures[0];
-        // let textures_for_light_pass = &self.downsample_textures.textures.last().unwrap();
-        let frame = self.surface.acquire_frame();
-        self.command_encoder.init_texture(frame.texture());
-        if let mut light_pass = self.command_encoder.render(
-            "light",
-            gpu::RenderTargetSet {
-                colors: &[gpu::RenderTarget {
-                    view: frame.texture_view(),
-                    init_op: gpu::InitOp::Clear(gpu::TextureColor::White),
-                    finish_op: gpu::FinishOp::Store,
-                }],
-                depth_stencil: None,
-            },
-        ) {
-            let mut rc = light_pass.with(&self.pipelines.light);
+    // NOTE: the welded cube with one normal and the same 4 uvs per face. The corners of a
+    // face share position, normal and uv, neighbouring faces only share positions so each
+    // face keeps 4 vertices of its own
+    #[test]
+    fn indexed_cube_has_vertex_per_face_corner() {
+        let (vertices, indices) = welded_cube();
+        let cube = CpuMesh {
+            vertices,
+            indices,
+            uvs: vec![Vec2::ZERO, Vec2::X, Vec2::ONE, Vec2::Y],
+            uv_indices: [0, 1, 2, 0, 2, 3].repeat(6).into_iter().map(Some).collect(),
+            normals: vec![
+                -Vec3A::X,
+                Vec3A::X,
+                -Vec3A::Y,
+                Vec3A::Y,
+                -Vec3A::Z,
+                Vec3A::Z,
+            ],
+            normal_indices: (0..36).map(|corner| Some(corner / 6)).collect(),
+            ..Default::default()
+        };
+        let (vertices, indices) = build_indexed_mesh(&cube);
+        assert_eq!(vertices.len(), 24);
+        assert_eq!(indices.len(), 36);
+        assert!(indices.iter().all(|idx| (*idx as usize) < vertices.len()));
+        for (corner, idx) in indices.iter().enumerate() {
+            let vertex = &vertices[*idx as usize];
+            assert_eq!(
+                vertex.ws_pos,
+                cube.vertices[cube.indices[corner]].to_array()
+            );
+            assert_eq!(
+                vertex.ws_normal,
+                cube.corner_normal(corner).unwrap().to_array()
+            );
+        }
+    }
 
-            let use_blurred_texture = self.input_state.use_blur;
-            let ao_index = self.input_state.ao_level;
-            let ao_texture = if use_blurred_texture {
-                &self.ao_textures.textures_after_blur[ao_index]
-            } else {
-                &self.ao_textures.textures[ao_index]
-            };
-            // let ao_texture = &self.ao_textures.textures[0];
-            // let ao_texture = &self.downsample_textures.textures[1].normal;
-            rc.bind(
-                0,
-                &LightPassParams {
-                    pos_view: textures_for_light_pass.pos.view,
-                    pos_sampler: textures_for_light_pass.pos.sampler,
-                    normal_view: textures_for_light_pass.normal.view,
-                    normal_sampler: textures_for_light_pass.normal.sampler,
-                    depth_view: textures_for_light_pass.depth.view,
-                    depth_sampler: textures_for_light_pass.depth.sampler,
-                    globals: Globals {
-                        mvp_transform: self.camera.vp().to_cols_array_2d(),
-                        mv_transform: self.camera.view().to_cols_array_2d(),
-                        cam_pos: self.camera.pos.to_array(),
-                        cam_dir: self.camera.right_forward_up()[1].to_array(),
-                        pad: [0; 2],
-                        mv_rot: self.camera.view_rot_only().to_cols_array_2d(),
-                    },
-                    ao_view: ao_texture.view,
-                    ao_sampler: ao_texture.sampler,
+    // NOTE: the vertex cache pass reorders triangles within each material draw, never
+    // across them
+    #[test]
+    fn indexed_mesh_keeps_material_ranges() {
+        let (vertices, indices) = welded_cube();
+        let cube = CpuMesh {
+            vertices,
+            indices,
+            material_runs: vec![
+                MaterialRun {
+                    first_index: 0,
+                    material: 0,
+                },
+                MaterialRun {
+                    first_index: 18,
+                    material: 1,
                 },
+            ],
+            ..Default::default()
+        };
+        let (_, unordered) = build_indexed_mesh(&cube);
+        let indexed = IndexedMesh::new(cube);
+        assert_eq!(indexed.indices.len(), unordered.len());
+        for range in [0..18, 18..36] {
+            assert_eq!(
+                sorted_triangles(&indexed.indices[range.clone()]),
+                sorted_triangles(&unordered[range])
             );
-            rc.bind_vertex(0, self.screen_quad_buf);
-            let num_quad_vertices = 6;
-            rc.draw(0, num_quad_vertices as _, 0, 1);
         }
-        self.command_encoder.present(frame);
+    }
 
-        let sp = self.ctx.submit(&mut self.command_encoder);
-        self.ctx.wait_for(&sp, !0);
+    #[test]
+    fn smooth_normals_cube_hard_edges() {
+        let (vertices, indices) = welded_cube();
+        assert_eq!(vertices.len(), 8);
+        // NOTE: 90 degree edges are above the threshold, every face stays flat
+        let (normals, normal_indices) = compute_smooth_normals(&vertices, &indices, 30.0);
+        assert_eq!(normal_indices.len(), indices.len());
+        assert_eq!(normals.len(), 24);
+        for (tri_i, tri) in indices.chunks_exact(3).enumerate() {
+            let [a, b, c] = [0, 1, 2].map(|i| vertices[tri[i]]);
+            let face_normal = (b - a).cross(c - a).normalize();
+            for corner in 3 * tri_i..3 * tri_i + 3 {
+                let normal = normals[normal_indices[corner]];
+                assert!(
+                    normal.abs_diff_eq(face_normal, 1e-6),
+                    "{normal} {face_normal}"
+                );
+            }
+        }
     }
 
-    pub fn handle_input(&mut self) {
-        let [r, f, u] = self.camera.right_forward_up();
+    #[test]
+    fn smooth_normals_cube_soft_corners() {
+        let (vertices, indices) = welded_cube();
+        let (normals, normal_indices) = compute_smooth_normals(&vertices, &indices, 100.0);
+        // NOTE: all three faces are within the threshold, one normal per corner position
+        assert_eq!(normals.len(), 8);
+        for (corner, pos_i) in indices.iter().enumerate() {
+            let normal = normals[normal_indices[corner]];
+            let pos = vertices[*pos_i];
+            assert!((normal.length() - 1.0).abs() < 1e-5);
+            // NOTE: area weighted and a quad corner touches one or two triangles, so not
+            // exactly the diagonal, but on the same side of every face
+            assert!((normal * pos).cmpgt(Vec3A::ZERO).all(), "{normal} at {pos}");
+        }
+    }
 
-        let speed = 6.0;
-        let angle_speed = 0.8;
-        let dt = self.delta_time;
+    #[test]
+    fn smooth_normals_sphere_point_outwards() {
+        let sphere = uv_sphere_mesh(Vec3A::ZERO, 2.0, 32, 16);
+        let (normals, normal_indices) =
+            compute_smooth_normals(&sphere.vertices, &sphere.indices, 60.0);
+        assert_eq!(normal_indices.len(), sphere.indices.len());
+        // NOTE: seam and pole vertices are duplicated so they only see faces on one side,
+        // still within a few degrees of the radial direction
+        for (corner, pos_i) in sphere.indices.iter().enumerate() {
+            let normal = normals[normal_indices[corner]];
+            let radial = sphere.vertices[*pos_i].normalize();
+            assert!(normal.dot(radial) > 0.99, "{normal} at {radial}");
+        }
+    }
 
-        for key in self.retained_input.held_keys.iter() {
-            match key {
-                winit::keyboard::KeyCode::KeyW => {
-                    self.camera.pos += f * dt * speed;
-                }
-                winit::keyboard::KeyCode::KeyA => {
-                    self.camera.pos -= r * dt * speed;
-                }
-                winit::keyboard::KeyCode::KeyS => {
-                    self.camera.pos -= f * dt * speed;
-                }
-                winit::keyboard::KeyCode::KeyD => {
-                    self.camera.pos += r * dt * speed;
-                }
-                winit::keyboard::KeyCode::KeyQ => {
-                    self.camera.pos -= u * dt * speed;
-                }
-                winit::keyboard::KeyCode::KeyE => {
-                    self.camera.pos += u * dt * speed;
-                }
+    fn test_camera(yaw: f32, reverse_z: bool) -> Camera {
+        Camera {
+            yaw,
+            vfov_rad: 90.0_f32.to_radians(),
+            reverse_z,
+            ..Camera::default_from_aspect(1.0)
+        }
+    }
 
-                // angle
-                winit::keyboard::KeyCode::KeyI => {
-                    self.camera.pitch += dt * angle_speed;
-                }
-                winit::keyboard::KeyCode::KeyJ => {
-                    self.camera.yaw += dt * angle_speed;
-                }
-                winit::keyboard::KeyCode::KeyK => {
-                    self.camera.pitch -= dt * angle_speed;
-                }
-                winit::keyboard::KeyCode::KeyL => {
-                    self.camera.yaw -= dt * angle_speed;
-                }
+    #[test]
+    fn camera_look_clamps_pitch() {
+        let max_pitch = 89.0_f32.to_radians();
+        let mut camera = test_camera(0.0, false);
+        camera.look(0.5, 10.0);
+        assert_eq!(camera.yaw, 0.5);
+        assert_eq!(camera.pitch, max_pitch);
+        camera.look(0.0, -20.0);
+        assert_eq!(camera.pitch, -max_pitch);
+    }
 
-                winit::keyboard::KeyCode::Digit1 => {
-                    self.input_state.ao_level = 0;
-                }
-                winit::keyboard::KeyCode::Digit2 => {
-                    self.input_state.ao_level = 1;
-                }
-                winit::keyboard::KeyCode::Digit3 => {
-                    self.input_state.ao_level = 2;
+    #[test]
+    fn args_reject_unknown_arguments() {
+        let parse =
+            |args: &[&str]| Args::parse(CommandLine::new(args.iter().map(|arg| arg.to_string())));
+        let Ok(args) = parse(&["--taa", "--anisotropy", "8"]) else {
+            panic!("valid flags rejected");
+        };
+        assert!(args.use_taa);
+        assert_eq!(args.anisotropy, 8);
+        assert_eq!(
+            parse(&["--tsao"]).err().as_deref(),
+            Some("unknown flag --tsao")
+        );
+        assert_eq!(
+            parse(&["scene.ojb"]).err().as_deref(),
+            Some("scene.ojb is not a mesh file, expected .obj, .ply, .gltf or .glb")
+        );
+        assert_eq!(
+            parse(&["--scale"]).err().as_deref(),
+            Some("--scale needs a value")
+        );
+    }
+
+    #[test]
+    fn frustum_plane_normals_point_inwards() {
+        let s = std::f32::consts::FRAC_1_SQRT_2;
+        // NOTE: view space normals of a 90 degree square frustum looking down -z
+        let expected = [
+            Vec3A::new(s, 0.0, -s),
+            Vec3A::new(-s, 0.0, -s),
+            Vec3A::new(0.0, s, -s),
+            Vec3A::new(0.0, -s, -s),
+            -Vec3A::Z,
+            Vec3A::Z,
+        ];
+        for yaw in [0.0, 1.0, -2.5] {
+            for reverse_z in [false, true] {
+                let camera = test_camera(yaw, reverse_z);
+                let rot = camera.rot_quat();
+                let mut planes = camera.frustum_planes();
+                if reverse_z {
+                    planes.swap(4, 5);
                 }
-                winit::keyboard::KeyCode::Digit4 => {
-                    self.input_state.ao_level = 3;
+                for (plane, normal) in planes.iter().zip(expected) {
+                    let normal = Vec3A::from(rot * Vec3::from(normal));
+                    assert!(
+                        plane.normal.abs_diff_eq(normal, 1e-4),
+                        "{} != {normal}",
+                        plane.normal
+                    );
                 }
-                winit::keyboard::KeyCode::Digit5 => {
-                    self.input_state.ao_level = 4;
+                // NOTE: near and far sit at their distance along the view direction
+                let forward = Vec3A::from(rot * -Vec3::Z);
+                assert!(planes[4].distance(camera.near * forward).abs() < 1e-4);
+                assert!(planes[5].distance(camera.far * forward).abs() < 1e-1);
+                for plane in planes[..4].iter() {
+                    assert!(plane.distance(camera.pos).abs() < 1e-4);
                 }
+            }
+        }
+    }
 
-                winit::keyboard::KeyCode::Digit6 => {
-                    self.input_state.ao_level = 5;
-                }
-                winit::keyboard::KeyCode::Digit7 => {
-                    self.input_state.ao_level = 6;
-                }
+    #[test]
+    fn aabb_in_camera_frustum() {
+        let camera = test_camera(0.0, false);
+        let planes = camera.frustum_planes();
+        let aabb = |center: Vec3A| Aabb {
+            min: center - 0.5,
+            max: center + 0.5,
+        };
+        assert!(aabb(Vec3A::new(0.0, 0.0, -10.0)).in_frustum(&planes));
+        // NOTE: partly inside still counts
+        assert!(aabb(Vec3A::new(10.0, 0.0, -10.0)).in_frustum(&planes));
+        assert!(!aabb(Vec3A::new(0.0, 0.0, 10.0)).in_frustum(&planes));
+        assert!(!aabb(Vec3A::new(20.0, 0.0, -10.0)).in_frustum(&planes));
+        assert!(!aabb(Vec3A::new(0.0, -20.0, -10.0)).in_frustum(&planes));
+        assert!(!aabb(Vec3A::new(0.0, 0.0, -2.0 * camera.far)).in_frustum(&planes));
+        // NOTE: a box around the camera is cut by the near plane
+        assert!(aabb(Vec3A::ZERO).in_frustum(&planes));
+    }
 
-                _ => {}
-            }
+    // NOTE: axis aligned planes around the -1..1 cube, so a box touching a face is exact
+    #[test]
+    fn aabb_touching_frustum_plane_is_inside() {
+        let planes = [
+            Vec3A::X,
+            -Vec3A::X,
+            Vec3A::Y,
+            -Vec3A::Y,
+            Vec3A::Z,
+            -Vec3A::Z,
+        ]
+        .map(|normal| Plane { normal, d: 1.0 });
+        let touching = Aabb {
+            min: Vec3A::new(1.0, 0.0, 0.0),
+            max: Vec3A::new(2.0, 0.5, 0.5),
+        };
+        assert!(touching.in_frustum(&planes));
+        let corner = Aabb {
+            min: Vec3A::splat(-2.0),
+            max: Vec3A::splat(-1.0),
+        };
+        assert!(corner.in_frustum(&planes));
+        let outside = Aabb {
+            min: Vec3A::new(1.01, 0.0, 0.0),
+            max: Vec3A::new(2.0, 0.5, 0.5),
+        };
+        assert!(!outside.in_frustum(&planes));
+        let inside = Aabb {
+            min: Vec3A::splat(-0.5),
+            max: Vec3A::splat(0.5),
+        };
+        assert!(inside.in_frustum(&planes));
+    }
 
-            self.input_state.ao_level = self.input_state.ao_level.min(NUM_AO_TEXTURES - 1);
+    // NOTE: every generated triangle winds ccw seen from outside, checked against the
+    // corner normals the generators write
+    fn assert_outward_winding(mesh: &CpuMesh) {
+        mesh.validate().unwrap();
+        for (tri_i, tri) in mesh.indices.chunks_exact(3).enumerate() {
+            let [a, b, c] = [0, 1, 2].map(|i| mesh.vertices[tri[i]]);
+            let face_normal = (b - a).cross(c - a);
+            assert!(face_normal.length() > 0.0, "degenerate triangle {tri_i}");
+            let normal = mesh.corner_normal(3 * tri_i).unwrap();
+            assert!(
+                face_normal.dot(normal) > 0.0,
+                "triangle {tri_i} winds inwards"
+            );
+        }
+    }
 
-            for key in self.retained_input.just_pressed_keys.iter() {
-                match key {
-                    winit::keyboard::KeyCode::KeyB => {
-                        self.input_state.use_blur = !self.input_state.use_blur;
+    #[test]
+    fn plane_mesh_counts() {
+        for n in [1, 2, 7] {
+            let plane = plane_mesh(Vec2::new(4.0, 2.0), n);
+            let n = n as usize;
+            assert_eq!(plane.vertices.len(), (n + 1) * (n + 1));
+            assert_eq!(plane.indices.len(), 6 * n * n);
+            assert_outward_winding(&plane);
+        }
+        // NOTE: 0 subdivisions is clamped to a single quad
+        assert_eq!(plane_mesh(Vec2::ONE, 0).indices.len(), 6);
+    }
 
-                        dbg!(self.input_state.use_blur);
-                    }
-                    winit::keyboard::KeyCode::KeyZ => {
-                        self.camera.save_state();
-                    }
-                    winit::keyboard::KeyCode::KeyY => {
-                        self.camera.load_state();
-                    }
-                    winit::keyboard::KeyCode::KeyR => {
-                        self.camera.reset();
-                    }
+    #[test]
+    fn box_mesh_counts() {
+        let cube = box_mesh(Vec3A::new(1.0, 2.0, 3.0), Vec3A::new(1.0, 2.0, 0.5));
+        assert_eq!(cube.vertices.len(), 24);
+        assert_eq!(cube.indices.len(), 36);
+        assert_outward_winding(&cube);
+    }
 
-                    winit::keyboard::KeyCode::ArrowLeft => {
-                        let mut i = self.mesh_to_draw;
-                        let n = self.meshes.len();
-                        i += n;
-                        i -= 1;
-                        i %= n;
-                        self.mesh_to_draw = i;
-                    }
-                    winit::keyboard::KeyCode::ArrowRight => {
-                        let mut i = self.mesh_to_draw;
-                        let n = self.meshes.len();
-                        i += 1;
-                        i %= n;
-                        self.mesh_to_draw = i;
-                    }
+    #[test]
+    fn uv_sphere_mesh_counts() {
+        for (segments, rings) in [(3, 2), (8, 4), (32, 16)] {
+            let sphere = uv_sphere_mesh(Vec3A::ZERO, 1.0, segments, rings);
+            let (segments, rings) = (segments as usize, rings as usize);
+            assert_eq!(sphere.vertices.len(), (rings + 1) * (segments + 1));
+            assert_eq!(sphere.indices.len(), 6 * segments * (rings - 1));
+            assert_outward_winding(&sphere);
+        }
+    }
 
-                    _ => {}
-                }
-            }
+    // NOTE: sign of the dot between each triangle's winding normal and its first corner
+    // normal, all positive for a mesh whose normals face out of its front faces
+    fn winding_agrees(mesh: &CpuMesh) -> Vec<bool> {
+        mesh.indices
+            .chunks_exact(3)
+            .enumerate()
+            .map(|(tri_i, tri)| {
+                let [a, b, c] = [0, 1, 2].map(|i| mesh.vertices[tri[i]]);
+                let normal = mesh.corner_normal(3 * tri_i).unwrap();
+                (b - a).cross(c - a).dot(normal) > 0.0
+            })
+            .collect()
+    }
+
+    // NOTE: mirroring positions and normals along an axis turns the winding inside out,
+    // flip_winding has to bring it back while every corner keeps its uv and normal
+    #[test]
+    fn flip_winding_after_axis_flip() {
+        let mut mesh = box_mesh(Vec3A::ZERO, Vec3A::new(1.0, 2.0, 3.0));
+        assert!(winding_agrees(&mesh).iter().all(|agrees| *agrees));
+        let mirror = Vec3A::new(-1.0, 1.0, 1.0);
+        for v in mesh.vertices.iter_mut().chain(mesh.normals.iter_mut()) {
+            *v *= mirror;
+        }
+        assert!(winding_agrees(&mesh).iter().all(|agrees| !*agrees));
+
+        let corners = |mesh: &CpuMesh| {
+            (0..mesh.indices.len())
+                .map(|corner| {
+                    (
+                        mesh.indices[corner],
+                        mesh.corner_uv(corner),
+                        mesh.corner_normal(corner),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let before = corners(&mesh);
+        mesh.flip_winding();
+        mesh.validate().unwrap();
+        assert!(winding_agrees(&mesh).iter().all(|agrees| *agrees));
+        let after = corners(&mesh);
+        for (tri_before, tri_after) in before.chunks_exact(3).zip(after.chunks_exact(3)) {
+            assert_eq!(tri_after, [tri_before[0], tri_before[2], tri_before[1]]);
         }
-        self.retained_input.just_pressed_keys.clear();
+
+        // NOTE: normals generated after the flip face out of the new fronts too
+        apply_normal_options(
+            &mut mesh,
+            NormalOptions {
+                file: FileNormals::Recompute,
+                generated: NormalMode::Smooth {
+                    threshold_deg: 30.0,
+                },
+            },
+        );
+        assert!(winding_agrees(&mesh).iter().all(|agrees| *agrees));
+        mesh.flip_winding();
+        mesh.flip_winding();
+        assert!(winding_agrees(&mesh).iter().all(|agrees| *agrees));
     }
 
-    pub fn recreate_pipelines_if_required(&mut self) {
-        // let geometry_shader_source = std::fs::read_to_string().unwrap();
-        let shader_modified_time = last_time_shader_modified();
-        if self.pipelines.last_modified_shader_time != shader_modified_time {
-            self.pipelines.last_modified_shader_time = shader_modified_time;
+    fn triangle_mesh() -> CpuMesh {
+        CpuMesh {
+            name: "triangle".to_string(),
+            vertices: vec![Vec3A::ZERO, Vec3A::X, Vec3A::Y],
+            indices: vec![0, 1, 2],
+            uvs: vec![Vec2::ZERO, Vec2::X],
+            uv_indices: vec![Some(0), None, Some(1)],
+            normals: vec![Vec3A::Z],
+            normal_indices: vec![Some(0); 3],
+            colors: vec![Vec4::ONE; 3],
+            materials: vec![Material::new("a")],
+            material_runs: vec![MaterialRun {
+                first_index: 0,
+                material: 0,
+            }],
+            groups: vec![("g".to_string(), 0..3)],
+        }
+    }
 
-            if let Some(new_pipelines) = Pipelines::create_pipelines(&self.ctx, &self.surface) {
-                dbg!("recompiled all shaders");
-                self.pipelines = new_pipelines;
+    #[test]
+    fn validate_rejects_malformed_meshes() {
+        triangle_mesh().validate().unwrap();
+        let malformed: [(&str, fn(&mut CpuMesh)); 11] = [
+            ("partial triangle", |mesh| mesh.indices.push(0)),
+            ("position", |mesh| mesh.indices[1] = 3),
+            ("uv count", |mesh| {
+                mesh.uv_indices.pop();
+            }),
+            ("uv", |mesh| mesh.uv_indices[1] = Some(2)),
+            ("normal count", |mesh| mesh.normal_indices.push(None)),
+            ("normal", |mesh| mesh.normal_indices[2] = Some(1)),
+            ("colors", |mesh| {
+                mesh.colors.pop();
+            }),
+            ("material", |mesh| mesh.material_runs[0].material = 1),
+            ("material run", |mesh| mesh.material_runs[0].first_index = 4),
+            ("group end", |mesh| mesh.groups[0].1 = 0..4),
+            ("group order", |mesh| mesh.groups[0].1 = 2..1),
+        ];
+        for (what, break_mesh) in malformed {
+            let mut mesh = triangle_mesh();
+            break_mesh(&mut mesh);
+            assert!(mesh.validate().is_err(), "{what} not caught");
+        }
+        // NOTE: optional parts left out entirely are fine
+        let mut mesh = triangle_mesh();
+        mesh.normal_indices.clear();
+        mesh.normals.clear();
+        mesh.colors.clear();
+        mesh.material_runs.clear();
+        mesh.groups.clear();
+        mesh.validate().unwrap();
+        CpuMesh::default().validate().unwrap();
+    }
+
+    // NOTE: random index buffers around the valid range, whatever validate accepts has to
+    // go through everything the upload does without panicking
+    #[test]
+    fn validate_fuzz_random_meshes() {
+        let mut rng = nanorand::WyRand::new_seed(0x95);
+        for _ in 0..2000 {
+            let num_vertices = rng.generate_range(0..6usize);
+            let num_uvs = rng.generate_range(0..4usize);
+            let num_normals = rng.generate_range(0..4usize);
+            let num_corners = rng.generate_range(0..13usize);
+            let mut index = |count: usize| rng.generate_range(0..count + 2);
+            let indices = (0..num_corners)
+                .map(|_| index(num_vertices))
+                .collect::<Vec<_>>();
+            let mut optional = |count: usize| {
+                let idx = index(count);
+                (idx <= count).then_some(idx)
+            };
+            let uv_indices = (0..num_corners).map(|_| optional(num_uvs)).collect();
+            let normal_indices = (0..num_corners).map(|_| optional(num_normals)).collect();
+            let mesh = CpuMesh {
+                name: "fuzz".to_string(),
+                vertices: (0..num_vertices)
+                    .map(|i| Vec3A::new(i as f32, (i * i) as f32, 1.0))
+                    .collect(),
+                indices,
+                uvs: vec![Vec2::ONE; num_uvs],
+                uv_indices,
+                normals: vec![Vec3A::Y; num_normals],
+                normal_indices,
+                ..Default::default()
+            };
+            if mesh.validate().is_err() {
+                continue;
             }
+            let (vertices, indices) = build_indexed_mesh(&mesh);
+            assert!(indices.iter().all(|idx| (*idx as usize) < vertices.len()));
+            compute_smooth_normals(&mesh.vertices, &mesh.indices, 45.0);
+            Bvh::new(&mesh.vertices, &mesh.indices);
+            extract_mesh_ranges(&mesh, &[0..mesh.indices.len()])
+                .validate()
+                .unwrap();
         }
     }
 
-    // pub fn init_compute_stuff(&mut self) {
+    // NOTE: random lines of obj keywords and numbers, any mesh the parser returns has to
+    // pass validate since obj meshes skip the separate check in practice
+    #[test]
+    fn validate_fuzz_parsed_obj() {
+        let tokens = [
+            "v", "vt", "vn", "f", "o", "g", "usemtl", "1", "2", "-1", "-3", "0", "1/1", "2//1",
+            "1/1/1", "3/-1/2", "//", "/", "x", "0.5", "1e3", "nan", "#",
+        ];
+        let mut rng = nanorand::WyRand::new_seed(0x95f);
+        for _ in 0..2000 {
+            let mut data = String::new();
+            for _ in 0..rng.generate_range(0..12usize) {
+                for _ in 0..rng.generate_range(1..6usize) {
+                    data.push_str(tokens[rng.generate_range(0..tokens.len())]);
+                    data.push(' ');
+                }
+                data.push('\n');
+            }
+            if let Ok(mesh) = parse_obj_file_from_reader(data.as_bytes()) {
+                if let Err(err) = mesh.validate() {
+                    panic!("{err} for\n{data}");
+                }
+            }
+        }
+    }
 
-    //     let shader =
-    //     let compute = self.ctx.create_compute_pipeline(gpu::ComputePipelineDesc {
-    //         name: "compute",
-    //         data_layouts: &[],
-    //         compute: ,
-    //     })
+    const PLY_VERTICES: [([f32; 3], [f32; 3], [u8; 3]); 5] = [
+        ([0.0, 0.0, 0.0], [0.0, 0.0, 1.0], [255, 0, 0]),
+        ([1.0, 0.0, 0.0], [0.0, 0.0, 2.0], [0, 255, 0]),
+        ([1.0, 1.0, 0.0], [0.0, 0.0, 1.0], [0, 0, 255]),
+        ([0.0, 1.0, 0.0], [0.0, 0.0, 1.0], [255, 255, 255]),
+        ([0.5, 0.5, 1.0], [0.0, 1.0, 0.0], [51, 102, 153]),
+    ];
+    const PLY_FACES: [&[i32]; 2] = [&[0, 1, 2, 3], &[3, 2, 4]];
+
+    fn ply_header(format: &str) -> String {
+        format!(
+            "ply\nformat {format} 1.0\ncomment fixture\nelement vertex 5\n\
+            property float x\nproperty float y\nproperty float z\n\
+            property float nx\nproperty float ny\nproperty float nz\n\
+            property uchar red\nproperty uchar green\nproperty uchar blue\n\
+            element face 2\nproperty list uchar int vertex_indices\nend_header\n"
+        )
+    }
 
-    // }
-}
+    fn ascii_ply(faces: &[&[i32]]) -> Vec<u8> {
+        let mut text = ply_header("ascii");
+        for (pos, normal, color) in PLY_VERTICES {
+            let values = pos.iter().chain(normal.iter()).map(|v| v.to_string());
+            let values = values.chain(color.iter().map(|c| c.to_string()));
+            text.push_str(&values.collect::<Vec<_>>().join(" "));
+            text.push('\n');
+        }
+        for face in faces {
+            text.push_str(&face.len().to_string());
+            for idx in face.iter() {
+                text.push_str(&format!(" {idx}"));
+            }
+            text.push('\n');
+        }
+        text.into_bytes()
+    }
 
-impl Camera {
-    pub fn view(&self) -> glam::Mat4 {
-        let rot = self.rot_quat();
-        let pos = Vec3::from_array(self.pos.to_array());
-        let view = Mat4::from_scale_rotation_translation(Vec3A::ONE.into(), rot, pos).inverse();
-        view
+    fn binary_ply(faces: &[&[i32]]) -> Vec<u8> {
+        let mut bytes = ply_header("binary_little_endian").into_bytes();
+        for (pos, normal, color) in PLY_VERTICES {
+            for v in pos.iter().chain(normal.iter()) {
+                bytes.extend_from_slice(&v.to_le_bytes());
+            }
+            bytes.extend_from_slice(&color);
+        }
+        for face in faces {
+            bytes.push(face.len() as u8);
+            for idx in face.iter() {
+                bytes.extend_from_slice(&idx.to_le_bytes());
+            }
+        }
+        bytes
     }
 
-    pub fn view_rot_only(&self) -> glam::Mat4 {
-        let rot = self.rot_quat();
-        let mat = glam::Mat4::from_rotation_translation(rot, Vec3::ZERO).inverse();
-        mat
+    #[test]
+    fn ply_ascii_and_binary_parse_the_same() {
+        let ascii = parse_ply_from_reader(ascii_ply(&PLY_FACES).as_slice()).unwrap();
+        let binary = parse_ply_from_reader(binary_ply(&PLY_FACES).as_slice()).unwrap();
+        for mesh in [&ascii, &binary] {
+            mesh.validate().unwrap();
+            assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3, 3, 2, 4]);
+            assert_eq!(mesh.vertices.len(), 5);
+            assert_eq!(mesh.normals[1], Vec3A::Z);
+            assert_eq!(mesh.colors[4], Vec4::new(0.2, 0.4, 0.6, 1.0));
+        }
+        assert_eq!(ascii.vertices, binary.vertices);
+        assert_eq!(ascii.normals, binary.normals);
+        assert_eq!(ascii.normal_indices, binary.normal_indices);
+        assert_eq!(ascii.colors, binary.colors);
+        assert_eq!(ascii.uv_indices, binary.uv_indices);
     }
 
-    pub fn rot_quat(&self) -> glam::Quat {
-        let rot_x = Quat::from_axis_angle(Vec3::X, self.pitch);
-        let rot_y = Quat::from_axis_angle(Vec3::Y, self.yaw);
-        let rot = rot_y * rot_x;
-        rot
+    #[test]
+    fn ply_rejects_negative_and_fractional_indices() {
+        let negative: [&[i32]; 2] = [&[0, 1, 2, 3], &[3, -1, 4]];
+        for data in [ascii_ply(&negative), binary_ply(&negative)] {
+            let err = parse_ply_from_reader(data.as_slice()).unwrap_err();
+            assert!(
+                matches!(err, PlyError::NonIntegerIndex { value } if value == -1.0),
+                "{err:?}"
+            );
+        }
+        let fractional = String::from_utf8(ascii_ply(&PLY_FACES))
+            .unwrap()
+            .replace("3 3 2 4", "3 3 2.5 4");
+        let err = parse_ply_from_reader(fractional.as_bytes()).unwrap_err();
+        assert!(
+            matches!(err, PlyError::NonIntegerIndex { value } if value == 2.5),
+            "{err:?}"
+        );
+        let out_of_range: [&[i32]; 1] = [&[0, 1, 5]];
+        let data = String::from_utf8(ascii_ply(&out_of_range))
+            .unwrap()
+            .replace("element face 2", "element face 1");
+        let err = parse_ply_from_reader(data.as_bytes()).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                PlyError::BadIndex {
+                    index: 5,
+                    num_vertices: 5
+                }
+            ),
+            "{err:?}"
+        );
+    }
+
+    fn random_vec(rng: &mut nanorand::WyRand, scale: f32) -> Vec3A {
+        Vec3A::new(
+            rng.generate::<f32>() - 0.5,
+            rng.generate::<f32>() - 0.5,
+            rng.generate::<f32>() - 0.5,
+        ) * 2.0
+            * scale
     }
 
-    pub fn projection(&self) -> glam::Mat4 {
-        glam::Mat4::perspective_rh(self.vfov_rad, self.aspect, 0.001, 100.0)
+    // NOTE: a ray through a shared edge hits both triangles at the same t, either one is
+    // a correct answer so only t has to match exactly
+    fn assert_same_hit(ray: &Ray, mesh: &CpuMesh, bvh: &Bvh) {
+        let indices = mesh.indices.iter().map(|i| *i as u32).collect::<Vec<_>>();
+        let expected = ray_mesh_intersect(ray, &mesh.vertices, &indices);
+        match (bvh.intersect(ray), expected) {
+            (None, None) => {}
+            (Some((tri_i, t)), Some((_, expected_t))) => {
+                assert!((t - expected_t).abs() <= 1e-5 * expected_t.max(1.0));
+                let tri = &mesh.indices[3 * tri_i..3 * tri_i + 3];
+                let corners = [0, 1, 2].map(|i| mesh.vertices[tri[i]]);
+                assert_eq!(ray_triangle_intersect(ray, corners), Some(t));
+            }
+            (hit, expected) => panic!("bvh {hit:?} != brute force {expected:?} for {ray:?}"),
+        }
     }
 
-    pub fn default_from_aspect(aspect: f32) -> Self {
-        Self {
-            pos: Vec3A::ZERO,
-            yaw: 0.0,
-            pitch: 0.0,
-            // vfov_rad: TAU / 4.0,
-            vfov_rad: 70.0_f32.to_radians(),
-            aspect,
+    fn bvh_test_mesh() -> CpuMesh {
+        let mut mesh = plane_mesh(Vec2::splat(8.0), 8);
+        append_mesh(
+            &mut mesh,
+            &uv_sphere_mesh(Vec3A::new(0.0, 1.0, 0.0), 1.0, 16, 8),
+        );
+        for i in 0..4 {
+            let center = Vec3A::new(i as f32 - 1.5, 0.5, 2.5);
+            append_mesh(&mut mesh, &box_mesh(center, Vec3A::new(0.4, 1.0, 0.4)));
         }
+        mesh
     }
 
-    pub fn vp(&self) -> glam::Mat4 {
-        let v = self.view();
-        let p = self.projection();
-        // dbg!(v);
-        p * v
+    #[test]
+    fn bvh_matches_brute_force() {
+        let mesh = bvh_test_mesh();
+        let bvh = Bvh::new(&mesh.vertices, &mesh.indices);
+        assert!(bvh.nodes.len() > 1);
+        assert_eq!(bvh.triangles.len(), mesh.indices.len() / 3);
+        let mut rng = nanorand::WyRand::new_seed(0x100);
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let ray = Ray {
+                origin: random_vec(&mut rng, 6.0),
+                direction: random_vec(&mut rng, 1.0).normalize_or(Vec3A::Y),
+            };
+            hits += bvh.intersect(&ray).is_some() as usize;
+            assert_same_hit(&ray, &mesh, &bvh);
+        }
+        // NOTE: so the comparison isn't only between misses
+        assert!(hits > 200, "{hits} hits");
     }
 
-    pub fn right_forward_up(&self) -> [Vec3A; 3] {
-        let v = self.view();
-        let rot = v.to_scale_rotation_translation().1.inverse();
+    // NOTE: every node of a y = 0 plane has a zero height aabb, the slab test still has to
+    // find the triangles in it
+    #[test]
+    fn bvh_flat_plane() {
+        let mesh = plane_mesh(Vec2::splat(4.0), 16);
+        let bvh = Bvh::new(&mesh.vertices, &mesh.indices);
+        assert!(bvh.nodes.len() > 1);
+        for node in bvh.nodes.iter() {
+            assert_eq!(node.aabb_min.y, 0.0);
+            assert_eq!(node.aabb_max.y, 0.0);
+        }
+        let root = &bvh.nodes[0];
+        assert_eq!(root.aabb_min, Vec3A::new(-2.0, 0.0, -2.0));
+        assert_eq!(root.aabb_max, Vec3A::new(2.0, 0.0, 2.0));
+
+        let mut rng = nanorand::WyRand::new_seed(0x101);
+        for _ in 0..500 {
+            let target = random_vec(&mut rng, 2.5) * Vec3A::new(1.0, 0.0, 1.0);
+            let origin = target + Vec3A::new(0.0, 3.0, 0.0) + random_vec(&mut rng, 1.0);
+            let ray = Ray {
+                origin,
+                direction: (target - origin).normalize(),
+            };
+            assert_same_hit(&ray, &mesh, &bvh);
+        }
+        let down = Ray {
+            origin: Vec3A::new(0.3, 1.0, -0.7),
+            direction: -Vec3A::Y,
+        };
+        let (_, t) = bvh.intersect(&down).unwrap();
+        assert!((t - 1.0).abs() < 1e-6);
+    }
 
-        let r = rot * Vec3A::X;
-        let f = rot * -Vec3A::Z;
-        let u = rot * Vec3A::Y;
+    // NOTE: the slab test gives t = 0 for an origin inside a node, the node still has to
+    // be visited and the hit is on the far side of the mesh
+    #[test]
+    fn bvh_ray_starting_inside_node() {
+        let mesh = uv_sphere_mesh(Vec3A::ZERO, 2.0, 24, 12);
+        let bvh = Bvh::new(&mesh.vertices, &mesh.indices);
+        let mut rng = nanorand::WyRand::new_seed(0x102);
+        for _ in 0..500 {
+            let ray = Ray {
+                origin: random_vec(&mut rng, 0.5),
+                direction: random_vec(&mut rng, 1.0).normalize_or(Vec3A::X),
+            };
+            let root = &bvh.nodes[0];
+            assert_eq!(
+                ray_aabb_intersect(&ray, root.aabb_min, root.aabb_max),
+                Some(0.0)
+            );
+            let (_, t) = bvh
+                .intersect(&ray)
+                .expect("ray from inside the sphere has to hit");
+            // NOTE: the tessellated sphere is a bit inside the true one
+            let distance = ray.at(t).length();
+            assert!(distance > 1.9 && distance <= 2.0 + 1e-4, "{distance}");
+            assert_same_hit(&ray, &mesh, &bvh);
+        }
+    }
 
-        [r, f, u]
+    // NOTE: everything outside printable ascii as \u escapes, utf16 pairs above the bmp
+    fn json_escape(text: &str) -> String {
+        let mut out = String::from("\"");
+        for ch in text.chars() {
+            match ch {
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                '\n' => out.push_str("\\n"),
+                '\t' => out.push_str("\\t"),
+                ' '..='~' => out.push(ch),
+                _ => {
+                    let mut units = [0u16; 2];
+                    for unit in ch.encode_utf16(&mut units) {
+                        out.push_str(&format!("\\u{unit:04x}"));
+                    }
+                }
+            }
+        }
+        out.push('"');
+        out
     }
-    pub fn save_state(&self) {
-        let path = std::path::Path::new("src/assets/cam/cam.txt");
-        let Ok(file) = std::fs::File::create(path) else {
-            dbg!("coulf not write cam state");
-            return;
-        };
-        let mut w = std::io::BufWriter::new(file);
 
-        let _ = w.write_fmt(format_args!(" {}", self.pos.x));
-        let _ = w.write_fmt(format_args!(" {}", self.pos.y));
-        let _ = w.write_fmt(format_args!(" {}", self.pos.z));
+    #[test]
+    fn json_string_escapes_round_trip() {
+        for text in [
+            "plain",
+            "quote \" backslash \\ slash /",
+            "tab\tnew\nline \r \u{8} \u{c}",
+            "caf\u{e9} \u{4e2d}\u{6587}",
+            "emoji \u{1f600} and \u{10ffff}",
+            "",
+        ] {
+            let parsed = parse_json(&json_escape(text)).unwrap();
+            assert_eq!(parsed.as_str(), Some(text));
+        }
+        let parsed = parse_json(r#"{"key": ["\/\b\f\r", "é"]}"#).unwrap();
+        let items = parsed.get("key").unwrap().items();
+        assert_eq!(items[0].as_str(), Some("/\u{8}\u{c}\r"));
+        assert_eq!(items[1].as_str(), Some("\u{e9}"));
+        assert!(parse_json(r#""\x""#).is_err());
+        assert!(parse_json(r#""\u12""#).is_err());
+        assert!(parse_json(r#""open"#).is_err());
+    }
 
-        let _ = w.write_fmt(format_args!(" {}", self.yaw));
-        let _ = w.write_fmt(format_args!(" {}", self.pitch));
-        let _ = w.write_fmt(format_args!(" {}", self.vfov_rad));
-        let _ = w.write_fmt(format_args!(" {}", self.aspect));
-        dbg!("saved cam state to file");
-        // self.
+    #[test]
+    fn json_depth_limit() {
+        let nested = |depth: usize| "[".repeat(depth) + &"]".repeat(depth);
+        assert!(parse_json(&nested(JSON_MAX_DEPTH)).is_ok());
+        assert_eq!(
+            parse_json(&nested(JSON_MAX_DEPTH + 1)).unwrap_err(),
+            "json nested too deeply"
+        );
+        let objects = r#"{"a":"#.repeat(100_000) + "1" + &"}".repeat(100_000);
+        assert!(parse_json(&objects).is_err());
     }
-    pub fn load_state(&mut self) {
-        let path = std::path::Path::new("src/assets/cam/cam.txt");
-        let Ok(file) = std::fs::File::open(path) else {
-            dbg!("coulf not read cam state");
-            return;
-        };
-        let reader = std::io::BufReader::new(file);
-        let Some(Ok(lines)) = reader.lines().next() else {
-            dbg!("coulf not load cam state");
-            return;
-        };
-        let mut args = lines.split_whitespace();
 
-        self.pos.x = args.next().unwrap().parse().unwrap();
-        self.pos.y = args.next().unwrap().parse().unwrap();
-        self.pos.z = args.next().unwrap().parse().unwrap();
+    #[test]
+    fn json_surrogates() {
+        let parse = |text: &str| parse_json(text).unwrap().as_str().unwrap().to_string();
+        assert_eq!(parse(r#""😀""#), "\u{1f600}");
+        assert_eq!(parse(r#""𝄞""#), "\u{1d11e}");
+        // NOTE: unpaired halves become U+FFFD without eating what follows
+        assert_eq!(parse(r#""\ud83d""#), "\u{fffd}");
+        assert_eq!(parse(r#""\ude00x""#), "\u{fffd}x");
+        assert_eq!(parse(r#""\ud83dA""#), "\u{fffd}A");
+        assert_eq!(parse(r#""\ud83d\u0041""#), "\u{fffd}A");
+        assert_eq!(parse(r#""\ud83d😀""#), "\u{fffd}\u{1f600}");
+    }
 
-        self.yaw = args.next().unwrap().parse().unwrap();
-        self.pitch = args.next().unwrap().parse().unwrap();
-        self.vfov_rad = args.next().unwrap().parse().unwrap();
-        self.aspect = args.next().unwrap().parse().unwrap();
+    fn encode_base64(bytes: &[u8]) -> String {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut out = String::new();
+        for chunk in bytes.chunks(3) {
+            let b = [0, 1, 2].map(|i| chunk.get(i).copied().unwrap_or(0) as u32);
+            let n = (b[0] << 16) | (b[1] << 8) | b[2];
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    out.push(ALPHABET[((n >> (18 - 6 * i)) & 63) as usize] as char);
+                } else {
+                    out.push('=');
+                }
+            }
+        }
+        out
     }
 
-    pub fn reset(&mut self) {
-        *self = Self::default_from_aspect(self.aspect);
+    #[test]
+    fn base64_round_trip() {
+        let bytes = (0..=255u8).chain([0, 1, 2, 3]).collect::<Vec<_>>();
+        for len in [0, 1, 2, 3, 4, 5, bytes.len()] {
+            let encoded = encode_base64(&bytes[..len]);
+            assert_eq!(decode_base64(&encoded).unwrap(), &bytes[..len]);
+        }
+        // NOTE: the url safe alphabet and line breaks decode too
+        assert_eq!(decode_base64("-_8=").unwrap(), vec![0xfb, 0xff]);
+        assert_eq!(decode_base64("aGVs\nbG8=").unwrap(), b"hello");
+        assert!(decode_base64("a*").is_err());
     }
-}
-pub fn load_sponza() -> CpuMesh {
-    dbg!("loading sponza");
-    let path = std::path::Path::new("src/assets/sponza/sponza.obj");
-    let mesh = parse_obj_file(path);
-    // let vertices = turn_mesh_into_pure_vertex_list(mesh);
 
-    mesh
-    // vertices
-}
+    // NOTE: one triangle, f32 positions then u16 indices padded to 4 bytes
+    fn gltf_triangle_buffer() -> Vec<u8> {
+        let mut buffer = vec![];
+        for v in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            buffer.extend_from_slice(&v.to_le_bytes());
+        }
+        for idx in [0u16, 2, 1, 0] {
+            buffer.extend_from_slice(&idx.to_le_bytes());
+        }
+        buffer
+    }
 
-pub fn load_cathedral() -> CpuMesh {
-    dbg!("loading sibenik cathedral");
-    let path = std::path::Path::new("src/assets/sibenik_cathedral/sibenik.obj");
-    let mesh = parse_obj_file(path);
-    // let vertices = turn_mesh_into_pure_vertex_list(mesh);
+    // NOTE: buffer is the json of buffer 0, the material name has escapes in it
+    fn gltf_triangle_json(buffer: &str) -> String {
+        format!(
+            r#"{{
+                "asset": {{"version": "2.0"}},
+                "scene": 0,
+                "scenes": [{{"nodes": [0]}}],
+                "nodes": [{{"mesh": 0, "translation": [0, 0, -1]}}],
+                "meshes": [{{"primitives": [
+                    {{"attributes": {{"POSITION": 0}}, "indices": 1, "material": 0}}
+                ]}}],
+                "materials": [{{
+                    "name": "café 😀 \"a\"",
+                    "pbrMetallicRoughness": {{"baseColorFactor": [1, 0.5, 0.25, 1]}}
+                }}],
+                "accessors": [
+                    {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"}},
+                    {{"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}}
+                ],
+                "bufferViews": [
+                    {{"buffer": 0, "byteOffset": 0, "byteLength": 36}},
+                    {{"buffer": 0, "byteOffset": 36, "byteLength": 6}}
+                ],
+                "buffers": [{buffer}]
+            }}"#
+        )
+    }
 
-    // vertices
-    mesh
-}
+    fn glb(json: &str, bin: &[u8]) -> Vec<u8> {
+        let mut json = json.as_bytes().to_vec();
+        json.resize(json.len().next_multiple_of(4), b' ');
+        let mut bin = bin.to_vec();
+        bin.resize(bin.len().next_multiple_of(4), 0);
+        let total = 12 + 8 + json.len() + 8 + bin.len();
+        let mut bytes = vec![];
+        for word in [
+            GLB_MAGIC,
+            2,
+            total as u32,
+            json.len() as u32,
+            GLB_CHUNK_JSON,
+        ] {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        bytes.extend_from_slice(&json);
+        for word in [bin.len() as u32, GLB_CHUNK_BIN] {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        bytes.extend_from_slice(&bin);
+        bytes
+    }
 
-// pub fn load_
+    fn assert_gltf_triangle(mesh: &CpuMesh) {
+        mesh.validate().unwrap();
+        assert_eq!(
+            mesh.vertices,
+            vec![
+                Vec3A::new(0.0, 0.0, -1.0),
+                Vec3A::new(1.0, 0.0, -1.0),
+                Vec3A::new(0.0, 1.0, -1.0),
+            ]
+        );
+        assert_eq!(mesh.indices, vec![0, 2, 1]);
+        assert_eq!(mesh.materials.len(), 1);
+        assert_eq!(mesh.materials[0].name, "caf\u{e9} \u{1f600} \"a\"");
+        assert_eq!(mesh.materials[0].diffuse, Vec3A::new(1.0, 0.5, 0.25));
+        assert_eq!(mesh.material_runs.len(), 1);
+        assert!(mesh.normals.is_empty());
+    }
 
-pub fn turn_mesh_into_pure_vertex_list(mesh: CpuMesh) -> Vec<Vertex> {
-    let mut vertices = vec![];
+    #[test]
+    fn gltf_data_uri_buffer() {
+        let buffer = gltf_triangle_buffer();
+        let uri = format!(
+            r#"{{"byteLength": {}, "uri": "data:application/octet-stream;base64,{}"}}"#,
+            buffer.len(),
+            encode_base64(&buffer)
+        );
+        let json = gltf_triangle_json(&uri);
+        let mesh = parse_gltf_from_bytes(json.as_bytes(), std::path::Path::new("")).unwrap();
+        assert_gltf_triangle(&mesh);
 
-    for idxs in mesh.indices.chunks_exact(3) {
-        let i0 = idxs[0];
-        let i1 = idxs[1];
-        let i2 = idxs[2];
+        let short = gltf_triangle_json(&uri.replace(r#""byteLength": 44"#, r#""byteLength": 48"#));
+        assert!(parse_gltf_from_bytes(short.as_bytes(), std::path::Path::new("")).is_err());
+    }
 
-        let v0 = mesh.vertices[i0];
-        let v1 = mesh.vertices[i1];
-        let v2 = mesh.vertices[i2];
-        let n = (v1 - v0).cross(v2 - v0).normalize();
+    #[test]
+    fn gltf_tiny_glb() {
+        let buffer = gltf_triangle_buffer();
+        let json = gltf_triangle_json(&format!(r#"{{"byteLength": {}}}"#, buffer.len()));
+        let bytes = glb(&json, &buffer);
+        let (glb_json, bin) = split_glb(&bytes).unwrap();
+        assert_eq!(glb_json.trim_end(), json);
+        assert_eq!(bin.unwrap(), buffer);
+        let mesh = parse_gltf_from_bytes(&bytes, std::path::Path::new("")).unwrap();
+        assert_gltf_triangle(&mesh);
+
+        assert!(split_glb(&bytes[..bytes.len() - 4]).is_err());
+        assert!(split_glb(b"glTF").is_err());
+    }
 
-        for pos in [v0, v1, v2] {
-            let new_vertex = Vertex {
-                ws_pos: pos.to_array(),
-                ws_normal: n.to_array(),
-            };
-            vertices.push(new_vertex);
+    // NOTE: counts far past the buffer have to fail before anything is allocated
+    #[test]
+    fn gltf_accessor_overflow() {
+        let buffers = vec![gltf_triangle_buffer()];
+        let read = |accessor: &str, view: &str| {
+            let doc = parse_json(&format!(
+                r#"{{"accessors": [{accessor}], "bufferViews": [{view}]}}"#
+            ))
+            .unwrap();
+            read_gltf_accessor(&doc, &buffers, 0)
+        };
+        let view = r#"{"buffer": 0, "byteLength": 36}"#;
+
+        let vec3s = r#"{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"}"#;
+        let (values, components) = read(vec3s, view).unwrap();
+        assert_eq!(components, 3);
+        assert_eq!(values, vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+
+        for accessor in [
+            r#"{"componentType": 5126, "count": 1e19, "type": "MAT4"}"#,
+            r#"{"bufferView": 0, "componentType": 5126, "count": 1e19, "type": "VEC3"}"#,
+            r#"{"bufferView": 0, "componentType": 5126, "count": 1e12, "type": "VEC3"}"#,
+            r#"{"bufferView": 0, "componentType": 5126, "count": 4, "type": "VEC3"}"#,
+            r#"{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                "byteOffset": 1e19}"#,
+            r#"{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                "byteOffset": 12}"#,
+        ] {
+            assert!(read(accessor, view).is_err(), "{accessor}");
         }
+        // NOTE: 2 * stride overflows usize
+        let strided = r#"{"buffer": 0, "byteStride": 1e19}"#;
+        assert!(read(vec3s, strided).is_err());
+        let strided = r#"{"buffer": 0, "byteStride": 16, "byteOffset": 4}"#;
+        let two_vec3s = r#"{"bufferView": 0, "componentType": 5126, "count": 2, "type": "VEC3"}"#;
+        let (values, _) = read(two_vec3s, strided).unwrap();
+        assert_eq!(values, vec![0.0, 0.0, 1.0, 0.0, 0.0, 1.0]);
+        assert!(read(vec3s, strided).is_err());
     }
 
-    vertices
-}
-
-pub fn upload_vertices(vertices: Vec<Vertex>, ctx: &gpu::Context) -> Mesh {
-    let vertex_buf = ctx.create_buffer(gpu::BufferDesc {
-        name: "vertex buffer",
-        size: (vertices.len() * std::mem::size_of::<Vertex>()) as u64,
-        memory: gpu::Memory::Shared,
-    });
-    unsafe {
-        std::ptr::copy_nonoverlapping(
-            vertices.as_ptr(),
-            vertex_buf.data() as *mut Vertex,
-            vertices.len(),
-        );
+    // NOTE: a fresh directory per test under the system temp dir, for fixtures that have
+    // to be real files like an obj next to its mtl
+    fn fixture_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("mssao-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
     }
-    let mesh = Mesh {
-        vertex_buf: vertex_buf.into(),
-        index_buf: None,
-        num_vertices: vertices.len(),
-        num_indices: 0,
-    };
 
-    ctx.sync_buffer(vertex_buf);
-    mesh
-}
+    #[test]
+    fn obj_split_two_materials() {
+        let dir = fixture_dir("split-materials");
+        std::fs::write(
+            dir.join("two.mtl"),
+            "newmtl stone\nKd 0.5 0.5 0.5\nNs 10\n\nnewmtl curtain\nKd 0.8 0.1 0.1\n\
+             Ks 0.2 0.2 0.2\nmap_Kd curtain.png\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("two.obj"),
+            "mtllib two.mtl\nv 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 2 0 0\nv 2 1 0\n\
+             usemtl stone\nf 1 2 3\nusemtl curtain\nf 2 5 6\nf 2 6 3\nusemtl stone\nf 1 3 4\n",
+        )
+        .unwrap();
+        let (split, materials) = parse_obj_file_materials(dir.join("two.obj")).unwrap();
+
+        assert_eq!(materials.len(), 2);
+        assert_eq!(materials[0].name, "stone");
+        assert_eq!(materials[0].diffuse, Vec3A::splat(0.5));
+        assert_eq!(materials[1].name, "curtain");
+        assert_eq!(materials[1].specular, Vec3A::splat(0.2));
+        assert_eq!(
+            materials[1].diffuse_texture.as_deref(),
+            Some(dir.join("curtain.png").as_path())
+        );
 
-pub fn upload_mesh(ctx: &gpu::Context, mesh: CpuMesh) -> Mesh {
-    let CpuMesh { vertices, indices } = mesh;
+        assert_eq!(split.len(), 2);
+        let (stone, stone_id) = &split[0];
+        let (curtain, curtain_id) = &split[1];
+        assert_eq!((*stone_id, *curtain_id), (0, 1));
+        assert_eq!(stone.name, "two/stone");
+        for (mesh, id) in split.iter() {
+            mesh.validate().unwrap();
+            assert_eq!(mesh.materials.len(), 1);
+            assert_eq!(mesh.materials[0].name, materials[*id].name);
+            assert_eq!(mesh.material_runs.len(), 1);
+            assert_eq!(mesh.material_runs[0].first_index, 0);
+        }
+        // NOTE: the two stone runs end up in one mesh, each mesh keeps only its vertices
+        assert_eq!(stone.indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(stone.vertices.len(), 4);
+        assert_eq!(curtain.indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(
+            curtain.vertices,
+            vec![
+                Vec3A::X,
+                Vec3A::new(2.0, 0.0, 0.0),
+                Vec3A::new(2.0, 1.0, 0.0),
+                Vec3A::new(1.0, 1.0, 0.0)
+            ]
+        );
 
-    let normals = indices
-        .chunks(3)
-        .map(|idxs| {
-            let i0 = idxs[0];
-            let i1 = idxs[1];
-            let i2 = idxs[2];
-
-            let v0 = vertices[i0];
-            let v1 = vertices[i1];
-            let v2 = vertices[i2];
-            let n = (v1 - v0).cross(v2 - v0).normalize();
-            n
-        })
-        .collect::<Vec<_>>();
-    let gpu_vertices = vertices
-        .iter()
-        .enumerate()
-        .map(|(i, v)| Vertex {
-            ws_pos: v.to_array(),
-            ws_normal: normals[i / 3].to_array(),
-        })
-        .collect::<Vec<_>>();
-    let vertex_buf = ctx.create_buffer(gpu::BufferDesc {
-        name: "vertex buffer",
-        size: (vertices.len() * std::mem::size_of::<Vertex>()) as u64,
-        memory: gpu::Memory::Shared,
-    });
-    unsafe {
-        std::ptr::copy_nonoverlapping(
-            gpu_vertices.as_ptr(),
-            vertex_buf.data() as *mut Vertex,
-            vertices.len(),
+        // NOTE: faces before the first usemtl go to a default material at the end
+        let data: &[u8] = b"v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\nusemtl stone\nf 3 2 1\n";
+        let (split, materials) = split_mesh_materials(&parse_obj_file_from_reader(data).unwrap());
+        assert_eq!(materials.len(), 2);
+        assert_eq!(materials[1].name, "default");
+        assert_eq!(
+            split.iter().map(|(_, id)| *id).collect::<Vec<_>>(),
+            vec![1, 0]
         );
+        assert_eq!(split[0].0.materials[0].name, "default");
+        std::fs::remove_dir_all(dir).unwrap();
     }
-    let indices = indices.iter().map(|idx| *idx as u32).collect::<Vec<_>>();
-    let index_buf = ctx.create_buffer(gpu::BufferDesc {
-        name: "index buffer",
-        size: (indices.len() * std::mem::size_of::<u32>()) as u64,
-        memory: gpu::Memory::Shared,
-    });
 
-    unsafe {
-        std::ptr::copy_nonoverlapping(
-            indices.as_ptr(),
-            index_buf.data() as *mut u32,
-            indices.len(),
+    #[test]
+    fn gltf_meshes_per_material() {
+        let buffer = gltf_triangle_buffer();
+        let uri = format!(
+            r#"{{"byteLength": {}, "uri": "data:application/octet-stream;base64,{}"}}"#,
+            buffer.len(),
+            encode_base64(&buffer)
+        );
+        // NOTE: a second primitive without a material next to the first one
+        let json = gltf_triangle_json(&uri).replace(
+            r#""material": 0}"#,
+            r#""material": 0}, {"attributes": {"POSITION": 0}, "indices": 1}"#,
         );
+        let dir = fixture_dir("load-gltf");
+        let path = dir.join("two.gltf");
+        std::fs::write(&path, json).unwrap();
+        let meshes = load_gltf(&path).unwrap();
+        assert_eq!(meshes.len(), 2);
+        let names = meshes
+            .iter()
+            .map(|(_, material)| material.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["caf\u{e9} \u{1f600} \"a\"", "default"]);
+        for (mesh, material) in meshes.iter() {
+            mesh.validate().unwrap();
+            assert_eq!(mesh.indices.len(), 3);
+            assert_eq!(mesh.vertices.len(), 3);
+            assert_eq!(mesh.materials.len(), 1);
+            assert_eq!(mesh.materials[0].name, material.name);
+            assert_eq!(mesh.name, format!("two/{}", material.name));
+        }
+        assert_eq!(meshes[0].1.diffuse, Vec3A::new(1.0, 0.5, 0.25));
+        assert_eq!(meshes[1].1.diffuse, Vec3A::ONE);
+
+        std::fs::write(dir.join("broken.gltf"), "{").unwrap();
+        assert!(matches!(
+            load_gltf(dir.join("broken.gltf")),
+            Err(ObjError::Gltf { .. })
+        ));
+        std::fs::remove_dir_all(dir).unwrap();
     }
 
-    let mesh = Mesh {
-        vertex_buf: vertex_buf.into(),
-        index_buf: Some(index_buf.into()),
-        num_vertices: vertices.len(),
-        num_indices: indices.len(),
-    };
+    #[test]
+    fn settings_clamp_out_of_range_values() {
+        let dir = fixture_dir("settings");
+        let path = dir.join("settings.toml");
+        std::fs::write(
+            &path,
+            "ao_level = 99\nao.num_samples = 1000\nshadow.cascade_count = 0\n",
+        )
+        .unwrap();
+        let settings = Settings::load(path.to_str().unwrap());
+        assert_eq!(settings.ao_level, NUM_AO_TEXTURES - 1);
+        assert_eq!(settings.ao_settings.num_samples, MAX_AO_SAMPLES as u32);
+        assert_eq!(settings.shadow_settings.cascade_count, 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 
-    ctx.sync_buffer(vertex_buf);
-    ctx.sync_buffer(index_buf);
+    // NOTE: big enough for a lod, so every part of the entry is written and read back
+    #[test]
+    fn processed_cache_round_trip() {
+        let mesh = uv_sphere_mesh(Vec3A::ZERO, 1.0, 96, 64);
+        let loaded = LoadedMesh::new(mesh.clone(), 3);
+        assert!(loaded.lod.is_some());
+        let dir = fixture_dir("processed-cache");
+        let path = dir.join("sphere.bin");
+        write_processed_cache(&path, &loaded).unwrap();
+
+        let Ok(read) = read_processed_cache(&path, mesh.clone(), 3) else {
+            panic!("cache entry should read back");
+        };
+        let buffers = |mesh: &IndexedMesh| {
+            (
+                bytemuck::cast_slice::<_, u8>(&mesh.vertices).to_vec(),
+                mesh.indices.clone(),
+            )
+        };
+        assert_eq!(buffers(&read.mesh), buffers(&loaded.mesh));
+        assert_eq!(
+            buffers(read.lod.as_ref().unwrap()),
+            buffers(loaded.lod.as_ref().unwrap())
+        );
+        assert_eq!(read.bvh.nodes.len(), loaded.bvh.nodes.len());
+        assert_eq!(read.bvh.tri_ids, loaded.bvh.tri_ids);
+        assert_eq!(read.file, 3);
+
+        // NOTE: a truncated entry or one for a different mesh is a miss
+        let data = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &data[..data.len() - 1]).unwrap();
+        assert!(read_processed_cache(&path, mesh, 3).is_err());
+        std::fs::write(&path, &data).unwrap();
+        let other = uv_sphere_mesh(Vec3A::ZERO, 1.0, 8, 4);
+        assert!(read_processed_cache(&path, other, 3).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 
-    mesh
-}
+    #[test]
+    fn processed_cache_rejects_bad_bvh_links() {
+        let mesh = uv_sphere_mesh(Vec3A::ZERO, 1.0, 32, 16);
+        let mut loaded = LoadedMesh::new(mesh.clone(), 0);
+        let inner = (1..loaded.bvh.nodes.len())
+            .find(|i| loaded.bvh.nodes[*i].tri_count == 0)
+            .unwrap();
+        let node = loaded.bvh.nodes[inner];
+        let dir = fixture_dir("processed-cache-links");
+        let path = dir.join("sphere.bin");
+        // NOTE: a link back to the root, a child shared by both sides and one out of range
+        for (left, right) in [
+            (0, node.right),
+            (node.left, node.left),
+            (node.left, u32::MAX),
+        ] {
+            loaded.bvh.nodes[inner].left = left;
+            loaded.bvh.nodes[inner].right = right;
+            write_processed_cache(&path, &loaded).unwrap();
+            assert!(read_processed_cache(&path, mesh.clone(), 0).is_err());
+        }
+        loaded.bvh.nodes[inner] = node;
+        write_processed_cache(&path, &loaded).unwrap();
+        assert!(read_processed_cache(&path, mesh, 0).is_ok());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 
-pub fn parse_obj_file<P: AsRef<std::path::Path>>(path: P) -> CpuMesh {
-    let mut vertices = vec![];
-    let mut normals = vec![];
-    let mut indices = vec![];
-    // pub fn parse_obj_file<R: std::io::BufRead>(file: R) {
-    if let Ok(file) = std::fs::File::open(path) {
-        let mut reader = std::io::BufReader::new(file);
-        let mut lines = reader.lines();
-        while let Some(Ok(line)) = lines.next() {
-            if let Some((pre, rest)) = line.split_once(" ") {
-                match pre {
-                    "v" => {
-                        let mut v = Vec3A::ZERO;
-                        for (i, x) in rest.split_whitespace().enumerate() {
-                            if let Ok(x) = x.parse() {
-                                if i > 2 {
-                                    dbg!(&line);
-                                }
-                                v[i] = x;
-                            }
-                        }
-                        vertices.push(v);
-                    }
-                    "vn" => {
-                        let mut v = Vec3A::ZERO;
-                        for (i, x) in rest.split(" ").enumerate() {
-                            if let Ok(x) = x.parse() {
-                                v[i] = x;
-                            }
-                        }
-                        normals.push(v);
-                    }
-                    "f" => {
-                        let vals = rest.split(" ");
-                        let mut these_indices = vec![];
-                        for val in vals {
-                            if let Some((v_idx, uv_idx)) = val.split_once("/") {
-                                if let Ok(v_idx) = v_idx.parse::<usize>() {
-                                    // NOTE: obj uses 1-based indices
-                                    these_indices.push(v_idx - 1);
-                                }
-                            }
-                        }
-                        let n = these_indices.len();
-                        match n {
-                            3 => {
-                                indices.extend(these_indices);
-                            }
-                            4 => {
-                                indices.push(these_indices[0]);
-                                indices.push(these_indices[1]);
-                                indices.push(these_indices[2]);
-
-                                indices.push(these_indices[2]);
-                                indices.push(these_indices[3]);
-                                indices.push(these_indices[0]);
-                            }
-                            _ => {
-                                dbg!(format!("weird idx len {n}"));
-                            }
-                        }
-                    }
-                    _ => {}
-                }
+    // NOTE: cargo runs tests from the package root, the same working directory the shader
+    // paths expect
+    #[test]
+    fn shader_includes_resolve_once() {
+        for entry in std::fs::read_dir("src").unwrap() {
+            let path = entry.unwrap().path();
+            if !path.extension().is_some_and(|ext| ext == "wgsl") {
+                continue;
+            }
+            let source = read_shader_source(&path).unwrap();
+            assert!(!source.contains("// #include"), "{}", path.display());
+            for (file, declaration) in [
+                ("globals.wgsl", "struct Globals {"),
+                ("fullscreen.wgsl", "fn vs_fullscreen("),
+            ] {
+                let count = source.matches(declaration).count();
+                assert!(count <= 1, "{} has {file} {count} times", path.display());
             }
         }
-        // for line in reader.lines() {
-        //     let (a, rest)
-        //     if let Some
-        //     // dbg!(line);
-        // }
-        // while let Some(line) = file.read_line()
+        for path in [
+            "src/shader.wgsl",
+            LIGHT_SHADER_PATH,
+            TAA_SHADER_PATH,
+            TSSAO_SHADER_PATH,
+            NORMAL_DEBUG_SHADER_PATH,
+        ] {
+            let source = read_shader_source(path).unwrap();
+            assert_eq!(source.matches("struct Globals {").count(), 1, "{path}");
+        }
     }
 
-    // dbg!(vertices.len());
-    // dbg!(normals.len());
-    // dbg!(indices.len());
-
-    CpuMesh { vertices, indices }
-}
+    fn parse_wgsl(name: &str, source: &str) -> naga::Module {
+        naga::front::wgsl::parse_str(source)
+            .unwrap_or_else(|err| panic!("{name}: {}", err.emit_to_string(source)))
+    }
 
-fn main() {
-    let event_loop = winit::event_loop::EventLoop::new().unwrap();
-    let window_attributes = winit::window::Window::default_attributes()
-        .with_title("ssao")
-        // .with_inner_size(winit::dpi::PhysicalSize::new(1024, 1024))
-        // .with_inner_size(winit::dpi::PhysicalSize::new(512, 512))
-        // .with_inner_size(winit::dpi::PhysicalSize::new(2048,2048))
-        .with_fullscreen(Some(winit::window::Fullscreen::Borderless(None)))
-        // .with_inner_size(winit::dpi::PhysicalSize::new(2048,2048))
-        ;
+    #[test]
+    fn globals_layout_matches_wgsl() {
+        let source = read_geometry_shader_source(NormalEncoding::Full).unwrap();
+        let module = parse_wgsl(GEOMETRY_SHADER_PATH, &source);
+        let mut layouter = naga::proc::Layouter::default();
+        layouter.update(module.to_ctx()).unwrap();
+        let (members, span) = module
+            .types
+            .iter()
+            .find_map(|(_, ty)| match (&ty.name, &ty.inner) {
+                (Some(name), naga::TypeInner::Struct { members, span }) if name == "Globals" => {
+                    Some((members.clone(), *span))
+                }
+                _ => None,
+            })
+            .expect("no struct Globals in shader.wgsl");
+        assert_eq!(span as usize, std::mem::size_of::<Globals>());
+
+        // NOTE: every float gets a distinct value so a member read at the wrong offset shows up
+        let mut globals: Globals = bytemuck::Zeroable::zeroed();
+        let floats: &mut [f32] = bytemuck::cast_slice_mut(bytemuck::bytes_of_mut(&mut globals));
+        for (i, value) in floats.iter_mut().enumerate() {
+            *value = i as f32 + 1.0;
+        }
 
-    let window = event_loop.create_window(window_attributes).unwrap();
+        let fields: [(&str, usize, &[u8]); 12] = [
+            (
+                "mvp_transform",
+                std::mem::offset_of!(Globals, mvp_transform),
+                bytemuck::bytes_of(&globals.mvp_transform),
+            ),
+            (
+                "mv_transform",
+                std::mem::offset_of!(Globals, mv_transform),
+                bytemuck::bytes_of(&globals.mv_transform),
+            ),
+            (
+                "mv_rot",
+                std::mem::offset_of!(Globals, mv_rot),
+                bytemuck::bytes_of(&globals.mv_rot),
+            ),
+            (
+                "cam_pos",
+                std::mem::offset_of!(Globals, cam_pos),
+                bytemuck::bytes_of(&globals.cam_pos),
+            ),
+            (
+                "cam_dir",
+                std::mem::offset_of!(Globals, cam_dir),
+                bytemuck::bytes_of(&globals.cam_dir),
+            ),
+            (
+                "prev_vp",
+                std::mem::offset_of!(Globals, prev_vp),
+                bytemuck::bytes_of(&globals.prev_vp),
+            ),
+            (
+                "viewport_size",
+                std::mem::offset_of!(Globals, viewport_size),
+                bytemuck::bytes_of(&globals.viewport_size),
+            ),
+            (
+                "inv_viewport_size",
+                std::mem::offset_of!(Globals, inv_viewport_size),
+                bytemuck::bytes_of(&globals.inv_viewport_size),
+            ),
+            (
+                "inv_mvp_transform",
+                std::mem::offset_of!(Globals, inv_mvp_transform),
+                bytemuck::bytes_of(&globals.inv_mvp_transform),
+            ),
+            (
+                "far_depth",
+                std::mem::offset_of!(Globals, far_depth),
+                bytemuck::bytes_of(&globals.far_depth),
+            ),
+            (
+                "time_secs",
+                std::mem::offset_of!(Globals, time_secs),
+                bytemuck::bytes_of(&globals.time_secs),
+            ),
+            (
+                "delta_time_secs",
+                std::mem::offset_of!(Globals, delta_time_secs),
+                bytemuck::bytes_of(&globals.delta_time_secs),
+            ),
+        ];
+        assert_eq!(members.len(), fields.len());
+
+        let bytes = bytemuck::bytes_of(&globals);
+        for (member, (name, offset, expected)) in members.iter().zip(fields) {
+            assert_eq!(member.name.as_deref(), Some(name));
+            assert_eq!(member.offset as usize, offset, "{name}");
+            let size = layouter[member.ty].size as usize;
+            assert_eq!(size, expected.len(), "{name}");
+            let start = member.offset as usize;
+            assert_eq!(&bytes[start..start + size], expected, "{name}");
+        }
+    }
 
-    let mut state = State::new(&window);
+    // NOTE: the sources exactly as Pipelines::rebuild and create_light_pipeline build them,
+    // validated the way blade does, which leaves resource bindings to its own layouts
+    #[test]
+    fn shaders_validate() {
+        let mut sources = Vec::new();
+        for normal_encoding in [NormalEncoding::Octahedral, NormalEncoding::Full] {
+            let normal_encoding_source = read_normal_encoding_source(normal_encoding).unwrap();
+            sources.push((
+                format!("{GEOMETRY_SHADER_PATH} {normal_encoding:?}"),
+                read_geometry_shader_source(normal_encoding).unwrap(),
+            ));
+            for bits in 0..=PipelineKey::ALL.0 {
+                let key = PipelineKey(bits);
+                sources.push((
+                    format!("{LIGHT_SHADER_PATH} {normal_encoding:?} {key:?}"),
+                    read_light_shader_source(normal_encoding, key).unwrap(),
+                ));
+            }
+            for path in [NORMAL_DEBUG_SHADER_PATH, SSR_SHADER_PATH] {
+                sources.push((
+                    format!("{path} {normal_encoding:?}"),
+                    format!(
+                        "{normal_encoding_source}\n{}",
+                        read_shader_source(path).unwrap()
+                    ),
+                ));
+            }
+        }
+        for path in [
+            DEPTH_DEBUG_SHADER_PATH,
+            LOADING_SHADER_PATH,
+            TAA_SHADER_PATH,
+            DOF_SHADER_PATH,
+            TSSAO_SHADER_PATH,
+            SKYBOX_SHADER_PATH,
+            COMPUTE_TEST_SHADER_PATH,
+            HI_Z_SHADER_PATH,
+        ] {
+            sources.push((path.to_string(), read_shader_source(path).unwrap()));
+        }
 
-    event_loop
-        .run(|event, target| {
-            target.set_control_flow(winit::event_loop::ControlFlow::Poll);
-            match event {
-                winit::event::Event::AboutToWait => window.request_redraw(),
-                winit::event::Event::WindowEvent { event, .. } => match event {
-                    winit::event::WindowEvent::Resized(_) => {}
-                    winit::event::WindowEvent::KeyboardInput {
-                        event:
-                            winit::event::KeyEvent {
-                                physical_key: winit::keyboard::PhysicalKey::Code(key_code),
-                                state: key_state,
-                                ..
-                            },
-                        ..
-                    } => match key_state {
-                        winit::event::ElementState::Pressed => {
-                            if state.retained_input.held_keys.insert(key_code) {
-                                state.retained_input.just_pressed_keys.insert(key_code);
-                            }
-                        }
-                        winit::event::ElementState::Released => {
-                            state.retained_input.held_keys.remove(&key_code);
-                        }
-                    },
-                    winit::event::WindowEvent::CloseRequested => {
-                        dbg!("closing");
-                        target.exit();
-                    }
-                    winit::event::WindowEvent::RedrawRequested => {
-                        let now = std::time::SystemTime::now();
-                        if let Ok(delta) = now.duration_since(state.prev_time) {
-                            state.delta_time = delta.as_secs_f32();
-                        }
-                        state.prev_time = now;
-                        state.recreate_pipelines_if_required();
-                        state.handle_input();
-                        state.render();
-                    }
-                    _ => {}
-                },
-                _ => {}
+        for (name, source) in sources {
+            let module = parse_wgsl(&name, &source);
+            let mut validator = naga::valid::Validator::new(
+                naga::valid::ValidationFlags::all() ^ naga::valid::ValidationFlags::BINDINGS,
+                naga::valid::Capabilities::all(),
+            );
+            if let Err(err) = validator.validate(&module) {
+                panic!("{name}: {}", err.emit_to_string(&source));
             }
-        })
-        .unwrap();
+        }
+    }
 }
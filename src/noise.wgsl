@@ -0,0 +1,6 @@
+// NOTE: included by the passes that spread a few taps per pixel into noise
+
+// NOTE: in 0..1, px is the pixel position
+fn interleaved_gradient_noise(px: vec2<f32>) -> f32 {
+    return fract(52.9829189 * fract(dot(px, vec2(0.06711056, 0.00583715))));
+}
@@ -0,0 +1,17 @@
+// #include "fullscreen.wgsl"
+// #include "globals.wgsl"
+
+var normal_view: texture_2d<f32>;
+
+@fragment
+fn fs_normal_debug(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    let size = textureDimensions(normal_view);
+    let texel = min(vec2<u32>(vertex.uv * vec2<f32>(size)), size - 1u);
+    let view_normal = decode_normal(textureLoad(normal_view, texel, 0));
+
+    // NOTE: g-buffer normals are in view space, mv_rot is only the view rotation
+    // for full screen passes so its transpose takes them back to world space
+    let view_rot = mat3x3(globals.mv_rot[0].xyz, globals.mv_rot[1].xyz, globals.mv_rot[2].xyz);
+    let ws_normal = normalize(transpose(view_rot) * view_normal);
+    return vec4(ws_normal * 0.5 + 0.5, 1.0);
+}
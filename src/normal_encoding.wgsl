@@ -0,0 +1,38 @@
+// NOTE: shared by every shader that reads or writes g-buffer normals, prepended to the
+// shader source on load together with the FULL_PRECISION_NORMALS const for the
+// normal target format, see NormalEncoding in main.rs
+
+// NOTE: folds the lower hemisphere over the diagonals of the octahedron
+fn oct_wrap(v: vec2<f32>) -> vec2<f32> {
+    let s = select(vec2(-1.0), vec2(1.0), v >= vec2(0.0));
+    return (1.0 - abs(v.yx)) * s;
+}
+
+// NOTE: octahedral encoding to the xy of the target, z and w are dropped by the two
+// channel target. The full precision path stores the normal as is
+fn encode_normal(n: vec3<f32>) -> vec4<f32> {
+    if FULL_PRECISION_NORMALS {
+        return vec4(n, 1.0);
+    }
+    var e = n.xy / (abs(n.x) + abs(n.y) + abs(n.z));
+    if n.z < 0.0 {
+        e = oct_wrap(e);
+    }
+    return vec4(e, 0.0, 1.0);
+}
+
+fn decode_normal(e: vec4<f32>) -> vec3<f32> {
+    if FULL_PRECISION_NORMALS {
+        return e.xyz;
+    }
+    var n = vec3(e.xy, 1.0 - abs(e.x) - abs(e.y));
+    let t = max(-n.z, 0.0);
+    n.x += select(t, -t, n.x >= 0.0);
+    n.y += select(t, -t, n.y >= 0.0);
+    return normalize(n);
+}
+
+// NOTE: normal of texel i from textureGather results of channels 0, 1 and 2
+fn decode_gathered_normal(gx: vec4<f32>, gy: vec4<f32>, gz: vec4<f32>, i: u32) -> vec3<f32> {
+    return decode_normal(vec4(gx[i], gy[i], gz[i], 1.0));
+}
@@ -0,0 +1,42 @@
+
+struct ParticleGlobals {
+    view_proj: mat4x4<f32>,
+    cam_right: vec3<f32>,
+    pad0: u32,
+    cam_up: vec3<f32>,
+    pad1: u32,
+};
+
+var<uniform> globals: ParticleGlobals;
+
+// NOTE: one per instance, see ParticleInstance
+struct Particle {
+    position: vec3<f32>,
+    size: f32,
+    color: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_pos: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+// NOTE: 4 vertices as a triangle strip, the corner is spread along the camera axes
+// so the quad always faces the screen
+@vertex
+fn vs_particle(@builtin(vertex_index) vertex_index: u32, particle: Particle) -> VertexOutput {
+    let uv = vec2(f32(vertex_index & 1u), f32(vertex_index >> 1u));
+    let corner = 2.0 * uv - 1.0;
+    let offset = globals.cam_right * corner.x + globals.cam_up * corner.y;
+    let ws_pos = particle.position + 0.5 * particle.size * offset;
+    return VertexOutput(globals.view_proj * vec4(ws_pos, 1.0), uv, particle.color);
+}
+
+// NOTE: round soft edged dot instead of a texture
+@fragment
+fn fs_particle(vs_out: VertexOutput) -> @location(0) vec4<f32> {
+    let d = length(2.0 * vs_out.uv - 1.0);
+    let alpha = vs_out.color.a * (1.0 - smoothstep(0.5, 1.0, d));
+    return vec4(vs_out.color.rgb, alpha);
+}
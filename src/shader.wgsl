@@ -1,56 +1,147 @@
+// #include "globals.wgsl"
 
-struct Globals {
-    mvp_transform: mat4x4<f32>,   
-    mv_transform: mat4x4<f32>,   
-    mv_rot: mat4x4<f32>,
-    cam_pos: vec3<f32>,
-    cam_dir: vec3<f32>,
+struct Material {
+    diffuse: vec4<f32>,
+    // NOTE: layer in albedo_view, -1 means use the flat diffuse color
+    texture_layer: i32,
+    // NOTE: 1 if the mesh has normals from the file, 0 means use the derivative normal
+    use_vertex_normals: u32,
+    // NOTE: written to the material target as is, see MaterialUniform
+    roughness: f32,
+    metalness: f32,
 };
 
-var<uniform> globals: Globals;
+var<uniform> material: Material;
+
+var albedo_view: texture_2d_array<f32>;
+var albedo_sampler: sampler;
+
+// NOTE: third bind group, see PreviousFrameGlobals in main.rs. Bound for every geometry
+// draw, nothing reads it until there is a motion vector target
+var<uniform> prev_mvp: mat4x4<f32>;
+var<uniform> prev_cam_pos: vec3<f32>;
 
 struct VertexOutput {
     @builtin(position) clip_pos: vec4<f32>,
     @location(0) view_pos: vec3<f32>,
-    // @location(1) view_normal: vec3<f32>,
+    @location(1) view_normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) color: vec4<f32>,
 };
 
 struct Vertex {
     ws_pos: vec3<f32>,
-    // ws_normal: vec3<f32>,
+    ws_normal: vec3<f32>,
+    uv: vec2<f32>,
+    color: vec4<f32>,
 };
 
-@vertex
-fn vs_main(vertex: Vertex) -> VertexOutput {
+// NOTE: VertexFormat::PackedNormals, normal is 10-10-10-2 snorm
+struct PackedVertex {
+    ws_pos: vec3<f32>,
+    ws_normal: u32,
+    uv: vec2<f32>,
+    color: vec4<f32>,
+};
+
+fn decode_normal_10_10_10_2(packed: u32) -> vec3<f32> {
+    let bits = vec3(packed, packed >> 10u, packed >> 20u) & vec3(0x3ffu);
+    // NOTE: shift up and back down to sign extend the 10 bit values
+    let signed = bitcast<vec3<i32>>(bits << vec3(22u)) >> vec3(22u);
+    return max(vec3<f32>(signed) / 511.0, vec3(-1.0));
+}
+
+fn transform_vertex(ws_pos: vec3<f32>, ws_normal: vec3<f32>, uv: vec2<f32>, color: vec4<f32>) -> VertexOutput {
     var vs_out: VertexOutput;
-    vs_out.clip_pos = globals.mvp_transform * vec4(vertex.ws_pos, 1.0);
-    vs_out.view_pos = (globals.mv_transform * vec4(vertex.ws_pos, 1.0)).xyz;
-    // vs_out.view_normal = (globals.mv_rot * vec4(vertex.ws_normal, 1.0)).xyz;
+    vs_out.clip_pos = globals.mvp_transform * vec4(ws_pos, 1.0);
+    vs_out.view_pos = (globals.mv_transform * vec4(ws_pos, 1.0)).xyz;
+    vs_out.view_normal = (globals.mv_rot * vec4(ws_normal, 0.0)).xyz;
+    vs_out.uv = uv;
+    vs_out.color = color;
 
     return vs_out;
 }
 
+@vertex
+fn vs_main(vertex: Vertex) -> VertexOutput {
+    return transform_vertex(vertex.ws_pos, vertex.ws_normal, vertex.uv, vertex.color);
+}
+
+@vertex
+fn vs_main_packed(vertex: PackedVertex) -> VertexOutput {
+    let ws_normal = decode_normal_10_10_10_2(vertex.ws_normal);
+    return transform_vertex(vertex.ws_pos, ws_normal, vertex.uv, vertex.color);
+}
+
+// NOTE: no position target, the passes after rebuild positions from the depth, see view_pos.wgsl
 struct FragmentOutput {
-    @location(0) view_pos: vec4<f32>,
-    @location(1) view_normal: vec4<f32>
+    @location(0) view_normal: vec4<f32>,
+    @location(1) albedo: vec4<f32>,
+    // NOTE: roughness and metalness in xy of the two channel target
+    @location(2) material: vec4<f32>,
 }
 
 @fragment
 fn fs_main(vs_out: VertexOutput) -> FragmentOutput {
-    // is 0 if depth is 0
-    let s = sign(abs(vs_out.view_pos.z));
-
-    let view_pos = vec4(vs_out.view_pos, s);
-
-    let dx = dpdx(view_pos.xyz);
-    let dy = dpdy(view_pos.xyz);
+    let dx = dpdx(vs_out.view_pos);
+    let dy = dpdy(vs_out.view_pos);
 
     var n = cross(dy,dx);
     n = normalize(n);
+    if (material.use_vertex_normals != 0u) {
+        n = normalize(vs_out.view_normal);
+    }
 
     // use true polygon normals (w value is not used)
-    let view_normal = vec4(n, 1.0);
+    let view_normal = encode_normal(n);
 
-    return FragmentOutput(view_pos, view_normal);
+    // NOTE: obj uvs have v pointing up, textures are stored top row first
+    let tex_uv = vec2(vs_out.uv.x, 1.0 - vs_out.uv.y);
+    let layer = max(material.texture_layer, 0);
+    let texture_albedo = textureSample(albedo_view, albedo_sampler, tex_uv, layer);
+    let material_albedo = select(material.diffuse, texture_albedo, material.texture_layer >= 0);
+    let albedo = material_albedo * vs_out.color;
+    let surface = vec4(material.roughness, material.metalness, 0.0, 1.0);
+
+    return FragmentOutput(view_normal, albedo, surface);
+}
+
+
+// NOTE: flat yellow-green albedo so the edges stand out, lines have no area so use the
+// interpolated vertex normal instead of the screen space derivative one
+@fragment
+fn fs_wireframe(vs_out: VertexOutput) -> FragmentOutput {
+    let view_normal = encode_normal(normalize(vs_out.view_normal));
+    let albedo = vec4(0.6, 1.0, 0.1, 1.0);
+    return FragmentOutput(view_normal, albedo, vec4(1.0, 0.0, 0.0, 1.0));
+}
+
+// NOTE: magenta so the back face edges contrast with both materials and fs_wireframe
+@fragment
+fn fs_backface(vs_out: VertexOutput) -> FragmentOutput {
+    let view_normal = encode_normal(normalize(vs_out.view_normal));
+    let albedo = vec4(1.0, 0.1, 0.8, 1.0);
+    return FragmentOutput(view_normal, albedo, vec4(1.0, 0.0, 0.0, 1.0));
+}
+
+// NOTE: yellow edges of the selected node, see State::selected_node
+@fragment
+fn fs_selection(vs_out: VertexOutput) -> FragmentOutput {
+    let view_normal = encode_normal(normalize(vs_out.view_normal));
+    let albedo = vec4(1.0, 0.9, 0.1, 1.0);
+    return FragmentOutput(view_normal, albedo, vec4(1.0, 0.0, 0.0, 1.0));
+}
+
+// NOTE: shadow map pass, globals.mvp_transform is the light view projection times model
+@vertex
+fn vs_shadow(vertex: Vertex) -> @builtin(position) vec4<f32> {
+    return globals.mvp_transform * vec4(vertex.ws_pos, 1.0);
 }
 
+@vertex
+fn vs_shadow_packed(vertex: PackedVertex) -> @builtin(position) vec4<f32> {
+    return globals.mvp_transform * vec4(vertex.ws_pos, 1.0);
+}
+
+@fragment
+fn fs_shadow() {}
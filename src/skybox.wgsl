@@ -0,0 +1,40 @@
+// NOTE: drawn after the light pass into the same target, the depth test against the
+// g-buffer depth only passes where nothing was drawn, see render_skybox in main.rs
+
+struct SkyboxUniform {
+    // NOTE: clip space to world space without the camera translation, so the
+    // transformed point is a direction from the camera
+    inv_vp_rot: mat4x4<f32>,
+    // NOTE: depth of the far plane, 0 with reverse z
+    far_depth: f32,
+    pad0: u32,
+    pad1: u32,
+    pad2: u32,
+};
+
+var<uniform> skybox: SkyboxUniform;
+var cube_view: texture_cube<f32>;
+var cube_sampler: sampler;
+
+struct SkyboxOutput {
+    @builtin(position) clip_pos: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+};
+
+// NOTE: same single triangle as vs_fullscreen but on the far plane
+@vertex
+fn vs_skybox(@builtin(vertex_index) vertex_index: u32) -> SkyboxOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    let ndc = vec2(2.0 * uv.x - 1.0, 1.0 - 2.0 * uv.y);
+    return SkyboxOutput(vec4(ndc, skybox.far_depth, 1.0), ndc);
+}
+
+@fragment
+fn fs_skybox(vertex: SkyboxOutput) -> @location(0) vec4<f32> {
+    // NOTE: any depth between the planes lies on the same ray
+    let world = skybox.inv_vp_rot * vec4(vertex.ndc, 0.5, 1.0);
+    let dir = normalize(world.xyz / world.w);
+    let c = textureSample(cube_view, cube_sampler, dir).rgb;
+    // NOTE: same as the end of fs_light
+    return vec4(pow(c, vec3<f32>(2.2)), 1.0);
+}
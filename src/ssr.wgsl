@@ -0,0 +1,147 @@
+// NOTE: screen space reflections over the lit image, marches the view space reflection
+// ray against the g-buffer depth and blends what it hits by fresnel and the material
+// roughness.
+// Misses and rays leaving the screen fall back to the ambient the light pass uses, see
+// render_ssr in main.rs
+
+// #include "fullscreen.wgsl"
+// #include "globals.wgsl"
+// #include "view_pos.wgsl"
+
+// #include "noise.wgsl"
+
+struct SsrUniform {
+    // NOTE: jittered like the geometry pass so the rays land on the same pixels
+    proj: mat4x4<f32>,
+    view_to_world: mat4x4<f32>,
+    max_distance: f32,
+    // NOTE: how far behind a surface the ray may be and still count as hitting it
+    thickness: f32,
+    steps: u32,
+    pad0: u32,
+};
+
+// NOTE: same as in light_shader.wgsl, see AmbientUniform
+struct AmbientParams {
+    sky: f32,
+    ground: f32,
+    use_bent_normal: u32,
+    use_ibl: u32,
+    ibl_intensity: f32,
+    pulse: f32,
+    pad1: u32,
+    pad2: u32,
+};
+
+var<uniform> ssr: SsrUniform;
+var<uniform> ambient: AmbientParams;
+
+var color_view: texture_2d<f32>;
+var color_sampler: sampler;
+
+var depth_view: texture_depth_2d;
+
+var normal_view: texture_2d<f32>;
+var normal_sampler: sampler;
+
+var albedo_view: texture_2d<f32>;
+var albedo_sampler: sampler;
+
+// NOTE: roughness in r, metalness in g, see MATERIAL_FORMAT
+var material_view: texture_2d<f32>;
+var material_sampler: sampler;
+
+var irradiance_view: texture_cube<f32>;
+var irradiance_sampler: sampler;
+
+const SSR_REFINE_STEPS = 4u;
+// NOTE: hits this close to the screen edge fade out to the fallback
+const SSR_EDGE_FADE = 0.1;
+
+fn view_to_uv(q: vec3<f32>) -> vec2<f32> {
+    let clip = ssr.proj * vec4(q, 1.0);
+    let ndc = clip.xy / clip.w;
+    return vec2(0.5 * ndc.x + 0.5, 0.5 - 0.5 * ndc.y);
+}
+
+// NOTE: positive when q is behind the g-buffer surface at its pixel, rh view space so
+// further away is more negative z. The background has positive z and is never hit
+fn depth_behind(q: vec3<f32>) -> f32 {
+    let scene = load_view_pos(depth_view, view_to_uv(q));
+    return select(-1.0, scene.z - q.z, scene.z < 0.0);
+}
+
+// NOTE: the ambient of the light pass along the world space direction, gamma encoded
+// the same way, so pow 2.2 like the end of fs_light
+fn ambient_fallback(ws_dir: vec3<f32>) -> vec3<f32> {
+    let hemisphere = vec3(mix(ambient.ground, ambient.sky, 0.5 * ws_dir.y + 0.5));
+    let ibl = ambient.ibl_intensity
+        * textureSampleLevel(irradiance_view, irradiance_sampler, ws_dir, 0.0).rgb;
+    return pow(select(hemisphere, ibl, ambient.use_ibl != 0u), vec3(2.2));
+}
+
+// NOTE: VertexOutput and vs_fullscreen come from fullscreen.wgsl
+@fragment
+fn fs_ssr(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<i32>(textureDimensions(color_view));
+    let texel = min(vec2<i32>(vertex.uv * vec2<f32>(size)), size - 1);
+    let lit = textureLoad(color_view, texel, 0).rgb;
+    let p = load_view_pos_texel(depth_view, texel).xyz;
+    let material = textureLoad(material_view, texel, 0).rg;
+    let gloss = (1.0 - material.r) * (1.0 - material.r);
+    if p.z >= 0.0 || gloss <= 0.0 {
+        return vec4(lit, 1.0);
+    }
+    let n = decode_normal(textureLoad(normal_view, texel, 0));
+    let v = normalize(p);
+    let r = reflect(v, n);
+
+    // NOTE: per pixel offset of the steps, banding turns into noise taa can resolve
+    let jitter = interleaved_gradient_noise(vertex.uv * vec2<f32>(size));
+    let num_steps = max(ssr.steps, 1u);
+    let step_len = ssr.max_distance / f32(num_steps);
+    var prev_t = 0.0;
+    var hit_t = -1.0;
+    for (var i = 0u; i < num_steps; i++) {
+        let t = (f32(i) + jitter) * step_len;
+        let q = p + t * r;
+        let uv = view_to_uv(q);
+        // NOTE: off screen or behind the camera, nothing left to hit
+        if q.z >= 0.0 || any(uv < vec2(0.0)) || any(uv > vec2(1.0)) {
+            break;
+        }
+        let behind = depth_behind(q);
+        if behind > 0.0 && behind < ssr.thickness {
+            // NOTE: bisect between the last step in front and this one
+            var lo = prev_t;
+            var hi = t;
+            for (var j = 0u; j < SSR_REFINE_STEPS; j++) {
+                let mid = 0.5 * (lo + hi);
+                if depth_behind(p + mid * r) > 0.0 {
+                    hi = mid;
+                } else {
+                    lo = mid;
+                }
+            }
+            hit_t = hi;
+            break;
+        }
+        prev_t = t;
+    }
+
+    let ws_r = (ssr.view_to_world * vec4(r, 0.0)).xyz;
+    var reflection = ambient_fallback(ws_r);
+    if hit_t >= 0.0 {
+        let hit_uv = view_to_uv(p + hit_t * r);
+        let hit = textureSampleLevel(color_view, color_sampler, hit_uv, 0.0).rgb;
+        let edge = min(min(hit_uv.x, 1.0 - hit_uv.x), min(hit_uv.y, 1.0 - hit_uv.y));
+        let fade = clamp(edge / SSR_EDGE_FADE, 0.0, 1.0) * (1.0 - hit_t / ssr.max_distance);
+        reflection = mix(reflection, hit, fade);
+    }
+
+    // NOTE: schlick fresnel, metals tint the reflection with their albedo
+    let albedo = pow(textureLoad(albedo_view, texel, 0).rgb, vec3(2.2));
+    let f0 = mix(vec3(0.04), albedo, material.g);
+    let fresnel = f0 + (1.0 - f0) * pow(1.0 - clamp(dot(n, -v), 0.0, 1.0), 5.0);
+    return vec4(mix(lit, reflection, fresnel * gloss), 1.0);
+}
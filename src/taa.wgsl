@@ -0,0 +1,69 @@
+// #include "fullscreen.wgsl"
+// #include "globals.wgsl"
+// #include "view_pos.wgsl"
+
+struct TaaUniform {
+    // NOTE: weight of the history in the blend, the rest comes from the current frame
+    history_weight: f32,
+    // NOTE: 0 on the first frame after enabling, the history holds garbage then
+    history_valid: u32,
+    pad0: u32,
+    pad1: u32,
+};
+
+var<uniform> taa: TaaUniform;
+
+var current_view: texture_2d<f32>;
+var current_sampler: sampler;
+
+var history_view: texture_2d<f32>;
+var history_sampler: sampler;
+
+var depth_view: texture_depth_2d;
+
+struct TaaOutput {
+    @location(0) color: vec4<f32>,
+    @location(1) history: vec4<f32>,
+};
+
+// NOTE: VertexOutput and vs_fullscreen come from fullscreen.wgsl
+@fragment
+fn fs_taa_resolve(vertex: VertexOutput) -> TaaOutput {
+    let size = vec2<i32>(textureDimensions(current_view));
+    let texel = min(vec2<i32>(vertex.uv * vec2<f32>(size)), size - 1);
+    let current = textureLoad(current_view, texel, 0);
+
+    // NOTE: color box of the 3x3 neighbourhood, history outside of it is from geometry
+    // that is no longer visible under this pixel and gets clamped away
+    var color_min = current.rgb;
+    var color_max = current.rgb;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let neighbour_texel = clamp(texel + vec2(x, y), vec2(0), size - 1);
+            let neighbour = textureLoad(current_view, neighbour_texel, 0).rgb;
+            color_min = min(color_min, neighbour);
+            color_max = max(color_max, neighbour);
+        }
+    }
+
+    // NOTE: camera motion only, the meshes are static so the world position of the pixel
+    // projected with last frame's camera is where it was on screen
+    let view_pos = load_view_pos_texel(depth_view, texel);
+    var prev_uv = vertex.uv;
+    // NOTE: rh view space, the cleared background has positive z and no motion
+    if (view_pos.z < 0.0) {
+        let ws_pos = (transpose(globals.mv_rot) * vec4(view_pos.xyz, 0.0)).xyz + globals.cam_pos;
+        let prev_clip = globals.prev_vp * vec4(ws_pos, 1.0);
+        let prev_ndc = prev_clip.xy / prev_clip.w;
+        prev_uv = vec2(0.5 * prev_ndc.x + 0.5, 0.5 - 0.5 * prev_ndc.y);
+    }
+
+    var weight = taa.history_weight;
+    if (taa.history_valid == 0u || any(prev_uv < vec2(0.0)) || any(prev_uv > vec2(1.0))) {
+        weight = 0.0;
+    }
+    let history = textureSampleLevel(history_view, history_sampler, prev_uv, 0.0).rgb;
+    let clamped_history = clamp(history, color_min, color_max);
+    let resolved = vec4(mix(current.rgb, clamped_history, weight), 1.0);
+    return TaaOutput(resolved, resolved);
+}
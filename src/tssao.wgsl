@@ -0,0 +1,69 @@
+// #include "fullscreen.wgsl"
+// #include "globals.wgsl"
+// #include "view_pos.wgsl"
+
+struct TssaoUniform {
+    // NOTE: weight of the current frame in the blend, the rest comes from the history
+    alpha: f32,
+    // NOTE: 0 on the first frame after enabling, the history holds garbage then
+    history_valid: u32,
+    // NOTE: relative view depth difference past which the history is from another surface
+    depth_tolerance: f32,
+    pad: u32,
+};
+
+var<uniform> tssao: TssaoUniform;
+
+var current_ao_view: texture_2d<f32>;
+var current_ao_sampler: sampler;
+
+var history_view: texture_2d<f32>;
+var history_sampler: sampler;
+
+var history_depth_view: texture_2d<f32>;
+
+var depth_view: texture_depth_2d;
+
+struct TssaoOutput {
+    @location(0) ao: vec4<f32>,
+    @location(1) depth: vec4<f32>,
+};
+
+// NOTE: VertexOutput and vs_fullscreen come from fullscreen.wgsl
+@fragment
+fn fs_tssao_resolve(vertex: VertexOutput) -> TssaoOutput {
+    // NOTE: the ao level picked in the ui can be smaller than the history
+    let current = textureSampleLevel(current_ao_view, current_ao_sampler, vertex.uv, 0.0);
+
+    let size = vec2<i32>(textureDimensions(depth_view));
+    let texel = min(vec2<i32>(vertex.uv * vec2<f32>(size)), size - 1);
+    let view_pos = load_view_pos_texel(depth_view, texel);
+    // NOTE: rh view space, the cleared background has positive z and nothing to accumulate
+    if (view_pos.z >= 0.0) {
+        return TssaoOutput(current, vec4(0.0));
+    }
+    let depth = -view_pos.z;
+
+    // NOTE: camera motion only like in taa.wgsl, the meshes are static
+    let ws_pos = (transpose(globals.mv_rot) * vec4(view_pos.xyz, 0.0)).xyz + globals.cam_pos;
+    let prev_clip = globals.prev_vp * vec4(ws_pos, 1.0);
+    let prev_ndc = prev_clip.xy / prev_clip.w;
+    let prev_uv = vec2(0.5 * prev_ndc.x + 0.5, 0.5 - 0.5 * prev_ndc.y);
+
+    // NOTE: clip w of a perspective projection is the view depth, so prev_clip.w is how far
+    // this point was from last frame's camera. The history depth at the reprojected texel
+    // being somewhere else means the point was hidden or off screen, i.e. disoccluded
+    let history_size = vec2<i32>(textureDimensions(history_depth_view));
+    let prev_texel = clamp(vec2<i32>(prev_uv * vec2<f32>(history_size)), vec2(0), history_size - 1);
+    let history_depth = textureLoad(history_depth_view, prev_texel, 0).x;
+    let disoccluded = abs(history_depth - prev_clip.w) > tssao.depth_tolerance * prev_clip.w;
+    let on_screen = prev_clip.w > 0.0 && all(prev_uv >= vec2(0.0)) && all(prev_uv <= vec2(1.0));
+
+    var alpha = tssao.alpha;
+    if (tssao.history_valid == 0u || !on_screen || disoccluded) {
+        alpha = 1.0;
+    }
+    let history = textureSampleLevel(history_view, history_sampler, prev_uv, 0.0);
+    let ao = mix(history.rgb, current.rgb, alpha);
+    return TssaoOutput(vec4(ao, 1.0), vec4(depth, 0.0, 0.0, 1.0));
+}
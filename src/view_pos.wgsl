@@ -0,0 +1,56 @@
+// NOTE: view space positions rebuilt from a depth level of the g-buffer, there is no
+// position target. Uses globals, so the including shader also includes globals.wgsl.
+// inv_mvp_transform is the jittered vp the g-buffer was drawn with, which works for every
+// level since ndc doesn't depend on the resolution
+
+// NOTE: background comes back as (1, 1, 1, 1), what the position targets used to be
+// cleared to, so rh view space z >= 0 still means nothing was drawn
+fn depth_to_view_pos(uv: vec2<f32>, depth: f32) -> vec4<f32> {
+    // NOTE: y flips from uv to ndc
+    let ndc = vec4(2.0 * uv.x - 1.0, 1.0 - 2.0 * uv.y, depth, 1.0);
+    let world = globals.inv_mvp_transform * ndc;
+    return select(
+        globals.mv_transform * vec4(world.xyz / world.w, 1.0),
+        vec4(1.0),
+        depth == globals.far_depth,
+    );
+}
+
+// NOTE: rebuilt at the texel center, so every uv inside a texel gets the same position
+// like the nearest samplers of the position targets did
+fn load_view_pos_texel(depth_view: texture_depth_2d, texel: vec2<i32>) -> vec4<f32> {
+    let size = vec2<i32>(textureDimensions(depth_view));
+    let clamped = clamp(texel, vec2(0), size - 1);
+    let uv = (vec2<f32>(clamped) + 0.5) / vec2<f32>(size);
+    return depth_to_view_pos(uv, textureLoad(depth_view, clamped, 0));
+}
+
+fn load_view_pos(depth_view: texture_depth_2d, uv: vec2<f32>) -> vec4<f32> {
+    let size = vec2<f32>(textureDimensions(depth_view));
+    return load_view_pos_texel(depth_view, vec2<i32>(floor(uv * size)));
+}
+
+// NOTE: the four texels textureGather reads at uv, in the same order, so the result lines
+// up with gathers of the other g-buffer textures at the same uv
+fn gather_view_pos(depth_view: texture_depth_2d, uv: vec2<f32>) -> array<vec4<f32>, 4> {
+    let size = vec2<f32>(textureDimensions(depth_view));
+    let base = vec2<i32>(floor(uv * size - 0.5));
+    return array(
+        load_view_pos_texel(depth_view, base + vec2(0, 1)),
+        load_view_pos_texel(depth_view, base + vec2(1, 1)),
+        load_view_pos_texel(depth_view, base + vec2(1, 0)),
+        load_view_pos_texel(depth_view, base),
+    );
+}
+
+// NOTE: depth buffer value of a view space position, the inverse of depth_to_view_pos
+// for the depth. Doesn't depend on x and y, the z of a position written with it is what
+// depth_to_view_pos gives back at any uv
+fn view_pos_to_depth(p: vec3<f32>) -> f32 {
+    if p.z >= 0.0 {
+        return globals.far_depth;
+    }
+    let ws_pos = (transpose(globals.mv_rot) * vec4(p, 0.0)).xyz + globals.cam_pos;
+    let clip = globals.mvp_transform * vec4(ws_pos, 1.0);
+    return clip.z / clip.w;
+}